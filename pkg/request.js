@@ -1,19 +1,55 @@
+import { merge_tile } from "./rs_pbrt.js";
+
 var endpoint = 0;
 
+// Synchronous, the scene loaders on the Rust side can't wait. A
+// synchronous request can't ask for an arraybuffer, so the bytes
+// arrive as x-user-defined text (one byte per character).
 export function get_content_web(path) {
 	var xhttp = new XMLHttpRequest();
-	xhttp.responseType = "arraybuffer";
-	var response;
-	//	xhttp.overrideMimeType('application/x-binary; charset=x-user-defined');
-	xhttp.onload = function(oEvent) {
-		response = xhttp.response;
-	}
-
 	var url = "content/" + path;
-	xhttp.open("GET", url, true);
+	xhttp.open("GET", url, false);
+	xhttp.overrideMimeType("text/plain; charset=x-user-defined");
 	xhttp.send(null);
+	if (xhttp.status !== 200) {
+		throw "GET " + url + " returned " + xhttp.status;
+	}
+	var text = xhttp.responseText;
+	var data = new Uint8Array(text.length);
+	for (var i = 0; i < text.length; ++i) {
+		data[i] = text.charCodeAt(i) & 0xff;
+	}
+	return data;
+}
+
+// Synchronous as well, exists() and size() of the WebBackend only
+// need the headers.
+function head_content(path) {
+	var xhttp = new XMLHttpRequest();
+	try {
+		xhttp.open("HEAD", "content/" + path, false);
+		xhttp.send(null);
+	} catch (e) {
+		return null;
+	}
+	return xhttp;
+}
 
-	return response;
+export function content_exists_web(path) {
+	var xhttp = head_content(path);
+	return xhttp !== null && xhttp.status === 200;
+}
+
+export function content_size_web(path) {
+	var xhttp = head_content(path);
+	if (xhttp === null || xhttp.status !== 200) {
+		return undefined;
+	}
+	var length = xhttp.getResponseHeader("Content-Length");
+	if (length === null) {
+		return undefined;
+	}
+	return Number(length);
 }
 
 var total_requests = 0;
@@ -70,32 +106,28 @@ export function http_request(x, y, tile_size, data) {
 	};
 	xhttp.onload = function(oEvent) {
 		var arraybuffer = xhttp.response;
-		if (arraybuffer) {
+		if (arraybuffer && xhttp.status === 200) {
+			// merge the HDR tile payload into the collector's film and draw
+			// the updated region (which overlaps neighbouring tiles)
+			var region = merge_tile(new Uint8Array(arraybuffer));
+			if (region.length < 16) {
+				return;
+			}
+			var header = new DataView(region.buffer, region.byteOffset, 16);
+			var x0 = header.getInt32(0, true);
+			var y0 = header.getInt32(4, true);
+			var width = header.getInt32(8, true);
+			var height = header.getInt32(12, true);
 			var canvas = document.getElementById("framebuffer");
 			var ctx = canvas.getContext('2d');
-			var byteArray = new Uint8Array(arraybuffer);
-
-			var dim = tile_size;
-			var data = new Uint8ClampedArray(4 * dim * dim);
-			var index=0;
-			for (var i = 0; i < dim; ++i) {
-				for (var j = 0; j < dim; ++j) {
-					var base = i * dim + j;
-					data[4 * base] = byteArray[3*base];
-					data[4 * base + 1] = byteArray[3*base+1];
-					data[4 * base + 2] = byteArray[3*base+2];
-					data[4 * base + 3] = 255;
-				}
+			var data = new Uint8ClampedArray(4 * width * height);
+			for (var base = 0; base < width * height; ++base) {
+				data[4 * base] = region[16 + 3 * base];
+				data[4 * base + 1] = region[16 + 3 * base + 1];
+				data[4 * base + 2] = region[16 + 3 * base + 2];
+				data[4 * base + 3] = 255;
 			}
-			var imageData = new ImageData(data, dim, dim);
-			var tempcanvas = document.createElement('canvas');
-			tempcanvas.width = dim;
-			tempcanvas.height = dim;
-			var tempctx = tempcanvas.getContext('2d');
-			tempctx.putImageData(imageData, 0, 0);
-
-			//			ctx.scale(10,10);
-			ctx.drawImage(tempcanvas, x*dim, y*dim);
+			ctx.putImageData(new ImageData(data, width, height), x0, y0);
 		}
 	};
 
@@ -118,6 +150,7 @@ export function http_request(x, y, tile_size, data) {
 	xhttp.responseType = "arraybuffer";
 
 	var body = {};
+	body["version"] = 1;
 	body["x"] = x;
 	body["y"] = y;
 	body["tile_size"] = tile_size;
@@ -4,6 +4,7 @@ use std::sync::Arc;
 
 // others
 // use time::PreciseTime;
+use smallvec::SmallVec;
 use typed_arena::Arena;
 // pbrt
 use crate::core::geometry::{bnd3_union_bnd3f, bnd3_union_pnt3f};
@@ -12,11 +13,14 @@ use crate::core::interaction::SurfaceInteraction;
 use crate::core::light::Light;
 use crate::core::material::Material;
 use crate::core::paramset::ParamSet;
-use crate::core::pbrt::Float;
+use crate::core::pbrt::{gamma, Float};
 use crate::core::primitive::Primitive;
 
 // see bvh.h
 
+/// Maximum number of children of a [WideBVHNode](struct.WideBVHNode.html).
+pub const MAX_BVH_WIDTH: usize = 8;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum SplitMethod {
     SAH,
@@ -97,6 +101,18 @@ impl Default for BucketInfo {
     }
 }
 
+#[derive(Debug, Default, Copy, Clone)]
+struct MortonPrimitive {
+    primitive_index: usize,
+    morton_code: u32,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct LBVHTreelet {
+    start_index: usize,
+    n_primitives: usize,
+}
+
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct LinearBVHNode {
     bounds: Bounds3f,
@@ -108,13 +124,118 @@ pub struct LinearBVHNode {
     pad: u8,
 }
 
+/// A node of a 4- or 8-wide BVH. The bounds of all children are
+/// stored next to each other (structure of arrays), so they can be
+/// tested against a ray in one go.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct WideBVHNode {
+    min_x: [Float; MAX_BVH_WIDTH],
+    min_y: [Float; MAX_BVH_WIDTH],
+    min_z: [Float; MAX_BVH_WIDTH],
+    max_x: [Float; MAX_BVH_WIDTH],
+    max_y: [Float; MAX_BVH_WIDTH],
+    max_z: [Float; MAX_BVH_WIDTH],
+    // leaf: offset of the first primitive
+    // interior: index of the child node
+    offset: [u32; MAX_BVH_WIDTH],
+    // zero for interior children
+    n_primitives: [u16; MAX_BVH_WIDTH],
+    n_children: u8,
+}
+
+impl WideBVHNode {
+    fn set_child_bounds(&mut self, i: usize, b: &Bounds3f) {
+        self.min_x[i] = b.p_min.x;
+        self.min_y[i] = b.p_min.y;
+        self.min_z[i] = b.p_min.z;
+        self.max_x[i] = b.p_max.x;
+        self.max_y[i] = b.p_max.y;
+        self.max_z[i] = b.p_max.z;
+    }
+    fn child_bounds(&self, i: usize) -> Bounds3f {
+        Bounds3f {
+            p_min: Point3f {
+                x: self.min_x[i],
+                y: self.min_y[i],
+                z: self.min_z[i],
+            },
+            p_max: Point3f {
+                x: self.max_x[i],
+                y: self.max_y[i],
+                z: self.max_z[i],
+            },
+        }
+    }
+    pub fn bounds(&self) -> Bounds3f {
+        let mut bounds: Bounds3f = Bounds3f::default();
+        for i in 0..self.n_children as usize {
+            bounds = bnd3_union_bnd3f(&bounds, &self.child_bounds(i));
+        }
+        bounds
+    }
+    /// Tests the ray against the bounds of all children, stores the
+    /// entry distances in `t_near` and returns a bit mask of the
+    /// children which were hit.
+    fn intersect_children(
+        &self,
+        ray: &Ray,
+        inv_dir: &Vector3f,
+        dir_is_neg: &[u8; 3],
+        t_near: &mut [Float; MAX_BVH_WIDTH],
+    ) -> u32 {
+        let (near_x, far_x) = if dir_is_neg[0] == 1_u8 {
+            (&self.max_x, &self.min_x)
+        } else {
+            (&self.min_x, &self.max_x)
+        };
+        let (near_y, far_y) = if dir_is_neg[1] == 1_u8 {
+            (&self.max_y, &self.min_y)
+        } else {
+            (&self.min_y, &self.max_y)
+        };
+        let (near_z, far_z) = if dir_is_neg[2] == 1_u8 {
+            (&self.max_z, &self.min_z)
+        } else {
+            (&self.min_z, &self.max_z)
+        };
+        // ensure robust bounds intersection
+        let robust: Float = 1.0 + 2.0 * gamma(3_i32);
+        let t_max: Float = ray.t_max.get();
+        let mut hits: u32 = 0;
+        for i in 0..MAX_BVH_WIDTH {
+            let t0: Float = ((near_x[i] - ray.o.x) * inv_dir.x)
+                .max((near_y[i] - ray.o.y) * inv_dir.y)
+                .max((near_z[i] - ray.o.z) * inv_dir.z)
+                .max(0.0 as Float);
+            let t1: Float = ((far_x[i] - ray.o.x) * inv_dir.x * robust)
+                .min((far_y[i] - ray.o.y) * inv_dir.y * robust)
+                .min((far_z[i] - ray.o.z) * inv_dir.z * robust)
+                .min(t_max);
+            t_near[i] = t0;
+            hits |= ((t0 <= t1) as u32) << i;
+        }
+        hits & ((1_u32 << self.n_children) - 1)
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+struct WideStackEntry {
+    offset: u32,
+    n_primitives: u16,
+    t_near: Float,
+}
+
 // BVHAccel -> Aggregate -> Primitive
 #[derive(Serialize, Deserialize)]
 pub struct BVHAccel {
     max_prims_in_node: usize,
     split_method: SplitMethod,
+    width: usize,
     pub primitives: Vec<Arc<Primitive>>,
+    // binary tree (width 2)
     pub nodes: Vec<LinearBVHNode>,
+    // wide tree (width 4 or 8)
+    pub wide_nodes: Vec<WideBVHNode>,
 }
 
 impl BVHAccel {
@@ -122,12 +243,16 @@ impl BVHAccel {
         p: Vec<Arc<Primitive>>,
         max_prims_in_node: usize,
         split_method: SplitMethod,
+        width: usize,
     ) -> Self {
+        let width: usize = width.clamp(2, MAX_BVH_WIDTH);
         let bvh = Arc::new(BVHAccel {
             max_prims_in_node: std::cmp::min(max_prims_in_node, 255),
             split_method: split_method.clone(),
+            width,
             primitives: p,
             nodes: Vec::new(),
+            wide_nodes: Vec::new(),
         });
         let num_prims = bvh.primitives.len();
 		println!("bvh {}", num_prims);
@@ -140,34 +265,51 @@ impl BVHAccel {
             let world_bound = bvh.primitives[i].world_bound();
             *item = BVHPrimitiveInfo::new(i, world_bound);
         }
-        // TODO: if (splitMethod == SplitMethod::HLBVH)
         let arena: Arena<BVHBuildNode> = Arena::with_capacity(1024 * 1024 / 64);
         let mut total_nodes: usize = 0;
         let mut ordered_prims: Vec<Arc<Primitive>> = Vec::with_capacity(num_prims);
         // let start = PreciseTime::now();
-        let root = BVHAccel::recursive_build(
-            bvh, // instead of self
-            &arena,
-            &mut primitive_info,
-            0,
-            num_prims,
-            &mut total_nodes,
-            &mut ordered_prims,
-        );
+        let root = if let SplitMethod::HLBVH = split_method {
+            BVHAccel::hlbvh_build(
+                &bvh,
+                &arena,
+                &primitive_info,
+                &mut total_nodes,
+                &mut ordered_prims,
+            )
+        } else {
+            BVHAccel::recursive_build(
+                bvh, // instead of self
+                &arena,
+                &mut primitive_info,
+                0,
+                num_prims,
+                &mut total_nodes,
+                &mut ordered_prims,
+            )
+        };
         // let end = PreciseTime::now();
         // flatten first
-        let mut nodes = vec![LinearBVHNode::default(); total_nodes];
-        let mut offset: usize = 0;
-        // let start = PreciseTime::now();
-        BVHAccel::flatten_bvh_tree(root, &mut nodes, &mut offset);
-        // let end = PreciseTime::now();
-        assert!(nodes.len() == total_nodes);
+        let mut nodes: Vec<LinearBVHNode> = Vec::new();
+        let mut wide_nodes: Vec<WideBVHNode> = Vec::new();
+        if width > 2 {
+            BVHAccel::flatten_wide_bvh_tree(root, width, &mut wide_nodes);
+        } else {
+            nodes = vec![LinearBVHNode::default(); total_nodes];
+            let mut offset: usize = 0;
+            // let start = PreciseTime::now();
+            BVHAccel::flatten_bvh_tree(root, &mut nodes, &mut offset);
+            // let end = PreciseTime::now();
+            assert!(nodes.len() == total_nodes);
+        }
         // primitives.swap(orderedPrims);
         let bvh_ordered_prims = Arc::new(BVHAccel {
             max_prims_in_node: std::cmp::min(max_prims_in_node, 255),
             split_method,
+            width,
             primitives: ordered_prims,
             nodes,
+            wide_nodes,
         });
         let unwrapped = Arc::try_unwrap(bvh_ordered_prims);
         unwrapped.ok().unwrap()
@@ -191,10 +333,19 @@ impl BVHAccel {
             split_method = SplitMethod::SAH;
         }
         let max_prims_in_node: i32 = ps.find_one_int("maxnodeprims", 4);
+        let mut width: i32 = ps.find_one_int("width", 2);
+        if width < 2 || width > MAX_BVH_WIDTH as i32 {
+            println!(
+                "WARNING: BVH width {} not supported (2 to {}).  Using 2.",
+                width, MAX_BVH_WIDTH
+            );
+            width = 2;
+        }
         Primitive::BVH(Box::new(BVHAccel::new(
             prims,
             max_prims_in_node as usize,
             split_method,
+            width as usize,
         )))
     }
     pub fn recursive_build<'a>(
@@ -377,6 +528,236 @@ impl BVHAccel {
         }
         node
     }
+    /// Builds the lower levels of the tree from primitives sorted by
+    /// the Morton codes of their centroids (LBVH), one treelet per
+    /// cell of a 4096 cell grid, and combines the treelets with the
+    /// surface area heuristic.
+    pub fn hlbvh_build<'a>(
+        bvh: &BVHAccel,
+        arena: &'a Arena<BVHBuildNode<'a>>,
+        primitive_info: &[BVHPrimitiveInfo],
+        total_nodes: &mut usize,
+        ordered_prims: &mut Vec<Arc<Primitive>>,
+    ) -> &'a BVHBuildNode<'a> {
+        // compute bounding box of all primitive centroids
+        let mut bounds: Bounds3f = Bounds3f::default();
+        for pi in primitive_info {
+            bounds = bnd3_union_pnt3f(&bounds, &pi.centroid);
+        }
+        // compute Morton indices of primitives
+        let morton_bits: u32 = 10;
+        let morton_scale: Float = (1 << morton_bits) as Float;
+        let mut morton_prims: Vec<MortonPrimitive> = primitive_info
+            .iter()
+            .map(|pi| {
+                let centroid_offset: Vector3f = bounds.offset(&pi.centroid);
+                MortonPrimitive {
+                    primitive_index: pi.primitive_number,
+                    morton_code: encode_morton_3(&(centroid_offset * morton_scale)),
+                }
+            })
+            .collect();
+        // radix sort primitive Morton indices
+        radix_sort(&mut morton_prims);
+        // create LBVH treelets at bottom of BVH
+
+        // find intervals of primitives for each treelet
+        let mut treelets_to_build: Vec<LBVHTreelet> = Vec::new();
+        let mut start: usize = 0;
+        for end in 1..=morton_prims.len() {
+            let mask: u32 = 0b0011_1111_1111_1100_0000_0000_0000_0000;
+            if end == morton_prims.len()
+                || (morton_prims[start].morton_code & mask)
+                    != (morton_prims[end].morton_code & mask)
+            {
+                // add entry to _treeletsToBuild_ for this treelet
+                treelets_to_build.push(LBVHTreelet {
+                    start_index: start,
+                    n_primitives: end - start,
+                });
+                start = end;
+            }
+        }
+        // create LBVHs for treelets
+        let first_bit_index: i32 = 29 - 12;
+        let mut finished_treelets: Vec<&'a BVHBuildNode<'a>> =
+            Vec::with_capacity(treelets_to_build.len());
+        for tr in &treelets_to_build {
+            finished_treelets.push(BVHAccel::emit_lbvh(
+                bvh,
+                arena,
+                primitive_info,
+                &morton_prims[tr.start_index..tr.start_index + tr.n_primitives],
+                total_nodes,
+                ordered_prims,
+                first_bit_index,
+            ));
+        }
+        // create and return SAH BVH from LBVH treelets
+        let n_treelets: usize = finished_treelets.len();
+        BVHAccel::build_upper_sah(arena, &mut finished_treelets, 0, n_treelets, total_nodes)
+    }
+    fn emit_lbvh<'a>(
+        bvh: &BVHAccel,
+        arena: &'a Arena<BVHBuildNode<'a>>,
+        primitive_info: &[BVHPrimitiveInfo],
+        morton_prims: &[MortonPrimitive],
+        total_nodes: &mut usize,
+        ordered_prims: &mut Vec<Arc<Primitive>>,
+        bit_index: i32,
+    ) -> &'a BVHBuildNode<'a> {
+        let n_primitives: usize = morton_prims.len();
+        assert!(n_primitives > 0);
+        if bit_index == -1 || n_primitives < bvh.max_prims_in_node {
+            // create and return leaf node of LBVH treelet
+            let node: &mut BVHBuildNode<'a> = arena.alloc(BVHBuildNode::default());
+            *total_nodes += 1_usize;
+            let mut bounds: Bounds3f = Bounds3f::default();
+            let first_prim_offset: usize = ordered_prims.len();
+            for mp in morton_prims {
+                let primitive_index: usize = mp.primitive_index;
+                ordered_prims.push(bvh.primitives[primitive_index].clone());
+                bounds = bnd3_union_bnd3f(&bounds, &primitive_info[primitive_index].bounds);
+            }
+            node.init_leaf(first_prim_offset, n_primitives, &bounds);
+            node
+        } else {
+            let mask: u32 = 1 << bit_index;
+            // advance to next subtree level if there's no LBVH split for this bit
+            let first_bit: u32 = morton_prims[0].morton_code & mask;
+            if first_bit == (morton_prims[n_primitives - 1].morton_code & mask) {
+                return BVHAccel::emit_lbvh(
+                    bvh,
+                    arena,
+                    primitive_info,
+                    morton_prims,
+                    total_nodes,
+                    ordered_prims,
+                    bit_index - 1,
+                );
+            }
+            // find LBVH split point for this dimension
+            let split_offset: usize =
+                morton_prims.partition_point(|mp| (mp.morton_code & mask) == first_bit);
+            assert!(split_offset > 0 && split_offset < n_primitives);
+            // create and return interior LBVH node
+            let node: &mut BVHBuildNode<'a> = arena.alloc(BVHBuildNode::default());
+            *total_nodes += 1_usize;
+            let c0 = BVHAccel::emit_lbvh(
+                bvh,
+                arena,
+                primitive_info,
+                &morton_prims[..split_offset],
+                total_nodes,
+                ordered_prims,
+                bit_index - 1,
+            );
+            let c1 = BVHAccel::emit_lbvh(
+                bvh,
+                arena,
+                primitive_info,
+                &morton_prims[split_offset..],
+                total_nodes,
+                ordered_prims,
+                bit_index - 1,
+            );
+            let axis: u8 = (bit_index % 3) as u8;
+            node.init_interior(axis, c0, c1);
+            node
+        }
+    }
+    fn build_upper_sah<'a>(
+        arena: &'a Arena<BVHBuildNode<'a>>,
+        treelet_roots: &mut Vec<&'a BVHBuildNode<'a>>,
+        start: usize,
+        end: usize,
+        total_nodes: &mut usize,
+    ) -> &'a BVHBuildNode<'a> {
+        assert!(start < end);
+        let n_nodes: usize = end - start;
+        if n_nodes == 1 {
+            return treelet_roots[start];
+        }
+        let node: &mut BVHBuildNode<'a> = arena.alloc(BVHBuildNode::default());
+        *total_nodes += 1_usize;
+        // compute bounds of all nodes under this HLBVH node
+        let mut bounds: Bounds3f = Bounds3f::default();
+        for root in treelet_roots.iter().take(end).skip(start) {
+            bounds = bnd3_union_bnd3f(&bounds, &root.bounds);
+        }
+        // compute bound of HLBVH node centroids, choose split dimension _dim_
+        let centroid = |b: &Bounds3f| -> Point3f { b.p_min * 0.5 + b.p_max * 0.5 };
+        let mut centroid_bounds: Bounds3f = Bounds3f::default();
+        for root in treelet_roots.iter().take(end).skip(start) {
+            centroid_bounds = bnd3_union_pnt3f(&centroid_bounds, &centroid(&root.bounds));
+        }
+        let dim: u8 = centroid_bounds.maximum_extent();
+        let dim_i: XYZEnum = match dim {
+            0 => XYZEnum::X,
+            1 => XYZEnum::Y,
+            _ => XYZEnum::Z,
+        };
+        let mut mid: usize = (start + end) / 2;
+        if centroid_bounds.p_max[dim_i] != centroid_bounds.p_min[dim_i] {
+            // allocate _BucketInfo_ for SAH partition buckets
+            let n_buckets: usize = 12;
+            let bucket = |b: &Bounds3f| -> usize {
+                let b: usize = (n_buckets as Float
+                    * centroid_bounds.offset(&centroid(b))[dim_i])
+                    as usize;
+                std::cmp::min(b, n_buckets - 1)
+            };
+            let mut buckets: [BucketInfo; 12] = [BucketInfo::default(); 12];
+            // initialize _BucketInfo_ for HLBVH SAH partition buckets
+            for root in treelet_roots.iter().take(end).skip(start) {
+                let b: usize = bucket(&root.bounds);
+                buckets[b].count += 1;
+                buckets[b].bounds = bnd3_union_bnd3f(&buckets[b].bounds, &root.bounds);
+            }
+            // compute costs for splitting after each bucket
+            let mut cost: [Float; 11] = [0.0; 11];
+            for (i, cost_item) in cost.iter_mut().enumerate() {
+                let mut b0: Bounds3f = Bounds3f::default();
+                let mut b1: Bounds3f = Bounds3f::default();
+                let mut count0: usize = 0;
+                let mut count1: usize = 0;
+                for item in buckets.iter().take(i + 1) {
+                    b0 = bnd3_union_bnd3f(&b0, &item.bounds);
+                    count0 += item.count;
+                }
+                for item in buckets.iter().skip(i + 1) {
+                    b1 = bnd3_union_bnd3f(&b1, &item.bounds);
+                    count1 += item.count;
+                }
+                *cost_item = 0.125
+                    + (count0 as Float * b0.surface_area() + count1 as Float * b1.surface_area())
+                        / bounds.surface_area();
+            }
+            // find bucket to split at that minimizes SAH metric
+            let mut min_cost: Float = cost[0];
+            let mut min_cost_split_bucket: usize = 0;
+            for (i, item) in cost.iter().enumerate() {
+                if item < &min_cost {
+                    min_cost = *item;
+                    min_cost_split_bucket = i;
+                }
+            }
+            // split nodes and create interior HLBVH SAH node
+            let (mut left, mut right): (Vec<&'a BVHBuildNode<'a>>, Vec<&'a BVHBuildNode<'a>>) =
+                treelet_roots[start..end]
+                    .iter()
+                    .partition(|root| bucket(&root.bounds) <= min_cost_split_bucket);
+            if !left.is_empty() && !right.is_empty() {
+                mid = start + left.len();
+                left.append(&mut right);
+                treelet_roots.splice(start..end, left);
+            }
+        }
+        let c0 = BVHAccel::build_upper_sah(arena, treelet_roots, start, mid, total_nodes);
+        let c1 = BVHAccel::build_upper_sah(arena, treelet_roots, mid, end, total_nodes);
+        node.init_interior(dim, c0, c1);
+        node
+    }
     pub fn flatten_bvh_tree<'a>(
         node: &BVHBuildNode<'a>,
         nodes: &mut Vec<LinearBVHNode>,
@@ -412,15 +793,111 @@ impl BVHAccel {
         }
         my_offset
     }
+    /// Collapses the binary build tree into nodes with up to `width`
+    /// children by repeatedly opening the interior child with the
+    /// largest surface area.
+    pub fn flatten_wide_bvh_tree<'a>(
+        node: &BVHBuildNode<'a>,
+        width: usize,
+        wide_nodes: &mut Vec<WideBVHNode>,
+    ) -> usize {
+        let my_offset: usize = wide_nodes.len();
+        wide_nodes.push(WideBVHNode::default());
+        let mut children: Vec<&BVHBuildNode<'a>> = Vec::with_capacity(width);
+        match (node.child1, node.child2) {
+            (Some(child1), Some(child2)) => {
+                children.push(child1);
+                children.push(child2);
+            }
+            _ => children.push(node),
+        }
+        while children.len() < width {
+            let mut largest: Option<usize> = None;
+            let mut largest_area: Float = -1.0;
+            for (i, child) in children.iter().enumerate() {
+                let area: Float = child.bounds.surface_area();
+                if child.n_primitives == 0 && area > largest_area {
+                    largest = Some(i);
+                    largest_area = area;
+                }
+            }
+            if let Some(i) = largest {
+                let child = children[i];
+                if let (Some(child1), Some(child2)) = (child.child1, child.child2) {
+                    children[i] = child1;
+                    children.insert(i + 1, child2);
+                }
+            } else {
+                break;
+            }
+        }
+        let mut wide_node: WideBVHNode = WideBVHNode {
+            n_children: children.len() as u8,
+            ..Default::default()
+        };
+        for (i, child) in children.iter().enumerate() {
+            wide_node.set_child_bounds(i, &child.bounds);
+            if child.n_primitives > 0 {
+                wide_node.offset[i] = child.first_prim_offset as u32;
+                wide_node.n_primitives[i] = child.n_primitives as u16;
+            } else {
+                wide_node.offset[i] =
+                    BVHAccel::flatten_wide_bvh_tree(child, width, wide_nodes) as u32;
+            }
+        }
+        wide_nodes[my_offset] = wide_node;
+        my_offset
+    }
+    /// Number of flattened nodes (binary or wide).
+    pub fn n_nodes(&self) -> usize {
+        self.nodes.len() + self.wide_nodes.len()
+    }
     // Primitive
     pub fn world_bound(&self) -> Bounds3f {
         if !self.nodes.is_empty() {
             self.nodes[0].bounds
+        } else if !self.wide_nodes.is_empty() {
+            self.wide_nodes[0].bounds()
         } else {
             Bounds3f::default()
         }
     }
+    /// The expected cost of tracing a ray through the flattened tree
+    /// (surface area heuristic), one unit per visited interior node
+    /// and per intersected primitive. Lower is better.
+    pub fn sah_cost(&self) -> Float {
+        if self.nodes.is_empty() && self.wide_nodes.is_empty() {
+            return 0.0 as Float;
+        }
+        let root_area: Float = self.world_bound().surface_area();
+        if root_area == 0.0 as Float {
+            return self.primitives.len() as Float;
+        }
+        let mut cost: Float = 0.0;
+        for node in &self.nodes {
+            let n: Float = if node.n_primitives > 0 {
+                node.n_primitives as Float
+            } else {
+                1.0 as Float
+            };
+            cost += n * node.bounds.surface_area() / root_area;
+        }
+        for node in &self.wide_nodes {
+            cost += node.bounds().surface_area() / root_area;
+            for i in 0..node.n_children as usize {
+                if node.n_primitives[i] > 0 {
+                    cost += node.n_primitives[i] as Float
+                        * node.child_bounds(i).surface_area()
+                        / root_area;
+                }
+            }
+        }
+        cost
+    }
     pub fn intersect(&self, ray: &Ray, isect: &mut SurfaceInteraction) -> bool {
+        if !self.wide_nodes.is_empty() {
+            return self.intersect_wide(ray, isect);
+        }
         if self.nodes.is_empty() {
             return false;
         }
@@ -483,6 +960,9 @@ impl BVHAccel {
         hit
     }
     pub fn intersect_p(&self, ray: &Ray) -> bool {
+        if !self.wide_nodes.is_empty() {
+            return self.intersect_p_wide(ray);
+        }
         if self.nodes.is_empty() {
             return false;
         }
@@ -534,6 +1014,97 @@ impl BVHAccel {
         }
         false
     }
+    fn intersect_wide(&self, ray: &Ray, isect: &mut SurfaceInteraction) -> bool {
+        let mut hit: bool = false;
+        let inv_dir: Vector3f = Vector3f {
+            x: 1.0 / ray.d.x,
+            y: 1.0 / ray.d.y,
+            z: 1.0 / ray.d.z,
+        };
+        let dir_is_neg: [u8; 3] = [
+            (inv_dir.x < 0.0) as u8,
+            (inv_dir.y < 0.0) as u8,
+            (inv_dir.z < 0.0) as u8,
+        ];
+        // follow ray through wide BVH nodes, nearest children first
+        let mut to_visit: SmallVec<[WideStackEntry; 64]> = SmallVec::new();
+        to_visit.push(WideStackEntry::default());
+        let mut t_near: [Float; MAX_BVH_WIDTH] = [0.0; MAX_BVH_WIDTH];
+        while let Some(entry) = to_visit.pop() {
+            // skip nodes behind the closest hit found so far
+            if entry.t_near > ray.t_max.get() {
+                continue;
+            }
+            if entry.n_primitives > 0 {
+                // intersect ray with primitives in leaf
+                for i in 0..entry.n_primitives {
+                    if self.primitives[entry.offset as usize + i as usize].intersect(ray, isect) {
+                        hit = true;
+                    }
+                }
+            } else {
+                let node: &WideBVHNode = &self.wide_nodes[entry.offset as usize];
+                let mut hits: u32 = node.intersect_children(ray, &inv_dir, &dir_is_neg, &mut t_near);
+                // sort hit children far to near, so the nearest is popped first
+                let first: usize = to_visit.len();
+                while hits != 0 {
+                    let i: usize = hits.trailing_zeros() as usize;
+                    hits &= hits - 1;
+                    let child: WideStackEntry = WideStackEntry {
+                        offset: node.offset[i],
+                        n_primitives: node.n_primitives[i],
+                        t_near: t_near[i],
+                    };
+                    let mut j: usize = to_visit.len();
+                    to_visit.push(child);
+                    while j > first && to_visit[j - 1].t_near < child.t_near {
+                        to_visit[j] = to_visit[j - 1];
+                        j -= 1;
+                    }
+                    to_visit[j] = child;
+                }
+            }
+        }
+        hit
+    }
+    fn intersect_p_wide(&self, ray: &Ray) -> bool {
+        let inv_dir: Vector3f = Vector3f {
+            x: 1.0 / ray.d.x,
+            y: 1.0 / ray.d.y,
+            z: 1.0 / ray.d.z,
+        };
+        let dir_is_neg: [u8; 3] = [
+            (inv_dir.x < 0.0) as u8,
+            (inv_dir.y < 0.0) as u8,
+            (inv_dir.z < 0.0) as u8,
+        ];
+        // any hit will do, no need to sort children
+        let mut to_visit: SmallVec<[WideStackEntry; 64]> = SmallVec::new();
+        to_visit.push(WideStackEntry::default());
+        let mut t_near: [Float; MAX_BVH_WIDTH] = [0.0; MAX_BVH_WIDTH];
+        while let Some(entry) = to_visit.pop() {
+            if entry.n_primitives > 0 {
+                for i in 0..entry.n_primitives {
+                    if self.primitives[entry.offset as usize + i as usize].intersect_p(ray) {
+                        return true;
+                    }
+                }
+            } else {
+                let node: &WideBVHNode = &self.wide_nodes[entry.offset as usize];
+                let mut hits: u32 = node.intersect_children(ray, &inv_dir, &dir_is_neg, &mut t_near);
+                while hits != 0 {
+                    let i: usize = hits.trailing_zeros() as usize;
+                    hits &= hits - 1;
+                    to_visit.push(WideStackEntry {
+                        offset: node.offset[i],
+                        n_primitives: node.n_primitives[i],
+                        t_near: t_near[i],
+                    });
+                }
+            }
+        }
+        false
+    }
     pub fn get_material(&self) -> Option<Arc<Material>> {
         None
     }
@@ -541,3 +1112,62 @@ impl BVHAccel {
         None
     }
 }
+
+fn left_shift_3(x: u32) -> u32 {
+    let mut x: u32 = x;
+    if x == (1 << 10) {
+        x -= 1;
+    }
+    x = (x | (x << 16)) & 0b0000_0011_0000_0000_0000_0000_1111_1111;
+    x = (x | (x << 8)) & 0b0000_0011_0000_0000_1111_0000_0000_1111;
+    x = (x | (x << 4)) & 0b0000_0011_0000_1100_0011_0000_1100_0011;
+    x = (x | (x << 2)) & 0b0000_1001_0010_0100_1001_0010_0100_1001;
+    x
+}
+
+fn encode_morton_3(v: &Vector3f) -> u32 {
+    assert!(v.x >= 0.0 as Float);
+    assert!(v.y >= 0.0 as Float);
+    assert!(v.z >= 0.0 as Float);
+    (left_shift_3(v.z as u32) << 2) | (left_shift_3(v.y as u32) << 1) | left_shift_3(v.x as u32)
+}
+
+fn radix_sort(v: &mut Vec<MortonPrimitive>) {
+    let mut temp_vector: Vec<MortonPrimitive> = vec![MortonPrimitive::default(); v.len()];
+    const BITS_PER_PASS: u32 = 6;
+    const N_BITS: u32 = 30;
+    const N_PASSES: u32 = N_BITS / BITS_PER_PASS;
+    const N_BUCKETS: usize = 1 << BITS_PER_PASS;
+    let bit_mask: u32 = (1 << BITS_PER_PASS) - 1;
+    for pass in 0..N_PASSES {
+        // perform one pass of radix sort, sorting _BITS_PER_PASS_ bits
+        let low_bit: u32 = pass * BITS_PER_PASS;
+        // set in and out vector pointers for radix sort pass
+        let (v_in, v_out): (&Vec<MortonPrimitive>, &mut Vec<MortonPrimitive>) = if pass & 1 == 1 {
+            (&temp_vector, v)
+        } else {
+            (v, &mut temp_vector)
+        };
+        // count number of zero bits in array for current radix sort bit
+        let mut bucket_count: [usize; N_BUCKETS] = [0; N_BUCKETS];
+        for mp in v_in.iter() {
+            let bucket: usize = ((mp.morton_code >> low_bit) & bit_mask) as usize;
+            bucket_count[bucket] += 1;
+        }
+        // compute starting index in output array for each bucket
+        let mut out_index: [usize; N_BUCKETS] = [0; N_BUCKETS];
+        for i in 1..N_BUCKETS {
+            out_index[i] = out_index[i - 1] + bucket_count[i - 1];
+        }
+        // store sorted values in output array
+        for mp in v_in.iter() {
+            let bucket: usize = ((mp.morton_code >> low_bit) & bit_mask) as usize;
+            v_out[out_index[bucket]] = *mp;
+            out_index[bucket] += 1;
+        }
+    }
+    // copy final result from _tempVector_, if needed
+    if N_PASSES & 1 == 1 {
+        std::mem::swap(v, &mut temp_vector);
+    }
+}
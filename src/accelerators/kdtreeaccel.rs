@@ -1,5 +1,6 @@
 // std
 use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
 use std::sync::Arc;
 
 // pbrt
@@ -121,6 +122,19 @@ impl Default for BoundEdge {
     }
 }
 
+/// Build statistics of a [KdTreeAccel](struct.KdTreeAccel.html).
+/// Primitives overlapping a split plane are referenced from both
+/// sides, so `primitive_references` can exceed the number of
+/// primitives.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct KdTreeStatistics {
+    pub leaves: usize,
+    pub empty_leaves: usize,
+    pub max_depth: usize,
+    pub max_leaf_primitives: usize,
+    pub primitive_references: usize,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct KdTreeAccel {
     pub isect_cost: i32,
@@ -439,11 +453,8 @@ impl KdTreeAccel {
         let mut bounds1: Bounds3f = *node_bounds;
         bounds0.p_max[best_axis_i] = t_split;
         bounds1.p_min[best_axis_i] = t_split;
-        // copy prims0
-        let mut prim_nums: Vec<usize> = Vec::with_capacity(prims0.len());
-        for i in 0..prims0.len() {
-            prim_nums.push(prims0[i]);
-        }
+        // copy prims0 (only the _n0_ primitives below the split)
+        let prim_nums: Vec<usize> = prims0[..n0].to_vec();
         self.build_tree(
             node_num + 1,
             &bounds0,
@@ -458,11 +469,8 @@ impl KdTreeAccel {
         );
         let above_child: i32 = self.next_free_node;
         self.nodes[node_num as usize].init_interior(best_axis, above_child, t_split);
-        // copy prims1
-        let mut prim_nums: Vec<usize> = Vec::with_capacity(prims1.len());
-        for i in 0..prims1.len() {
-            prim_nums.push(prims1[i]);
-        }
+        // copy prims1 (only the _n1_ primitives above the split)
+        let prim_nums: Vec<usize> = prims1[..n1].to_vec();
         self.build_tree(
             above_child,
             &bounds1,
@@ -476,6 +484,66 @@ impl KdTreeAccel {
             bad_refines,
         );
     }
+    pub fn n_nodes(&self) -> usize {
+        self.next_free_node as usize
+    }
+    /// The expected cost of tracing a ray through the tree (surface
+    /// area heuristic), one unit per visited interior node and per
+    /// intersected primitive, comparable to
+    /// [BVHAccel::sah_cost()](../bvh/struct.BVHAccel.html#method.sah_cost).
+    pub fn sah_cost(&self) -> Float {
+        let root_area: Float = self.bounds.surface_area();
+        if self.nodes.is_empty() || root_area == 0.0 as Float {
+            return self.primitives.len() as Float;
+        }
+        let mut cost: Float = 0.0;
+        let mut todo: Vec<(usize, Bounds3f)> = vec![(0_usize, self.bounds)];
+        while let Some((node_idx, bounds)) = todo.pop() {
+            let node: &KdAccelNode = &self.nodes[node_idx];
+            if node.is_leaf() {
+                cost += node.n_primitives() as Float * bounds.surface_area() / root_area;
+            } else {
+                cost += bounds.surface_area() / root_area;
+                let axis: XYZEnum = match node.split_axis() {
+                    0 => XYZEnum::X,
+                    1 => XYZEnum::Y,
+                    _ => XYZEnum::Z,
+                };
+                let mut below: Bounds3f = bounds;
+                let mut above: Bounds3f = bounds;
+                below.p_max[axis] = node.split_pos();
+                above.p_min[axis] = node.split_pos();
+                todo.push((node_idx + 1, below));
+                todo.push((node.above_child() as usize, above));
+            }
+        }
+        cost
+    }
+    /// Counts leaves and primitive references of the built tree.
+    pub fn statistics(&self) -> KdTreeStatistics {
+        let mut stats: KdTreeStatistics = KdTreeStatistics::default();
+        if self.nodes.is_empty() {
+            return stats;
+        }
+        let mut todo: Vec<(usize, usize)> = vec![(0_usize, 0_usize)];
+        while let Some((node_idx, depth)) = todo.pop() {
+            let node: &KdAccelNode = &self.nodes[node_idx];
+            stats.max_depth = stats.max_depth.max(depth);
+            if node.is_leaf() {
+                let n_primitives: usize = node.n_primitives() as usize;
+                stats.leaves += 1;
+                if n_primitives == 0 {
+                    stats.empty_leaves += 1;
+                }
+                stats.max_leaf_primitives = stats.max_leaf_primitives.max(n_primitives);
+                stats.primitive_references += n_primitives;
+            } else {
+                todo.push((node_idx + 1, depth + 1));
+                todo.push((node.above_child() as usize, depth + 1));
+            }
+        }
+        stats
+    }
     // Primitive
     pub fn world_bound(&self) -> Bounds3f {
         self.bounds
@@ -497,8 +565,8 @@ impl KdTreeAccel {
             y: 1.0 / ray.d.y,
             z: 1.0 / ray.d.z,
         };
-        let mut todo: [KdToDo; MAX_TODO] = [KdToDo::default(); MAX_TODO];
-        let mut todo_pos: usize = 0;
+        // no need to initialize all entries (like an array would)
+        let mut todo: SmallVec<[KdToDo; MAX_TODO]> = SmallVec::new();
         // traverse kd-tree nodes in order for ray
         let mut hit: bool = false;
         let mut node_idx: usize = 0;
@@ -546,11 +614,12 @@ impl KdTreeAccel {
                     node_idx = second_idx;
                 } else {
                     // enqueue _second_child_ in todo list
-                    todo[todo_pos].node = second_child;
-                    todo[todo_pos].idx = second_idx;
-                    todo[todo_pos].t_min = t_plane;
-                    todo[todo_pos].t_max = t_max;
-                    todo_pos += 1;
+                    todo.push(KdToDo {
+                        node: second_child,
+                        idx: second_idx,
+                        t_min: t_plane,
+                        t_max,
+                    });
                     node_opt = first_child;
                     node_idx = first_idx;
                     t_max = t_plane;
@@ -581,12 +650,11 @@ impl KdTreeAccel {
                     }
                 }
                 // grab next node to process from todo list
-                if todo_pos > 0 {
-                    todo_pos -= 1;
-                    node_opt = todo[todo_pos].node;
-                    node_idx = todo[todo_pos].idx;
-                    t_min = todo[todo_pos].t_min;
-                    t_max = todo[todo_pos].t_max;
+                if let Some(next) = todo.pop() {
+                    node_opt = next.node;
+                    node_idx = next.idx;
+                    t_min = next.t_min;
+                    t_max = next.t_max;
                 } else {
                     break;
                 }
@@ -611,8 +679,8 @@ impl KdTreeAccel {
             y: 1.0 / ray.d.y,
             z: 1.0 / ray.d.z,
         };
-        let mut todo: [KdToDo; MAX_TODO] = [KdToDo::default(); MAX_TODO];
-        let mut todo_pos: usize = 0;
+        // no need to initialize all entries (like an array would)
+        let mut todo: SmallVec<[KdToDo; MAX_TODO]> = SmallVec::new();
         let mut node_idx: usize = 0;
         let mut node_opt: Option<&KdAccelNode> = self.nodes.get(node_idx);
         while let Some(node) = node_opt {
@@ -640,12 +708,11 @@ impl KdTreeAccel {
                     }
                 }
                 // grab next node to process from todo list
-                if todo_pos > 0 {
-                    todo_pos -= 1;
-                    node_opt = todo[todo_pos].node;
-                    node_idx = todo[todo_pos].idx;
-                    t_min = todo[todo_pos].t_min;
-                    t_max = todo[todo_pos].t_max;
+                if let Some(next) = todo.pop() {
+                    node_opt = next.node;
+                    node_idx = next.idx;
+                    t_min = next.t_min;
+                    t_max = next.t_max;
                 } else {
                     break;
                 }
@@ -687,11 +754,12 @@ impl KdTreeAccel {
                     node_idx = second_idx;
                 } else {
                     // enqueue _second_child_ in todo list
-                    todo[todo_pos].node = second_child;
-                    todo[todo_pos].idx = second_idx;
-                    todo[todo_pos].t_min = t_plane;
-                    todo[todo_pos].t_max = t_max;
-                    todo_pos += 1;
+                    todo.push(KdToDo {
+                        node: second_child,
+                        idx: second_idx,
+                        t_min: t_plane,
+                        t_max,
+                    });
                     node_opt = first_child;
                     node_idx = first_idx;
                     t_max = t_plane;
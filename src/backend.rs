@@ -1,64 +1,356 @@
-use fastly::{Error,Request,Response,Body};
-use image::error::DecodingError;
-use std::io::BufRead;
-use std::io::BufReader;
-use std::io::Read;
-use std::str;
+//! Scene content (the main **.pbrt** file, included files, textures,
+//! PLY meshes, etc.) is fetched through a **ContentBackend**. The
+//! backend is selected per render (and per thread) in
+//! [entry()](../entry/fn.entry.html), all loaders call
+//! [get_content_string()](fn.get_content_string.html) or
+//! [get_content_binary()](fn.get_content_binary.html) and don't know
+//! where the bytes come from.
+//!
+//! Relative file names found in a scene (textures, lens files, BSDF
+//! tables, spectra, PLY meshes) are resolved against the scene's
+//! search directory with [resolve_filename()](fn.resolve_filename.html)
+//! before they are handed to the backend.
+
+// std
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+// others
+#[cfg(feature = "ecp")]
+use fastly::Request;
+use fastly::Error;
 
 #[cfg(not(feature = "ecp"))]
 #[cfg(not(test))]
 use wasm_bindgen::prelude::*;
 
+#[cfg(feature = "ecp")]
 const PBRT_CONTENT_BACKEND_NAME: &str = "pbrt_content";
+#[cfg(feature = "ecp")]
+const PBRT_CONTENT_BASE_URL: &str = "https://pbrt-edge.s3.us-west-2.amazonaws.com";
 
 #[cfg(not(feature = "ecp"))]
 #[cfg(not(test))]
 #[wasm_bindgen(raw_module = "./request.js")]
 extern "C" {
-    pub fn get_content_web(data: String) -> Vec<u8>;
+    #[wasm_bindgen(catch)]
+    pub fn get_content_web(path: String) -> Result<Vec<u8>, JsValue>;
+    pub fn content_exists_web(path: String) -> bool;
+    pub fn content_size_web(path: String) -> Option<f64>;
 }
 
-pub fn get_content_string(path: &str) -> Result<String, Error> {
+thread_local! {
+    // per thread, a render worker might parse several scenes at once
+    static CONTENT_BACKEND: RefCell<Arc<dyn ContentBackend>> =
+        RefCell::new(default_content_backend());
+    static SEARCH_DIRECTORY: RefCell<Option<PathBuf>> = RefCell::new(None);
+}
+
+/// Where scene content comes from.
+pub trait ContentBackend: Send + Sync {
+    /// Fetch the content at `path` as raw bytes.
+    fn get_binary(&self, path: &str) -> Result<Vec<u8>, Error>;
+    /// Fetch the content at `path` as UTF-8 text.
+    fn get_string(&self, path: &str) -> Result<String, Error> {
+        let body: Vec<u8> = self.get_binary(path)?;
+        match String::from_utf8(body) {
+            Ok(s) => Ok(s),
+            Err(_) => Err(Error::msg(format!(
+                "Could not get string data from {}",
+                path
+            ))),
+        }
+    }
+    /// Check if there is any content at `path`.
+    fn exists(&self, path: &str) -> bool;
+    /// Size of the content at `path` in bytes (if it exists).
+    fn size(&self, path: &str) -> Option<u64>;
+}
+
+/// Paths are handed around as "/scene/file", backends treat them
+/// relative to their own root.
+fn relative_path(path: &str) -> &str {
+    path.trim_start_matches('/')
+}
+
+/// Reads content from a directory on the local file system, e.g. the
+/// **content** directory of this repository.
+pub struct LocalBackend {
+    pub root: PathBuf,
+}
+
+impl LocalBackend {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        LocalBackend { root: root.into() }
+    }
+    pub fn resolve(&self, path: &str) -> PathBuf {
+        self.root.join(relative_path(path))
+    }
+}
+
+impl ContentBackend for LocalBackend {
+    fn get_binary(&self, path: &str) -> Result<Vec<u8>, Error> {
+        let full_path: PathBuf = self.resolve(path);
+        match fs::read(&full_path) {
+            Ok(data) => Ok(data),
+            Err(e) => Err(Error::msg(format!(
+                "Couldn't read {:?}: {}",
+                full_path, e
+            ))),
+        }
+    }
+    fn exists(&self, path: &str) -> bool {
+        self.resolve(path).is_file()
+    }
+    fn size(&self, path: &str) -> Option<u64> {
+        match fs::metadata(self.resolve(path)) {
+            Ok(metadata) if metadata.is_file() => Some(metadata.len()),
+            _ => None,
+        }
+    }
+}
+
+/// Keeps content in memory, handy for tests and for scenes which
+/// were sent along with a request.
+#[derive(Default)]
+pub struct MemoryBackend {
+    files: RwLock<HashMap<String, Vec<u8>>>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        MemoryBackend::default()
+    }
+    pub fn insert(&self, path: &str, data: Vec<u8>) {
+        self.files
+            .write()
+            .unwrap()
+            .insert(relative_path(path).to_string(), data);
+    }
+    pub fn insert_string(&self, path: &str, data: &str) {
+        self.insert(path, data.as_bytes().to_vec());
+    }
+}
+
+impl ContentBackend for MemoryBackend {
+    fn get_binary(&self, path: &str) -> Result<Vec<u8>, Error> {
+        match self.files.read().unwrap().get(relative_path(path)) {
+            Some(data) => Ok(data.clone()),
+            None => Err(Error::msg(format!("No content stored for {}", path))),
+        }
+    }
+    fn exists(&self, path: &str) -> bool {
+        self.files
+            .read()
+            .unwrap()
+            .contains_key(relative_path(path))
+    }
+    fn size(&self, path: &str) -> Option<u64> {
+        self.files
+            .read()
+            .unwrap()
+            .get(relative_path(path))
+            .map(|data| data.len() as u64)
+    }
+}
+
+/// Fetches content from the S3 bucket through a Fastly backend.
+#[cfg(feature = "ecp")]
+pub struct HttpBackend {
+    pub base_url: String,
+    pub backend_name: String,
+    pub ttl: u32,
+}
 
-	#[cfg(feature = "ecp")]
-	{
-		let url = format!("https://pbrt-edge.s3.us-west-2.amazonaws.com/{}", path);
-		let mut b = Request::new("GET", url);
-		b.set_ttl(60 * 10);
-		println!("URL Path: {}", b.get_url_str());
-		let mut resp = b.send(PBRT_CONTENT_BACKEND_NAME)?;
-		let body = resp.take_body();
-		Ok(body.into_string())
-	}
-	#[cfg(not(feature = "ecp"))]
-	{
-		let body : Vec<u8> = get_content_web(path.to_string());
-		let msg = format!("Could not get string data from {}", path);
-		let ret = str::from_utf8(&body).expect(&msg);
-		Ok(ret.to_string())
-	}
+#[cfg(feature = "ecp")]
+impl Default for HttpBackend {
+    fn default() -> Self {
+        HttpBackend {
+            base_url: PBRT_CONTENT_BASE_URL.to_string(),
+            backend_name: PBRT_CONTENT_BACKEND_NAME.to_string(),
+            ttl: 60 * 10,
+        }
+    }
+}
+
+#[cfg(feature = "ecp")]
+impl HttpBackend {
+    fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url, relative_path(path))
+    }
+}
+
+#[cfg(feature = "ecp")]
+impl ContentBackend for HttpBackend {
+    fn get_binary(&self, path: &str) -> Result<Vec<u8>, Error> {
+        let mut b = Request::get(self.url(path));
+        b.set_ttl(self.ttl);
+        println!("URL Path: {}", b.get_url_str());
+        let mut resp = b.send(self.backend_name.as_str())?;
+        if !resp.get_status().is_success() {
+            return Err(Error::msg(format!(
+                "GET {} returned {}",
+                self.url(path),
+                resp.get_status()
+            )));
+        }
+        let body = resp.take_body();
+        Ok(body.into_bytes())
+    }
+    fn exists(&self, path: &str) -> bool {
+        self.size(path).is_some()
+    }
+    fn size(&self, path: &str) -> Option<u64> {
+        let mut b = Request::head(self.url(path));
+        b.set_ttl(self.ttl);
+        match b.send(self.backend_name.as_str()) {
+            Ok(resp) if resp.get_status().is_success() => {
+                resp.get_content_length().map(|l| l as u64)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Asks the JavaScript side (see **request.js**) for the content. The
+/// request is synchronous, the scene loaders can't wait for it.
+/// [exists()](trait.ContentBackend.html#tymethod.exists) and
+/// [size()](trait.ContentBackend.html#tymethod.size) send a HEAD
+/// request, the size is unknown if the server doesn't send a
+/// Content-Length.
+#[cfg(not(feature = "ecp"))]
+#[cfg(not(test))]
+pub struct WebBackend {}
+
+#[cfg(not(feature = "ecp"))]
+#[cfg(not(test))]
+impl ContentBackend for WebBackend {
+    fn get_binary(&self, path: &str) -> Result<Vec<u8>, Error> {
+        match get_content_web(relative_path(path).to_string()) {
+            Ok(data) => Ok(data),
+            Err(e) => Err(Error::msg(format!(
+                "Couldn't fetch {}: {}",
+                path,
+                e.as_string().unwrap_or_else(|| format!("{:?}", e))
+            ))),
+        }
+    }
+    fn exists(&self, path: &str) -> bool {
+        content_exists_web(relative_path(path).to_string())
+    }
+    fn size(&self, path: &str) -> Option<u64> {
+        content_size_web(relative_path(path).to_string()).map(|size| size as u64)
+    }
+}
+
+/// The backend used if nobody selected one: the S3 bucket on Fastly,
+/// **request.js** in the browser and the current directory natively.
+pub fn default_content_backend() -> Arc<dyn ContentBackend> {
+    #[cfg(feature = "ecp")]
+    {
+        Arc::new(HttpBackend::default())
+    }
+    #[cfg(not(feature = "ecp"))]
+    #[cfg(not(test))]
+    #[cfg(target_arch = "wasm32")]
+    {
+        Arc::new(WebBackend {})
+    }
+    #[cfg(not(feature = "ecp"))]
+    #[cfg(not(test))]
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        Arc::new(LocalBackend::new(
+            std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+        ))
+    }
+    #[cfg(not(feature = "ecp"))]
+    #[cfg(test)]
+    {
+        Arc::new(LocalBackend::new(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("content"),
+        ))
+    }
+}
+
+/// Restores the backend selected before
+/// [set_content_backend()](fn.set_content_backend.html) when dropped.
+pub struct ContentBackendGuard {
+    previous: Option<Arc<dyn ContentBackend>>,
+}
+
+impl Drop for ContentBackendGuard {
+    fn drop(&mut self) {
+        if let Some(previous) = self.previous.take() {
+            CONTENT_BACKEND.with(|backend| *backend.borrow_mut() = previous);
+        }
+    }
+}
+
+/// Select the backend the following content requests of this thread
+/// go to, as long as the returned guard lives.
+#[must_use = "the previous backend is restored when the guard is dropped"]
+pub fn set_content_backend(backend: Arc<dyn ContentBackend>) -> ContentBackendGuard {
+    let previous: Arc<dyn ContentBackend> =
+        CONTENT_BACKEND.with(|current| current.replace(backend));
+    ContentBackendGuard {
+        previous: Some(previous),
+    }
+}
+
+/// The backend currently selected on this thread.
+pub fn content_backend() -> Arc<dyn ContentBackend> {
+    CONTENT_BACKEND.with(|backend| backend.borrow().clone())
+}
+
+pub fn get_content_string(path: &str) -> Result<String, Error> {
+    content_backend().get_string(path)
 }
 
 pub fn get_content_binary(path: &str) -> Result<Vec<u8>, Error> {
+    content_backend().get_binary(path)
+}
 
-	#[cfg(feature = "ecp")]
-	{
-		let url = format!("https://pbrt-edge.s3.us-west-2.amazonaws.com{}", path);
-		let mut b = Request::new("GET", url);
-		b.set_ttl(60 * 10);
-		println!("URL Path: {}", b.get_url_str());
-		let mut resp = b.send(PBRT_CONTENT_BACKEND_NAME)?;
-		let body = resp.take_body();
-		Ok(body.into_bytes())
-	}
-	#[cfg(not(feature = "ecp"))]
-	{
-		// // JLTODO
-		// let fullpath = format!("ganesha/{}", path);
-		// let body : Vec<u8> = get_content_web(fullpath);
-		// Ok(body)
-		return Ok(vec![])
-	}
+pub fn content_exists(path: &str) -> bool {
+    content_backend().exists(path)
+}
 
+pub fn content_size(path: &str) -> Option<u64> {
+    content_backend().size(path)
 }
 
+// see fileutil.cpp
+
+/// Set the directory relative file names are resolved against (see
+/// [ApiState::set_search_directory()](../core/api/struct.ApiState.html)).
+pub fn set_search_directory(search_directory: Option<PathBuf>) {
+    SEARCH_DIRECTORY.with(|directory| *directory.borrow_mut() = search_directory);
+}
+
+pub fn search_directory() -> Option<PathBuf> {
+    SEARCH_DIRECTORY.with(|directory| directory.borrow().clone())
+}
+
+/// Resolve `filename` against `search_directory`. The result is the
+/// "/scene/file" style path all backends understand. Empty and
+/// absolute file names are returned unchanged.
+pub fn resolve_filename_in<P: AsRef<Path>>(search_directory: Option<P>, filename: &str) -> String {
+    if filename.is_empty() || Path::new(filename).is_absolute() {
+        return filename.to_string();
+    }
+    if let Some(search_directory) = search_directory {
+        let mut path_buf: PathBuf = PathBuf::from("/");
+        path_buf.push(search_directory.as_ref());
+        path_buf.push(filename);
+        String::from(path_buf.to_str().unwrap())
+    } else {
+        filename.to_string()
+    }
+}
+
+/// Resolve `filename` against the current search directory.
+pub fn resolve_filename(filename: &str) -> String {
+    resolve_filename_in(search_directory(), filename)
+}
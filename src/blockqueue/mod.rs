@@ -51,6 +51,15 @@ impl BlockQueue {
             next: AtomicUsize::new(0),
         }
     }
+    /// Create a block queue for the given `blocks` (in that order),
+    /// e.g. the ones left over by another queue.
+    pub fn from_blocks(blocks: Vec<(u32, u32)>, dim: (u32, u32)) -> BlockQueue {
+        BlockQueue {
+            blocks,
+            dimensions: dim,
+            next: AtomicUsize::new(0),
+        }
+    }
     /// Get the dimensions of an individual block in the queue
     pub fn block_dim(&self) -> (u32, u32) {
         self.dimensions
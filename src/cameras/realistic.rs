@@ -3,6 +3,7 @@ use std::cell::Cell;
 use std::path::PathBuf;
 use std::sync::Arc;
 // pbrt
+use crate::backend::resolve_filename_in;
 use crate::core::camera::{Camera, CameraSample};
 use crate::core::film::Film;
 use crate::core::floatfile::read_float_file;
@@ -147,15 +148,10 @@ impl RealisticCamera {
         // TODO: std::swap(shutterclose, shutteropen);
         assert!(shutterclose >= shutteropen);
         // realistic camera-specific parameters
-        let mut lens_file: String = params.find_one_filename("lensfile", String::from(""));
-        if lens_file != "" {
-            if let Some(ref search_directory) = search_directory {
-                let mut path_buf: PathBuf = PathBuf::from("/");
-                path_buf.push(search_directory);
-                path_buf.push(lens_file);
-                lens_file = String::from(path_buf.to_str().unwrap());
-            }
-        }
+        let lens_file: String = resolve_filename_in(
+            search_directory,
+            &params.find_one_filename("lensfile", String::from("")),
+        );
         if lens_file == "" {
             println!("ERROR: No lens description file supplied!");
         } else {
@@ -0,0 +1,301 @@
+//! A native collector which doesn't render itself. It walks the
+//! [BlockQueue](../blockqueue/struct.BlockQueue.html), sends a
+//! [TileJob](../tilejob/struct.TileJob.html) for each tile to one of
+//! several render workers (e.g. **rs_pbrt_worker** or the Fastly
+//! worker), merges the returned
+//! [TilePayloads](../core/film/struct.TilePayload.html) into the
+//! **Film**, and writes the image once all tiles are back.
+//!
+//! A tile which fails (connection error, timeout, 5xx status, broken
+//! payload or one for the wrong pixels) is sent to the next worker in
+//! the list, up to `retries` times. A 4xx status means the tile job
+//! itself is refused, it isn't sent again. Tiles which fail on all
+//! attempts are handed back to the collector, which renders them
+//! itself.
+
+// std
+use std::path::Path;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::Mutex;
+use std::time::Duration;
+// pbrt
+#[cfg(not(target_arch = "wasm32"))]
+use crate::blockqueue::BlockQueue;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::core::film::{Film, TilePayload};
+use crate::core::geometry::Bounds2f;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::core::geometry::{Bounds2i, Point2i};
+use crate::core::sampler::SampleRange;
+use crate::tilejob::{scene_hash, TileJob};
+
+#[derive(Debug, Clone)]
+pub struct Dispatcher {
+    /// Base URLs of the workers, e.g. `http://10.0.0.2:8080`
+    pub workers: Vec<String>,
+    /// Tiles each worker renders at the same time
+    pub jobs_per_worker: usize,
+    /// How often a failed tile is sent again
+    pub retries: u32,
+    /// Time a worker gets for a single tile
+    pub timeout: Duration,
+    /// Everything but the tile position sent with each job
+    pub tile_job: TileJob,
+}
+
+impl Dispatcher {
+    /// The scene `path` has to be found by the workers as
+    /// `<scene>/main.pbrt` (or `<scene>/main.snapshot`) within their
+    /// content, `<scene>` is the name of the directory containing
+    /// `path`.
+    pub fn new(workers: Vec<String>, path: &Path) -> Self {
+        let scene: String = match path.parent().and_then(|dir| dir.file_name()) {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => String::new(),
+        };
+        let mut tile_job: TileJob = TileJob::new(&scene, 0, 0, 16);
+        // the workers render from the same scene content as we do
+        if path.file_name().map_or(false, |name| name == "main.pbrt") {
+            let snapshot_file = path.with_file_name("main.snapshot");
+            let data: std::io::Result<Vec<u8>> = if snapshot_file.is_file() {
+                std::fs::read(&snapshot_file)
+            } else {
+                std::fs::read(path)
+            };
+            if let Ok(data) = data {
+                tile_job.scene_hash = Some(scene_hash(&data));
+            }
+        }
+        Dispatcher {
+            workers,
+            jobs_per_worker: 2,
+            retries: 3,
+            timeout: Duration::from_secs(300),
+            tile_job,
+        }
+    }
+    /// Send the scene name the workers know the scene by (the scene
+    /// hash is not checked then).
+    pub fn set_scene(&mut self, scene: &str) {
+        self.tile_job.filename = scene.to_string();
+        self.tile_job.scene_hash = None;
+    }
+    /// The workers have to use the same overrides (command line
+    /// options) as the collector.
+    pub fn for_render(
+        &self,
+        tile_size: i32,
+        crop_window: &Bounds2f,
+        pixelsamples: u32,
+        quick_render: bool,
+        integrator_arg: &Option<String>,
+    ) -> Dispatcher {
+        let mut dispatcher: Dispatcher = self.clone();
+        let tile_job: &mut TileJob = &mut dispatcher.tile_job;
+        tile_job.tile_size = tile_size;
+        tile_job.crop_window = Some([
+            crop_window.p_min.x,
+            crop_window.p_max.x,
+            crop_window.p_min.y,
+            crop_window.p_max.y,
+        ]);
+        if quick_render {
+            tile_job.pixelsamples = Some(1);
+        } else if pixelsamples > 0 {
+            tile_job.pixelsamples = Some(pixelsamples);
+        }
+        tile_job.integrator = integrator_arg.clone();
+        dispatcher
+    }
+    /// Only render the pixel samples in `samples` (one pass of a
+    /// progressive render).
+    pub fn for_samples(&self, samples: &SampleRange) -> Dispatcher {
+        let mut dispatcher: Dispatcher = self.clone();
+        let tile_job: &mut TileJob = &mut dispatcher.tile_job;
+        tile_job.first_sample = samples.first_sample as u32;
+        tile_job.last_sample = samples.last_sample.map(|last_sample| last_sample as u32);
+        tile_job.seed_offset = samples.seed_offset;
+        dispatcher
+    }
+    /// Send all tiles of `block_queue` (which partition
+    /// `sample_bounds`) to the workers and merge the results into
+    /// `film`. Returns the tiles which failed on all attempts, they
+    /// are left to render for the caller.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn dispatch_block_queue(
+        &self,
+        block_queue: &BlockQueue,
+        sample_bounds: &Bounds2i,
+        film: &Film,
+    ) -> BlockQueue {
+        if self.workers.is_empty() {
+            println!("WARNING: No workers to send tiles to");
+            return BlockQueue::from_blocks(block_queue.iter().collect(), block_queue.block_dim());
+        }
+        let num_requests: usize = self.workers.len() * std::cmp::max(self.jobs_per_worker, 1);
+        println!(
+            "Sending {} tile(s) to {} worker(s) ({} at a time) ...",
+            block_queue.len(),
+            self.workers.len(),
+            num_requests
+        );
+        let agent: ureq::Agent = ureq::AgentBuilder::new().timeout(self.timeout).build();
+        let mut template: TileJob = self.tile_job.clone();
+        // with --quick the film has a different size
+        template.resolution = Some([film.full_resolution.x, film.full_resolution.y]);
+        let failed: Mutex<Vec<(u32, u32)>> = Mutex::new(Vec::new());
+        crossbeam::scope(|scope| {
+            let (payload_tx, payload_rx) = crossbeam::channel::bounded(num_requests);
+            for i in 0..num_requests {
+                let payload_tx = payload_tx.clone();
+                let agent = &agent;
+                let template = &template;
+                let failed = &failed;
+                scope.spawn(move |_| {
+                    while let Some((x, y)) = block_queue.next() {
+                        let mut tile_job: TileJob = template.clone();
+                        tile_job.x = x;
+                        tile_job.y = y;
+                        let pixel_bounds: Bounds2i =
+                            film_tile_bounds(film, sample_bounds, &tile_job);
+                        match self.send_tile_job(agent, &tile_job, &pixel_bounds, i) {
+                            Some(payload) => payload_tx
+                                .send(payload)
+                                .unwrap_or_else(|_| panic!("Failed to send tile")),
+                            None => failed.lock().unwrap().push((x, y)),
+                        }
+                    }
+                });
+            }
+            drop(payload_tx);
+            // merge tiles as they arrive
+            for payload in payload_rx.iter() {
+                film.merge_tile_payload(&payload);
+            }
+        })
+        .unwrap();
+        let failed: Vec<(u32, u32)> = failed.into_inner().unwrap();
+        if !failed.is_empty() {
+            println!(
+                "WARNING: {} tile(s) failed on all workers, rendering them here",
+                failed.len()
+            );
+        }
+        BlockQueue::from_blocks(failed, block_queue.block_dim())
+    }
+    /// Try the workers one after another (starting with worker
+    /// `first`) until one of them returns a valid payload covering
+    /// `pixel_bounds`.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn send_tile_job(
+        &self,
+        agent: &ureq::Agent,
+        tile_job: &TileJob,
+        pixel_bounds: &Bounds2i,
+        first: usize,
+    ) -> Option<TilePayload> {
+        let body: String = tile_job.to_json();
+        for attempt in 0..=self.retries {
+            let worker: &str = &self.workers[(first + attempt as usize) % self.workers.len()];
+            let url: String = format!("{}/rendertile", worker.trim_end_matches('/'));
+            let error: RequestError = match request_tile(agent, &url, &body) {
+                Ok(payload) if same_bounds(&payload.pixel_bounds, pixel_bounds) => {
+                    return Some(payload)
+                }
+                Ok(payload) => RequestError {
+                    status: None,
+                    error: format!(
+                        "payload covers {:?} instead of {:?}",
+                        payload.pixel_bounds, pixel_bounds
+                    ),
+                },
+                Err(e) => e,
+            };
+            println!(
+                "WARNING: Tile ({}, {}) failed on {} (attempt {} of {}): {}",
+                tile_job.x,
+                tile_job.y,
+                worker,
+                attempt + 1,
+                self.retries + 1,
+                error.error
+            );
+            // other workers would refuse the same job
+            if let Some(400..=499) = error.status {
+                break;
+            }
+            // give a busy or restarting worker some time
+            if attempt < self.retries {
+                std::thread::sleep(Duration::from_millis(100 << attempt.min(6)));
+            }
+        }
+        println!("ERROR: Giving up on tile ({}, {})", tile_job.x, tile_job.y);
+        None
+    }
+}
+
+/// The pixels the payload for `tile_job` covers: those of the tile
+/// (see **render_tile()** of the integrators) plus the filter extent.
+#[cfg(not(target_arch = "wasm32"))]
+fn film_tile_bounds(film: &Film, sample_bounds: &Bounds2i, tile_job: &TileJob) -> Bounds2i {
+    let tile_size: i32 = tile_job.tile_size;
+    let x0: i32 = sample_bounds.p_min.x + tile_job.x as i32 * tile_size;
+    let x1: i32 = std::cmp::min(x0 + tile_size, sample_bounds.p_max.x);
+    let y0: i32 = sample_bounds.p_min.y + tile_job.y as i32 * tile_size;
+    let y1: i32 = std::cmp::min(y0 + tile_size, sample_bounds.p_max.y);
+    film.get_film_tile_bounds(&Bounds2i::new(
+        Point2i { x: x0, y: y0 },
+        Point2i { x: x1, y: y1 },
+    ))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn same_bounds(a: &Bounds2i, b: &Bounds2i) -> bool {
+    a.p_min.x == b.p_min.x
+        && a.p_min.y == b.p_min.y
+        && a.p_max.x == b.p_max.x
+        && a.p_max.y == b.p_max.y
+}
+
+/// Why a tile request failed, `status` is set if the worker answered.
+#[cfg(not(target_arch = "wasm32"))]
+struct RequestError {
+    status: Option<u16>,
+    error: String,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn request_tile(agent: &ureq::Agent, url: &str, body: &str) -> Result<TilePayload, RequestError> {
+    let response: ureq::Response = match agent
+        .post(url)
+        .set("Content-Type", "application/json")
+        .send_string(body)
+    {
+        Ok(response) => response,
+        // workers answer 4xx and 5xx with a TileJobError
+        Err(ureq::Error::Status(status, response)) => {
+            let error: String = response.into_string().unwrap_or_default();
+            return Err(RequestError {
+                status: Some(status),
+                error: format!("status {} {}", status, error),
+            });
+        }
+        Err(e) => {
+            return Err(RequestError {
+                status: None,
+                error: format!("{}", e),
+            })
+        }
+    };
+    let mut data: Vec<u8> = Vec::new();
+    if let Err(e) = response.into_reader().read_to_end(&mut data) {
+        return Err(RequestError {
+            status: None,
+            error: format!("{}", e),
+        });
+    }
+    TilePayload::from_bytes(&data).map_err(|e| RequestError {
+        status: None,
+        error: format!("{} ({} bytes)", e, data.len()),
+    })
+}
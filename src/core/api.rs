@@ -4,6 +4,7 @@
 
 // std
 use serde::{Deserialize, Serialize};
+use std::cell::Cell;
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::path::PathBuf;
@@ -14,7 +15,8 @@ use std::time::Instant;
 
 // pbrt
 use crate::accelerators::bvh::{BVHAccel, SplitMethod};
-use crate::accelerators::kdtreeaccel::KdTreeAccel;
+use crate::backend::{search_directory, set_search_directory};
+use crate::accelerators::kdtreeaccel::{KdTreeAccel, KdTreeStatistics};
 use crate::cameras::environment::EnvironmentCamera;
 use crate::cameras::orthographic::OrthographicCamera;
 use crate::cameras::perspective::PerspectiveCamera;
@@ -22,9 +24,13 @@ use crate::cameras::realistic::RealisticCamera;
 use crate::core::camera::Camera;
 use crate::core::film::Film;
 use crate::core::filter::Filter;
+use crate::core::geometry::{bnd2_intersect_bnd2i, bnd3_union_bnd3f};
 use crate::core::geometry::{vec3_coordinate_system, vec3_cross_vec3};
-use crate::core::geometry::{Bounds2f, Bounds2i, Normal3f, Point2f, Point2i, Point3f, Vector3f};
+use crate::core::geometry::{Bounds2f, Bounds2i, Bounds3f, Normal3f, Point2f, Point2i, Point3f};
+use crate::core::geometry::{Ray, Vector3f};
+use crate::core::guiding::PathGuiding;
 use crate::core::integrator::{Integrator, SamplerIntegrator};
+use crate::core::interaction::SurfaceInteraction;
 use crate::core::light::Light;
 use crate::core::material::Material;
 use crate::core::medium::get_medium_scattering_properties;
@@ -35,8 +41,11 @@ use crate::core::pbrt::{clamp_t, lerp};
 use crate::core::pbrt::{Float, Spectrum};
 use crate::core::primitive::{GeometricPrimitive, Primitive, TransformedPrimitive};
 use crate::core::reflection::FourierBSDFTable;
-use crate::core::sampler::Sampler;
+use crate::core::rng::Rng;
+use crate::core::sampler::{AdaptiveSampling, SampleRange, Sampler};
+use crate::core::sampling::uniform_sample_sphere;
 use crate::core::scene::Scene;
+use crate::core::snapshot::SceneSnapshot;
 use crate::core::shape::Shape;
 use crate::core::texture::{
     CylindricalMapping2D, IdentityMapping3D, PlanarMapping2D, SphericalMapping2D, Texture,
@@ -84,15 +93,19 @@ use crate::samplers::random::RandomSampler;
 use crate::samplers::sobol::SobolSampler;
 use crate::samplers::stratified::StratifiedSampler;
 use crate::samplers::zerotwosequence::ZeroTwoSequenceSampler;
-// use crate::shapes::curve::create_curve_shape;
-// use crate::shapes::cylinder::Cylinder;
-// use crate::shapes::disk::Disk;
+use crate::shapes::cone::Cone;
+use crate::shapes::curve::create_curve_shape;
+use crate::shapes::cylinder::Cylinder;
+use crate::shapes::disk::Disk;
+use crate::shapes::heightfield::{create_heightfield_mesh, Heightfield};
+use crate::shapes::hyperboloid::Hyperboloid;
 use crate::shapes::loopsubdiv::loop_subdivide;
 use crate::shapes::nurbs::nurbs_evaluate_surface;
 use crate::shapes::nurbs::Homogeneous3;
+use crate::shapes::paraboloid::Paraboloid;
 use crate::shapes::plymesh::create_ply_mesh;
 use crate::shapes::sphere::Sphere;
-use crate::shapes::triangle::{Triangle, TriangleMesh};
+use crate::shapes::triangle::{create_alpha_textures, Triangle, TriangleMesh};
 use crate::textures::checkerboard::Checkerboard2DTexture;
 use crate::textures::constant::ConstantTexture;
 use crate::textures::dots::DotsTexture;
@@ -104,6 +117,8 @@ use crate::textures::mix::MixTexture;
 use crate::textures::scale::ScaleTexture;
 use crate::textures::windy::WindyTexture;
 use crate::textures::wrinkled::WrinkledTexture;
+use crate::tilejob::{TileJob, TileJobError};
+use crate::collector::Dispatcher;
 
 #[cfg(not(feature = "ecp"))]
 use crate::entry::log;
@@ -129,6 +144,7 @@ pub struct EcpState {
     pub x: Option<u32>,
     pub y: Option<u32>,
     pub filename: String,
+    pub samples: SampleRange,
 }
 
 impl Default for EcpState {
@@ -140,6 +156,7 @@ impl Default for EcpState {
             x: None,
             y: None,
             filename: "".to_string(),
+            samples: SampleRange::default(),
         }
     }
 }
@@ -193,6 +210,18 @@ pub struct ApiState {
     pushed_transforms: Vec<TransformSet>,
     pushed_active_transform_bits: Vec<u8>,
     param_set: ParamSet,
+    snapshot_file: String,
+    bvh_benchmark_rays: usize,
+    tile_job: Option<TileJob>,
+    #[serde(skip)]
+    dispatcher: Option<Dispatcher>,
+    #[serde(skip)]
+    transform_cache: TransformCache,
+    // object and material IDs given to the primitives so far
+    #[serde(skip)]
+    object_count: u32,
+    #[serde(skip)]
+    material_ids: HashMap<usize, u32>,
 }
 
 impl Default for ApiState {
@@ -229,10 +258,58 @@ impl Default for ApiState {
             pushed_transforms: Vec::new(),
             pushed_active_transform_bits: Vec::new(),
             param_set: ParamSet::default(),
+            snapshot_file: String::new(),
+            bvh_benchmark_rays: 0_usize,
+            tile_job: None,
+            dispatcher: None,
+            transform_cache: TransformCache::default(),
+            object_count: 0_u32,
+            material_ids: HashMap::new(),
         }
     }
 }
 
+impl ApiState {
+    /// Relative file names in the scene are resolved against
+    /// `search_directory` (see
+    /// [resolve_filename()](../../backend/fn.resolve_filename.html)).
+    pub fn set_search_directory(&mut self, search_directory: PathBuf) {
+        set_search_directory(Some(search_directory.clone()));
+        self.search_directory = Some(Box::new(search_directory));
+    }
+    /// Overrides the filename of the **Film** (see **--outfile**).
+    pub fn set_image_file(&mut self, image_file: &str) {
+        self.render_options.image_file = image_file.to_string();
+    }
+    /// Automatically reduce a number of quality settings to render
+    /// more quickly (see **--quick**).
+    pub fn set_quick_render(&mut self, quick_render: bool) {
+        self.render_options.quick_render = quick_render;
+    }
+    /// Write the built scene to `snapshot_file` (see
+    /// [SceneSnapshot](../snapshot/struct.SceneSnapshot.html)) instead
+    /// of rendering it (see **--snapshot**).
+    pub fn set_snapshot_file(&mut self, snapshot_file: &str) {
+        self.snapshot_file = snapshot_file.to_string();
+    }
+    /// Compare the BVH split methods on the scene's primitives with
+    /// `rays` random rays instead of rendering (see **--bvhbench**).
+    pub fn set_bvh_benchmark(&mut self, rays: usize) {
+        self.bvh_benchmark_rays = rays;
+    }
+    /// The film and sampler overrides of `tile_job` are applied once
+    /// the scene description is complete (see
+    /// [apply_tile_job()](struct.RenderOptions.html#method.apply_tile_job)).
+    pub fn set_tile_job(&mut self, tile_job: &TileJob) {
+        self.tile_job = Some(tile_job.clone());
+    }
+    /// Send the tiles to render workers instead of rendering them
+    /// (see [Dispatcher](../../collector/struct.Dispatcher.html)).
+    pub fn set_dispatcher(&mut self, dispatcher: Dispatcher) {
+        self.dispatcher = Some(dispatcher);
+    }
+}
+
 #[derive(Debug, Default, Copy, Clone, Serialize, Deserialize)]
 pub struct TransformSet {
     pub t: [Transform; 2],
@@ -249,7 +326,47 @@ impl TransformSet {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+/// Hands out shared copies of the transforms used by shapes, lights
+/// and instances. All triangles of a mesh, all segments of a curve
+/// and all shapes below the same **Transform** statement end up
+/// pointing to the same two matrices instead of storing their own.
+#[derive(Default)]
+pub struct TransformCache {
+    hash_table: HashMap<[u32; 32], Arc<Transform>>,
+}
+
+impl TransformCache {
+    /// Returns the cached transform and its inverse.
+    pub fn lookup(&mut self, t: &Transform) -> (Arc<Transform>, Arc<Transform>) {
+        (self.get(t), self.get(&Transform::inverse(t)))
+    }
+    /// Returns the cached copy of `t`, storing it first if necessary.
+    pub fn get(&mut self, t: &Transform) -> Arc<Transform> {
+        let mut key: [u32; 32] = [0_u32; 32];
+        for i in 0..4 {
+            for j in 0..4 {
+                key[i * 4 + j] = t.m.m[i][j].to_bits();
+                key[16 + i * 4 + j] = t.m_inv.m[i][j].to_bits();
+            }
+        }
+        self.hash_table
+            .entry(key)
+            .or_insert_with(|| Arc::new(*t))
+            .clone()
+    }
+    /// Number of distinct transforms in the cache.
+    pub fn len(&self) -> usize {
+        self.hash_table.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.hash_table.is_empty()
+    }
+    pub fn clear(&mut self) {
+        self.hash_table.clear();
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct RenderOptions {
     pub transform_start_time: Float,
     pub transform_end_time: Float,
@@ -266,16 +383,50 @@ pub struct RenderOptions {
     pub camera_name: String, // "perspective";
     pub camera_params: ParamSet,
     pub camera_to_world: TransformSet,
+    // media, lights and primitives end up in the Scene (see SceneSnapshot)
+    #[serde(skip)]
     pub named_media: HashMap<String, Arc<Medium>>,
+    #[serde(skip)]
     pub lights: Vec<Arc<Light>>,
+    #[serde(skip)]
     pub primitives: Vec<Arc<Primitive>>,
+    #[serde(skip)]
     pub instances: HashMap<String, Vec<Arc<Primitive>>>,
+    #[serde(skip)]
+    pub instance_area_lights: HashMap<String, Vec<InstanceAreaLightShape>>,
     pub current_instance: String,
     pub have_scattering_media: bool, // false
     pub crop_window: Bounds2f,
+    pub image_file: String,
+    pub quick_render: bool,
+    /// names of the light groups (the first one for lights outside
+    /// of any `LightGroup`)
+    pub light_groups: Vec<String>,
 }
 
 impl RenderOptions {
+    /// Override resolution, crop window and sampler of the scene with
+    /// the values a collector sent along with a tile job.
+    pub fn apply_tile_job(&mut self, tile_job: &TileJob) {
+        if let Some(resolution) = tile_job.resolution {
+            self.film_params.erase_int("xresolution");
+            self.film_params.erase_int("yresolution");
+            self.film_params
+                .add_int(String::from("xresolution"), resolution[0]);
+            self.film_params
+                .add_int(String::from("yresolution"), resolution[1]);
+        }
+        if let Some(cr) = tile_job.crop_window {
+            self.film_params.erase_float("cropwindow");
+            self.crop_window = Bounds2f {
+                p_min: Point2f { x: cr[0], y: cr[2] },
+                p_max: Point2f { x: cr[1], y: cr[3] },
+            };
+        }
+        if let Some(ref sampler_name) = tile_job.sampler {
+            self.sampler_name = sampler_name.clone();
+        }
+    }
     pub fn make_integrator(
         &self,
         pixelsamples: u32,
@@ -285,7 +436,9 @@ impl RenderOptions {
         let some_camera: Option<Arc<Camera>> = self.make_camera();
         if let Some(camera) = some_camera {
             let some_sampler: Option<Box<Sampler>>;
-            if pixelsamples != 0_u32 {
+            if pixelsamples != 0_u32 || self.quick_render {
+                // a quick render uses a single sample per pixel
+                let pixelsamples: u32 = if self.quick_render { 1 } else { pixelsamples };
                 // copy all bool and integer values, except pixelsamples
                 let mut new_sampler_params: ParamSet = ParamSet::default();
                 new_sampler_params.key_word = self.sampler_params.key_word.clone();
@@ -298,6 +451,9 @@ impl RenderOptions {
                 for i in &self.sampler_params.ints {
                     if i.name == "pixelsamples" {
                         new_sampler_params.add_int(i.name.clone(), pixelsamples as i32);
+                    } else if self.quick_render && (i.name == "xsamples" || i.name == "ysamples")
+                    {
+                        new_sampler_params.add_int(i.name.clone(), 1);
                     } else {
                         new_sampler_params.add_int(i.name.clone(), i.values[0]);
                     }
@@ -321,7 +477,7 @@ impl RenderOptions {
                 if integrator_name == "whitted" {
                     // CreateWhittedIntegrator
                     let max_depth: i32 = self.integrator_params.find_one_int("maxdepth", 5);
-                    let pixel_bounds: Bounds2i = camera.get_film().get_sample_bounds();
+                    let pixel_bounds: Bounds2i = self.find_pixel_bounds(&camera);
                     let integrator = Box::new(Integrator::Sampler(SamplerIntegrator::Whitted(
                         WhittedIntegrator::new(max_depth as u32, camera, sampler, pixel_bounds),
                     )));
@@ -340,13 +496,7 @@ impl RenderOptions {
                     } else {
                         panic!("Strategy \"{}\" for direct lighting unknown.", st);
                     }
-                    // TODO: const int *pb = params.FindInt("pixelbounds", &np);
-                    let xres: i32 = self.film_params.find_one_int("xresolution", 1280);
-                    let yres: i32 = self.film_params.find_one_int("yresolution", 720);
-                    let pixel_bounds: Bounds2i = Bounds2i {
-                        p_min: Point2i { x: 0, y: 0 },
-                        p_max: Point2i { x: xres, y: yres },
-                    };
+                    let pixel_bounds: Bounds2i = self.find_pixel_bounds(&camera);
                     let integrator = Box::new(Integrator::Sampler(
                         SamplerIntegrator::DirectLighting(DirectLightingIntegrator::new(
                             strategy,
@@ -360,29 +510,17 @@ impl RenderOptions {
                 } else if integrator_name == "path" {
                     // CreatePathIntegrator
                     let max_depth: i32 = self.integrator_params.find_one_int("maxdepth", 5);
-                    let pb: Vec<i32> = self.integrator_params.find_int("pixelbounds");
-                    let np: usize = pb.len();
-                    let pixel_bounds: Bounds2i = camera.get_film().get_sample_bounds();
-                    if np > 0 as usize {
-                        if np != 4 as usize {
-                            panic!(
-                                "Expected four values for \"pixelbounds\" parameter. Got {}.",
-                                np
-                            );
-                        } else {
-                            println!("TODO: pixelBounds = Intersect(...)");
-                            // pixelBounds = Intersect(pixelBounds,
-                            //                         Bounds2i{{pb[0], pb[2]}, {pb[1], pb[3]}});
-                            // if (pixelBounds.Area() == 0)
-                            //     Error("Degenerate \"pixelbounds\" specified.");
-                        }
-                    }
+                    let pixel_bounds: Bounds2i = self.find_pixel_bounds(&camera);
                     let rr_threshold: Float = self
                         .integrator_params
                         .find_one_float("rrthreshold", 1.0 as Float);
                     let light_strategy: String = self
                         .integrator_params
                         .find_one_string("lightsamplestrategy", String::from("spatial"));
+                    let guiding: Option<PathGuiding> = PathGuiding::create(
+                        &self.integrator_params,
+                        sampler.get_samples_per_pixel(),
+                    );
                     let integrator = Box::new(Integrator::Sampler(SamplerIntegrator::Path(
                         PathIntegrator::new(
                             max_depth as u32,
@@ -391,29 +529,14 @@ impl RenderOptions {
                             pixel_bounds,
                             rr_threshold,
                             light_strategy,
+                            guiding,
                         ),
                     )));
                     some_integrator = Some(integrator);
                 } else if integrator_name == "volpath" {
                     // CreateVolPathIntegrator
                     let max_depth: i32 = self.integrator_params.find_one_int("maxdepth", 5);
-                    let pb: Vec<i32> = self.integrator_params.find_int("pixelbounds");
-                    let np: usize = pb.len();
-                    let pixel_bounds: Bounds2i = camera.get_film().get_sample_bounds();
-                    if np > 0 as usize {
-                        if np != 4 as usize {
-                            panic!(
-                                "Expected four values for \"pixelbounds\" parameter. Got {}.",
-                                np
-                            );
-                        } else {
-                            println!("TODO: pixelBounds = Intersect(...)");
-                            // pixelBounds = Intersect(pixelBounds,
-                            //                         Bounds2i{{pb[0], pb[2]}, {pb[1], pb[3]}});
-                            // if (pixelBounds.Area() == 0)
-                            //     Error("Degenerate \"pixelbounds\" specified.");
-                        }
-                    }
+                    let pixel_bounds: Bounds2i = self.find_pixel_bounds(&camera);
                     let rr_threshold: Float = self
                         .integrator_params
                         .find_one_float("rrthreshold", 1.0 as Float);
@@ -445,7 +568,7 @@ impl RenderOptions {
                         println!(" limiting maxdepth to 5");
                         max_depth = 5;
                     }
-                    let pixel_bounds: Bounds2i = camera.get_film().get_sample_bounds();
+                    let pixel_bounds: Bounds2i = self.find_pixel_bounds(&camera);
                     let light_strategy: String = self
                         .integrator_params
                         .find_one_string("lightsamplestrategy", String::from("power"));
@@ -460,11 +583,11 @@ impl RenderOptions {
                 } else if integrator_name == "mlt" {
                     // CreateMLTIntegrator
                     let max_depth: i32 = self.integrator_params.find_one_int("maxdepth", 5);
-                    let n_bootstrap: i32 = self
+                    let mut n_bootstrap: i32 = self
                         .integrator_params
                         .find_one_int("bootstrapsamples", 100_000);
                     let n_chains: i32 = self.integrator_params.find_one_int("chains", 1000);
-                    let mutations_per_pixel: i32 = self
+                    let mut mutations_per_pixel: i32 = self
                         .integrator_params
                         .find_one_int("mutationsperpixel", 100);
                     let large_step_probability: Float = self
@@ -473,6 +596,10 @@ impl RenderOptions {
                     let sigma: Float = self
                         .integrator_params
                         .find_one_float("sigma", 0.01 as Float);
+                    if self.quick_render {
+                        mutations_per_pixel = std::cmp::max(1, mutations_per_pixel / 16);
+                        n_bootstrap = std::cmp::max(1, n_bootstrap / 16);
+                    }
                     let integrator = Box::new(Integrator::MLT(MLTIntegrator::new(
                         camera,
                         max_depth as u32,
@@ -485,23 +612,7 @@ impl RenderOptions {
                     some_integrator = Some(integrator);
                 } else if integrator_name == "ao" || integrator_name == "ambientocclusion" {
                     // CreateAOIntegrator
-                    let pb: Vec<i32> = self.integrator_params.find_int("pixelbounds");
-                    let np: usize = pb.len();
-                    let pixel_bounds: Bounds2i = camera.get_film().get_sample_bounds();
-                    if np > 0 as usize {
-                        if np != 4 as usize {
-                            panic!(
-                                "Expected four values for \"pixelbounds\" parameter. Got {}.",
-                                np
-                            );
-                        } else {
-                            println!("TODO: pixelBounds = Intersect(...)");
-                            // pixelBounds = Intersect(pixelBounds,
-                            //                         Bounds2i{{pb[0], pb[2]}, {pb[1], pb[3]}});
-                            // if (pixelBounds.Area() == 0)
-                            //     Error("Degenerate \"pixelbounds\" specified.");
-                        }
-                    }
+                    let pixel_bounds: Bounds2i = self.find_pixel_bounds(&camera);
                     let cos_sample: bool = self.integrator_params.find_one_bool("cossample", true);
                     let n_samples: i32 = self.integrator_params.find_one_int("nsamples", 64 as i32);
                     let integrator = Box::new(Integrator::Sampler(SamplerIntegrator::AO(
@@ -525,7 +636,9 @@ impl RenderOptions {
                     let radius: Float = self
                         .integrator_params
                         .find_one_float("radius", 1.0 as Float);
-                    // TODO: if (PbrtOptions.quickRender) nIterations = std::max(1, nIterations / 16);
+                    if self.quick_render {
+                        n_iterations = std::cmp::max(1, n_iterations / 16);
+                    }
                     let integrator = Box::new(Integrator::SPPM(SPPMIntegrator::new(
                         camera,
                         n_iterations,
@@ -539,13 +652,63 @@ impl RenderOptions {
                     println!("Integrator \"{}\" unknown.", integrator_name);
                 }
             } else {
-                panic!("Unable to create sampler.");
+                println!("ERROR: Unable to create sampler.");
             }
         } else {
-            panic!("Unable to create camera.");
+            println!("ERROR: Unable to create camera.");
         }
         some_integrator
     }
+    /// Adaptive and progressive sampling as requested by the
+    /// integrator parameters.
+    pub fn get_adaptive_sampling(&self) -> AdaptiveSampling {
+        let max_relative_error: Float = self
+            .integrator_params
+            .find_one_float("maxrelativeerror", 0.0 as Float);
+        let min_samples: i32 = self.integrator_params.find_one_int("minsamples", 8);
+        let pass_samples: i32 = self.integrator_params.find_one_int("passsamples", 0);
+        if max_relative_error < 0.0 as Float || min_samples < 0 || pass_samples < 0 {
+            println!(
+                "WARNING: Negative \"maxrelativeerror\", \"minsamples\" or \"passsamples\" ignored"
+            );
+        }
+        AdaptiveSampling {
+            max_relative_error: max_relative_error.max(0.0 as Float),
+            min_samples: std::cmp::max(min_samples, 0) as i64,
+            pass_samples: std::cmp::max(pass_samples, 0) as i64,
+        }
+    }
+    /// The film's sample bounds, intersected with the integrator's
+    /// optional `"integer pixelbounds" [x0 x1 y0 y1]` to render only
+    /// a region of the image.
+    pub fn find_pixel_bounds(&self, camera: &Arc<Camera>) -> Bounds2i {
+        let sample_bounds: Bounds2i = camera.get_film().get_sample_bounds();
+        let pb: Vec<i32> = self.integrator_params.find_int("pixelbounds");
+        let np: usize = pb.len();
+        if np == 0 {
+            return sample_bounds;
+        }
+        if np != 4 {
+            panic!(
+                "Expected four values for \"pixelbounds\" parameter. Got {}.",
+                np
+            );
+        }
+        let pixel_bounds: Bounds2i = bnd2_intersect_bnd2i(
+            &sample_bounds,
+            &Bounds2i {
+                p_min: Point2i { x: pb[0], y: pb[2] },
+                p_max: Point2i { x: pb[1], y: pb[3] },
+            },
+        );
+        if pixel_bounds.p_min.x >= pixel_bounds.p_max.x
+            || pixel_bounds.p_min.y >= pixel_bounds.p_max.y
+        {
+            println!("ERROR: Degenerate \"pixelbounds\" specified.");
+            return sample_bounds;
+        }
+        pixel_bounds
+    }
     pub fn make_scene(&self) -> Scene {
         #[cfg(ecp)]
         let acc = Instant::now();
@@ -572,12 +735,15 @@ impl RenderOptions {
                 &self.film_params,
                 filter,
                 &self.crop_window,
+                &self.image_file,
+                self.quick_render,
+                &self.light_groups,
             );
             if let Some(film) = some_film {
                 let animated_cam_to_world: AnimatedTransform = AnimatedTransform::new(
-                    &self.camera_to_world.t[0],
+                    &Arc::new(self.camera_to_world.t[0]),
                     self.transform_start_time,
-                    &self.camera_to_world.t[1],
+                    &Arc::new(self.camera_to_world.t[1]),
                     self.transform_end_time,
                 );
                 let clipping_start: Float = 0.0; // ADDED
@@ -635,12 +801,16 @@ impl Default for RenderOptions {
             lights: Vec::new(),
             primitives: Vec::new(),
             instances: HashMap::new(),
+            instance_area_lights: HashMap::new(),
             current_instance: String::from(""),
             have_scattering_media: false,
             crop_window: Bounds2f {
                 p_min: Point2f { x: 0.0, y: 0.0 },
                 p_max: Point2f { x: 1.0, y: 1.0 },
             },
+            image_file: String::new(),
+            quick_render: false,
+            light_groups: vec![String::from("default")],
         }
     }
 }
@@ -655,9 +825,15 @@ pub struct GraphicsState {
     pub material: String,
     pub named_materials: Arc<HashMap<String, Option<Arc<Material>>>>,
     pub current_material: String,
+    /// created once per **Material** directive, so all shapes using
+    /// it share the same material (and material ID)
+    #[serde(skip)]
+    pub material_instance: Option<Arc<Material>>,
     pub area_light_params: ParamSet,
     pub area_light: String,
     pub reverse_orientation: bool,
+    /// index into **RenderOptions::light_groups** for new lights
+    pub light_group: usize,
 }
 
 impl GraphicsState {
@@ -685,9 +861,11 @@ impl GraphicsState {
             material: String::from(""),
             named_materials,
             current_material,
+            material_instance: None,
             area_light_params: ParamSet::default(),
             area_light: String::from(""),
             reverse_orientation: false,
+            light_group: 0_usize,
         }
     }
     // pub fn get_material_for_shape(
@@ -710,6 +888,42 @@ impl GraphicsState {
     // }
 }
 
+impl Clone for GraphicsState {
+    fn clone(&self) -> Self {
+        let mut material_params: ParamSet = ParamSet::default();
+        material_params.copy_from(&self.material_params);
+        let mut area_light_params: ParamSet = ParamSet::default();
+        area_light_params.copy_from(&self.area_light_params);
+        GraphicsState {
+            current_inside_medium: self.current_inside_medium.clone(),
+            current_outside_medium: self.current_outside_medium.clone(),
+            float_textures: self.float_textures.clone(),
+            spectrum_textures: self.spectrum_textures.clone(),
+            material_params,
+            material: self.material.clone(),
+            named_materials: self.named_materials.clone(),
+            current_material: self.current_material.clone(),
+            material_instance: self.material_instance.clone(),
+            area_light_params,
+            area_light: self.area_light.clone(),
+            reverse_orientation: self.reverse_orientation,
+            light_group: self.light_group,
+        }
+    }
+}
+
+/// An emissive **Shape** inside an **ObjectBegin**/**ObjectEnd**
+/// block. Area lights can't be shared between instances, so the
+/// shape is created again (in world space) for every
+/// **ObjectInstance**, see
+/// [pbrt_object_instance()](fn.pbrt_object_instance.html).
+#[derive(Clone)]
+pub struct InstanceAreaLightShape {
+    params: ParamSet,
+    graphics_state: GraphicsState,
+    transform: TransformSet,
+}
+
 fn create_material(api_state: &ApiState, bsdf_state: &mut BsdfState) -> Option<Arc<Material>> {
     // CreateMaterial
     let mut material_params = ParamSet::default();
@@ -736,6 +950,8 @@ fn create_material(api_state: &ApiState, bsdf_state: &mut BsdfState) -> Option<A
                 );
             }
         }
+    } else if let Some(material_instance) = &api_state.graphics_state.material_instance {
+        return Some(material_instance.clone());
     } else {
         // MakeMaterial
         if api_state.graphics_state.material == "" || api_state.graphics_state.material == "none" {
@@ -912,8 +1128,10 @@ fn make_light(api_state: &mut ApiState, medium_interface: &MediumInterface) {
                 z: from.z,
             })
             * Transform::inverse(&dir_to_z);
+        let (light_to_world, world_to_light) = api_state.transform_cache.lookup(&light2world);
         let spot_light = Arc::new(Light::Spot(Box::new(SpotLight::new(
-            &light2world,
+            &light_to_world,
+            &world_to_light,
             medium_interface,
             &(i * sc),
             coneangle,
@@ -931,9 +1149,13 @@ fn make_light(api_state: &mut ApiState, medium_interface: &MediumInterface) {
         let texname: String = api_state
             .param_set
             .find_one_filename("mapname", String::from(""));
+        let (light_to_world, world_to_light) = api_state
+            .transform_cache
+            .lookup(&api_state.cur_transform.t[0]);
         let projection_light = Arc::new(Light::GonioPhotometric(Box::new(
             GonioPhotometricLight::new(
-                &api_state.cur_transform.t[0],
+                &light_to_world,
+                &world_to_light,
                 medium_interface,
                 &(i * sc),
                 texname,
@@ -952,8 +1174,12 @@ fn make_light(api_state: &mut ApiState, medium_interface: &MediumInterface) {
         let texname: String = api_state
             .param_set
             .find_one_filename("mapname", String::from(""));
+        let (light_to_world, world_to_light) = api_state
+            .transform_cache
+            .lookup(&api_state.cur_transform.t[0]);
         let projection_light = Arc::new(Light::Projection(Box::new(ProjectionLight::new(
-            &api_state.cur_transform.t[0],
+            &light_to_world,
+            &world_to_light,
             medium_interface,
             &(i * sc),
             texname,
@@ -986,8 +1212,12 @@ fn make_light(api_state: &mut ApiState, medium_interface: &MediumInterface) {
         );
         let dir: Vector3f = from - to;
         // return std::make_shared<DistantLight>(light2world, L * sc, dir);
+        let (light_to_world, world_to_light) = api_state
+            .transform_cache
+            .lookup(&api_state.cur_transform.t[0]);
         let distant_light = Arc::new(Light::Distant(Box::new(DistantLight::new(
-            &api_state.cur_transform.t[0],
+            &light_to_world,
+            &world_to_light,
             &(l * sc),
             &dir,
         ))));
@@ -999,24 +1229,21 @@ fn make_light(api_state: &mut ApiState, medium_interface: &MediumInterface) {
         let sc: Spectrum = api_state
             .param_set
             .find_one_spectrum("scale", Spectrum::new(1.0 as Float));
-        let mut texmap: String = api_state
+        let texmap: String = api_state
             .param_set
             .find_one_filename("mapname", String::from(""));
-        if texmap != "" {
-            if let Some(ref search_directory) = api_state.search_directory {
-                // texmap = AbsolutePath(ResolveFilename(texmap));
-                let mut path_buf: PathBuf = PathBuf::from("/");
-                path_buf.push(search_directory.as_ref());
-                path_buf.push(texmap);
-                texmap = String::from(path_buf.to_str().unwrap());
-            }
+        let mut n_samples: i32 = api_state.param_set.find_one_int("nsamples", 1 as i32);
+        if api_state.render_options.quick_render {
+            n_samples = std::cmp::max(1, n_samples / 4);
         }
-        let n_samples: i32 = api_state.param_set.find_one_int("nsamples", 1 as i32);
-        // TODO: if (PbrtOptions.quickRender) nSamples = std::max(1, nSamples / 4);
 
         // return std::make_shared<InfiniteAreaLight>(light2world, L * sc, nSamples, texmap);
+        let (light_to_world, world_to_light) = api_state
+            .transform_cache
+            .lookup(&api_state.cur_transform.t[0]);
         let infinte_light = Arc::new(Light::InfiniteArea(Box::new(InfiniteAreaLight::new(
-            &api_state.cur_transform.t[0],
+            &light_to_world,
+            &world_to_light,
             &(l * sc),
             n_samples,
             texmap,
@@ -1218,14 +1445,7 @@ fn make_texture(api_state: &mut ApiState) {
                 wrap_mode = ImageWrap::Clamp;
             }
             let scale: Float = tp.find_float("scale", 1.0);
-            let mut filename: String = tp.find_filename("filename", String::new());
-            if let Some(ref search_directory) = api_state.search_directory {
-                // filename = AbsolutePath(ResolveFilename(filename));
-                let mut path_buf: PathBuf = PathBuf::from("/");
-                path_buf.push(search_directory.as_ref());
-                path_buf.push(filename);
-                filename = String::from(path_buf.to_str().unwrap());
-            }
+            let filename: String = tp.find_filename("filename", String::new());
             // TODO: default depends on:
             // HasExtension(filename,
             // ".tga") ||
@@ -1454,14 +1674,7 @@ fn make_texture(api_state: &mut ApiState) {
                 wrap_mode = ImageWrap::Clamp;
             }
             let scale: Float = tp.find_float("scale", 1.0);
-            let mut filename: String = tp.find_filename("filename", String::new());
-            if let Some(ref search_directory) = api_state.search_directory {
-                // filename = AbsolutePath(ResolveFilename(filename));
-                let mut path_buf: PathBuf = PathBuf::from("/");
-                path_buf.push(search_directory.as_ref());
-                path_buf.push(filename);
-                filename = String::from(path_buf.to_str().unwrap());
-            }
+            let filename: String = tp.find_filename("filename", String::new());
             // TODO: default depends on:
             // HasExtension(filename,
             // ".tga") ||
@@ -1702,10 +1915,61 @@ pub fn make_accelerator(
             primitives.to_owned(),
             accelerator_params,
         )));
+    } else if accelerator_name == "auto" {
+        let chosen: &str = choose_accelerator(primitives);
+        println!(
+            "Accelerator \"auto\": using \"{}\" for {} primitives",
+            chosen,
+            primitives.len()
+        );
+        some_accelerator = make_accelerator(chosen, primitives, accelerator_params);
     }
     some_accelerator
 }
 
+/// Above this number of primitives `Accelerator "auto"` always uses
+/// a BVH. Building a kd-tree needs `(max_depth + 1) * n` primitive
+/// indices and gets slow for large scenes.
+pub const AUTO_KDTREE_MAX_PRIMITIVES: usize = 4096;
+/// Above this (relative) primitive size `Accelerator "auto"` uses a
+/// BVH (see [choose_accelerator()](fn.choose_accelerator.html)).
+pub const AUTO_KDTREE_MAX_OVERLAP: f64 = 4.0;
+
+/// Picks the accelerator for `Accelerator "auto"` from the number of
+/// primitives and the distribution of their bounds. A kd-tree wins
+/// for scenes of small, evenly sized primitives, but primitives
+/// straddling split planes get referenced from several leaves. Large
+/// (relative to a uniform distribution) primitives and instances or
+/// moving objects, whose bounds are loose, favour a BVH.
+pub fn choose_accelerator(primitives: &[Arc<Primitive>]) -> &'static str {
+    let n: usize = primitives.len();
+    if n < 2 || n > AUTO_KDTREE_MAX_PRIMITIVES {
+        return "bvh";
+    }
+    let mut scene_bounds: Bounds3f = Bounds3f::default();
+    let mut prim_area: f64 = 0.0;
+    for prim in primitives {
+        if let Primitive::Transformed(_) = **prim {
+            return "bvh";
+        }
+        let b: Bounds3f = prim.world_bound();
+        prim_area += b.surface_area() as f64;
+        scene_bounds = bnd3_union_bnd3f(&scene_bounds, &b);
+    }
+    let scene_area: f64 = scene_bounds.surface_area() as f64;
+    if scene_area <= 0.0 {
+        return "bvh";
+    }
+    // n equally sized primitives filling the scene bounds would each
+    // cover about n^(-2/3) of its surface area
+    let overlap: f64 = prim_area / scene_area / (n as f64).powf(1.0 / 3.0);
+    if overlap > AUTO_KDTREE_MAX_OVERLAP {
+        "bvh"
+    } else {
+        "kdtree"
+    }
+}
+
 pub fn make_camera(
     camera_name: &str,
     camera_params: &ParamSet,
@@ -1733,27 +1997,16 @@ pub fn make_camera(
         );
         some_camera = Some(camera);
     } else if camera_name == "realistic" {
-        // if let Some(ref search_directory) = api_state.search_directory {
-        //     let camera: Arc<Camera> = RealisticCamera::create(
-        //         &camera_params,
-        //         animated_cam_to_world,
-        //         film,
-        //         medium_interface.outside,
-        //         // additional parameters:
-        //         Some(search_directory),
-        //     );
-        //     some_camera = Some(camera);
-        // } else {
+        let search_directory: Option<PathBuf> = search_directory();
         let camera: Arc<Camera> = RealisticCamera::create(
             &camera_params,
             animated_cam_to_world,
             film,
             medium_interface.outside,
             // additional parameters:
-            None,
+            search_directory.as_ref(),
         );
         some_camera = Some(camera);
-    // }
     } else if camera_name == "environment" {
         let camera: Arc<Camera> = EnvironmentCamera::create(
             &camera_params,
@@ -1823,9 +2076,19 @@ pub fn make_film(
     param_set: &ParamSet,
     filter: Box<Filter>,
     crop_window: &Bounds2f,
+    image_file: &str,
+    quick_render: bool,
+    light_groups: &[String],
 ) -> Option<Arc<Film>> {
     if name == "image" {
-        Some(Film::create(param_set, filter, crop_window))
+        Some(Film::create(
+            param_set,
+            filter,
+            crop_window,
+            image_file,
+            quick_render,
+            light_groups,
+        ))
     } else {
         println!("Film \"{}\" unknown.", name);
         None
@@ -1833,7 +2096,7 @@ pub fn make_film(
 }
 
 fn get_shapes_and_materials(
-    api_state: &ApiState,
+    api_state: &mut ApiState,
     bsdf_state: &mut BsdfState,
 ) -> (Vec<Arc<Shape>>, Vec<Option<Arc<Material>>>) {
     if shape_may_set_material_parameters(&api_state.param_set) {
@@ -1845,24 +2108,17 @@ fn get_shapes_and_materials(
     let mut shapes: Vec<Arc<Shape>> = Vec::new();
     let mut materials: Vec<Option<Arc<Material>>> = Vec::new();
     // pbrtShape (api.cpp:1153)
-    // TODO: if (!curTransform.IsAnimated()) { ... }
-    // TODO: transformCache.Lookup(curTransform[0], &ObjToWorld, &WorldToObj);
-    let mut obj_to_world: Transform = Transform {
-        m: api_state.cur_transform.t[0].m,
-        m_inv: api_state.cur_transform.t[0].m_inv,
-    };
-    let world_to_obj = if api_state.cur_transform.is_animated() {
+    let (obj_to_world, world_to_obj) = if api_state.cur_transform.is_animated() {
         if api_state.graphics_state.area_light != "" {
             println!("WARNING: Ignoring currently set area light when creating animated shape",);
         }
-        // set both transforms to identity
-        obj_to_world = Transform::default();
-        Transform::default()
+        // set both transforms to identity, the shapes get wrapped
+        // into a TransformedPrimitive (see create_shape_primitives)
+        api_state.transform_cache.lookup(&Transform::default())
     } else {
-        Transform {
-            m: api_state.cur_transform.t[0].m_inv,
-            m_inv: api_state.cur_transform.t[0].m,
-        }
+        api_state
+            .transform_cache
+            .lookup(&api_state.cur_transform.t[0])
     };
     // MakeShapes (api.cpp:296)
     if api_state.param_set.name == "sphere" {
@@ -1883,58 +2139,114 @@ fn get_shapes_and_materials(
         let mtl: Option<Arc<Material>> = create_material(&api_state, bsdf_state);
         shapes.push(sphere);
         materials.push(mtl);
-    // } else if api_state.param_set.name == "cylinder" {
-    //     let radius: Float = api_state.param_set.find_one_float("radius", 1.0);
-    //     let z_min: Float = api_state.param_set.find_one_float("zmin", -radius);
-    //     let z_max: Float = api_state.param_set.find_one_float("zmax", radius);
-    //     let phi_max: Float = api_state.param_set.find_one_float("phimax", 360.0 as Float);
-    //     let cylinder = Arc::new(Shape::Clndr(Cylinder::new(
-    //         obj_to_world,
-    //         world_to_obj,
-    //         false,
-    //         radius,
-    //         z_min,
-    //         z_max,
-    //         phi_max,
-    //     )));
-    //     let mtl: Option<Arc<Material>> = create_material(&api_state, bsdf_state);
-    //     shapes.push(cylinder);
-    //     materials.push(mtl);
-    // } else if api_state.param_set.name == "disk" {
-    //     let height: Float = api_state.param_set.find_one_float("height", 0.0);
-    //     let radius: Float = api_state.param_set.find_one_float("radius", 1.0);
-    //     let inner_radius: Float = api_state.param_set.find_one_float("innerradius", 0.0);
-    //     let phi_max: Float = api_state.param_set.find_one_float("phimax", 360.0);
-    //     let disk = Arc::new(Shape::Dsk(Disk::new(
-    //         obj_to_world,
-    //         world_to_obj,
-    //         false,
-    //         height,
-    //         radius,
-    //         inner_radius,
-    //         phi_max,
-    //     )));
-    //     let mtl: Option<Arc<Material>> = create_material(&api_state, bsdf_state);
-    //     shapes.push(disk);
-    //     materials.push(mtl);
-	} else if api_state.param_set.name == "cone" {
-        println!("TODO: CreateConeShape");
+    } else if api_state.param_set.name == "cylinder" {
+        // CreateCylinderShape
+        let radius: Float = api_state.param_set.find_one_float("radius", 1.0 as Float);
+        let z_min: Float = api_state.param_set.find_one_float("zmin", -1.0 as Float);
+        let z_max: Float = api_state.param_set.find_one_float("zmax", 1.0 as Float);
+        let phi_max: Float = api_state.param_set.find_one_float("phimax", 360.0 as Float);
+        let cylinder = Arc::new(Shape::Clndr(Cylinder::new(
+            obj_to_world,
+            world_to_obj,
+            api_state.graphics_state.reverse_orientation,
+            radius,
+            z_min,
+            z_max,
+            phi_max,
+        )));
+        let mtl: Option<Arc<Material>> = create_material(&api_state, bsdf_state);
+        shapes.push(cylinder);
+        materials.push(mtl);
+    } else if api_state.param_set.name == "disk" {
+        // CreateDiskShape
+        let height: Float = api_state.param_set.find_one_float("height", 0.0 as Float);
+        let radius: Float = api_state.param_set.find_one_float("radius", 1.0 as Float);
+        let inner_radius: Float = api_state.param_set.find_one_float("innerradius", 0.0 as Float);
+        let phi_max: Float = api_state.param_set.find_one_float("phimax", 360.0 as Float);
+        let disk = Arc::new(Shape::Dsk(Disk::new(
+            obj_to_world,
+            world_to_obj,
+            api_state.graphics_state.reverse_orientation,
+            height,
+            radius,
+            inner_radius,
+            phi_max,
+        )));
+        let mtl: Option<Arc<Material>> = create_material(&api_state, bsdf_state);
+        shapes.push(disk);
+        materials.push(mtl);
+    } else if api_state.param_set.name == "cone" {
+        // CreateConeShape
+        let radius: Float = api_state.param_set.find_one_float("radius", 1.0 as Float);
+        let height: Float = api_state.param_set.find_one_float("height", 1.0 as Float);
+        let phi_max: Float = api_state.param_set.find_one_float("phimax", 360.0 as Float);
+        let cone = Arc::new(Shape::Cn(Cone::new(
+            obj_to_world,
+            world_to_obj,
+            api_state.graphics_state.reverse_orientation,
+            height,
+            radius,
+            phi_max,
+        )));
+        let mtl: Option<Arc<Material>> = create_material(&api_state, bsdf_state);
+        shapes.push(cone);
+        materials.push(mtl);
     } else if api_state.param_set.name == "paraboloid" {
-        println!("TODO: CreateParaboloidShape");
+        // CreateParaboloidShape
+        let radius: Float = api_state.param_set.find_one_float("radius", 1.0 as Float);
+        let z_min: Float = api_state.param_set.find_one_float("zmin", 0.0 as Float);
+        let z_max: Float = api_state.param_set.find_one_float("zmax", 1.0 as Float);
+        let phi_max: Float = api_state.param_set.find_one_float("phimax", 360.0 as Float);
+        let paraboloid = Arc::new(Shape::Prbld(Paraboloid::new(
+            obj_to_world,
+            world_to_obj,
+            api_state.graphics_state.reverse_orientation,
+            radius,
+            z_min,
+            z_max,
+            phi_max,
+        )));
+        let mtl: Option<Arc<Material>> = create_material(&api_state, bsdf_state);
+        shapes.push(paraboloid);
+        materials.push(mtl);
     } else if api_state.param_set.name == "hyperboloid" {
-        println!("TODO: CreateHyperboloidShape");
-    // } else if api_state.param_set.name == "curve" {
-    //     let mtl: Option<Arc<Material>> = create_material(&api_state, bsdf_state);
-    //     let curve_shapes: Vec<Arc<Shape>> = create_curve_shape(
-    //         &obj_to_world,
-    //         &world_to_obj,
-    //         false, // reverse_orientation
-    //         &api_state.param_set,
-    //     );
-    //     for shape in curve_shapes {
-    //         shapes.push(shape.clone());
-    //         materials.push(mtl.clone());
-    //     }
+        // CreateHyperboloidShape
+        let p1: Point3f = api_state
+            .param_set
+            .find_one_point3f("p1", Point3f::default());
+        let p2: Point3f = api_state.param_set.find_one_point3f(
+            "p2",
+            Point3f {
+                x: 1.0,
+                y: 1.0,
+                z: 1.0,
+            },
+        );
+        let phi_max: Float = api_state.param_set.find_one_float("phimax", 360.0 as Float);
+        let hyperboloid = Arc::new(Shape::Hprbld(Hyperboloid::new(
+            obj_to_world,
+            world_to_obj,
+            api_state.graphics_state.reverse_orientation,
+            p1,
+            p2,
+            phi_max,
+        )));
+        let mtl: Option<Arc<Material>> = create_material(&api_state, bsdf_state);
+        shapes.push(hyperboloid);
+        materials.push(mtl);
+    } else if api_state.param_set.name == "curve" {
+        // CreateCurveShape
+        let mtl: Option<Arc<Material>> = create_material(&api_state, bsdf_state);
+        let curve_shapes: Vec<Arc<Shape>> = create_curve_shape(
+            &obj_to_world,
+            &world_to_obj,
+            api_state.graphics_state.reverse_orientation,
+            &api_state.param_set,
+        );
+        for shape in curve_shapes {
+            shapes.push(shape.clone());
+            materials.push(mtl.clone());
+        }
     } else if api_state.param_set.name == "trianglemesh" {
         let vi = api_state.param_set.find_int("indices");
         let p = api_state.param_set.find_point3f("P");
@@ -1998,7 +2310,11 @@ fn get_shapes_and_materials(
                 );
             }
         }
-        // TODO: alpha
+        // look up an alpha texture, if applicable
+        let (alpha_tex, shadow_alpha_tex) = create_alpha_textures(
+            &api_state.param_set,
+            &api_state.graphics_state.float_textures,
+        );
         // CreateTriangleMesh
         // transform mesh vertices to world space
         let mut p_ws: Vec<Point3f> = Vec::new();
@@ -2022,8 +2338,8 @@ fn get_shapes_and_materials(
             s_ws, // in world space
             n_ws, // in world space
             uvs,
-            None,
-            None,
+            alpha_tex,
+            shadow_alpha_tex,
         ));
         let mtl: Option<Arc<Material>> = create_material(&api_state, bsdf_state);
         for id in 0..mesh.n_triangles {
@@ -2054,7 +2370,62 @@ fn get_shapes_and_materials(
             panic!("No search directory for plymesh.");
         }
     } else if api_state.param_set.name == "heightfield" {
-        println!("TODO: CreateHeightfield");
+        // CreateHeightfield
+        let nu: i32 = api_state.param_set.find_one_int("nu", -1);
+        let nv: i32 = api_state.param_set.find_one_int("nv", -1);
+        let pz: Vec<Float> = api_state.param_set.find_float("Pz");
+        let mode: String = api_state
+            .param_set
+            .find_one_string("mode", String::from("grid"));
+        if nu < 2 || nv < 2 {
+            println!(
+                "ERROR: Heightfield needs \"nu\" and \"nv\" of at least 2 (got {} and {})",
+                nu, nv
+            );
+        } else if pz.len() != (nu * nv) as usize {
+            println!(
+                "ERROR: Heightfield needs {} \"Pz\" values (got {})",
+                nu * nv,
+                pz.len()
+            );
+        } else {
+            let mtl: Option<Arc<Material>> = create_material(&api_state, bsdf_state);
+            if mode == "triangles" {
+                let mesh = create_heightfield_mesh(
+                    &obj_to_world,
+                    &world_to_obj,
+                    api_state.graphics_state.reverse_orientation,
+                    nu,
+                    nv,
+                    &pz,
+                );
+                for id in 0..mesh.n_triangles {
+                    let triangle = Arc::new(Shape::Trngl(Triangle::new(
+                        mesh.clone(),
+                        id.try_into().unwrap(),
+                    )));
+                    shapes.push(triangle.clone());
+                    materials.push(mtl.clone());
+                }
+            } else {
+                if mode != "grid" {
+                    println!(
+                        "WARNING: Heightfield mode \"{}\" unknown. Using \"grid\".",
+                        mode
+                    );
+                }
+                let heightfield = Arc::new(Shape::Hghtfld(Heightfield::new(
+                    obj_to_world,
+                    world_to_obj,
+                    api_state.graphics_state.reverse_orientation,
+                    nu,
+                    nv,
+                    pz,
+                )));
+                shapes.push(heightfield);
+                materials.push(mtl);
+            }
+        }
     } else if api_state.param_set.name == "loopsubdiv" {
         // CreateLoopSubdiv
         let n_levels: i32 = api_state
@@ -2396,10 +2767,10 @@ pub fn pbrt_init(
 }
 
 pub fn pbrt_cleanup(
-    api_state: &ApiState,
+    api_state: &mut ApiState,
     ecp_state: &EcpState,
     integrator_arg: &Option<String>,
-) -> Option<Vec<u8>> {
+) -> Result<Option<Vec<u8>>, TileJobError> {
     #[cfg(ecp)]
     let now = Instant::now();
     // assert!(
@@ -2411,30 +2782,311 @@ pub fn pbrt_cleanup(
     //     "Missing end to pbrtTransformBegin()"
     // );
 
+    if let Some(tile_job) = api_state.tile_job.take() {
+        api_state.render_options.apply_tile_job(&tile_job);
+        if let Some(pixelsamples) = tile_job.pixelsamples {
+            api_state.pixelsamples = pixelsamples;
+        }
+    }
+    if api_state.bvh_benchmark_rays > 0 {
+        run_accelerator_benchmark(
+            &api_state.render_options.primitives,
+            &api_state.render_options.accelerator_params,
+            api_state.bvh_benchmark_rays,
+        );
+        return Ok(None);
+    }
+    let scene = api_state.render_options.make_scene();
+    api_state.transform_cache.clear();
+    if api_state.snapshot_file != "" {
+        write_scene_snapshot(api_state, &scene);
+        return Ok(None);
+    }
+    let ret = pbrt_render(
+        &api_state.render_options,
+        api_state.pixelsamples,
+        api_state.number_of_threads,
+        &scene,
+        ecp_state,
+        integrator_arg,
+        api_state.dispatcher.as_ref(),
+    );
+    #[cfg(ecp)]
+    println!("pbrt_cleanup: {}", now.elapsed().as_millis());
+    ret
+}
+
+/// Render the tile of `tile_job` for a scene which was loaded from a
+/// [SceneSnapshot](../snapshot/struct.SceneSnapshot.html) instead of
+/// being parsed and built. The snapshot itself is left untouched, it
+/// might be shared by several tile jobs.
+pub fn pbrt_render_snapshot(
+    snapshot: &SceneSnapshot,
+    tile_job: &TileJob,
+    number_of_threads: u8,
+    ecp_state: &EcpState,
+) -> Result<Option<Vec<u8>>, TileJobError> {
+    let mut render_options: RenderOptions = snapshot.render_options.clone();
+    render_options.apply_tile_job(tile_job);
+    pbrt_render(
+        &render_options,
+        tile_job.pixelsamples.unwrap_or(snapshot.pixelsamples),
+        number_of_threads,
+        &snapshot.scene,
+        ecp_state,
+        &tile_job.integrator,
+        None,
+    )
+}
+
+fn pbrt_render(
+    render_options: &RenderOptions,
+    pixelsamples: u32,
+    num_threads: u8,
+    scene: &Scene,
+    ecp_state: &EcpState,
+    integrator_arg: &Option<String>,
+    dispatcher: Option<&Dispatcher>,
+) -> Result<Option<Vec<u8>>, TileJobError> {
     // MakeIntegrator
-    let some_integrator: Option<Box<Integrator>> = api_state
-        .render_options
-        .make_integrator(api_state.pixelsamples, integrator_arg);
+    let some_integrator: Option<Box<Integrator>> =
+        render_options.make_integrator(pixelsamples, integrator_arg);
+    let dispatcher: Option<Dispatcher> = dispatcher.map(|dispatcher| {
+        dispatcher.for_render(
+            ecp_state.tile_size,
+            &render_options.crop_window,
+            pixelsamples,
+            render_options.quick_render,
+            integrator_arg,
+        )
+    });
     if let Some(mut integrator) = some_integrator {
-        let scene = api_state.render_options.make_scene();
-        let num_threads: u8 = api_state.number_of_threads;
-        let ret = integrator.render(
-            &scene,
+        if !ecp_state.is_collector() {
+            check_tile(&integrator, ecp_state)?;
+        }
+        Ok(integrator.render(
+            scene,
             num_threads,
             ecp_state.is_collector(),
             ecp_state.tile_size,
             ecp_state.x,
             ecp_state.y,
             &ecp_state.filename,
-        );
-        #[cfg(ecp)]
-        println!("pbrt_cleanup: {}", now.elapsed().as_millis());
-        ret
+            &ecp_state.samples,
+            &render_options.get_adaptive_sampling(),
+            dispatcher.as_ref(),
+        ))
     } else {
-        panic!("Unable to create integrator.");
+        Err(TileJobError::new(422, "Unable to create integrator"))
+    }
+}
+
+/// Workers only render tiles of the grid a collector would split
+/// the (parsed) scene into.
+fn check_tile(integrator: &Integrator, ecp_state: &EcpState) -> Result<(), TileJobError> {
+    let (x, y) = match (ecp_state.x, ecp_state.y) {
+        (Some(x), Some(y)) => (x, y),
+        _ => return Ok(()),
+    };
+    match integrator.get_tile_count(ecp_state.tile_size) {
+        Some(n_tiles) if x < n_tiles.x as u32 && y < n_tiles.y as u32 => Ok(()),
+        Some(n_tiles) => Err(TileJobError::new(
+            422,
+            &format!(
+                "Tile ({}, {}) is outside of the {}x{} tiles of the scene",
+                x, y, n_tiles.x, n_tiles.y
+            ),
+        )),
+        None => Err(TileJobError::new(
+            422,
+            "The integrator of the scene can't render tiles",
+        )),
     }
 }
 
+fn write_scene_snapshot(api_state: &ApiState, scene: &Scene) {
+    match SceneSnapshot::to_bytes(api_state.pixelsamples, &api_state.render_options, scene) {
+        Ok(data) => {
+            println!(
+                "Writing scene snapshot {:?} ({} bytes)",
+                api_state.snapshot_file,
+                data.len()
+            );
+            if let Err(e) = std::fs::write(&api_state.snapshot_file, &data) {
+                println!(
+                    "ERROR: Unable to write scene snapshot {:?}: {}",
+                    api_state.snapshot_file, e
+                );
+            }
+        }
+        Err(e) => println!("ERROR: Unable to serialize scene: {}", e),
+    }
+}
+
+/// Random rays for comparing accelerators: they start on the
+/// bounding sphere of `bounds` and point at random positions inside
+/// of it.
+pub fn accelerator_test_rays(bounds: &Bounds3f, n_rays: usize) -> Vec<Ray> {
+    let mut center: Point3f = Point3f::default();
+    let mut radius: Float = 0.0;
+    Bounds3f::bounding_sphere(bounds, &mut center, &mut radius);
+    let mut rng: Rng = Rng::new();
+    rng.set_sequence(0_u64);
+    let mut rays: Vec<Ray> = Vec::with_capacity(n_rays);
+    for _ in 0..n_rays {
+        let u: Point2f = Point2f {
+            x: rng.uniform_float(),
+            y: rng.uniform_float(),
+        };
+        let o: Point3f = center + uniform_sample_sphere(u) * radius;
+        let target: Point3f = bounds.lerp(&Point3f {
+            x: rng.uniform_float(),
+            y: rng.uniform_float(),
+            z: rng.uniform_float(),
+        });
+        rays.push(Ray {
+            o,
+            d: (target - o).normalize(),
+            t_max: Cell::new(std::f32::INFINITY),
+            time: 0.0 as Float,
+            medium: None,
+            differential: None,
+        });
+    }
+    rays
+}
+
+/// Builds a [BVHAccel](../../accelerators/bvh/struct.BVHAccel.html)
+/// with the SAH and the HLBVH split method (binary, 4- and 8-wide)
+/// and a [KdTreeAccel](../../accelerators/kdtreeaccel/struct.KdTreeAccel.html)
+/// and prints build time, size, SAH cost, the time it takes to trace
+/// `n_rays` random rays through each of them and how many rays hit
+/// something else than with the first one.
+fn run_accelerator_benchmark(primitives: &[Arc<Primitive>], params: &ParamSet, n_rays: usize) {
+    let max_prims_in_node: usize = params.find_one_int("maxnodeprims", 4) as usize;
+    println!(
+        "Accelerator benchmark: {} primitives, {} rays",
+        primitives.len(),
+        n_rays
+    );
+    if primitives.is_empty() {
+        return;
+    }
+    let mut scene_bounds: Bounds3f = Bounds3f::default();
+    for prim in primitives {
+        scene_bounds = bnd3_union_bnd3f(&scene_bounds, &prim.world_bound());
+    }
+    let rays: Vec<Ray> = accelerator_test_rays(&scene_bounds, n_rays);
+    println!(
+        "{:>6} {:>5} {:>10} {:>9} {:>9} {:>12} {:>12} {:>6} {:>6}",
+        "split",
+        "width",
+        "build [ms]",
+        "nodes",
+        "SAH cost",
+        "isect [ms]",
+        "isect_p [ms]",
+        "hits",
+        "diffs"
+    );
+    let configs: [(&str, SplitMethod, usize); 7] = [
+        ("sah", SplitMethod::SAH, 2),
+        ("hlbvh", SplitMethod::HLBVH, 2),
+        ("sah", SplitMethod::SAH, 4),
+        ("hlbvh", SplitMethod::HLBVH, 4),
+        ("sah", SplitMethod::SAH, 8),
+        ("hlbvh", SplitMethod::HLBVH, 8),
+        // width 0 -> kd-tree
+        ("kdtree", SplitMethod::SAH, 0),
+    ];
+    // nearest hit of the first configuration for each ray
+    let mut reference: Vec<Option<Float>> = Vec::new();
+    for (name, split_method, width) in &configs {
+        let start = std::time::Instant::now();
+        let (accelerator, n_nodes, sah_cost): (Primitive, usize, Float) = if *width == 0 {
+            let kd_tree: KdTreeAccel = KdTreeAccel::new(primitives.to_vec(), 80, 1, 0.5, 1, -1);
+            let (n_nodes, sah_cost) = (kd_tree.n_nodes(), kd_tree.sah_cost());
+            (Primitive::KdTree(Box::new(kd_tree)), n_nodes, sah_cost)
+        } else {
+            let bvh: BVHAccel = BVHAccel::new(
+                primitives.to_vec(),
+                max_prims_in_node,
+                split_method.clone(),
+                *width,
+            );
+            let (n_nodes, sah_cost) = (bvh.n_nodes(), bvh.sah_cost());
+            (Primitive::BVH(Box::new(bvh)), n_nodes, sah_cost)
+        };
+        let build_time = start.elapsed();
+        let mut hits: usize = 0;
+        let mut diffs: usize = 0;
+        let mut t_hits: Vec<Option<Float>> = Vec::with_capacity(rays.len());
+        let start = std::time::Instant::now();
+        for ray in &rays {
+            let r: Ray = ray.clone();
+            let mut isect: SurfaceInteraction = SurfaceInteraction::default();
+            if accelerator.intersect(&r, &mut isect) {
+                hits += 1;
+                t_hits.push(Some(r.t_max.get()));
+            } else {
+                t_hits.push(None);
+            }
+        }
+        let intersect_time = start.elapsed();
+        let start = std::time::Instant::now();
+        let mut shadow_hits: Vec<bool> = Vec::with_capacity(rays.len());
+        for ray in &rays {
+            shadow_hits.push(accelerator.intersect_p(ray));
+        }
+        let intersect_p_time = start.elapsed();
+        if reference.is_empty() {
+            reference = t_hits.clone();
+        }
+        for i in 0..rays.len() {
+            let same: bool = match (reference[i], t_hits[i]) {
+                (Some(t0), Some(t1)) => (t0 - t1).abs() <= 1e-4 as Float * t0.max(1.0 as Float),
+                (None, None) => true,
+                _ => false,
+            };
+            if !same || shadow_hits[i] != t_hits[i].is_some() {
+                diffs += 1;
+            }
+        }
+        println!(
+            "{:>6} {:>5} {:>10.1} {:>9} {:>9.2} {:>12.1} {:>12.1} {:>6} {:>6}",
+            name,
+            if *width == 0 {
+                String::from("-")
+            } else {
+                width.to_string()
+            },
+            build_time.as_secs_f64() * 1000.0,
+            n_nodes,
+            sah_cost,
+            intersect_time.as_secs_f64() * 1000.0,
+            intersect_p_time.as_secs_f64() * 1000.0,
+            hits,
+            diffs
+        );
+        if let Primitive::KdTree(kd_tree) = &accelerator {
+            let stats: KdTreeStatistics = kd_tree.statistics();
+            println!(
+                "kdtree: {} leaves ({} empty), max depth {}, {:.2} primitives per leaf (max {}), {:.2} references per primitive",
+                stats.leaves,
+                stats.empty_leaves,
+                stats.max_depth,
+                stats.primitive_references as f64 / (stats.leaves - stats.empty_leaves).max(1) as f64,
+                stats.max_leaf_primitives,
+                stats.primitive_references as f64 / primitives.len() as f64
+            );
+        }
+    }
+    println!(
+        "Accelerator \"auto\" picks \"{}\"",
+        choose_accelerator(primitives)
+    );
+}
+
 pub fn pbrt_translate(api_state: &mut ApiState, dx: Float, dy: Float, dz: Float) {
     // println!("Translate {} {} {}", dx, dy, dz);
     let translate: Transform = Transform::translate(&Vector3f {
@@ -2587,7 +3239,7 @@ pub fn pbrt_active_transform_start_time(api_state: &mut ApiState) {
 }
 
 pub fn pbrt_transform_times(api_state: &mut ApiState, start: Float, end: Float) {
-    println!("TransformTimes {} {}", start, end);
+    // println!("TransformTimes {} {}", start, end);
     api_state.render_options.transform_start_time = start;
     api_state.render_options.transform_end_time = end;
 }
@@ -2700,23 +3352,9 @@ pub fn pbrt_world_begin(api_state: &mut ApiState) {
 
 pub fn pbrt_attribute_begin(api_state: &mut ApiState) {
     // println!("AttributeBegin");
-    let mut material_param_set: ParamSet = ParamSet::default();
-    material_param_set.copy_from(&api_state.graphics_state.material_params);
-    let mut area_light_param_set: ParamSet = ParamSet::default();
-    area_light_param_set.copy_from(&api_state.graphics_state.area_light_params);
-    api_state.pushed_graphics_states.push(GraphicsState {
-        current_inside_medium: api_state.graphics_state.current_inside_medium.clone(),
-        current_outside_medium: api_state.graphics_state.current_outside_medium.clone(),
-        float_textures: api_state.graphics_state.float_textures.clone(),
-        spectrum_textures: api_state.graphics_state.spectrum_textures.clone(),
-        material_params: material_param_set,
-        material: api_state.graphics_state.material.clone(),
-        named_materials: api_state.graphics_state.named_materials.clone(),
-        current_material: api_state.graphics_state.current_material.clone(),
-        area_light_params: area_light_param_set,
-        area_light: api_state.graphics_state.area_light.clone(),
-        reverse_orientation: api_state.graphics_state.reverse_orientation,
-    });
+    api_state
+        .pushed_graphics_states
+        .push(api_state.graphics_state.clone());
     api_state.pushed_transforms.push(TransformSet {
         t: [
             Transform {
@@ -2785,7 +3423,7 @@ pub fn pbrt_texture(api_state: &mut ApiState, params: ParamSet) {
     make_texture(api_state);
 }
 
-pub fn pbrt_material(api_state: &mut ApiState, params: ParamSet) {
+pub fn pbrt_material(api_state: &mut ApiState, bsdf_state: &mut BsdfState, params: ParamSet) {
     // println!("MakeMaterial \"{}\"", params.name);
     // print_params(&params);
     api_state.param_set = params;
@@ -2795,6 +3433,8 @@ pub fn pbrt_material(api_state: &mut ApiState, params: ParamSet) {
         .material_params
         .copy_from(&api_state.param_set);
     api_state.graphics_state.current_material = String::new();
+    api_state.graphics_state.material_instance = None;
+    api_state.graphics_state.material_instance = create_material(&api_state, bsdf_state);
 }
 
 pub fn pbrt_make_named_material(
@@ -2815,7 +3455,9 @@ pub fn pbrt_make_named_material(
         .material_params
         .copy_from(&api_state.param_set);
     api_state.graphics_state.current_material = String::new();
+    api_state.graphics_state.material_instance = None;
     let mtl: Option<Arc<Material>> = create_material(&api_state, bsdf_state);
+    api_state.graphics_state.material_instance = mtl.clone();
     if let Some(_named_material) = api_state
         .graphics_state
         .named_materials
@@ -2838,7 +3480,29 @@ pub fn pbrt_light_source(api_state: &mut ApiState, params: ParamSet) {
     // print_params(&params);
     api_state.param_set = params;
     let mi: MediumInterface = create_medium_interface(&api_state);
+    let n_lights: usize = api_state.render_options.lights.len();
     make_light(api_state, &mi);
+    let light_group: usize = api_state.graphics_state.light_group;
+    for light in api_state.render_options.lights.iter_mut().skip(n_lights) {
+        if let Some(light) = Arc::get_mut(light) {
+            light.set_light_group(light_group);
+        }
+    }
+}
+
+/// Lights (and area lights) created after `LightGroup "name"` within
+/// the current attribute block belong to that group, see the
+/// `"lightgroups"` AOV.
+pub fn pbrt_light_group(api_state: &mut ApiState, params: ParamSet) {
+    let light_groups: &mut Vec<String> = &mut api_state.render_options.light_groups;
+    api_state.graphics_state.light_group =
+        match light_groups.iter().position(|name| *name == params.name) {
+            Some(index) => index,
+            None => {
+                light_groups.push(params.name.clone());
+                light_groups.len() - 1
+            }
+        };
 }
 
 pub fn pbrt_area_light_source(api_state: &mut ApiState, params: ParamSet) {
@@ -2855,10 +3519,73 @@ pub fn pbrt_area_light_source(api_state: &mut ApiState, params: ParamSet) {
 pub fn pbrt_shape(api_state: &mut ApiState, bsdf_state: &mut BsdfState, params: ParamSet) {
     // println!("Shape \"{}\"", params.name);
     // print_params(&params);
+    if api_state.render_options.current_instance != ""
+        && api_state.graphics_state.area_light != String::new()
+    {
+        // emissive shapes are created per instance
+        let shape: InstanceAreaLightShape = InstanceAreaLightShape {
+            params,
+            graphics_state: api_state.graphics_state.clone(),
+            transform: api_state.cur_transform,
+        };
+        api_state
+            .render_options
+            .instance_area_lights
+            .entry(api_state.render_options.current_instance.clone())
+            .or_default()
+            .push(shape);
+        return;
+    }
     api_state.param_set = params;
+    let (prims, area_lights) = create_shape_primitives(api_state, bsdf_state);
+    // add _prims_ and _areaLights_ to scene or current instance
+    if api_state.render_options.current_instance != "" {
+        if let Some(instance_vec) = api_state
+            .render_options
+            .instances
+            .get_mut(&api_state.render_options.current_instance.clone())
+        {
+            for prim in prims {
+                instance_vec.push(prim.clone());
+            }
+        }
+    } else {
+        for prim in prims {
+            api_state.render_options.primitives.push(prim.clone());
+        }
+        if !area_lights.is_empty() {
+            for area_light in area_lights {
+                api_state.render_options.lights.push(area_light.clone());
+            }
+        }
+    }
+}
+
+/// The same ID for all primitives sharing a material (0 for none).
+fn get_material_id(api_state: &mut ApiState, material: &Option<Arc<Material>>) -> u32 {
+    match material {
+        Some(material) => {
+            let next_id: u32 = api_state.material_ids.len() as u32 + 1;
+            *api_state
+                .material_ids
+                .entry(Arc::as_ptr(material) as usize)
+                .or_insert(next_id)
+        }
+        None => 0_u32,
+    }
+}
+
+/// Creates the primitives (and area lights) for the shape described
+/// by `api_state.param_set` and the current graphics state.
+fn create_shape_primitives(
+    api_state: &mut ApiState,
+    bsdf_state: &mut BsdfState,
+) -> (Vec<Arc<Primitive>>, Vec<Arc<Light>>) {
     // collect area lights
     let mut prims: Vec<Arc<Primitive>> = Vec::new();
     let mut area_lights: Vec<Arc<Light>> = Vec::new();
+    api_state.object_count += 1;
+    let object_id: u32 = api_state.object_count;
     // possibly create area light for shape (see pbrtShape())
     if api_state.graphics_state.area_light != String::new() {
         // MakeAreaLight
@@ -2866,7 +3593,7 @@ pub fn pbrt_shape(api_state: &mut ApiState, bsdf_state: &mut BsdfState, params:
             || api_state.graphics_state.area_light == "diffuse"
         {
             // first create the shape
-            let (shapes, materials) = get_shapes_and_materials(&api_state, bsdf_state);
+            let (shapes, materials) = get_shapes_and_materials(api_state, bsdf_state);
             assert_eq!(shapes.len(), materials.len());
             // MediumInterface
             let mi: MediumInterface = create_medium_interface(&api_state);
@@ -2886,57 +3613,69 @@ pub fn pbrt_shape(api_state: &mut ApiState, bsdf_state: &mut BsdfState, params:
                 let n_samples: i32 = // try "nsamples" first
                     api_state.graphics_state.area_light_params.find_one_int("nsamples",
                                                                   1);
-                let n_samples: i32 = // try "samples"next
+                let mut n_samples: i32 = // try "samples"next
                     api_state.graphics_state.area_light_params.find_one_int("samples",
                                                                   n_samples);
                 let two_sided: bool = api_state
                     .graphics_state
                     .area_light_params
                     .find_one_bool("twosided", false);
-                // TODO: if (PbrtOptions.quickRender) nSamples = std::max(1, nSamples / 4);
+                if api_state.render_options.quick_render {
+                    n_samples = std::cmp::max(1, n_samples / 4);
+                }
                 let l_emit: Spectrum = l * sc;
+                let mut diffuse_area_light: DiffuseAreaLight = DiffuseAreaLight::new(
+                    &light_to_world,
+                    &mi,
+                    &l_emit,
+                    n_samples,
+                    shape.clone(),
+                    two_sided,
+                );
+                diffuse_area_light.light_group = api_state.graphics_state.light_group;
                 let area_light: Arc<Light> =
-                    Arc::new(Light::DiffuseArea(Box::new(DiffuseAreaLight::new(
-                        &light_to_world,
-                        &mi,
-                        &l_emit,
-                        n_samples,
-                        shape.clone(),
-                        two_sided,
-                    ))));
+                    Arc::new(Light::DiffuseArea(Box::new(diffuse_area_light)));
                 area_lights.push(area_light.clone());
-                let geo_prim = Arc::new(Primitive::Geometric(Box::new(GeometricPrimitive::new(
+                let mut geo_prim: GeometricPrimitive = GeometricPrimitive::new(
                     shape.clone(),
                     material.clone(),
                     Some(area_light.clone()),
                     Some(Arc::new(mi.clone())),
-                ))));
-                prims.push(geo_prim.clone());
+                );
+                geo_prim.object_id = object_id;
+                geo_prim.material_id = get_material_id(api_state, material);
+                prims.push(Arc::new(Primitive::Geometric(Box::new(geo_prim))));
             }
         }
     } else {
         // continue with shape itself
-        let (shapes, materials) = get_shapes_and_materials(&api_state, bsdf_state);
+        let (shapes, materials) = get_shapes_and_materials(api_state, bsdf_state);
         assert_eq!(shapes.len(), materials.len());
         // MediumInterface
         let mi: MediumInterface = create_medium_interface(&api_state);
         for i in 0..shapes.len() {
             let shape = &shapes[i];
             let material = &materials[i];
-            let geo_prim = Arc::new(Primitive::Geometric(Box::new(GeometricPrimitive::new(
+            let mut geo_prim: GeometricPrimitive = GeometricPrimitive::new(
                 shape.clone(),
                 material.clone(),
                 None,
                 Some(Arc::new(mi.clone())),
-            ))));
-            prims.push(geo_prim.clone());
+            );
+            geo_prim.object_id = object_id;
+            geo_prim.material_id = get_material_id(api_state, material);
+            prims.push(Arc::new(Primitive::Geometric(Box::new(geo_prim))));
         }
         // animated?
         if api_state.cur_transform.is_animated() {
+            let start_transform: Arc<Transform> =
+                api_state.transform_cache.get(&api_state.cur_transform.t[0]);
+            let end_transform: Arc<Transform> =
+                api_state.transform_cache.get(&api_state.cur_transform.t[1]);
             let animated_object_to_world: AnimatedTransform = AnimatedTransform::new(
-                &api_state.cur_transform.t[0],
+                &start_transform,
                 api_state.render_options.transform_start_time,
-                &api_state.cur_transform.t[1],
+                &end_transform,
                 api_state.render_options.transform_end_time,
             );
             if prims.len() > 1 {
@@ -2944,6 +3683,7 @@ pub fn pbrt_shape(api_state: &mut ApiState, bsdf_state: &mut BsdfState, params:
                     prims.clone(),
                     4,
                     SplitMethod::SAH,
+                    2,
                 ))));
                 prims.clear();
                 prims.push(bvh);
@@ -2956,30 +3696,7 @@ pub fn pbrt_shape(api_state: &mut ApiState, bsdf_state: &mut BsdfState, params:
             }
         }
     }
-    // add _prims_ and _areaLights_ to scene or current instance
-    if api_state.render_options.current_instance != "" {
-        if !area_lights.is_empty() {
-            println!("WARNING: Area lights not supported with object instancing");
-        }
-        if let Some(instance_vec) = api_state
-            .render_options
-            .instances
-            .get_mut(&api_state.render_options.current_instance.clone())
-        {
-            for prim in prims {
-                instance_vec.push(prim.clone());
-            }
-        }
-    } else {
-        for prim in prims {
-            api_state.render_options.primitives.push(prim.clone());
-        }
-        if !area_lights.is_empty() {
-            for area_light in area_lights {
-                api_state.render_options.lights.push(area_light.clone());
-            }
-        }
-    }
+    (prims, area_lights)
 }
 
 // Attempt to determine if the ParamSet for a shape may provide a value for
@@ -3072,6 +3789,10 @@ pub fn pbrt_object_begin(api_state: &mut ApiState, params: ParamSet) {
         .render_options
         .instances
         .insert(api_state.param_set.name.clone(), Vec::new());
+    api_state
+        .render_options
+        .instance_area_lights
+        .remove(&api_state.param_set.name);
     api_state.render_options.current_instance = api_state.param_set.name.clone();
 }
 
@@ -3084,7 +3805,19 @@ pub fn pbrt_object_end(api_state: &mut ApiState) {
     pbrt_attribute_end(api_state);
 }
 
-pub fn pbrt_object_instance(api_state: &mut ApiState, params: ParamSet) {
+/// Adds an instance of the named object to the scene. The object's
+/// non-emissive primitives are shared: their BVH is built on first
+/// use and each instance only adds a transformed reference to it to
+/// the scene's BVH. Emissive shapes are not shared, they are created
+/// again in world space for every instance (see
+/// [InstanceAreaLightShape](struct.InstanceAreaLightShape.html)),
+/// with their own mesh, primitives and one area light per triangle.
+/// Their memory grows with the number of instances.
+pub fn pbrt_object_instance(
+    api_state: &mut ApiState,
+    bsdf_state: &mut BsdfState,
+    params: ParamSet,
+) {
     // println!("ObjectInstance \"{}\"", params.name);
     api_state.param_set = params;
     // perform object instance error checking
@@ -3092,57 +3825,22 @@ pub fn pbrt_object_instance(api_state: &mut ApiState, params: ParamSet) {
         println!("ERROR: ObjectInstance can't be called inside instance definition");
         return;
     }
-    if let Some(instance_vec) = api_state
-        .render_options
-        .instances
-        .get_mut(&api_state.param_set.name.clone())
-    {
+    let name: String = api_state.param_set.name.clone();
+    if api_state.render_options.instances.contains_key(&name) {
+        create_instance_area_lights(api_state, bsdf_state, &name);
+    }
+    if let Some(instance_vec) = api_state.render_options.instances.get_mut(&name) {
         if instance_vec.is_empty() {
             return;
         }
         // TODO: ++nObjectInstancesUsed;
         if instance_vec.len() > 1_usize {
             // create aggregate for instance _Primitive_s
-            if api_state.render_options.accelerator_name == "bvh" {
-                //  CreateBVHAccelerator
-                let split_method_name: String = api_state
-                    .render_options
-                    .accelerator_params
-                    .find_one_string("splitmethod", String::from("sah"));
-                let split_method;
-                if split_method_name == "sah" {
-                    split_method = SplitMethod::SAH;
-                } else if split_method_name == "hlbvh" {
-                    split_method = SplitMethod::HLBVH;
-                } else if split_method_name == "middle" {
-                    split_method = SplitMethod::Middle;
-                } else if split_method_name == "equal" {
-                    split_method = SplitMethod::EqualCounts;
-                } else {
-                    println!(
-                        "WARNING: BVH split method \"{}\" unknown.  Using \"sah\".",
-                        split_method_name
-                    );
-                    split_method = SplitMethod::SAH;
-                }
-                let max_prims_in_node: i32 = api_state
-                    .render_options
-                    .accelerator_params
-                    .find_one_int("maxnodeprims", 4);
-                let accelerator: Arc<Primitive> =
-                    Arc::new(Primitive::BVH(Box::new(BVHAccel::new(
-                        instance_vec.clone(),
-                        max_prims_in_node as usize,
-                        split_method,
-                    ))));
-                instance_vec.clear();
-                instance_vec.push(accelerator);
-            } else if api_state.render_options.accelerator_name == "kdtree" {
-                // println!("TODO: CreateKdTreeAccelerator");
-                // WARNING: Use BVHAccel for now !!!
-                let accelerator: Arc<Primitive> = Arc::new(Primitive::BVH(Box::new(
-                    BVHAccel::new(instance_vec.clone(), 4, SplitMethod::SAH),
-                )));
+            if let Some(accelerator) = make_accelerator(
+                &api_state.render_options.accelerator_name,
+                instance_vec,
+                &api_state.render_options.accelerator_params,
+            ) {
                 instance_vec.clear();
                 instance_vec.push(accelerator);
             } else {
@@ -3153,10 +3851,14 @@ pub fn pbrt_object_instance(api_state: &mut ApiState, params: ParamSet) {
             }
         }
         // create _animatedInstanceToWorld_ transform for instance
+        let start_transform: Arc<Transform> =
+            api_state.transform_cache.get(&api_state.cur_transform.t[0]);
+        let end_transform: Arc<Transform> =
+            api_state.transform_cache.get(&api_state.cur_transform.t[1]);
         let animated_instance_to_world: AnimatedTransform = AnimatedTransform::new(
-            &api_state.cur_transform.t[0],
+            &start_transform,
             api_state.render_options.transform_start_time,
-            &api_state.cur_transform.t[1],
+            &end_transform,
             api_state.render_options.transform_end_time,
         );
         let prim: Arc<Primitive> = Arc::new(Primitive::Transformed(Box::new(
@@ -3170,3 +3872,35 @@ pub fn pbrt_object_instance(api_state: &mut ApiState, params: ParamSet) {
         );
     }
 }
+
+/// Creates the emissive shapes of the named object (see
+/// [InstanceAreaLightShape](struct.InstanceAreaLightShape.html)) in
+/// world space for the current instance transform.
+fn create_instance_area_lights(api_state: &mut ApiState, bsdf_state: &mut BsdfState, name: &str) {
+    let mut shapes: Vec<InstanceAreaLightShape> =
+        match api_state.render_options.instance_area_lights.remove(name) {
+            Some(shapes) => shapes,
+            None => return,
+        };
+    let instance_to_world: TransformSet = api_state.cur_transform;
+    let params: ParamSet = std::mem::take(&mut api_state.param_set);
+    for shape in shapes.iter_mut() {
+        pbrt_attribute_begin(api_state);
+        std::mem::swap(&mut api_state.graphics_state, &mut shape.graphics_state);
+        std::mem::swap(&mut api_state.param_set, &mut shape.params);
+        for i in 0..2 {
+            api_state.cur_transform.t[i] = instance_to_world.t[i] * shape.transform.t[i];
+        }
+        let (prims, area_lights) = create_shape_primitives(api_state, bsdf_state);
+        std::mem::swap(&mut api_state.param_set, &mut shape.params);
+        std::mem::swap(&mut api_state.graphics_state, &mut shape.graphics_state);
+        pbrt_attribute_end(api_state);
+        api_state.render_options.primitives.extend(prims);
+        api_state.render_options.lights.extend(area_lights);
+    }
+    api_state.param_set = params;
+    api_state
+        .render_options
+        .instance_area_lights
+        .insert(name.to_string(), shapes);
+}
@@ -0,0 +1,148 @@
+//! Images rendered with only a few samples per pixel are noisy. A
+//! joint (cross) bilateral filter averages neighbouring pixels which
+//! see similar surfaces, judged by the albedo, normal and depth AOVs
+//! of the film (see [Aov](../film/enum.Aov.html)), not by their noisy
+//! colors. The illumination (the color divided by the albedo) is
+//! filtered, so textures stay sharp.
+//!
+//! The denoiser is selected on the film and runs on the assembled
+//! float image before it is written:
+//!
+//! ```text
+//! Film "image" "string denoiser" [ "bilateral" ] "integer denoiseradius" [ 4 ]
+//! ```
+
+// pbrt
+use crate::core::paramset::ParamSet;
+use crate::core::pbrt::Float;
+
+/// The AOVs guiding the denoiser, normalized and row-major like the
+/// image.
+pub struct DenoiserGuides<'a> {
+    /// RGB albedo per pixel
+    pub albedo: &'a [Float],
+    /// Shading normal per pixel
+    pub normal: &'a [Float],
+    /// Distance to the camera per pixel (zero if nothing was hit)
+    pub depth: &'a [Float],
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct BilateralDenoiser {
+    /// Half of the width of the filter window (in pixels)
+    pub radius: i32,
+    pub sigma_spatial: Float,
+    pub sigma_normal: Float,
+    pub sigma_albedo: Float,
+    /// Relative to the depth of the farther pixel
+    pub sigma_depth: Float,
+}
+
+impl BilateralDenoiser {
+    pub fn new(radius: i32) -> Self {
+        BilateralDenoiser {
+            radius,
+            sigma_spatial: (radius as Float / 2.0 as Float).max(0.5 as Float),
+            sigma_normal: 0.1 as Float,
+            sigma_albedo: 0.05 as Float,
+            sigma_depth: 0.05 as Float,
+        }
+    }
+    /// The denoiser selected by the `"string denoiser"` film
+    /// parameter (**None** for `"none"`, the default).
+    pub fn create(params: &ParamSet) -> Option<BilateralDenoiser> {
+        let name: String = params.find_one_string("denoiser", String::from("none"));
+        match name.as_str() {
+            "none" => None,
+            "bilateral" => {
+                let radius: i32 = params.find_one_int("denoiseradius", 4);
+                if radius < 1 {
+                    println!(
+                        "WARNING: \"denoiseradius\" {} too small, not denoising.",
+                        radius
+                    );
+                    return None;
+                }
+                Some(BilateralDenoiser::new(radius))
+            }
+            _ => {
+                println!("WARNING: Denoiser {:?} unknown, not denoising.", name);
+                None
+            }
+        }
+    }
+    /// Filter the linear RGB values of a `width` x `height` image.
+    pub fn denoise(
+        &self,
+        rgb: &[Float],
+        guides: &DenoiserGuides,
+        width: usize,
+        height: usize,
+    ) -> Vec<Float> {
+        let n_pixels: usize = width * height;
+        assert_eq!(rgb.len(), 3 * n_pixels);
+        // demodulate the albedo (where there is one)
+        let demodulate = |i: usize| -> [Float; 3] {
+            let mut a: [Float; 3] = [1.0 as Float; 3];
+            for (c, item) in a.iter_mut().enumerate() {
+                if guides.albedo[3 * i + c] > 0.01 as Float {
+                    *item = guides.albedo[3 * i + c];
+                }
+            }
+            a
+        };
+        let mut illumination: Vec<Float> = vec![0.0 as Float; 3 * n_pixels];
+        for i in 0..n_pixels {
+            let a: [Float; 3] = demodulate(i);
+            for c in 0..3 {
+                illumination[3 * i + c] = rgb[3 * i + c] / a[c];
+            }
+        }
+        let inv_spatial: Float = 1.0 as Float / (2.0 as Float * self.sigma_spatial.powi(2));
+        let inv_normal: Float = 1.0 as Float / (2.0 as Float * self.sigma_normal.powi(2));
+        let inv_albedo: Float = 1.0 as Float / (2.0 as Float * self.sigma_albedo.powi(2));
+        let inv_depth: Float = 1.0 as Float / (2.0 as Float * self.sigma_depth.powi(2));
+        let distance_squared = |v: &[Float], i: usize, j: usize| -> Float {
+            (0..3).map(|c| (v[3 * i + c] - v[3 * j + c]).powi(2)).sum()
+        };
+        let mut output: Vec<Float> = vec![0.0 as Float; 3 * n_pixels];
+        for y in 0..height as i32 {
+            for x in 0..width as i32 {
+                let i: usize = y as usize * width + x as usize;
+                let mut sum: [Float; 3] = [0.0 as Float; 3];
+                let mut weight_sum: Float = 0.0 as Float;
+                let y0: i32 = std::cmp::max(0, y - self.radius);
+                let y1: i32 = std::cmp::min(height as i32 - 1, y + self.radius);
+                let x0: i32 = std::cmp::max(0, x - self.radius);
+                let x1: i32 = std::cmp::min(width as i32 - 1, x + self.radius);
+                for qy in y0..=y1 {
+                    for qx in x0..=x1 {
+                        let j: usize = qy as usize * width + qx as usize;
+                        let spatial: Float = ((qx - x).pow(2) + (qy - y).pow(2)) as Float;
+                        let depth: Float = guides.depth[i].max(guides.depth[j]);
+                        let relative_depth: Float = if depth > 0.0 as Float {
+                            (guides.depth[i] - guides.depth[j]) / depth
+                        } else {
+                            0.0 as Float
+                        };
+                        let weight: Float = (-spatial * inv_spatial
+                            - distance_squared(guides.normal, i, j) * inv_normal
+                            - distance_squared(guides.albedo, i, j) * inv_albedo
+                            - relative_depth * relative_depth * inv_depth)
+                            .exp();
+                        for (c, item) in sum.iter_mut().enumerate() {
+                            *item += weight * illumination[3 * j + c];
+                        }
+                        weight_sum += weight;
+                    }
+                }
+                // the center pixel has weight 1, so weight_sum >= 1
+                let a: [Float; 3] = demodulate(i);
+                for c in 0..3 {
+                    output[3 * i + c] = sum[c] / weight_sum * a[c];
+                }
+            }
+        }
+        output
+    }
+}
@@ -10,36 +10,407 @@
 //!
 
 // std
-use std::ops::{DerefMut, Index};
-use std::path::Path;
+#[cfg(not(feature = "openexr"))]
+use std::ffi::OsStr;
+use std::io::{Cursor, Read};
+use std::ops::DerefMut;
+#[cfg(feature = "openexr")]
+use std::ops::Index;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock, RwLockWriteGuard};
 
 // others
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 #[cfg(feature = "openexr")]
 use openexr::{FrameBuffer, Header, PixelType, ScanlineOutputFile};
 use smallvec::SmallVec;
 // pbrt
+use crate::core::denoiser::{BilateralDenoiser, DenoiserGuides};
 use crate::core::filter::Filter;
 use crate::core::geometry::{
-    bnd2_intersect_bnd2i, pnt2_ceil, pnt2_floor, pnt2_inside_exclusivei, pnt2_max_pnt2i,
-    pnt2_min_pnt2i,
+    bnd2_intersect_bnd2i, bnd2_union_bnd2i, pnt2_ceil, pnt2_floor, pnt2_inside_exclusivei,
+    pnt2_max_pnt2i, pnt2_min_pnt2i,
 };
-use crate::core::geometry::{Bounds2f, Bounds2i, Point2f, Point2i, Vector2f};
+use crate::core::geometry::{pnt3_distancef, vec3_abs_dot_nrmf};
+use crate::core::geometry::{Bounds2f, Bounds2i, Normal3f, Point2f, Point2i, Point3f};
+use crate::core::geometry::{Vector2f, Vector2i, Vector3f};
+use crate::core::interaction::SurfaceInteraction;
 use crate::core::paramset::ParamSet;
 use crate::core::pbrt::{clamp_t, gamma_correct};
 use crate::core::pbrt::{Float, Spectrum};
+use crate::core::reflection::BxdfType;
 use crate::core::spectrum::xyz_to_rgb;
 
 // see film.h
 
 const FILTER_TABLE_WIDTH: usize = 16;
 
+type FilterOffsets = SmallVec<[usize; 16]>;
+
+lazy_static::lazy_static! {
+    static ref COLLECTOR_FILM: RwLock<Option<Arc<Film>>> = RwLock::new(None);
+}
+
+/// Running mean and variance of the luminance of the samples taken
+/// for a single pixel (Welford's algorithm). Estimators of different
+/// tiles or passes can be merged.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct VarianceEstimator {
+    n: i64,
+    mean: Float,
+    m2: Float,
+}
+
+impl VarianceEstimator {
+    pub fn add(&mut self, x: Float) {
+        self.n += 1;
+        let delta: Float = x - self.mean;
+        self.mean += delta / self.n as Float;
+        self.m2 += delta * (x - self.mean);
+    }
+    pub fn merge(&self, other: &VarianceEstimator) -> VarianceEstimator {
+        if self.n == 0 {
+            return *other;
+        }
+        if other.n == 0 {
+            return *self;
+        }
+        let n: i64 = self.n + other.n;
+        let delta: Float = other.mean - self.mean;
+        VarianceEstimator {
+            n,
+            mean: self.mean + delta * other.n as Float / n as Float,
+            m2: self.m2
+                + other.m2
+                + delta * delta * (self.n as Float * other.n as Float) / n as Float,
+        }
+    }
+    pub fn count(&self) -> i64 {
+        self.n
+    }
+    pub fn mean(&self) -> Float {
+        self.mean
+    }
+    pub fn variance(&self) -> Float {
+        if self.n > 1 {
+            self.m2 / (self.n - 1) as Float
+        } else {
+            0.0 as Float
+        }
+    }
+    /// Standard error of the mean relative to the mean (zero for a
+    /// pixel which is black in all samples).
+    pub fn relative_error(&self) -> Float {
+        if self.n == 0 {
+            return std::f32::INFINITY;
+        }
+        let error: Float = (self.variance() / self.n as Float).sqrt();
+        if error == 0.0 as Float {
+            0.0 as Float
+        } else if self.mean > 0.0 as Float {
+            error / self.mean
+        } else {
+            std::f32::INFINITY
+        }
+    }
+}
+
+/// Arbitrary output variables (AOVs) a film can store in addition to
+/// the image, see [AovLayout](struct.AovLayout.html).
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Aov {
+    /// Directional-hemispherical reflectance of the first hit (RGB)
+    Albedo,
+    /// Shading normal of the first hit (world space)
+    Normal,
+    /// Distance from the camera to the first hit
+    Depth,
+    /// Position of the first hit (world space)
+    Position,
+    /// ID of the **Shape** statement which created the first hit
+    ObjectId,
+    /// ID of the material of the first hit
+    MaterialId,
+    /// Radiance after at most one scattering event (RGB)
+    Direct,
+    /// Radiance after two or more scattering events (RGB)
+    Indirect,
+    /// Radiance per light group (RGB for each `LightGroup`)
+    LightGroups,
+}
+
+impl Aov {
+    pub fn from_name(name: &str) -> Option<Aov> {
+        match name {
+            "albedo" => Some(Aov::Albedo),
+            "normal" => Some(Aov::Normal),
+            "depth" => Some(Aov::Depth),
+            "position" => Some(Aov::Position),
+            "objectid" => Some(Aov::ObjectId),
+            "materialid" => Some(Aov::MaterialId),
+            "direct" => Some(Aov::Direct),
+            "indirect" => Some(Aov::Indirect),
+            "lightgroups" => Some(Aov::LightGroups),
+            _ => None,
+        }
+    }
+    /// The name used in the `"string aovs"` film parameter.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Depth => "depth",
+            Aov::Position => "position",
+            Aov::ObjectId => "objectid",
+            Aov::MaterialId => "materialid",
+            Aov::Direct => "direct",
+            Aov::Indirect => "indirect",
+            Aov::LightGroups => "lightgroups",
+        }
+    }
+    /// Radiance AOVs are scaled like the image.
+    pub fn is_radiance(&self) -> bool {
+        matches!(self, Aov::Direct | Aov::Indirect | Aov::LightGroups)
+    }
+    /// IDs can't be filtered, the ID of the sample closest to the
+    /// pixel center wins.
+    pub fn is_id(&self) -> bool {
+        matches!(self, Aov::ObjectId | Aov::MaterialId)
+    }
+}
+
+/// Where the AOVs are found within the floats stored per pixel. All
+/// values are sums weighted like the image samples (and normalized by
+/// the filter weight sum on output), except for IDs, which are stored
+/// as (squared distance of the sample to the pixel center, ID).
+#[derive(Debug, Clone)]
+pub struct AovLayout {
+    pub aovs: Vec<Aov>,
+    pub light_groups: Vec<String>,
+    offsets: Vec<usize>,
+    /// The number of floats per pixel
+    pub stride: usize,
+}
+
+impl AovLayout {
+    pub fn new(aovs: Vec<Aov>, light_groups: &[String]) -> Self {
+        let mut offsets: Vec<usize> = Vec::with_capacity(aovs.len());
+        let mut stride: usize = 0;
+        for aov in &aovs {
+            offsets.push(stride);
+            stride += match aov {
+                Aov::Depth => 1,
+                Aov::ObjectId | Aov::MaterialId => 2,
+                Aov::LightGroups => 3 * light_groups.len(),
+                _ => 3,
+            };
+        }
+        AovLayout {
+            aovs,
+            light_groups: light_groups.to_vec(),
+            offsets,
+            stride,
+        }
+    }
+    pub fn offset(&self, aov: Aov) -> Option<usize> {
+        self.aovs
+            .iter()
+            .position(|a| *a == aov)
+            .map(|i| self.offsets[i])
+    }
+    /// The floats of `n_pixels` pixels without any samples.
+    fn new_pixels(&self, n_pixels: usize) -> Vec<Float> {
+        let mut values: Vec<Float> = vec![0.0 as Float; n_pixels * self.stride];
+        for (aov, offset) in self.aovs.iter().zip(self.offsets.iter()) {
+            if aov.is_id() {
+                for pixel in values.chunks_mut(self.stride) {
+                    pixel[*offset] = std::f32::INFINITY;
+                }
+            }
+        }
+        values
+    }
+    fn add_sample(
+        &self,
+        values: &mut [Float],
+        sample: &AovSample,
+        weight: Float,
+        distance_squared: Float,
+    ) {
+        for (aov, offset) in self.aovs.iter().zip(self.offsets.iter()) {
+            let v: &mut [Float] = &mut values[*offset..];
+            match aov {
+                Aov::Albedo => add_weighted_rgb(v, &sample.albedo, weight),
+                Aov::Normal => {
+                    v[0] += sample.normal.x * weight;
+                    v[1] += sample.normal.y * weight;
+                    v[2] += sample.normal.z * weight;
+                }
+                Aov::Depth => v[0] += sample.depth * weight,
+                Aov::Position => {
+                    v[0] += sample.position.x * weight;
+                    v[1] += sample.position.y * weight;
+                    v[2] += sample.position.z * weight;
+                }
+                Aov::ObjectId | Aov::MaterialId => {
+                    if distance_squared < v[0] {
+                        v[0] = distance_squared;
+                        v[1] = if *aov == Aov::ObjectId {
+                            sample.object_id as Float
+                        } else {
+                            sample.material_id as Float
+                        };
+                    }
+                }
+                Aov::Direct => add_weighted_rgb(v, &sample.direct, weight),
+                Aov::Indirect => add_weighted_rgb(v, &sample.indirect, weight),
+                Aov::LightGroups => {
+                    for (i, l) in sample.light_groups.iter().enumerate() {
+                        add_weighted_rgb(&mut v[3 * i..], l, weight);
+                    }
+                }
+            }
+        }
+    }
+    /// Add the values of another tile (or payload) for the same pixel.
+    fn merge(&self, values: &mut [Float], other: &[Float]) {
+        for (i, aov) in self.aovs.iter().enumerate() {
+            let start: usize = self.offsets[i];
+            let end: usize = self.offsets.get(i + 1).copied().unwrap_or(self.stride);
+            if aov.is_id() {
+                if other[start] < values[start] {
+                    values[start] = other[start];
+                    values[start + 1] = other[start + 1];
+                }
+            } else {
+                for (v, o) in values[start..end].iter_mut().zip(other[start..end].iter()) {
+                    *v += o;
+                }
+            }
+        }
+    }
+}
+
+fn add_weighted_rgb(values: &mut [Float], l: &Spectrum, weight: Float) {
+    let mut rgb: [Float; 3] = [0.0 as Float; 3];
+    l.to_rgb(&mut rgb);
+    for c in 0..3 {
+        values[c] += rgb[c] * weight;
+    }
+}
+
+/// The AOVs of a single camera ray. The integrators fill in the first
+/// hit with [set_first_hit()](#method.set_first_hit) and every
+/// contribution to the radiance with
+/// [add_radiance()](#method.add_radiance).
+#[derive(Debug, Default, Clone)]
+pub struct AovSample {
+    pub albedo: Spectrum,
+    pub normal: Normal3f,
+    pub depth: Float,
+    pub position: Point3f,
+    pub object_id: u32,
+    pub material_id: u32,
+    pub direct: Spectrum,
+    pub indirect: Spectrum,
+    /// radiance per light group (see **RenderOptions::light_groups**)
+    pub light_groups: Vec<Spectrum>,
+}
+
+impl AovSample {
+    pub fn new(n_light_groups: usize) -> Self {
+        AovSample {
+            light_groups: vec![Spectrum::default(); n_light_groups],
+            ..Default::default()
+        }
+    }
+    /// Forget everything about the previous camera ray.
+    pub fn clear(&mut self) {
+        self.albedo = Spectrum::default();
+        self.normal = Normal3f::default();
+        self.depth = 0.0 as Float;
+        self.position = Point3f::default();
+        self.object_id = 0_u32;
+        self.material_id = 0_u32;
+        self.clear_radiance();
+    }
+    /// Forget the radiance (but not the first hit), e.g. if the
+    /// radiance returned for the camera ray was discarded.
+    pub fn clear_radiance(&mut self) {
+        self.direct = Spectrum::default();
+        self.indirect = Spectrum::default();
+        for l in self.light_groups.iter_mut() {
+            *l = Spectrum::default();
+        }
+    }
+    /// Store the first surface seen from the camera (at `origin`),
+    /// the scattering functions of `isect` have to be computed
+    /// already for the albedo.
+    pub fn set_first_hit(&mut self, origin: &Point3f, isect: &SurfaceInteraction) {
+        self.normal = isect.shading.n;
+        self.depth = pnt3_distancef(origin, &isect.common.p);
+        self.position = isect.common.p;
+        if let Some(primitive_raw) = isect.primitive {
+            let primitive = unsafe { &*primitive_raw };
+            let (object_id, material_id) = primitive.get_ids();
+            self.object_id = object_id;
+            self.material_id = material_id;
+        }
+        if let Some(ref bsdf) = isect.bsdf {
+            // estimate the reflectance with a few stratified samples
+            let mut albedo: Spectrum = Spectrum::default();
+            for (i, j) in &[(0, 0), (0, 1), (1, 0), (1, 1)] {
+                let u: Point2f = Point2f {
+                    x: (*i as Float + 0.5) / 2.0 as Float,
+                    y: (*j as Float + 0.5) / 2.0 as Float,
+                };
+                let mut wi: Vector3f = Vector3f::default();
+                let mut pdf: Float = 0.0 as Float;
+                let mut sampled_type: u8 = 0_u8;
+                let f: Spectrum = bsdf.sample_f(
+                    &isect.common.wo,
+                    &mut wi,
+                    &u,
+                    &mut pdf,
+                    BxdfType::BsdfAll as u8,
+                    &mut sampled_type,
+                );
+                if pdf > 0.0 as Float {
+                    albedo += f * vec3_abs_dot_nrmf(&wi, &isect.shading.n) / pdf;
+                }
+            }
+            self.albedo = albedo / 4.0 as Float;
+        }
+    }
+    /// Account for radiance `l` which was scattered `depth` times on
+    /// its way from a light of `light_group` to the camera (0 for
+    /// light seen directly).
+    pub fn add_radiance(&mut self, l: &Spectrum, depth: u32, light_group: usize) {
+        if depth <= 1 {
+            self.direct += *l;
+        } else {
+            self.indirect += *l;
+        }
+        if let Some(group) = self.light_groups.get_mut(light_group) {
+            *group += *l;
+        }
+    }
+    fn scale_radiance(&mut self, scale: Float) {
+        self.direct *= Spectrum::new(scale);
+        self.indirect *= Spectrum::new(scale);
+        for l in self.light_groups.iter_mut() {
+            *l *= Spectrum::new(scale);
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Pixel {
     xyz: [Float; 3],
     filter_weight_sum: Float,
     splat_xyz: [Float; 3],
     pad: Float,
+    variance: VarianceEstimator,
 }
 
 impl Default for Pixel {
@@ -49,6 +420,7 @@ impl Default for Pixel {
             filter_weight_sum: 0.0 as Float,
             splat_xyz: [Float::default(), Float::default(), Float::default()],
             pad: 0.0 as Float,
+            variance: VarianceEstimator::default(),
         }
     }
 }
@@ -57,6 +429,8 @@ impl Default for Pixel {
 pub struct FilmTilePixel {
     contrib_sum: Spectrum,
     filter_weight_sum: Float,
+    /// samples taken for this pixel (not its neighbours)
+    variance: VarianceEstimator,
 }
 
 pub struct FilmTile<'a> {
@@ -67,6 +441,8 @@ pub struct FilmTile<'a> {
     filter_table_size: usize,
     pixels: Vec<FilmTilePixel>,
     max_sample_luminance: Float,
+    aov_layout: Option<&'a AovLayout>,
+    aov_pixels: Vec<Float>,
 }
 
 impl<'a> FilmTile<'a> {
@@ -76,7 +452,9 @@ impl<'a> FilmTile<'a> {
         filter_table: &'a [Float; FILTER_TABLE_WIDTH * FILTER_TABLE_WIDTH],
         filter_table_size: usize,
         max_sample_luminance: Float,
+        aov_layout: Option<&'a AovLayout>,
     ) -> Self {
+        let n_pixels: usize = std::cmp::max(0, pixel_bounds.area()) as usize;
         FilmTile {
             pixel_bounds,
             filter_radius,
@@ -89,6 +467,11 @@ impl<'a> FilmTile<'a> {
             // TODO: pixels = std::vector<FilmTilePixel>(std::max(0, pixelBounds.Area()));
             pixels: vec![FilmTilePixel::default(); pixel_bounds.area() as usize],
             max_sample_luminance,
+            aov_layout,
+            aov_pixels: match aov_layout {
+                Some(layout) => layout.new_pixels(n_pixels),
+                None => Vec::new(),
+            },
         }
     }
     pub fn add_sample(&mut self, p_film: Point2f, l: &mut Spectrum, sample_weight: Float) {
@@ -97,6 +480,74 @@ impl<'a> FilmTile<'a> {
             *l *= Spectrum::new(self.max_sample_luminance / l.y());
         }
         // compute sample's raster bounds
+        let (p0, p1, ifx, ify) = self.get_filter_support(p_film);
+        // track the variance of the pixel the sample was taken for
+        let pixel: Point2i = Point2i {
+            x: p_film.x.floor() as i32,
+            y: p_film.y.floor() as i32,
+        };
+        if pnt2_inside_exclusivei(pixel, &self.pixel_bounds) {
+            let idx = self.get_pixel_index(pixel.x, pixel.y);
+            self.pixels[idx].variance.add(l.y() * sample_weight);
+        }
+
+        // loop over filter support and add sample to pixel arrays
+        for y in p0.y..p1.y {
+            for x in p0.x..p1.x {
+                // evaluate filter value at $(x,y)$ pixel
+                let offset: usize =
+                    ify[(y - p0.y) as usize] * self.filter_table_size + ifx[(x - p0.x) as usize];
+                let filter_weight: Float = self.filter_table[offset];
+                // update pixel values with filtered sample contribution
+                let idx = self.get_pixel_index(x, y);
+                let pixel = &mut self.pixels[idx];
+                pixel.contrib_sum +=
+                    *l * Spectrum::new(sample_weight) * Spectrum::new(filter_weight);
+                pixel.filter_weight_sum += filter_weight;
+            }
+        }
+    }
+    /// Add the AOVs of a camera ray to the pixels around `p_film`
+    /// (ignored if the film doesn't store any).
+    pub fn add_aov_sample(&mut self, p_film: Point2f, aov: &AovSample, sample_weight: Float) {
+        let layout: &AovLayout = match self.aov_layout {
+            Some(layout) => layout,
+            None => return,
+        };
+        // clamp the radiance like the image sample
+        let l: Spectrum = aov.direct + aov.indirect;
+        let mut clamped: Option<AovSample> = None;
+        if l.y() > self.max_sample_luminance {
+            let mut aov: AovSample = aov.clone();
+            aov.scale_radiance(self.max_sample_luminance / l.y());
+            clamped = Some(aov);
+        }
+        let aov: &AovSample = clamped.as_ref().unwrap_or(aov);
+        let (p0, p1, ifx, ify) = self.get_filter_support(p_film);
+        for y in p0.y..p1.y {
+            for x in p0.x..p1.x {
+                let offset: usize =
+                    ify[(y - p0.y) as usize] * self.filter_table_size + ifx[(x - p0.x) as usize];
+                let filter_weight: Float = self.filter_table[offset];
+                // distance to the pixel center (for IDs)
+                let dx: Float = p_film.x - (x as Float + 0.5);
+                let dy: Float = p_film.y - (y as Float + 0.5);
+                let idx = self.get_pixel_index(x, y);
+                layout.add_sample(
+                    &mut self.aov_pixels[idx * layout.stride..(idx + 1) * layout.stride],
+                    aov,
+                    sample_weight * filter_weight,
+                    dx * dx + dy * dy,
+                );
+            }
+        }
+    }
+    /// The pixels a sample at `p_film` contributes to and the filter
+    /// table offsets for their columns and rows.
+    fn get_filter_support(
+        &self,
+        p_film: Point2f,
+    ) -> (Point2i, Point2i, FilterOffsets, FilterOffsets) {
         let p_film_discrete: Point2f = p_film - Vector2f { x: 0.5, y: 0.5 };
         let p0f: Point2f = pnt2_ceil(p_film_discrete - self.filter_radius);
         let mut p0: Point2i = Point2i {
@@ -110,11 +561,8 @@ impl<'a> FilmTile<'a> {
         };
         p0 = pnt2_max_pnt2i(p0, self.pixel_bounds.p_min);
         p1 = pnt2_min_pnt2i(p1, self.pixel_bounds.p_max);
-
-        // loop over filter support and add sample to pixel arrays
-
         // precompute $x$ and $y$ filter table offsets
-        let mut ifx: SmallVec<[usize; 16]> = SmallVec::with_capacity(p1.x as usize - p0.x as usize);
+        let mut ifx: FilterOffsets = SmallVec::with_capacity(p1.x as usize - p0.x as usize);
         for x in p0.x..p1.x {
             let fx: Float = ((x as Float - p_film_discrete.x)
                 * self.inv_filter_radius.x
@@ -122,7 +570,7 @@ impl<'a> FilmTile<'a> {
                 .abs();
             ifx.push(fx.floor().min(self.filter_table_size as Float - 1.0) as usize);
         }
-        let mut ify: SmallVec<[usize; 16]> = SmallVec::with_capacity(p1.y as usize - p0.y as usize);
+        let mut ify: FilterOffsets = SmallVec::with_capacity(p1.y as usize - p0.y as usize);
         for y in p0.y..p1.y {
             let fy: Float = ((y as Float - p_film_discrete.y)
                 * self.inv_filter_radius.y
@@ -130,19 +578,15 @@ impl<'a> FilmTile<'a> {
                 .abs();
             ify.push(fy.floor().min(self.filter_table_size as Float - 1.0) as usize);
         }
-        for y in p0.y..p1.y {
-            for x in p0.x..p1.x {
-                // evaluate filter value at $(x,y)$ pixel
-                let offset: usize =
-                    ify[(y - p0.y) as usize] * self.filter_table_size + ifx[(x - p0.x) as usize];
-                let filter_weight: Float = self.filter_table[offset];
-                // update pixel values with filtered sample contribution
-                let idx = self.get_pixel_index(x, y);
-                let pixel = &mut self.pixels[idx];
-                pixel.contrib_sum +=
-                    *l * Spectrum::new(sample_weight) * Spectrum::new(filter_weight);
-                pixel.filter_weight_sum += filter_weight;
-            }
+        (p0, p1, ifx, ify)
+    }
+    /// Luminance statistics of the samples taken so far for `pixel`
+    /// (**None** outside of the tile).
+    pub fn get_pixel_variance(&self, pixel: Point2i) -> Option<VarianceEstimator> {
+        if pnt2_inside_exclusivei(pixel, &self.pixel_bounds) {
+            Some(self.pixels[self.get_pixel_index(pixel.x, pixel.y)].variance)
+        } else {
+            None
         }
     }
     fn get_pixel_index(&self, x: i32, y: i32) -> usize {
@@ -152,6 +596,166 @@ impl<'a> FilmTile<'a> {
     }
 }
 
+/// Identifies a [TilePayload](struct.TilePayload.html) encoded by
+/// [to_bytes()](struct.TilePayload.html#method.to_bytes).
+pub const TILE_PAYLOAD_MAGIC: [u8; 4] = *b"PBRT";
+/// Increase whenever the binary layout of a tile payload changes.
+pub const TILE_PAYLOAD_VERSION: u32 = 2;
+
+/// The HDR result a worker sends back for a (padded) film tile. The
+/// values are linear and neither normalized, clamped nor scaled, so
+/// the collector can add the payloads of neighbouring tiles (or of
+/// several passes over the same tile) and tonemap the image once.
+///
+/// Binary layout (little-endian):
+///
+/// * magic `b"PBRT"` and `u32` version
+/// * pixel bounds (4 x `i32`), samples per pixel (`u32`)
+/// * integrator name (`u32` length followed by UTF-8 bytes)
+/// * splat bounds (4 x `i32`), AOV floats per pixel (`u32`)
+/// * XYZ and filter weight sum (4 x `f32`) per pixel in pixel bounds
+/// * splat XYZ (3 x `f32`) per pixel in splat bounds
+/// * AOVs (see [AovLayout](struct.AovLayout.html)) per pixel in pixel
+///   bounds
+#[derive(Debug, Default, Clone)]
+pub struct TilePayload {
+    /// The pixels covered by the film tile (including the filter extent)
+    pub pixel_bounds: Bounds2i,
+    /// The number of samples the worker took per pixel
+    pub samples_per_pixel: u32,
+    /// The name of the integrator which rendered the tile
+    pub integrator: String,
+    /// XYZ sums and filter weight sum for each pixel (row-major)
+    pub pixels: Vec<[Float; 4]>,
+    /// The pixels with splatted contributions (can be empty)
+    pub splat_bounds: Bounds2i,
+    /// Splatted XYZ sums for each pixel within the splat bounds
+    pub splat_xyz: Vec<[Float; 3]>,
+    /// The number of AOV floats per pixel (0 without AOVs)
+    pub aov_stride: u32,
+    /// AOV floats for each pixel (row-major)
+    pub aovs: Vec<Float>,
+}
+
+impl TilePayload {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let name: &[u8] = self.integrator.as_bytes();
+        let header: usize = 4 + 4 + 16 + 4 + 4 + name.len() + 16 + 4;
+        let mut data: Vec<u8> = Vec::with_capacity(
+            header + self.pixels.len() * 16 + self.splat_xyz.len() * 12 + self.aovs.len() * 4,
+        );
+        data.extend_from_slice(&TILE_PAYLOAD_MAGIC);
+        data.write_u32::<LittleEndian>(TILE_PAYLOAD_VERSION)
+            .unwrap();
+        write_bounds(&mut data, &self.pixel_bounds);
+        data.write_u32::<LittleEndian>(self.samples_per_pixel)
+            .unwrap();
+        data.write_u32::<LittleEndian>(name.len() as u32).unwrap();
+        data.extend_from_slice(name);
+        write_bounds(&mut data, &self.splat_bounds);
+        data.write_u32::<LittleEndian>(self.aov_stride).unwrap();
+        for pixel in &self.pixels {
+            for item in pixel {
+                data.write_f32::<LittleEndian>(*item).unwrap();
+            }
+        }
+        for splat in &self.splat_xyz {
+            for item in splat {
+                data.write_f32::<LittleEndian>(*item).unwrap();
+            }
+        }
+        for item in &self.aovs {
+            data.write_f32::<LittleEndian>(*item).unwrap();
+        }
+        data
+    }
+    pub fn from_bytes(data: &[u8]) -> std::io::Result<TilePayload> {
+        let mut reader = Cursor::new(data);
+        let mut magic: [u8; 4] = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != TILE_PAYLOAD_MAGIC {
+            return Err(invalid_payload(String::from("not a tile payload")));
+        }
+        let version: u32 = reader.read_u32::<LittleEndian>()?;
+        if version != TILE_PAYLOAD_VERSION {
+            return Err(invalid_payload(format!(
+                "version {} (expected {})",
+                version, TILE_PAYLOAD_VERSION
+            )));
+        }
+        let pixel_bounds: Bounds2i = read_bounds(&mut reader)?;
+        let samples_per_pixel: u32 = reader.read_u32::<LittleEndian>()?;
+        let name_len: usize = reader.read_u32::<LittleEndian>()? as usize;
+        let mut name: Vec<u8> = vec![0; std::cmp::min(name_len, data.len())];
+        reader.read_exact(&mut name)?;
+        let integrator: String = String::from_utf8(name)
+            .map_err(|_| invalid_payload(String::from("integrator name is not UTF-8")))?;
+        let splat_bounds: Bounds2i = read_bounds(&mut reader)?;
+        let aov_stride: u32 = reader.read_u32::<LittleEndian>()?;
+        // check the size before allocating anything
+        let n_pixels: usize = std::cmp::max(0, pixel_bounds.area()) as usize;
+        let n_splats: usize = std::cmp::max(0, splat_bounds.area()) as usize;
+        let n_aovs: usize = n_pixels * aov_stride as usize;
+        let remaining: usize = data.len() - reader.position() as usize;
+        if remaining != n_pixels * 16 + n_splats * 12 + n_aovs * 4 {
+            return Err(invalid_payload(format!(
+                "{} pixels, {} splats and {} AOV floats don't match {} bytes",
+                n_pixels, n_splats, n_aovs, remaining
+            )));
+        }
+        let mut pixels: Vec<[Float; 4]> = vec![[0.0 as Float; 4]; n_pixels];
+        for pixel in pixels.iter_mut() {
+            reader.read_f32_into::<LittleEndian>(pixel)?;
+        }
+        let mut splat_xyz: Vec<[Float; 3]> = vec![[0.0 as Float; 3]; n_splats];
+        for splat in splat_xyz.iter_mut() {
+            reader.read_f32_into::<LittleEndian>(splat)?;
+        }
+        let mut aovs: Vec<Float> = vec![0.0 as Float; n_aovs];
+        reader.read_f32_into::<LittleEndian>(&mut aovs)?;
+        Ok(TilePayload {
+            pixel_bounds,
+            samples_per_pixel,
+            integrator,
+            pixels,
+            splat_bounds,
+            splat_xyz,
+            aov_stride,
+            aovs,
+        })
+    }
+}
+
+fn write_bounds(data: &mut Vec<u8>, bounds: &Bounds2i) {
+    for v in &[
+        bounds.p_min.x,
+        bounds.p_min.y,
+        bounds.p_max.x,
+        bounds.p_max.y,
+    ] {
+        data.write_i32::<LittleEndian>(*v).unwrap();
+    }
+}
+
+fn read_bounds(reader: &mut Cursor<&[u8]>) -> std::io::Result<Bounds2i> {
+    let mut v: [i32; 4] = [0; 4];
+    reader.read_i32_into::<LittleEndian>(&mut v)?;
+    if v[2] < v[0] || v[3] < v[1] {
+        return Err(invalid_payload(format!("bounds {:?}", v)));
+    }
+    Ok(Bounds2i {
+        p_min: Point2i { x: v[0], y: v[1] },
+        p_max: Point2i { x: v[2], y: v[3] },
+    })
+}
+
+fn invalid_payload(msg: String) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("invalid tile payload: {}", msg),
+    )
+}
+
 pub struct Film {
     // Film Public Data
     /// The overall resolution of the image in pixels
@@ -170,6 +774,11 @@ pub struct Film {
     filter_table: [Float; FILTER_TABLE_WIDTH * FILTER_TABLE_WIDTH],
     scale: Float,
     max_sample_luminance: Float,
+    /// The AOVs requested by the `"string aovs"` parameter (if any)
+    pub aov_layout: Option<AovLayout>,
+    aov_pixels: RwLock<Vec<Float>>,
+    /// Applied to the image before it's written (see `"string denoiser"`)
+    pub denoiser: Option<BilateralDenoiser>,
 }
 
 impl Film {
@@ -219,15 +828,48 @@ impl Film {
             filter_table,
             scale,
             max_sample_luminance,
+            aov_layout: None,
+            aov_pixels: RwLock::new(Vec::new()),
+            denoiser: None,
         }
     }
-    pub fn create(params: &ParamSet, filter: Box<Filter>, crop_window: &Bounds2f) -> Arc<Film> {
-        let filename: String = params.find_one_string("filename", String::new());
-        let xres: i32 = params.find_one_int("xresolution", 1280);
-        let yres: i32 = params.find_one_int("yresolution", 720);
+    /// Store the AOVs of `layout` for each pixel (in addition to the
+    /// image).
+    pub fn set_aov_layout(&mut self, layout: AovLayout) {
+        let n_pixels: usize = self.cropped_pixel_bounds.area() as usize;
+        self.aov_pixels = RwLock::new(layout.new_pixels(n_pixels));
+        self.aov_layout = Some(layout);
+    }
+    pub fn create(
+        params: &ParamSet,
+        filter: Box<Filter>,
+        crop_window: &Bounds2f,
+        image_file: &str,
+        quick_render: bool,
+        light_groups: &[String],
+    ) -> Arc<Film> {
+        let params_filename: String = params.find_one_string("filename", String::new());
+        let filename: String = if image_file != "" {
+            if params_filename != "" {
+                println!(
+                    "WARNING: Output filename supplied on command line, {:?} is overriding \
+                     filename provided in scene description file, {:?}.",
+                    image_file, params_filename
+                );
+            }
+            String::from(image_file)
+        } else if params_filename != "" {
+            params_filename
+        } else {
+            String::from("pbrt.png")
+        };
+        let mut xres: i32 = params.find_one_int("xresolution", 1280);
+        let mut yres: i32 = params.find_one_int("yresolution", 720);
+        if quick_render {
+            xres = std::cmp::max(1, xres / 4);
+            yres = std::cmp::max(1, yres / 4);
+        }
         let resolution: Point2i = Point2i { x: xres, y: yres };
-        // TODO: if (PbrtOptions.quickRender) xres = std::max(1, xres / 4);
-        // TODO: if (PbrtOptions.quickRender) yres = std::max(1, yres / 4);
         let mut crop: Bounds2f = Bounds2f {
             p_min: Point2f { x: 0.0, y: 0.0 },
             p_max: Point2f { x: 1.0, y: 1.0 },
@@ -250,7 +892,7 @@ impl Film {
         let diagonal: Float = params.find_one_float("diagonal", 35.0);
         let max_sample_luminance: Float =
             params.find_one_float("maxsampleluminance", std::f32::INFINITY);
-        Arc::new(Film::new(
+        let mut film: Film = Film::new(
             resolution,
             crop,
             filter,
@@ -258,7 +900,31 @@ impl Film {
             filename,
             scale,
             max_sample_luminance,
-        ))
+        );
+        let mut aovs: Vec<Aov> = Vec::new();
+        for name in params.find_string("aovs") {
+            match Aov::from_name(&name) {
+                Some(aov) => {
+                    if !aovs.contains(&aov) {
+                        aovs.push(aov);
+                    }
+                }
+                None => println!("WARNING: Unknown AOV {:?} ignored.", name),
+            }
+        }
+        // the denoiser needs some AOVs to guide it
+        film.denoiser = BilateralDenoiser::create(params);
+        if film.denoiser.is_some() {
+            for aov in &[Aov::Albedo, Aov::Normal, Aov::Depth] {
+                if !aovs.contains(aov) {
+                    aovs.push(*aov);
+                }
+            }
+        }
+        if !aovs.is_empty() {
+            film.set_aov_layout(AovLayout::new(aovs, light_groups));
+        }
+        Arc::new(film)
     }
     pub fn get_cropped_pixel_bounds(&self) -> Bounds2i {
         self.cropped_pixel_bounds
@@ -306,6 +972,19 @@ impl Film {
         }
     }
     pub fn get_film_tile(&self, sample_bounds: &Bounds2i) -> FilmTile {
+        FilmTile::new(
+            self.get_film_tile_bounds(sample_bounds),
+            self.filter.get_radius(),
+            &self.filter_table,
+            FILTER_TABLE_WIDTH,
+            self.max_sample_luminance,
+            self.aov_layout.as_ref(),
+        )
+    }
+    /// The pixels a [FilmTile](struct.FilmTile.html) for
+    /// `sample_bounds` covers, see
+    /// [get_film_tile()](#method.get_film_tile).
+    pub fn get_film_tile_bounds(&self, sample_bounds: &Bounds2i) -> Bounds2i {
         // bound image pixels that samples in _sample_bounds_ contribute to
         let half_pixel: Vector2f = Vector2f { x: 0.5, y: 0.5 };
         let float_bounds: Bounds2f = Bounds2f {
@@ -328,19 +1007,12 @@ impl Film {
             x: p_max.x.floor() as i32,
             y: p_max.y.floor() as i32,
         } + Point2i { x: 1, y: 1 };
-        let tile_pixel_bounds: Bounds2i = bnd2_intersect_bnd2i(
+        bnd2_intersect_bnd2i(
             &Bounds2i {
                 p_min: p0,
                 p_max: p1,
             },
             &self.cropped_pixel_bounds,
-        );
-        FilmTile::new(
-            tile_pixel_bounds,
-            self.filter.get_radius(),
-            &self.filter_table,
-            FILTER_TABLE_WIDTH,
-            self.max_sample_luminance,
         )
     }
     pub fn merge_film_tile(&self, tile: &FilmTile) {
@@ -365,9 +1037,23 @@ impl Film {
                 merge_pixel.xyz[i] += item;
             }
             merge_pixel.filter_weight_sum += tile_pixel.filter_weight_sum;
+            merge_pixel.variance = merge_pixel.variance.merge(&tile_pixel.variance);
             // write pixel back
             // pixels_write[offset as usize] = *merge_pixel;
         }
+        if let Some(ref layout) = self.aov_layout {
+            self.merge_aov_pixels(layout, &tile.pixel_bounds, &tile.aov_pixels);
+        }
+    }
+    fn merge_aov_pixels(&self, layout: &AovLayout, bounds: &Bounds2i, values: &[Float]) {
+        let mut aov_pixels = self.aov_pixels.write().unwrap();
+        for (pixel, other) in bounds.into_iter().zip(values.chunks(layout.stride)) {
+            if !pnt2_inside_exclusivei(pixel, &self.cropped_pixel_bounds) {
+                continue;
+            }
+            let offset: usize = self.get_pixel_offset(pixel) * layout.stride;
+            layout.merge(&mut aov_pixels[offset..offset + layout.stride], other);
+        }
     }
     pub fn set_image(&self, img: &[Spectrum]) {
         let n_pixels: i32 = self.cropped_pixel_bounds.area();
@@ -436,95 +1122,36 @@ impl Film {
     }
     #[cfg(not(feature = "openexr"))]
     pub fn write_image(&self, splat_scale: Float) {
-        let mut rgb: Vec<Float> =
-            vec![0.0 as Float; (3 * self.cropped_pixel_bounds.area()) as usize];
-        let mut offset;
-        for p in &self.cropped_pixel_bounds {
-            // convert pixel XYZ color to RGB
-            assert!(pnt2_inside_exclusivei(p, &self.cropped_pixel_bounds));
-            let width: i32 = self.cropped_pixel_bounds.p_max.x - self.cropped_pixel_bounds.p_min.x;
-            offset = ((p.x - self.cropped_pixel_bounds.p_min.x)
-                + (p.y - self.cropped_pixel_bounds.p_min.y) * width) as usize;
-            let pixel: &Pixel = &self.pixels.read().unwrap()[offset];
-
-            let start: usize = 3 * offset;
-            let mut rgb_array: [Float; 3] = [0.0 as Float; 3];
-            xyz_to_rgb(&pixel.xyz, &mut rgb_array); // TODO: Use 'rgb' directly.
-            rgb[start] = rgb_array[0];
-            rgb[start + 1] = rgb_array[1];
-            rgb[start + 2] = rgb_array[2];
-            // normalize pixel with weight sum
-            let filter_weight_sum: Float = pixel.filter_weight_sum;
-            if filter_weight_sum != 0.0 as Float {
-                let inv_wt: Float = 1.0 as Float / filter_weight_sum;
-                rgb[start] = (rgb[start] * inv_wt).max(0.0 as Float);
-                rgb[start + 1] = (rgb[start + 1] * inv_wt).max(0.0 as Float);
-                rgb[start + 2] = (rgb[start + 2] * inv_wt).max(0.0 as Float);
-            }
-            // add splat value at pixel
-            let mut splat_rgb: [Float; 3] = [0.0 as Float; 3];
-            let pixel_splat_xyz: &[Float; 3] = &pixel.splat_xyz;
-            let splat_xyz: [Float; 3] = [
-                *pixel_splat_xyz.index(0),
-                *pixel_splat_xyz.index(1),
-                *pixel_splat_xyz.index(2),
-            ];
-            xyz_to_rgb(&splat_xyz, &mut splat_rgb);
-            rgb[start] += splat_scale * splat_rgb[0];
-            rgb[start + 1] += splat_scale * splat_rgb[1];
-            rgb[start + 2] += splat_scale * splat_rgb[2];
-            // scale pixel value by _scale_
-            rgb[start] *= self.scale;
-            rgb[start + 1] *= self.scale;
-            rgb[start + 2] *= self.scale;
+        let mut filename: PathBuf = Path::new(&self.filename).to_path_buf();
+        if filename.extension() == Some(OsStr::new("exr")) {
+            filename.set_extension("png");
+            println!(
+                "WARNING: No OpenEXR support, writing {:?} instead of {:?}",
+                filename, self.filename
+            );
         }
-        let filename = "pbrt.png";
         println!(
             "Writing image {:?} with bounds {:?}",
-            filename, // TODO: self.filename,
-            self.cropped_pixel_bounds
+            filename, self.cropped_pixel_bounds
         );
         // TODO: pbrt::WriteImage(filename, &rgb[0], croppedPixelBounds, fullResolution);
-        let mut buffer: Vec<u8> = vec![0.0 as u8; (3 * self.cropped_pixel_bounds.area()) as usize];
         // 8-bit format; apply gamma (see WriteImage(...) in imageio.cpp)
+        let buffer: Vec<u8> = rgb_to_rgb8(&self.get_image_rgb(splat_scale));
         let width: u32 =
             (self.cropped_pixel_bounds.p_max.x - self.cropped_pixel_bounds.p_min.x) as u32;
         let height: u32 =
             (self.cropped_pixel_bounds.p_max.y - self.cropped_pixel_bounds.p_min.y) as u32;
-        for y in 0..height {
-            for x in 0..width {
-                // red
-                let index: usize = (3 * (y * width + x)) as usize;
-                buffer[index] = clamp_t(
-                    255.0 as Float * gamma_correct(rgb[index]) + 0.5,
-                    0.0 as Float,
-                    255.0 as Float,
-                ) as u8;
-                // green
-                let index: usize = (3 * (y * width + x) + 1) as usize;
-                buffer[index] = clamp_t(
-                    255.0 as Float * gamma_correct(rgb[index]) + 0.5,
-                    0.0 as Float,
-                    255.0 as Float,
-                ) as u8;
-                // blue
-                let index: usize = (3 * (y * width + x) + 2) as usize;
-                buffer[index] = clamp_t(
-                    255.0 as Float * gamma_correct(rgb[index]) + 0.5,
-                    0.0 as Float,
-                    255.0 as Float,
-                ) as u8;
-            }
-        }
-        // write "pbrt.png" to disk
-        image::save_buffer(
-            &Path::new("pbrt.png"),
+        // write image to disk (the format depends on the file extension)
+        if let Err(e) = image::save_buffer(
+            &filename,
             &buffer,
             width,
             height,
             image::ColorType::Rgb8,
-        )
-        .unwrap();
+        ) {
+            println!("ERROR: Unable to write image {:?}: {}", filename, e);
+        }
+        self.write_aov_images();
     }
     #[cfg(feature = "openexr")]
     pub fn write_image(&self, splat_scale: Float) {
@@ -575,6 +1202,12 @@ impl Film {
             exr[offset].1 = rgb[start + 1];
             exr[offset].2 = rgb[start + 2];
         }
+        if self.denoiser.is_some() {
+            rgb = self.get_image_rgb(splat_scale);
+            for (i, item) in exr.iter_mut().enumerate() {
+                *item = (rgb[3 * i], rgb[3 * i + 1], rgb[3 * i + 2]);
+            }
+        }
         let filename = "pbrt.png";
         println!(
             "Writing image {:?} with bounds {:?}",
@@ -644,144 +1277,314 @@ impl Film {
             image::ColorType::Rgb8,
         )
         .unwrap();
+        self.write_aov_images();
     }
 
-    pub fn get_tile_image(
+    /// Collect the full filter extent of `tile` and everything which
+    /// was splatted onto the film into a
+    /// [TilePayload](struct.TilePayload.html), so a collector can
+    /// merge it with the contributions of neighbouring tiles (see
+    /// [merge_tile_payload()](#method.merge_tile_payload)).
+    pub fn get_tile_payload(
         &self,
         tile: &FilmTile,
-        tile_size: i32,
-        x: i32,
-        y: i32,
-        sample_bounds: Bounds2i,
-        splat_scale: Float,
-    ) -> Vec<u8> {
-        let x0: i32 = sample_bounds.p_min.x + x * tile_size;
-        let x1: i32 = std::cmp::min(x0 + tile_size, sample_bounds.p_max.x);
-        let y0: i32 = sample_bounds.p_min.y + y * tile_size;
-        let y1: i32 = std::cmp::min(y0 + tile_size, sample_bounds.p_max.y);
-        let tile_bounds: Bounds2i =
-            Bounds2i::new(Point2i { x: x0, y: y0 }, Point2i { x: x1, y: y1 });
-
-        //        let pixels = vec![Pixel::default(); tile.pixel_bounds.area() as usize];
-        let mut rgb: Vec<Float> = vec![0.0 as Float; (3 * tile_bounds.area()) as usize];
-
-        // println!(
-        //     "Tile Bounds {:?} vs tile.pixel_bounds {:?}",
-        //     tile_bounds, tile.pixel_bounds
-        // );
-        // TODO: ProfilePhase p(Prof::MergeFilmTile);
-        // println!("Merging film tile {:?}", tile.pixel_bounds);
-        // TODO: std::lock_guard<std::mutex> lock(mutex);
-        let mut pid = 0;
-        for pixel in &tile_bounds {
-            // merge _pixel_ into _Film::pixels_
-			let px = clamp_t(pixel.x, 0, pixel.x);
-			let py = clamp_t(pixel.y, 0, pixel.y);
-            let idx = tile.get_pixel_index(px,py);
-			if idx >= tile.pixels.len() {
-				println!("We are about to panic. {} {} -> {} {} gives us {} > {}. Bounds is {:?}", x, y, pixel.x, pixel.y, idx, tile.pixels.len(), tile_bounds);
-			}
-            let tile_pixel = &tile.pixels[idx];
-            let mut merge_pixel = Pixel::default();
+        samples_per_pixel: u32,
+        integrator: &str,
+    ) -> TilePayload {
+        let n_pixels: usize = std::cmp::max(0, tile.pixel_bounds.area()) as usize;
+        let mut pixels: Vec<[Float; 4]> = Vec::with_capacity(n_pixels);
+        for tile_pixel in tile.pixels.iter().take(n_pixels) {
             let mut xyz: [Float; 3] = [0.0; 3];
             tile_pixel.contrib_sum.to_xyz(&mut xyz);
-            for (i, item) in xyz.iter().enumerate() {
-                merge_pixel.xyz[i] += item;
+            pixels.push([xyz[0], xyz[1], xyz[2], tile_pixel.filter_weight_sum]);
+        }
+        // find the pixels with splats (if any)
+        let pixels_read = self.pixels.read().unwrap();
+        let mut splat_bounds: Option<Bounds2i> = None;
+        for (p, pixel) in (&self.cropped_pixel_bounds)
+            .into_iter()
+            .zip(pixels_read.iter())
+        {
+            if pixel.splat_xyz.iter().all(|v| *v == 0.0 as Float) {
+                continue;
             }
-            merge_pixel.filter_weight_sum += tile_pixel.filter_weight_sum;
-
-            let start: usize = 3 * pid as usize;
-            pid = pid + 1;
-            let mut rgb_array: [Float; 3] = [0.0 as Float; 3];
-            xyz_to_rgb(&merge_pixel.xyz, &mut rgb_array); // TODO: Use 'rgb' directly.
-            rgb[start] = rgb_array[0];
-            rgb[start + 1] = rgb_array[1];
-            rgb[start + 2] = rgb_array[2];
-            // normalize pixel with weight sum
-            let filter_weight_sum: Float = merge_pixel.filter_weight_sum;
-            if filter_weight_sum != 0.0 as Float {
-                let inv_wt: Float = 1.0 as Float / filter_weight_sum;
-                rgb[start] = (rgb[start] * inv_wt).max(0.0 as Float);
-                rgb[start + 1] = (rgb[start + 1] * inv_wt).max(0.0 as Float);
-                rgb[start + 2] = (rgb[start + 2] * inv_wt).max(0.0 as Float);
+            let b: Bounds2i = Bounds2i {
+                p_min: p,
+                p_max: p + Point2i { x: 1, y: 1 },
+            };
+            splat_bounds = match splat_bounds {
+                Some(bounds) => Some(bnd2_union_bnd2i(&bounds, &b)),
+                None => Some(b),
+            };
+        }
+        let mut splat_xyz: Vec<[Float; 3]> = Vec::new();
+        if let Some(splat_bounds) = splat_bounds {
+            for p in &splat_bounds {
+                splat_xyz.push(pixels_read[self.get_pixel_offset(p)].splat_xyz);
             }
-            // add splat value at pixel
-            let mut splat_rgb: [Float; 3] = [0.0 as Float; 3];
-            let pixel_splat_xyz: &[Float; 3] = &merge_pixel.splat_xyz;
-            let splat_xyz: [Float; 3] = [
-                *pixel_splat_xyz.index(0),
-                *pixel_splat_xyz.index(1),
-                *pixel_splat_xyz.index(2),
-            ];
-            xyz_to_rgb(&splat_xyz, &mut splat_rgb);
-            rgb[start] += splat_scale * splat_rgb[0];
-            rgb[start + 1] += splat_scale * splat_rgb[1];
-            rgb[start + 2] += splat_scale * splat_rgb[2];
+        }
+        TilePayload {
+            pixel_bounds: tile.pixel_bounds,
+            samples_per_pixel,
+            integrator: String::from(integrator),
+            pixels,
+            splat_bounds: splat_bounds.unwrap_or_default(),
+            splat_xyz,
+            aov_stride: tile.aov_layout.map_or(0, |layout| layout.stride as u32),
+            aovs: tile.aov_pixels.clone(),
+        }
+    }
+    /// Add a [TilePayload](struct.TilePayload.html) to the film,
+    /// pixels outside of the film are ignored. Payloads are sums, so
+    /// several passes over the same tile can be merged progressively.
+    /// Returns the bounds of the pixels which changed (can be empty).
+    pub fn merge_tile_payload(&self, payload: &TilePayload) -> Bounds2i {
+        let mut pixels_write = self.pixels.write().unwrap();
+        let mut changed: Bounds2i = Bounds2i::default();
+        for (pixel, values) in (&payload.pixel_bounds)
+            .into_iter()
+            .zip(payload.pixels.iter())
+        {
+            if !pnt2_inside_exclusivei(pixel, &self.cropped_pixel_bounds) {
+                continue;
+            }
+            let merge_pixel = &mut pixels_write[self.get_pixel_offset(pixel)];
+            for c in 0..3 {
+                merge_pixel.xyz[c] += values[c];
+            }
+            merge_pixel.filter_weight_sum += values[3];
+            changed = bnd2_intersect_bnd2i(&payload.pixel_bounds, &self.cropped_pixel_bounds);
+        }
+        for (pixel, splat) in (&payload.splat_bounds)
+            .into_iter()
+            .zip(payload.splat_xyz.iter())
+        {
+            if !pnt2_inside_exclusivei(pixel, &self.cropped_pixel_bounds) {
+                continue;
+            }
+            let merge_pixel = &mut pixels_write[self.get_pixel_offset(pixel)];
+            for c in 0..3 {
+                merge_pixel.splat_xyz[c] += splat[c];
+            }
+        }
+        if let Some(ref layout) = self.aov_layout {
+            if payload.aov_stride as usize == layout.stride {
+                self.merge_aov_pixels(layout, &payload.pixel_bounds, &payload.aovs);
+            } else {
+                println!(
+                    "WARNING: Tile payload with {} AOV floats per pixel ignored for AOVs \
+                     (expected {})",
+                    payload.aov_stride, layout.stride
+                );
+            }
+        }
+        let splat_bounds: Bounds2i =
+            bnd2_intersect_bnd2i(&payload.splat_bounds, &self.cropped_pixel_bounds);
+        if splat_bounds.p_min.x < splat_bounds.p_max.x && splat_bounds.p_min.y < splat_bounds.p_max.y
+        {
+            if changed.area() > 0 {
+                changed = bnd2_union_bnd2i(&changed, &splat_bounds);
+            } else {
+                changed = splat_bounds;
+            }
+        }
+        changed
+    }
+    /// Gamma corrected 8-bit RGB values of the film pixels within
+    /// `bounds` (which have to be inside the cropped pixel bounds).
+    pub fn get_image_rgb8(&self, bounds: &Bounds2i, splat_scale: Float) -> Vec<u8> {
+        rgb_to_rgb8(&self.get_pixels_rgb(bounds, splat_scale))
+    }
+    /// Linear RGB values of the whole (cropped) image, denoised if the
+    /// film has a [denoiser](../denoiser/struct.BilateralDenoiser.html).
+    pub fn get_image_rgb(&self, splat_scale: Float) -> Vec<Float> {
+        let rgb: Vec<Float> = self.get_pixels_rgb(&self.cropped_pixel_bounds, splat_scale);
+        let denoiser: &BilateralDenoiser = match self.denoiser {
+            Some(ref denoiser) => denoiser,
+            None => return rgb,
+        };
+        let mut albedo: Vec<Float> = Vec::with_capacity(rgb.len());
+        let mut normal: Vec<Float> = Vec::with_capacity(rgb.len());
+        let mut depth: Vec<Float> = Vec::with_capacity(rgb.len() / 3);
+        for p in &self.cropped_pixel_bounds {
+            albedo.extend(self.get_aov(Aov::Albedo, p));
+            normal.extend(self.get_aov(Aov::Normal, p));
+            depth.extend(self.get_aov(Aov::Depth, p));
+        }
+        let guides: DenoiserGuides = DenoiserGuides {
+            albedo: &albedo,
+            normal: &normal,
+            depth: &depth,
+        };
+        let diagonal: Vector2i = self.cropped_pixel_bounds.diagonal();
+        denoiser.denoise(&rgb, &guides, diagonal.x as usize, diagonal.y as usize)
+    }
+    /// Linear RGB values of the film pixels within `bounds` (which
+    /// have to be inside the cropped pixel bounds).
+    fn get_pixels_rgb(&self, bounds: &Bounds2i, splat_scale: Float) -> Vec<Float> {
+        let n_pixels: usize = std::cmp::max(0, bounds.area()) as usize;
+        let mut rgb: Vec<Float> = Vec::with_capacity(3 * n_pixels);
+        if n_pixels == 0 {
+            return rgb;
+        }
+        let width: i32 = self.cropped_pixel_bounds.p_max.x - self.cropped_pixel_bounds.p_min.x;
+        let pixels_read = self.pixels.read().unwrap();
+        for p in bounds {
+            assert!(pnt2_inside_exclusivei(p, &self.cropped_pixel_bounds));
+            let offset: i32 = (p.x - self.cropped_pixel_bounds.p_min.x)
+                + (p.y - self.cropped_pixel_bounds.p_min.y) * width;
+            rgb.extend(&self.get_pixel_rgb(&pixels_read[offset as usize], splat_scale));
+        }
+        rgb
+    }
+    /// Normalized, scaled RGB value of `pixel` including splats.
+    fn get_pixel_rgb(&self, pixel: &Pixel, splat_scale: Float) -> [Float; 3] {
+        let mut rgb: [Float; 3] = [0.0 as Float; 3];
+        xyz_to_rgb(&pixel.xyz, &mut rgb);
+        // normalize pixel with weight sum
+        let filter_weight_sum: Float = pixel.filter_weight_sum;
+        if filter_weight_sum != 0.0 as Float {
+            let inv_wt: Float = 1.0 as Float / filter_weight_sum;
+            for item in rgb.iter_mut() {
+                *item = (*item * inv_wt).max(0.0 as Float);
+            }
+        }
+        // add splat value at pixel
+        let mut splat_rgb: [Float; 3] = [0.0 as Float; 3];
+        xyz_to_rgb(&pixel.splat_xyz, &mut splat_rgb);
+        for (item, splat) in rgb.iter_mut().zip(splat_rgb.iter()) {
+            *item += splat_scale * splat;
             // scale pixel value by _scale_
-            rgb[start] *= self.scale;
-            rgb[start + 1] *= self.scale;
-            rgb[start + 2] *= self.scale;
+            *item *= self.scale;
         }
-
-        let mut buffer: Vec<u8> = vec![0.0 as u8; (3 * tile_bounds.area()) as usize];
-        // 8-bit format; apply gamma (see WriteImage(...) in imageio.cpp)
-        let width: u32 = (tile_bounds.p_max.x - tile_bounds.p_min.x) as u32;
-        let height: u32 = (tile_bounds.p_max.y - tile_bounds.p_min.y) as u32;
-        for y in 0..height {
-            for x in 0..width {
-                // red
-                let index: usize = (3 * (y * width + x)) as usize;
-                buffer[index] = clamp_t(
-                    255.0 as Float * gamma_correct(rgb[index]) + 0.5,
-                    0.0 as Float,
-                    255.0 as Float,
-                ) as u8;
-                // green
-                let index: usize = (3 * (y * width + x) + 1) as usize;
-                buffer[index] = clamp_t(
-                    255.0 as Float * gamma_correct(rgb[index]) + 0.5,
-                    0.0 as Float,
-                    255.0 as Float,
-                ) as u8;
-                // blue
-                let index: usize = (3 * (y * width + x) + 2) as usize;
-                buffer[index] = clamp_t(
-                    255.0 as Float * gamma_correct(rgb[index]) + 0.5,
-                    0.0 as Float,
-                    255.0 as Float,
-                ) as u8;
-
-                // let r = match y % 3 {
-                //     0 => 100,
-                //     _ => 0,
-                // };
-                // let g = match y % 3 {
-                //     1 => 150,
-                //     _ => 0,
-                // };
-                // let b = match y % 3 {
-                //     2 => 200,
-                //     _ => 0,
-                // };
-                // let index: usize = (3 * (y * width + x)) as usize;
-                // buffer[index] = r;
-                // let index: usize = (3 * (y * width + x) + 1) as usize;
-                // buffer[index] = g;
-                // let index: usize = (3 * (y * width + x) + 2) as usize;
-                // buffer[index] = b;
-            }
-        }
-        #[cfg(test)]
-        image::save_buffer(
-            &Path::new("pbrt.png"),
-            &buffer,
+        rgb
+    }
+    /// The normalized (and for radiance AOVs scaled) values of `aov`
+    /// for `pixel`, empty if the film doesn't store `aov` or the pixel
+    /// is outside of the image. Light groups return RGB for each group.
+    pub fn get_aov(&self, aov: Aov, pixel: Point2i) -> Vec<Float> {
+        let layout: &AovLayout = match self.aov_layout {
+            Some(ref layout) => layout,
+            None => return Vec::new(),
+        };
+        let offset: usize = match layout.offset(aov) {
+            Some(offset) => offset,
+            None => return Vec::new(),
+        };
+        if !pnt2_inside_exclusivei(pixel, &self.cropped_pixel_bounds) {
+            return Vec::new();
+        }
+        let p: usize = self.get_pixel_offset(pixel);
+        let filter_weight_sum: Float = self.pixels.read().unwrap()[p].filter_weight_sum;
+        let aov_pixels = self.aov_pixels.read().unwrap();
+        let values: &[Float] = &aov_pixels[p * layout.stride + offset..];
+        match aov {
+            // no sample, no ID
+            Aov::ObjectId | Aov::MaterialId => vec![if values[0].is_finite() {
+                values[1]
+            } else {
+                0.0 as Float
+            }],
+            Aov::Depth => vec![normalize_aov(values[0], filter_weight_sum, 1.0 as Float)],
+            _ => {
+                let scale: Float = if aov.is_radiance() {
+                    self.scale
+                } else {
+                    1.0 as Float
+                };
+                let n: usize = if aov == Aov::LightGroups {
+                    3 * layout.light_groups.len()
+                } else {
+                    3
+                };
+                values[..n]
+                    .iter()
+                    .map(|v| normalize_aov(*v, filter_weight_sum, scale))
+                    .collect()
+            }
+        }
+    }
+    /// Write each AOV to a PFM file next to the image, e.g.
+    /// `pbrt.depth.pfm` or `pbrt.lightgroup_<name>.pfm`.
+    pub fn write_aov_images(&self) {
+        let layout: &AovLayout = match self.aov_layout {
+            Some(ref layout) => layout,
+            None => return,
+        };
+        let width: usize =
+            (self.cropped_pixel_bounds.p_max.x - self.cropped_pixel_bounds.p_min.x) as usize;
+        let height: usize =
+            (self.cropped_pixel_bounds.p_max.y - self.cropped_pixel_bounds.p_min.y) as usize;
+        for aov in &layout.aovs {
+            let channels: usize = if *aov == Aov::LightGroups {
+                3 * layout.light_groups.len()
+            } else if *aov == Aov::Depth || aov.is_id() {
+                1
+            } else {
+                3
+            };
+            let mut values: Vec<Float> = Vec::with_capacity(width * height * channels);
+            for p in &self.cropped_pixel_bounds {
+                values.extend(self.get_aov(*aov, p));
+            }
+            if *aov == Aov::LightGroups {
+                for (i, name) in layout.light_groups.iter().enumerate() {
+                    let group: Vec<Float> = values
+                        .chunks(channels)
+                        .flat_map(|v| v[3 * i..3 * i + 3].to_vec())
+                        .collect();
+                    let suffix: String = format!("lightgroup_{}", name);
+                    self.write_aov_image(&suffix, &group, width, height, 3);
+                }
+            } else {
+                self.write_aov_image(aov.name(), &values, width, height, channels);
+            }
+        }
+    }
+    fn write_aov_image(
+        &self,
+        suffix: &str,
+        values: &[Float],
+        width: usize,
+        height: usize,
+        channels: usize,
+    ) {
+        let mut filename: PathBuf = Path::new(&self.filename).to_path_buf();
+        filename.set_extension(format!("{}.pfm", suffix));
+        println!("Writing AOV {:?}", filename);
+        // PFM: greyscale or RGB, little-endian, bottom row first
+        let mut data: Vec<u8> = Vec::with_capacity(32 + values.len() * 4);
+        let header: String = format!(
+            "{}\n{} {}\n-1.0\n",
+            if channels == 1 { "Pf" } else { "PF" },
             width,
-            height,
-            image::ColorType::Rgb8,
-        )
-        .unwrap();
-
-        buffer
+            height
+        );
+        data.extend_from_slice(header.as_bytes());
+        for row in values.chunks(width * channels).rev() {
+            for v in row {
+                data.write_f32::<LittleEndian>(*v).unwrap();
+            }
+        }
+        if let Err(e) = std::fs::write(&filename, &data) {
+            println!("ERROR: Unable to write AOV {:?}: {}", filename, e);
+        }
+    }
+    /// Luminance statistics of all samples merged so far for `pixel`
+    /// (**None** outside of the image). Tile payloads from workers
+    /// don't carry them.
+    pub fn get_pixel_variance(&self, pixel: Point2i) -> Option<VarianceEstimator> {
+        if !pnt2_inside_exclusivei(pixel, &self.cropped_pixel_bounds) {
+            return None;
+        }
+        let pixels_read = self.pixels.read().unwrap();
+        Some(pixels_read[self.get_pixel_offset(pixel)].variance)
+    }
+    fn get_pixel_offset(&self, p: Point2i) -> usize {
+        let width: i32 = self.cropped_pixel_bounds.p_max.x - self.cropped_pixel_bounds.p_min.x;
+        let offset: i32 = (p.x - self.cropped_pixel_bounds.p_min.x)
+            + (p.y - self.cropped_pixel_bounds.p_min.y) * width;
+        offset as usize
     }
     // pub fn get_pixel<'a>(&self, p: &Point2i) -> &'a Pixel {
     //     assert!(pnt2_inside_exclusivei(p, &self.cropped_pixel_bounds));
@@ -791,3 +1594,64 @@ impl Film {
     //     &self.pixels.read().unwrap()[offset as usize]
     // }
 }
+
+/// Gamma corrected 8-bit values of linear `rgb` values.
+fn rgb_to_rgb8(rgb: &[Float]) -> Vec<u8> {
+    rgb.iter()
+        .map(|item| {
+            clamp_t(
+                255.0 as Float * gamma_correct(*item) + 0.5,
+                0.0 as Float,
+                255.0 as Float,
+            ) as u8
+        })
+        .collect()
+}
+
+fn normalize_aov(value: Float, filter_weight_sum: Float, scale: Float) -> Float {
+    if filter_weight_sum != 0.0 as Float {
+        value / filter_weight_sum * scale
+    } else {
+        0.0 as Float
+    }
+}
+
+/// The collector keeps its film around to merge the tile payloads
+/// sent back by the workers as they arrive.
+pub fn set_collector_film(film: Option<Arc<Film>>) {
+    *COLLECTOR_FILM.write().unwrap() = film;
+}
+
+/// Merge an encoded [TilePayload](struct.TilePayload.html) into the
+/// collector's film and tonemap the updated region: x, y, width and
+/// height (little-endian `i32`, relative to the cropped pixel bounds)
+/// followed by 8-bit RGB values. Returns an empty vector if nothing
+/// was merged.
+pub fn merge_collector_tile(data: &[u8]) -> Vec<u8> {
+    let mut output: Vec<u8> = Vec::new();
+    let payload: TilePayload = match TilePayload::from_bytes(data) {
+        Ok(payload) => payload,
+        Err(e) => {
+            println!("ERROR: {} ({} bytes)", e, data.len());
+            return output;
+        }
+    };
+    if let Some(film) = COLLECTOR_FILM.read().unwrap().as_ref() {
+        let bounds: Bounds2i = film.merge_tile_payload(&payload);
+        if bounds.area() > 0 {
+            let p_min: Point2i = film.cropped_pixel_bounds.p_min;
+            for v in &[
+                bounds.p_min.x - p_min.x,
+                bounds.p_min.y - p_min.y,
+                bounds.p_max.x - bounds.p_min.x,
+                bounds.p_max.y - bounds.p_min.y,
+            ] {
+                output.write_i32::<LittleEndian>(*v).unwrap();
+            }
+            output.extend(film.get_image_rgb8(&bounds, 1.0 as Float));
+        }
+    } else {
+        println!("ERROR: No collector film to merge tiles into");
+    }
+    output
+}
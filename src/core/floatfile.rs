@@ -1,18 +1,16 @@
 //! Read text files that store a series of floating-point values.
 
 // std
-use std::fs::File;
 use std::io::{BufRead, BufReader};
-use std::path::Path;
 use std::result::Result;
 // pbrt
+use crate::backend::get_content_binary;
 use crate::core::pbrt::Float;
 
 pub fn read_float_file(filename: &str, values: &mut Vec<Float>) -> bool {
-    let path = Path::new(&filename);
-    let result = File::open(path);
-    if let Result::Ok(f) = result {
-        let reader = BufReader::new(f);
+    let result = get_content_binary(filename);
+    if let Result::Ok(data) = result {
+        let reader = BufReader::new(data.as_slice());
         for (line_number, line_result) in reader.lines().enumerate() {
             if let Result::Ok(line) = line_result {
                 if !line.is_empty() {
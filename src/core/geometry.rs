@@ -1989,6 +1989,21 @@ pub fn bnd2_intersect_bnd2i(b1: &Bounds2i, b2: &Bounds2i) -> Bounds2i {
     }
 }
 
+/// Construct a new box that bounds the space encompassed by two
+/// other bounding boxes.
+pub fn bnd2_union_bnd2i(b1: &Bounds2i, b2: &Bounds2i) -> Bounds2i {
+    Bounds2i {
+        p_min: Point2i {
+            x: std::cmp::min(b1.p_min.x, b2.p_min.x),
+            y: std::cmp::min(b1.p_min.y, b2.p_min.y),
+        },
+        p_max: Point2i {
+            x: std::cmp::max(b1.p_max.x, b2.p_max.x),
+            y: std::cmp::max(b1.p_max.y, b2.p_max.y),
+        },
+    }
+}
+
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct Bounds3f {
     pub p_min: Point3f,
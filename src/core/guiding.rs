@@ -0,0 +1,547 @@
+//! Light which reaches a diffuse surface only via specular bounces
+//! (caustics) or through a small gap is rarely found by sampling the
+//! BSDF. Path guiding learns the incident radiance while rendering
+//! and samples directions proportional to it, following "Practical
+//! Path Guiding for Efficient Light-Transport Simulation" (Müller et
+//! al. 2017): a binary tree over the scene bounds (splitting regions
+//! which received many samples) holds a directional quadtree per
+//! leaf (an SD-tree).
+//!
+//! The first passes of the render train the SD-tree, each pass twice
+//! as long as the one before. Every pass (after the first) is guided
+//! by the quadtrees of the pass before, mixed with BSDF sampling by
+//! one-sample MIS. The options of the
+//! [PathIntegrator](../../integrators/path/struct.PathIntegrator.html)
+//! are:
+//!
+//! ```text
+//! Integrator "path" "bool guiding" [ "true" ]
+//!   "integer guidingsamples" [ 16 ] "float guidingbsdffraction" [ 0.5 ]
+//! ```
+
+// std
+use std::f32::consts::PI;
+use std::sync::Mutex;
+// pbrt
+use crate::core::geometry::{spherical_direction, spherical_phi};
+use crate::core::geometry::{Bounds3f, Point2f, Point3f, Vector3f};
+use crate::core::paramset::ParamSet;
+use crate::core::pbrt::{clamp_t, Float, Spectrum};
+use crate::core::pbrt::{INV_2_PI, INV_4_PI};
+use crate::core::reflection::{Bsdf, BxdfType};
+use crate::core::rng::FLOAT_ONE_MINUS_EPSILON;
+use crate::core::sampler::{AdaptiveSampling, SampleRange};
+use crate::core::scene::Scene;
+
+/// Quadrants with a larger fraction of the energy of their tree are
+/// split for the next pass.
+const SPLIT_FRACTION: Float = 0.01;
+const MAX_DEPTH: u32 = 20;
+/// Leaves with fewer samples in a pass keep their old quadtree.
+const MIN_SAMPLES: u32 = 16;
+/// Leaves of the spatial tree with more samples in the first pass are
+/// split (the threshold grows with the square root of the samples per
+/// pass).
+const SPATIAL_SPLIT_SAMPLES: Float = 12000.0;
+const MAX_SPATIAL_DEPTH: u32 = 60;
+
+/// A node splits its square into four quadrants (`x + 2 * y`).
+#[derive(Debug, Default, Copy, Clone)]
+struct QuadNode {
+    /// Radiance recorded per quadrant
+    energy: [Float; 4],
+    /// Node index per quadrant (0 for leaves, the root isn't a child)
+    children: [u32; 4],
+}
+
+/// A quadtree over the unit square of directions (see
+/// [dir_to_square()](fn.dir_to_square.html)).
+#[derive(Debug, Clone)]
+pub struct DTree {
+    nodes: Vec<QuadNode>,
+    samples: u32,
+}
+
+impl Default for DTree {
+    fn default() -> Self {
+        DTree {
+            nodes: vec![QuadNode::default()],
+            samples: 0_u32,
+        }
+    }
+}
+
+impl DTree {
+    pub fn get_energy(&self) -> Float {
+        self.nodes[0].energy.iter().sum()
+    }
+    pub fn get_samples(&self) -> u32 {
+        self.samples
+    }
+    pub fn get_node_count(&self) -> usize {
+        self.nodes.len()
+    }
+    pub fn record(&mut self, u: &Point2f, energy: Float) {
+        self.samples += 1;
+        let mut u: Point2f = *u;
+        let mut node: usize = 0;
+        loop {
+            let q: usize = quadrant(&mut u);
+            self.nodes[node].energy[q] += energy;
+            match self.nodes[node].children[q] {
+                0 => break,
+                child => node = child as usize,
+            }
+        }
+    }
+    /// The density of [sample()](#method.sample) on the unit square.
+    pub fn pdf(&self, u: &Point2f) -> Float {
+        let mut u: Point2f = *u;
+        let mut pdf: Float = 1.0 as Float;
+        let mut node: usize = 0;
+        loop {
+            let energy: &[Float; 4] = &self.nodes[node].energy;
+            let total: Float = energy.iter().sum();
+            if total <= 0.0 as Float {
+                return 0.0 as Float;
+            }
+            let q: usize = quadrant(&mut u);
+            pdf *= 4.0 as Float * energy[q] / total;
+            match self.nodes[node].children[q] {
+                0 => return pdf,
+                child => node = child as usize,
+            }
+        }
+    }
+    /// Warp a uniform sample to a point on the unit square, the
+    /// tree must have some energy.
+    pub fn sample(&self, u: &Point2f) -> Point2f {
+        let mut u: Point2f = *u;
+        let mut origin: Point2f = Point2f::default();
+        let mut size: Float = 1.0 as Float;
+        let mut node: usize = 0;
+        loop {
+            let energy: &[Float; 4] = &self.nodes[node].energy;
+            // choose the column, then the row within it
+            let qx: usize = choose(energy[0] + energy[2], energy[1] + energy[3], &mut u.x);
+            let qy: usize = choose(energy[qx], energy[qx + 2], &mut u.y);
+            size *= 0.5 as Float;
+            origin.x += qx as Float * size;
+            origin.y += qy as Float * size;
+            match self.nodes[node].children[qx + 2 * qy] {
+                0 => {
+                    return Point2f {
+                        x: origin.x + u.x * size,
+                        y: origin.y + u.y * size,
+                    }
+                }
+                child => node = child as usize,
+            }
+        }
+    }
+    /// The tree for the next pass: quadrants holding more than
+    /// **SPLIT_FRACTION** of the energy are split (or stay split),
+    /// all others become leaves. The recorded energy is kept.
+    pub fn refine(&self) -> DTree {
+        let total: Float = self.get_energy();
+        let mut nodes: Vec<QuadNode> = vec![QuadNode {
+            energy: self.nodes[0].energy,
+            children: [0_u32; 4],
+        }];
+        // (node in self, node in the new tree, depth)
+        let mut stack: Vec<(Option<usize>, usize, u32)> = vec![(Some(0), 0, 1)];
+        while let Some((src, dst, depth)) = stack.pop() {
+            for q in 0..4 {
+                let energy: Float = nodes[dst].energy[q];
+                if energy <= total * SPLIT_FRACTION || depth >= MAX_DEPTH {
+                    continue;
+                }
+                let src_child: Option<usize> = match src {
+                    Some(src) if self.nodes[src].children[q] != 0 => {
+                        Some(self.nodes[src].children[q] as usize)
+                    }
+                    _ => None,
+                };
+                let child_energy: [Float; 4] = match src_child {
+                    Some(src_child) => self.nodes[src_child].energy,
+                    None => [energy * 0.25 as Float; 4],
+                };
+                nodes.push(QuadNode {
+                    energy: child_energy,
+                    children: [0_u32; 4],
+                });
+                let child: usize = nodes.len() - 1;
+                nodes[dst].children[q] = child as u32;
+                stack.push((src_child, child, depth + 1));
+            }
+        }
+        DTree {
+            nodes,
+            samples: self.samples,
+        }
+    }
+    /// Same subdivision, nothing recorded.
+    pub fn cleared(&self) -> DTree {
+        DTree {
+            nodes: self
+                .nodes
+                .iter()
+                .map(|node| QuadNode {
+                    energy: [0.0 as Float; 4],
+                    children: node.children,
+                })
+                .collect(),
+            samples: 0_u32,
+        }
+    }
+}
+
+/// Index of the quadrant containing **u**, which is mapped to the
+/// unit square of that quadrant.
+fn quadrant(u: &mut Point2f) -> usize {
+    let qx: usize = (u.x >= 0.5 as Float) as usize;
+    let qy: usize = (u.y >= 0.5 as Float) as usize;
+    u.x = (2.0 as Float * u.x - qx as Float).min(FLOAT_ONE_MINUS_EPSILON);
+    u.y = (2.0 as Float * u.y - qy as Float).min(FLOAT_ONE_MINUS_EPSILON);
+    qx + 2 * qy
+}
+
+/// Choose 0 or 1 with probabilities proportional to **a** and **b**
+/// and remap **u** to [0, 1).
+fn choose(a: Float, b: Float, u: &mut Float) -> usize {
+    let p: Float = if a + b > 0.0 as Float {
+        a / (a + b)
+    } else {
+        0.5 as Float
+    };
+    if *u < p {
+        *u = (*u / p).min(FLOAT_ONE_MINUS_EPSILON);
+        0
+    } else {
+        *u = ((*u - p) / (1.0 as Float - p)).min(FLOAT_ONE_MINUS_EPSILON);
+        1
+    }
+}
+
+/// Equal-area mapping of a normalized direction to the unit square
+/// (cosine of theta and phi), the density on the sphere is the
+/// density on the square times **INV_4_PI**.
+pub fn dir_to_square(d: &Vector3f) -> Point2f {
+    Point2f {
+        x: clamp_t(
+            (d.z + 1.0 as Float) * 0.5 as Float,
+            0.0 as Float,
+            FLOAT_ONE_MINUS_EPSILON,
+        ),
+        y: clamp_t(
+            spherical_phi(d) * INV_2_PI,
+            0.0 as Float,
+            FLOAT_ONE_MINUS_EPSILON,
+        ),
+    }
+}
+
+pub fn square_to_dir(u: &Point2f) -> Vector3f {
+    let cos_theta: Float = 2.0 as Float * u.x - 1.0 as Float;
+    let sin_theta: Float = (0.0 as Float)
+        .max(1.0 as Float - cos_theta * cos_theta)
+        .sqrt();
+    spherical_direction(sin_theta, cos_theta, 2.0 as Float * PI * u.y)
+}
+
+/// A vertex of a training path, its incident radiance is known once
+/// the path is finished.
+#[derive(Debug, Copy, Clone)]
+pub struct GuidingVertex {
+    pub p: Point3f,
+    pub wi: Vector3f,
+    /// Path throughput after scattering into **wi**
+    pub beta: Spectrum,
+    /// Radiance of the path before scattering into **wi**
+    pub l: Spectrum,
+    /// Density of **wi** (BSDF and guiding mixed)
+    pub pdf: Float,
+}
+
+/// A node of the spatial tree splits its box in half along the axis
+/// `depth % 3`.
+#[derive(Debug, Default, Copy, Clone)]
+struct SpatialNode {
+    /// Node index per half (0 for leaves, the root isn't a child)
+    children: [u32; 2],
+    /// Quadtree index of a leaf
+    dtree: usize,
+}
+
+pub struct PathGuiding {
+    /// Samples per pixel of the training passes
+    pub training_samples: i64,
+    /// Probability of sampling the BSDF instead of the quadtree
+    pub bsdf_fraction: Float,
+    /// A cube around the scene bounds
+    bounds: Bounds3f,
+    nodes: Vec<SpatialNode>,
+    /// Training passes so far
+    iteration: i32,
+    /// Quadtrees guiding the current pass
+    sampling: Vec<DTree>,
+    /// Quadtrees recording the current pass (empty after training)
+    training: Vec<Mutex<DTree>>,
+}
+
+impl PathGuiding {
+    pub fn new(training_samples: i64, bsdf_fraction: Float) -> Self {
+        PathGuiding {
+            training_samples,
+            bsdf_fraction,
+            bounds: Bounds3f::default(),
+            nodes: Vec::new(),
+            iteration: 0_i32,
+            sampling: Vec::new(),
+            training: Vec::new(),
+        }
+    }
+    /// The guiding selected by the `"bool guiding"` integrator
+    /// parameter (**None** by default).
+    pub fn create(params: &ParamSet, samples_per_pixel: i64) -> Option<PathGuiding> {
+        if !params.find_one_bool("guiding", false) {
+            return None;
+        }
+        let training_samples: i32 = params.find_one_int(
+            "guidingsamples",
+            std::cmp::max(1, samples_per_pixel / 4) as i32,
+        );
+        let mut bsdf_fraction: Float = params.find_one_float("guidingbsdffraction", 0.5 as Float);
+        if training_samples < 1 || training_samples as i64 >= samples_per_pixel {
+            println!(
+                "WARNING: \"guidingsamples\" {} leaves no samples to guide, not guiding.",
+                training_samples
+            );
+            return None;
+        }
+        // the BSDF covers the directions the quadtrees have missed
+        if !(bsdf_fraction > 0.0 as Float && bsdf_fraction <= 1.0 as Float) {
+            println!(
+                "WARNING: \"guidingbsdffraction\" {} outside of (0, 1], using 0.5.",
+                bsdf_fraction
+            );
+            bsdf_fraction = 0.5 as Float;
+        }
+        Some(PathGuiding::new(training_samples as i64, bsdf_fraction))
+    }
+    pub fn preprocess(&mut self, scene: &Scene) {
+        // halving a cube gives cube shaped leaves every third level
+        let b: Bounds3f = *scene.world_bound();
+        let diag: Vector3f = b.diagonal();
+        let size: Float = diag.x.max(diag.y).max(diag.z);
+        self.bounds = Bounds3f {
+            p_min: b.p_min,
+            p_max: Point3f {
+                x: b.p_min.x + size,
+                y: b.p_min.y + size,
+                z: b.p_min.z + size,
+            },
+        };
+        self.nodes = vec![SpatialNode::default()];
+        self.iteration = 0_i32;
+        self.sampling = vec![DTree::default()];
+        self.training = Vec::new();
+    }
+    /// Training passes of 1, 2, 4, ... samples per pixel within the
+    /// first **training_samples**, followed by the (progressive)
+    /// passes of the remaining samples.
+    pub fn get_passes(
+        &self,
+        samples: &SampleRange,
+        samples_per_pixel: i64,
+        sampling: &AdaptiveSampling,
+    ) -> Vec<SampleRange> {
+        let last_sample: i64 = match samples.last_sample {
+            Some(last_sample) => std::cmp::min(last_sample, samples_per_pixel),
+            None => samples_per_pixel,
+        };
+        let training_end: i64 = std::cmp::min(self.training_samples, last_sample);
+        let mut passes: Vec<SampleRange> = Vec::new();
+        let mut first_sample: i64 = samples.first_sample;
+        let mut pass_samples: i64 = 1;
+        while first_sample + pass_samples <= training_end {
+            passes.push(SampleRange {
+                first_sample,
+                last_sample: Some(first_sample + pass_samples),
+                seed_offset: samples.seed_offset,
+            });
+            first_sample += pass_samples;
+            pass_samples *= 2;
+        }
+        if first_sample < last_sample {
+            let remaining: SampleRange = SampleRange {
+                first_sample,
+                last_sample: Some(last_sample),
+                seed_offset: samples.seed_offset,
+            };
+            passes.append(&mut sampling.get_passes(&remaining, samples_per_pixel));
+        }
+        passes
+    }
+    /// The SD-tree trained in the previous pass guides the next one,
+    /// which records a new SD-tree if it is a training pass.
+    pub fn prepare_pass(&mut self, pass: &SampleRange) {
+        if !self.training.is_empty() {
+            let training: Vec<DTree> = self
+                .training
+                .drain(..)
+                .map(|tree| tree.into_inner().unwrap())
+                .collect();
+            for (sampling, training) in self.sampling.iter_mut().zip(training.iter()) {
+                if training.get_samples() >= MIN_SAMPLES {
+                    *sampling = training.refine();
+                }
+            }
+            // split the leaves which received many samples, the
+            // halves start with the quadtree of the leaf
+            let threshold: Float =
+                SPATIAL_SPLIT_SAMPLES * (2.0 as Float).powi(self.iteration).sqrt();
+            self.iteration += 1;
+            // (node, depth, samples of a new leaf)
+            let mut stack: Vec<(usize, u32, Option<Float>)> = vec![(0, 0, None)];
+            while let Some((node, depth, samples)) = stack.pop() {
+                let children: [u32; 2] = self.nodes[node].children;
+                if children[0] != 0 {
+                    for child in children.iter() {
+                        stack.push((*child as usize, depth + 1, None));
+                    }
+                    continue;
+                }
+                let dtree: usize = self.nodes[node].dtree;
+                let samples: Float =
+                    samples.unwrap_or_else(|| training[dtree].get_samples() as Float);
+                if samples <= threshold || depth >= MAX_SPATIAL_DEPTH {
+                    continue;
+                }
+                self.sampling.push(self.sampling[dtree].clone());
+                let halves: [SpatialNode; 2] = [
+                    SpatialNode {
+                        children: [0_u32; 2],
+                        dtree,
+                    },
+                    SpatialNode {
+                        children: [0_u32; 2],
+                        dtree: self.sampling.len() - 1,
+                    },
+                ];
+                for (half, child) in halves.iter().enumerate() {
+                    self.nodes.push(*child);
+                    self.nodes[node].children[half] = (self.nodes.len() - 1) as u32;
+                    stack.push((
+                        self.nodes.len() - 1,
+                        depth + 1,
+                        Some(samples * 0.5 as Float),
+                    ));
+                }
+            }
+        }
+        let is_training: bool = match pass.last_sample {
+            Some(last_sample) => last_sample <= self.training_samples,
+            None => false,
+        };
+        if is_training {
+            self.training = self
+                .sampling
+                .iter()
+                .map(|tree| Mutex::new(tree.cleared()))
+                .collect();
+        }
+    }
+    pub fn is_training(&self) -> bool {
+        !self.training.is_empty()
+    }
+    fn get_dtree(&self, p: &Point3f) -> usize {
+        let offset: Vector3f = self.bounds.offset(p);
+        let mut offset: [Float; 3] = [offset.x, offset.y, offset.z];
+        let mut node: usize = 0;
+        let mut axis: usize = 0;
+        loop {
+            let children: [u32; 2] = self.nodes[node].children;
+            if children[0] == 0 {
+                return self.nodes[node].dtree;
+            }
+            let x: Float = clamp_t(offset[axis], 0.0 as Float, FLOAT_ONE_MINUS_EPSILON);
+            let half: usize = (x >= 0.5 as Float) as usize;
+            offset[axis] = 2.0 as Float * x - half as Float;
+            node = children[half] as usize;
+            axis = (axis + 1) % 3;
+        }
+    }
+    /// The quadtree guiding directions at **p** (**None** if it
+    /// hasn't been trained yet).
+    pub fn lookup(&self, p: &Point3f) -> Option<&DTree> {
+        if self.sampling.is_empty() {
+            return None;
+        }
+        let tree: &DTree = &self.sampling[self.get_dtree(p)];
+        if tree.get_energy() > 0.0 as Float {
+            Some(tree)
+        } else {
+            None
+        }
+    }
+    /// Only diffuse BSDFs (without a BSSRDF) are guided, the BSDF
+    /// samples its glossy and specular lobes better than a quadtree.
+    pub fn is_guidable(bsdf: &Bsdf) -> bool {
+        let sides: u8 = BxdfType::BsdfReflection as u8 | BxdfType::BsdfTransmission as u8;
+        let specular: u8 = BxdfType::BsdfSpecular as u8 | sides;
+        let glossy: u8 = BxdfType::BsdfGlossy as u8 | sides;
+        !bsdf.bxdfs.is_empty() && bsdf.num_components(specular) + bsdf.num_components(glossy) == 0
+    }
+    /// Sample the BSDF (with probability **bsdf_fraction**) or the
+    /// quadtree, the **pdf** is the one of both strategies combined.
+    pub fn sample_f(
+        &self,
+        tree: &DTree,
+        bsdf: &Bsdf,
+        wo: &Vector3f,
+        wi: &mut Vector3f,
+        u_strategy: Float,
+        u: &Point2f,
+        pdf: &mut Float,
+        sampled_type: &mut u8,
+    ) -> Spectrum {
+        let bsdf_flags: u8 = BxdfType::BsdfAll as u8;
+        let mut bsdf_pdf: Float = 0.0 as Float;
+        let f: Spectrum = if u_strategy < self.bsdf_fraction {
+            bsdf.sample_f(wo, wi, u, &mut bsdf_pdf, bsdf_flags, sampled_type)
+        } else {
+            *wi = square_to_dir(&tree.sample(u));
+            bsdf_pdf = bsdf.pdf(wo, wi, bsdf_flags);
+            *sampled_type = bsdf_flags & !(BxdfType::BsdfSpecular as u8);
+            bsdf.f(wo, wi, bsdf_flags)
+        };
+        if bsdf_pdf == 0.0 as Float {
+            // the BSDF doesn't scatter into **wi**
+            *pdf = 0.0 as Float;
+            return f;
+        }
+        let guide_pdf: Float = tree.pdf(&dir_to_square(wi)) * INV_4_PI;
+        *pdf = self.bsdf_fraction * bsdf_pdf + (1.0 as Float - self.bsdf_fraction) * guide_pdf;
+        f
+    }
+    /// Record the radiance arriving at the vertices of a finished
+    /// training path with radiance **l**.
+    pub fn record_path(&self, vertices: &[GuidingVertex], l: &Spectrum) {
+        for vertex in vertices {
+            let beta: Float = vertex.beta.y();
+            if beta <= 0.0 as Float || vertex.pdf <= 0.0 as Float {
+                continue;
+            }
+            let radiance: Float = (*l - vertex.l).y().max(0.0 as Float) / beta;
+            if !radiance.is_finite() {
+                continue;
+            }
+            let dtree: usize = self.get_dtree(&vertex.p);
+            self.training[dtree]
+                .lock()
+                .unwrap()
+                .record(&dir_to_square(&vertex.wi), radiance / vertex.pdf);
+        }
+    }
+}
@@ -8,16 +8,19 @@ use std::time::Instant;
 
 // pbrt
 use crate::blockqueue::BlockQueue;
+use crate::collector::Dispatcher;
 use crate::core::camera::{Camera, CameraSample};
-use crate::core::film::{Film, FilmTile};
-use crate::core::geometry::{pnt2_inside_exclusivei, vec3_abs_dot_nrmf};
+#[cfg(target_arch = "wasm32")]
+use crate::core::film::set_collector_film;
+use crate::core::film::{AovSample, Film, FilmTile, VarianceEstimator};
+use crate::core::geometry::{bnd2_intersect_bnd2i, pnt2_inside_exclusivei, vec3_abs_dot_nrmf};
 use crate::core::geometry::{Bounds2i, Point2f, Point2i, Ray, Vector2i, Vector3f};
 use crate::core::interaction::{Interaction, InteractionCommon, SurfaceInteraction};
 use crate::core::light::is_delta_light;
 use crate::core::light::{Light, VisibilityTester};
 use crate::core::pbrt::{Float, Spectrum};
 use crate::core::reflection::BxdfType;
-use crate::core::sampler::Sampler;
+use crate::core::sampler::{AdaptiveSampling, SampleRange, Sampler};
 use crate::core::sampling::power_heuristic;
 use crate::core::sampling::Distribution1D;
 use crate::core::scene::Scene;
@@ -68,28 +71,73 @@ impl Integrator {
         x: Option<u32>,
         y: Option<u32>,
         filename: &str,
+        samples: &SampleRange,
+        sampling: &AdaptiveSampling,
+        dispatcher: Option<&Dispatcher>,
     ) -> Option<Vec<u8>> {
+		#[cfg(target_arch = "wasm32")]
 		#[cfg(not(feature = "ecp"))]
 		#[cfg(not(test))]
 		start_render();
 
         match self {
             Integrator::BDPT(integrator) => {
-                integrator.render(scene, num_threads, tile_size, collector, x, y, filename)
+                if sampling.is_adaptive() || sampling.pass_samples > 0 {
+                    println!(
+                        "WARNING: Adaptive and progressive sampling need a sampler integrator"
+                    );
+                }
+                integrator.render(
+                    scene,
+                    num_threads,
+                    tile_size,
+                    collector,
+                    x,
+                    y,
+                    filename,
+                    samples,
+                    dispatcher,
+                )
             }
             // Integrator::MLT(integrator) => integrator.render(scene, num_threads),
             // Integrator::SPPM(integrator) => integrator.render(scene, num_threads),
-            Integrator::Sampler(integrator) => {
-                integrator.render(scene, num_threads, tile_size, collector, x, y, filename)
-            }
+            Integrator::Sampler(integrator) => integrator.render(
+                scene,
+                num_threads,
+                tile_size,
+                collector,
+                x,
+                y,
+                filename,
+                samples,
+                sampling,
+                dispatcher,
+            ),
             _ => {
 				println!("NO RENDERER");
 				None
 			},
         }
     }
+    /// The number of tiles a collector splits the sample bounds into,
+    /// **None** for integrators which can't render tiles.
+    pub fn get_tile_count(&self, tile_size: i32) -> Option<Point2i> {
+        let (camera, pixel_bounds) = match self {
+            Integrator::BDPT(integrator) => (integrator.get_camera(), integrator.pixel_bounds),
+            Integrator::Sampler(integrator) => {
+                (integrator.get_camera(), integrator.get_pixel_bounds())
+            }
+            _ => return None,
+        };
+        let sample_bounds: Bounds2i =
+            bnd2_intersect_bnd2i(&camera.get_film().get_sample_bounds(), &pixel_bounds);
+        let sample_extent: Vector2i = sample_bounds.diagonal();
+        Some(Point2i {
+            x: (sample_extent.x + tile_size - 1) / tile_size,
+            y: (sample_extent.y + tile_size - 1) / tile_size,
+        })
+    }
 }
-
 pub enum SamplerIntegrator {
     AO(AOIntegrator),
     DirectLighting(DirectLightingIntegrator),
@@ -108,6 +156,23 @@ impl SamplerIntegrator {
             SamplerIntegrator::Whitted(integrator) => integrator.preprocess(scene),
         }
     }
+    /// Split `samples` into the ranges rendered one after another.
+    pub fn get_passes(
+        &self,
+        samples: &SampleRange,
+        sampling: &AdaptiveSampling,
+    ) -> Vec<SampleRange> {
+        match self {
+            SamplerIntegrator::Path(integrator) => integrator.get_passes(samples, sampling),
+            _ => sampling.get_passes(samples, self.get_sampler().get_samples_per_pixel()),
+        }
+    }
+    /// Called before each pass of the native collector.
+    pub fn prepare_pass(&mut self, pass: &SampleRange) {
+        if let SamplerIntegrator::Path(integrator) = self {
+            integrator.prepare_pass(pass);
+        }
+    }
 
     pub fn render_tile<'a>(
         &self,
@@ -118,6 +183,8 @@ impl SamplerIntegrator {
         tile_size: i32,
         scene: &Scene,
         film: &'a Arc<Film>,
+        samples: &SampleRange,
+        sampling: &AdaptiveSampling,
     ) -> FilmTile<'a> {
         let tile: Point2i = Point2i {
             x: x as i32,
@@ -131,7 +198,7 @@ impl SamplerIntegrator {
         let integrator = &self;
 
         let mut tile_sampler: Box<Sampler> = sampler.clone_with_seed(0_u64);
-        tile_sampler.reseed(seed as u64);
+        tile_sampler.reseed(seed as u64 + samples.seed_offset);
         let x0: i32 = sample_bounds.p_min.x + tile.x * tile_size;
         let x1: i32 = std::cmp::min(x0 + tile_size, sample_bounds.p_max.x);
         let y0: i32 = sample_bounds.p_min.y + tile.y * tile_size;
@@ -139,12 +206,27 @@ impl SamplerIntegrator {
         let tile_bounds: Bounds2i =
             Bounds2i::new(Point2i { x: x0, y: y0 }, Point2i { x: x1, y: y1 });
         let mut film_tile = film.get_film_tile(&tile_bounds);
+        let mut aov_sample: Option<AovSample> = match film.aov_layout {
+            Some(ref layout) if self.has_aovs() => Some(AovSample::new(layout.light_groups.len())),
+            _ => None,
+        };
         for pixel in &tile_bounds {
             tile_sampler.start_pixel(pixel);
             if !pnt2_inside_exclusivei(pixel, &pixel_bounds) {
                 continue;
             }
-            let mut done: bool = false;
+            // samples of earlier passes (none for pixels outside the image)
+            let previous: Option<VarianceEstimator> = if sampling.is_adaptive() {
+                film.get_pixel_variance(pixel)
+            } else {
+                None
+            };
+            if let Some(previous) = previous {
+                if sampling.has_converged(&previous) {
+                    continue;
+                }
+            }
+            let mut done: bool = !tile_sampler.start_sample_range(samples);
             while !done {
                 // let's use the copy_arena crate instead of pbrt's MemoryArena
                 // let mut arena: Arena = Arena::with_capacity(262144); // 256kB
@@ -161,6 +243,9 @@ impl SamplerIntegrator {
                 // evaluate radiance along camera ray
                 let mut l: Spectrum = Spectrum::new(0.0 as Float);
                 let y: Float = l.y();
+                if let Some(aov) = aov_sample.as_mut() {
+                    aov.clear();
+                }
                 if ray_weight > 0.0 {
                     // ADDED
                     let clipping_start: Float = camera.get_clipping_start();
@@ -169,11 +254,12 @@ impl SamplerIntegrator {
                         ray.o = ray.position(clipping_start);
                     }
                     // ADDED
-                    l = integrator.li(
+                    l = integrator.li_aov(
                         &mut ray,
                         scene,
                         &mut tile_sampler, // &mut arena,
                         0_i32,
+                        aov_sample.as_mut(),
                     );
                 }
                 if l.has_nans() {
@@ -209,7 +295,19 @@ impl SamplerIntegrator {
                 //          camera_sample, ray, l);
                 // add camera ray's contribution to image
                 film_tile.add_sample(camera_sample.p_film, &mut l, ray_weight);
-                done = !tile_sampler.start_next_sample();
+                if let Some(aov) = aov_sample.as_mut() {
+                    if l.is_black() {
+                        // the radiance might have been discarded
+                        aov.clear_radiance();
+                    }
+                    film_tile.add_aov_sample(camera_sample.p_film, aov, ray_weight);
+                }
+                done = !tile_sampler.start_next_sample_in_range(samples);
+                if let (false, Some(previous), Some(current)) =
+                    (done, previous, film_tile.get_pixel_variance(pixel))
+                {
+                    done = sampling.has_converged(&previous.merge(&current));
+                }
             } // arena is dropped here !
         }
         // println!(
@@ -230,10 +328,21 @@ impl SamplerIntegrator {
         x_start: Option<u32>,
         y_start: Option<u32>,
         filename: &str,
+        samples: &SampleRange,
+        sampling: &AdaptiveSampling,
+        dispatcher: Option<&Dispatcher>,
     ) -> Option<Vec<u8>> {
 		println!("render_tile");
         let film = self.get_camera().get_film();
-        let sample_bounds: Bounds2i = film.get_sample_bounds();
+        if film.aov_layout.is_some() && !self.has_aovs() {
+            println!(
+                "WARNING: The {:?} integrator doesn't fill in AOVs",
+                self.get_name()
+            );
+        }
+        // only the tiles covering the "pixelbounds" are rendered
+        let sample_bounds: Bounds2i =
+            bnd2_intersect_bnd2i(&film.get_sample_bounds(), &self.get_pixel_bounds());
         self.preprocess(scene);
         let sample_extent: Vector2i = sample_bounds.diagonal();
         let x: i32 = (sample_extent.x + tile_size - 1) / tile_size;
@@ -241,26 +350,72 @@ impl SamplerIntegrator {
         let n_tiles: Point2i = Point2i { x, y };
         // TODO: ProgressReporter reporter(nTiles.x * nTiles.y, "Rendering");
         if collector {
-            let block_queue = BlockQueue::new(
-                (
-                    (n_tiles.x * tile_size) as u32,
-                    (n_tiles.y * tile_size) as u32,
-                ),
-                (tile_size as u32, tile_size as u32),
-                (0, 0),
-            );
-            let bq = &block_queue;
-            let film = &film;
-            while let Some((x, y)) = bq.next() {
-                #[cfg(not(feature = "ecp"))]
-                #[cfg(not(test))]
-				http_request(x, y, tile_size, filename.to_string());
-
-                #[cfg(test)]
-                {
-                    let film_tile =
-                        self.render_tile(x, y, n_tiles, sample_bounds, tile_size, scene, film);
-                    film.merge_film_tile(&film_tile);
+            let new_block_queue = || {
+                BlockQueue::new(
+                    (
+                        (n_tiles.x * tile_size) as u32,
+                        (n_tiles.y * tile_size) as u32,
+                    ),
+                    (tile_size as u32, tile_size as u32),
+                    (0, 0),
+                )
+            };
+            // in the browser the tiles are rendered by workers
+            #[cfg(target_arch = "wasm32")]
+            {
+                let block_queue = new_block_queue();
+                let bq = &block_queue;
+                // merge the padded tiles as they come back
+                set_collector_film(Some(film.clone()));
+                while let Some((x, y)) = bq.next() {
+                    #[cfg(not(feature = "ecp"))]
+                    #[cfg(not(test))]
+                    http_request(x, y, tile_size, filename.to_string());
+                }
+            }
+            // natively the collector renders the full image itself
+            // (unless there are workers to send the tiles to), pass
+            // after pass in progressive mode
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                let passes: Vec<SampleRange> = self.get_passes(samples, sampling);
+                for (i, pass) in passes.iter().enumerate() {
+                    self.prepare_pass(pass);
+                    let integrator: &SamplerIntegrator = self;
+                    if passes.len() > 1 {
+                        println!(
+                            "Pass {} of {}: samples {}..{}",
+                            i + 1,
+                            passes.len(),
+                            pass.first_sample,
+                            pass.last_sample.unwrap_or_default()
+                        );
+                    }
+                    let mut block_queue = new_block_queue();
+                    if let Some(dispatcher) = dispatcher {
+                        // only the tiles no worker could render are left
+                        block_queue = dispatcher.for_samples(pass).dispatch_block_queue(
+                            &block_queue,
+                            &sample_bounds,
+                            &film,
+                        );
+                    }
+                    render_block_queue(&block_queue, num_threads, &film, |x, y| {
+                        integrator.render_tile(
+                            x,
+                            y,
+                            n_tiles,
+                            sample_bounds,
+                            tile_size,
+                            scene,
+                            &film,
+                            pass,
+                            sampling,
+                        )
+                    });
+                    if i + 1 < passes.len() {
+                        film.write_image(1.0 as Float);
+                    }
                 }
             }
         } else {
@@ -269,19 +424,24 @@ impl SamplerIntegrator {
             let film = &film;
             let x = x_start.unwrap();
             let y = y_start.unwrap();
-            let film_tile = self.render_tile(x, y, n_tiles, sample_bounds, tile_size, scene, film);
-            //            film.merge_film_tile(&film_tile);
-            let tile_image = film.get_tile_image(
-                &film_tile,
-                tile_size,
-                x as i32,
-                y as i32,
+            let film_tile = self.render_tile(
+                x,
+                y,
+                n_tiles,
                 sample_bounds,
-                1.0 as Float,
+                tile_size,
+                scene,
+                film,
+                samples,
+                sampling,
             );
-            return Some(tile_image);
+            // the collector merges the padded tile with its neighbours
+            let samples_per_pixel: u32 =
+                samples.count(self.get_sampler().get_samples_per_pixel()) as u32;
+            let payload = film.get_tile_payload(&film_tile, samples_per_pixel, self.get_name());
+            return Some(payload.to_bytes());
         }
-        #[cfg(test)]
+        #[cfg(not(target_arch = "wasm32"))]
         film.write_image(1.0 as Float);
 
         // this is the return
@@ -300,6 +460,33 @@ impl SamplerIntegrator {
             SamplerIntegrator::Whitted(integrator) => integrator.li(ray, scene, sampler, depth),
         }
     }
+    /// Same as [li()](#method.li), but also fills in the AOVs of the
+    /// camera ray for the integrators which support them (see
+    /// [has_aovs()](#method.has_aovs)).
+    pub fn li_aov(
+        &self,
+        ray: &mut Ray,
+        scene: &Scene,
+        sampler: &mut Sampler,
+        depth: i32,
+        aovs: Option<&mut AovSample>,
+    ) -> Spectrum {
+        match self {
+            SamplerIntegrator::Path(integrator) => {
+                integrator.li_aov(ray, scene, sampler, depth, aovs)
+            }
+            SamplerIntegrator::VolPath(integrator) => {
+                integrator.li_aov(ray, scene, sampler, depth, aovs)
+            }
+            _ => self.li(ray, scene, sampler, depth),
+        }
+    }
+    pub fn has_aovs(&self) -> bool {
+        matches!(
+            self,
+            SamplerIntegrator::Path(_) | SamplerIntegrator::VolPath(_)
+        )
+    }
     pub fn get_camera(&self) -> Arc<Camera> {
         match self {
             SamplerIntegrator::AO(integrator) => integrator.get_camera(),
@@ -309,6 +496,16 @@ impl SamplerIntegrator {
             SamplerIntegrator::Whitted(integrator) => integrator.get_camera(),
         }
     }
+    /// The name used for the integrator in scene files.
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            SamplerIntegrator::AO(_) => "ambientocclusion",
+            SamplerIntegrator::DirectLighting(_) => "directlighting",
+            SamplerIntegrator::Path(_) => "path",
+            SamplerIntegrator::VolPath(_) => "volpath",
+            SamplerIntegrator::Whitted(_) => "whitted",
+        }
+    }
     pub fn get_sampler(&self) -> &Sampler {
         match self {
             SamplerIntegrator::AO(integrator) => integrator.get_sampler(),
@@ -365,6 +562,57 @@ impl SamplerIntegrator {
     }
 }
 
+/// Let `num_threads` worker threads (all cores for 0) pull tiles from
+/// the [BlockQueue](../../blockqueue/struct.BlockQueue.html), render
+/// them via `render_tile`, and merge the resulting
+/// [FilmTiles](../film/struct.FilmTile.html) into `film`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn render_block_queue<'a, F>(
+    block_queue: &BlockQueue,
+    num_threads: u8,
+    film: &'a Film,
+    render_tile: F,
+) where
+    F: Fn(u32, u32) -> FilmTile<'a> + Sync,
+{
+    if block_queue.len() == 0 {
+        return;
+    }
+    let num_cores: usize = if num_threads == 0 {
+        num_cpus::get()
+    } else {
+        num_threads as usize
+    };
+    println!(
+        "Rendering {} tile(s) with {} thread(s) ...",
+        block_queue.len(),
+        num_cores
+    );
+    let render_tile = &render_tile;
+    crossbeam::scope(|scope| {
+        let (tile_tx, tile_rx) = crossbeam::channel::bounded(num_cores);
+        // spawn worker threads
+        for _ in 0..num_cores {
+            let tile_tx = tile_tx.clone();
+            scope.spawn(move |_| {
+                while let Some((x, y)) = block_queue.next() {
+                    let film_tile: FilmTile = render_tile(x, y);
+                    // send the tile through the channel to main thread
+                    tile_tx
+                        .send(film_tile)
+                        .unwrap_or_else(|_| panic!("Failed to send tile"));
+                }
+            });
+        }
+        drop(tile_tx);
+        // merge tiles as they arrive
+        for film_tile in tile_rx.iter() {
+            film.merge_film_tile(&film_tile);
+        }
+    })
+    .unwrap();
+}
+
 // see integrator.cpp
 
 /// Most basic direct lighting strategy.
@@ -434,12 +682,24 @@ pub fn uniform_sample_one_light(
     handle_media: bool,
     light_distrib: Option<&Distribution1D>,
 ) -> Spectrum {
+    sample_one_light(it, scene, sampler, handle_media, light_distrib).0
+}
+
+/// Like [uniform_sample_one_light()](fn.uniform_sample_one_light.html),
+/// but also returns the light group of the light which was sampled.
+pub fn sample_one_light(
+    it: &dyn Interaction,
+    scene: &Scene,
+    sampler: &mut Sampler,
+    handle_media: bool,
+    light_distrib: Option<&Distribution1D>,
+) -> (Spectrum, usize) {
     // TODO: ProfilePhase p(Prof::DirectLighting);
 
     // randomly choose a single light to sample, _light_
     let n_lights: usize = scene.lights.len();
     if n_lights == 0_usize {
-        return Spectrum::default();
+        return (Spectrum::default(), 0_usize);
     }
     let light_num: usize;
     let mut light_pdf: Option<Float> = Some(0.0 as Float);
@@ -449,7 +709,7 @@ pub fn uniform_sample_one_light(
         light_num = light_distribution.sample_discrete(sampler.get_1d(), light_pdf.as_mut());
         pdf = light_pdf.unwrap();
         if pdf == 0.0 as Float {
-            return Spectrum::default();
+            return (Spectrum::default(), 0_usize);
         }
     } else {
         light_num = std::cmp::min(
@@ -461,7 +721,7 @@ pub fn uniform_sample_one_light(
     let light = &scene.lights[light_num];
     let u_light: Point2f = sampler.get_2d();
     let u_scattering: Point2f = sampler.get_2d();
-    estimate_direct(
+    let ld: Spectrum = estimate_direct(
         it,
         u_scattering,
         light,
@@ -470,7 +730,8 @@ pub fn uniform_sample_one_light(
         sampler,
         handle_media,
         false,
-    ) / pdf
+    ) / pdf;
+    (ld, light.get_light_group())
 }
 
 /// Computes a direct lighting estimate for a single light source sample.
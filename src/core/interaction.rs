@@ -510,6 +510,16 @@ impl<'a> SurfaceInteraction<'a> {
         }
         Spectrum::default()
     }
+    /// The light group of the area light which was hit (0 if none).
+    pub fn get_light_group(&self) -> usize {
+        if let Some(primitive_raw) = self.primitive {
+            let primitive = unsafe { &*primitive_raw };
+            if let Some(area_light) = primitive.get_area_light() {
+                return area_light.get_light_group();
+            }
+        }
+        0_usize
+    }
 }
 
 impl<'a> Interaction for SurfaceInteraction<'a> {
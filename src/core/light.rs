@@ -167,6 +167,28 @@ impl Light {
             Light::Spot(light) => light.get_n_samples(),
         }
     }
+    pub fn get_light_group(&self) -> usize {
+        match self {
+            Light::DiffuseArea(light) => light.light_group,
+            Light::Distant(light) => light.light_group,
+            Light::GonioPhotometric(light) => light.light_group,
+            Light::InfiniteArea(light) => light.light_group,
+            Light::Point(light) => light.light_group,
+            Light::Projection(light) => light.light_group,
+            Light::Spot(light) => light.light_group,
+        }
+    }
+    pub fn set_light_group(&mut self, light_group: usize) {
+        match self {
+            Light::DiffuseArea(light) => light.light_group = light_group,
+            Light::Distant(light) => light.light_group = light_group,
+            Light::GonioPhotometric(light) => light.light_group = light_group,
+            Light::InfiniteArea(light) => light.light_group = light_group,
+            Light::Point(light) => light.light_group = light_group,
+            Light::Projection(light) => light.light_group = light_group,
+            Light::Spot(light) => light.light_group = light_group,
+        }
+    }
     // AreaLight
     pub fn l(&self, intr: &InteractionCommon, w: &Vector3f) -> Spectrum {
         match self {
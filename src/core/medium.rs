@@ -333,7 +333,9 @@ impl HenyeyGreenstein {
 
 #[derive(Default, Clone, Serialize, Deserialize)]
 pub struct MediumInterface {
+    #[serde(with = "crate::core::snapshot::shared::option")]
     pub inside: Option<Arc<Medium>>,
+    #[serde(with = "crate::core::snapshot::shared::option")]
     pub outside: Option<Arc<Medium>>,
 }
 
@@ -3,11 +3,13 @@
 pub mod api;
 pub mod bssrdf;
 pub mod camera;
+pub mod denoiser;
 pub mod efloat;
 pub mod film;
 pub mod filter;
 pub mod floatfile;
 pub mod geometry;
+pub mod guiding;
 pub mod integrator;
 pub mod interaction;
 pub mod interpolation;
@@ -30,6 +32,7 @@ pub mod sampler;
 pub mod sampling;
 pub mod scene;
 pub mod shape;
+pub mod snapshot;
 pub mod sobolmatrices;
 pub mod spectrum;
 pub mod texture;
@@ -3,12 +3,10 @@
 // std
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::env;
-use std::fs::File;
-use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 // pbrt
+use crate::backend::resolve_filename;
 use crate::core::floatfile::read_float_file;
 use crate::core::geometry::{Normal3f, Point2f, Point3f, Vector2f, Vector3f};
 use crate::core::pbrt::{Float, Spectrum};
@@ -19,7 +17,7 @@ use crate::textures::constant::ConstantTexture;
 
 // see paramset.h
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct ParamSetItem<T> {
     pub name: String,
     pub values: Vec<T>,
@@ -27,7 +25,7 @@ pub struct ParamSetItem<T> {
     pub looked_up: bool, // false
 }
 
-#[derive(Default, Serialize, Deserialize)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct ParamSet {
     pub key_word: String,
     pub name: String,
@@ -172,40 +170,28 @@ impl ParamSet {
         self.erase_spectrum(name.clone());
         let mut s: Vec<Spectrum> = Vec::with_capacity(names.len());
         for name in &names {
-            // std::string filename = AbsolutePath(ResolveFilename(name));
-            let fn_str: &String = &name;
-            let _f = File::open(fn_str.clone()).unwrap();
-            let ip: &Path = Path::new(fn_str.as_str());
-            if ip.is_relative() {
-                let cp: PathBuf = env::current_dir().unwrap();
-                let pb: PathBuf = cp.join(ip);
-                let search_directory: &Path = pb.as_path().parent().unwrap();
-                let mut path_buf: PathBuf = PathBuf::from("/");
-                path_buf.push(search_directory);
-                path_buf.push(ip.file_name().unwrap());
-                let filename = String::from(path_buf.to_str().unwrap());
-                let mut vals: Vec<Float> = Vec::new();
-                if !read_float_file(&filename, &mut vals) {
+            let filename: String = resolve_filename(name);
+            let mut vals: Vec<Float> = Vec::new();
+            if !read_float_file(&filename, &mut vals) {
+                println!(
+                    "WARNING: Unable to read SPD file {:?}. Using black distribution.",
+                    filename
+                );
+                s.push(Spectrum::default());
+            } else {
+                if vals.len() % 2 == 1_usize {
                     println!(
-                        "WARNING: Unable to read SPD file {:?}. Using black distribution.",
+                        "WARNING: Extra value found in spectrum file {:?}. Ignoring it.",
                         filename
                     );
-                    s.push(Spectrum::default());
-                } else {
-                    if vals.len() % 2 == 1_usize {
-                        println!(
-                            "WARNING: Extra value found in spectrum file {:?}. Ignoring it.",
-                            filename
-                        );
-                    }
-                    let mut wls: Vec<Float> = Vec::new();
-                    let mut v: Vec<Float> = Vec::new();
-                    for j in 0..(vals.len() / 2_usize) {
-                        wls.push(vals[2 * j]);
-                        v.push(vals[2 * j + 1]);
-                    }
-                    s.push(Spectrum::from_sampled(&wls[..], &v[..], wls.len() as i32));
                 }
+                let mut wls: Vec<Float> = Vec::new();
+                let mut v: Vec<Float> = Vec::new();
+                for j in 0..(vals.len() / 2_usize) {
+                    wls.push(vals[2 * j]);
+                    v.push(vals[2 * j + 1]);
+                }
+                s.push(Spectrum::from_sampled(&wls[..], &v[..], wls.len() as i32));
             }
         }
         let n_values: usize = s.len();
@@ -224,6 +210,15 @@ impl ParamSet {
             looked_up: false,
         });
     }
+    pub fn add_strings(&mut self, name: String, values: Vec<String>) {
+        let n_values: usize = values.len();
+        self.strings.push(ParamSetItem::<String> {
+            name,
+            values,
+            n_values,
+            looked_up: false,
+        });
+    }
     pub fn add_texture(&mut self, name: String, value: String) {
         self.textures.push(ParamSetItem::<String> {
             name,
@@ -410,6 +405,24 @@ impl ParamSet {
             });
         }
     }
+    pub fn erase_int(&mut self, name: &str) -> bool {
+        for i in 0..self.ints.len() {
+            if self.ints[i].name == name {
+                self.ints.remove(i);
+                return true;
+            }
+        }
+        false
+    }
+    pub fn erase_float(&mut self, name: &str) -> bool {
+        for i in 0..self.floats.len() {
+            if self.floats[i].name == name {
+                self.floats.remove(i);
+                return true;
+            }
+        }
+        false
+    }
     pub fn erase_spectrum(&mut self, name: String) -> bool {
         for i in 0..self.spectra.len() {
             if self.spectra[i].name == name {
@@ -487,8 +500,7 @@ impl ParamSet {
         if filename == "" {
             return d;
         }
-        // TODO: filename = AbsolutePath(ResolveFilename(filename));
-        filename
+        resolve_filename(&filename)
     }
     pub fn find_texture(&self, name: &str) -> String {
         let d: String = String::new();
@@ -598,6 +610,19 @@ impl ParamSet {
         }
         values
     }
+    pub fn find_string(&self, name: &str) -> Vec<String> {
+        let mut values: Vec<String> = Vec::new();
+        for v in &self.strings {
+            if v.name == name {
+                let n_values = v.n_values;
+                // v.looked_up = true;
+                for i in 0..n_values {
+                    values.push(v.values[i].clone());
+                }
+            }
+        }
+        values
+    }
 }
 
 #[derive(Default)]
@@ -75,6 +75,14 @@ impl Primitive {
             Primitive::KdTree(primitive) => primitive.get_material(),
         }
     }
+    /// The object and material ID (see `Aov::ObjectId` and
+    /// `Aov::MaterialId`), zero for aggregates.
+    pub fn get_ids(&self) -> (u32, u32) {
+        match self {
+            Primitive::Geometric(primitive) => (primitive.object_id, primitive.material_id),
+            _ => (0_u32, 0_u32),
+        }
+    }
     pub fn compute_scattering_functions(
         &self,
         isect: &mut SurfaceInteraction,
@@ -101,10 +109,19 @@ impl Primitive {
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct GeometricPrimitive {
+    #[serde(with = "crate::core::snapshot::shared")]
     pub shape: Arc<Shape>,
+    #[serde(with = "crate::core::snapshot::shared::option")]
     pub material: Option<Arc<Material>>,
+    #[serde(with = "crate::core::snapshot::shared::option")]
     pub area_light: Option<Arc<Light>>,
     pub medium_interface: Option<Arc<MediumInterface>>,
+    /// the `Shape` statement the primitive was created for (starting
+    /// with 1, for the object ID AOV)
+    pub object_id: u32,
+    /// distinguishes the materials of the scene (starting with 1,
+    /// for the material ID AOV)
+    pub material_id: u32,
 }
 
 impl GeometricPrimitive {
@@ -121,6 +138,8 @@ impl GeometricPrimitive {
                     material,
                     area_light: Some(area_light),
                     medium_interface: Some(medium_interface),
+                    object_id: 0,
+                    material_id: 0,
                 }
             } else {
                 GeometricPrimitive {
@@ -128,6 +147,8 @@ impl GeometricPrimitive {
                     material,
                     area_light: Some(area_light),
                     medium_interface: None,
+                    object_id: 0,
+                    material_id: 0,
                 }
             }
         } else if let Some(medium_interface) = medium_interface {
@@ -136,6 +157,8 @@ impl GeometricPrimitive {
                 material,
                 area_light: None,
                 medium_interface: Some(medium_interface),
+                object_id: 0,
+                material_id: 0,
             }
         } else {
             GeometricPrimitive {
@@ -143,6 +166,8 @@ impl GeometricPrimitive {
                 material,
                 area_light: None,
                 medium_interface: None,
+                object_id: 0,
+                material_id: 0,
             }
         }
     }
@@ -208,6 +233,7 @@ impl GeometricPrimitive {
 
 #[derive(Serialize, Deserialize)]
 pub struct TransformedPrimitive {
+    #[serde(with = "crate::core::snapshot::shared")]
     pub primitive: Arc<Primitive>,
     pub primitive_to_world: AnimatedTransform,
 }
@@ -260,9 +286,8 @@ impl TransformedPrimitive {
                 // is.shading.dpdv = new_isect.shading.dpdv;
                 // is.shading.dndu = new_isect.shading.dndu;
                 // is.shading.dndv = new_isect.shading.dndv;
-                return true;
             }
-            false
+            true
         } else {
             false
         }
@@ -6,15 +6,14 @@
 
 // std
 use std::f32::consts::PI;
-use std::fs::File;
-use std::io::Read;
-use std::path::Path;
+use std::io::{Cursor, Read};
 use std::sync::Arc;
 // others
 use byteorder::{LittleEndian, ReadBytesExt};
 use num::Zero;
 use smallvec::SmallVec;
 // pbrt
+use crate::backend::get_content_binary;
 use crate::core::bssrdf::SeparableBssrdfAdapter;
 use crate::core::geometry::{
     nrm_cross_vec3, nrm_dot_vec3f, nrm_faceforward_vec3, vec3_abs_dot_vec3f, vec3_dot_nrmf,
@@ -78,14 +77,13 @@ pub struct FourierBSDFTable {
 
 impl FourierBSDFTable {
     pub fn read(&mut self, filename: &str) -> bool {
-        let path = Path::new(&filename);
-        let result = File::open(path);
+        let result = get_content_binary(filename);
         if result.is_err() {
             println!("ERROR: Unable to open tabulated BSDF file {:?}", filename);
             return false;
         }
         // header
-        let mut file = result.unwrap();
+        let mut file = Cursor::new(result.unwrap());
         let mut buffer = [0; 8];
         let io_result = file.read_exact(&mut buffer);
         if io_result.is_ok() {
@@ -4,6 +4,7 @@
 
 // pbrt
 use crate::core::camera::CameraSample;
+use crate::core::film::VarianceEstimator;
 use crate::core::geometry::{Point2f, Point2i};
 use crate::core::pbrt::Float;
 use crate::integrators::mlt::MLTSampler;
@@ -16,6 +17,89 @@ use crate::samplers::zerotwosequence::ZeroTwoSequenceSampler;
 
 // see sampler.h
 
+/// The pixel samples a worker renders for a tile, several workers can
+/// split the samples of the same tile. Tiles with a different
+/// `seed_offset` get independent random sequences.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct SampleRange {
+    /// index of the first pixel sample to take
+    pub first_sample: i64,
+    /// one past the last pixel sample (all samples if **None**)
+    pub last_sample: Option<i64>,
+    /// added to the seed of each tile sampler
+    pub seed_offset: u64,
+}
+
+impl SampleRange {
+    pub fn contains(&self, sample_num: i64) -> bool {
+        sample_num >= self.first_sample
+            && match self.last_sample {
+                Some(last_sample) => sample_num < last_sample,
+                None => true,
+            }
+    }
+    /// Number of samples taken per pixel for a sampler with
+    /// `samples_per_pixel` samples.
+    pub fn count(&self, samples_per_pixel: i64) -> i64 {
+        let last_sample: i64 = match self.last_sample {
+            Some(last_sample) => std::cmp::min(last_sample, samples_per_pixel),
+            None => samples_per_pixel,
+        };
+        std::cmp::max(0, last_sample - self.first_sample)
+    }
+}
+
+/// Adaptive and progressive sampling for the **SamplerIntegrators**,
+/// set by the integrator parameters `"float maxrelativeerror"`,
+/// `"integer minsamples"` and `"integer passsamples"`. The sampler's
+/// pixel samples are the budget per pixel.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct AdaptiveSampling {
+    /// stop sampling a pixel once the standard error of its mean
+    /// luminance is below this fraction of the mean (0 to take all
+    /// samples)
+    pub max_relative_error: Float,
+    /// samples per pixel to take before the error is checked
+    pub min_samples: i64,
+    /// render the image in passes of this many samples per pixel and
+    /// write it after each pass (0 for a single pass)
+    pub pass_samples: i64,
+}
+
+impl AdaptiveSampling {
+    pub fn is_adaptive(&self) -> bool {
+        self.max_relative_error > 0.0 as Float
+    }
+    pub fn has_converged(&self, variance: &VarianceEstimator) -> bool {
+        self.is_adaptive()
+            && variance.count() >= std::cmp::max(self.min_samples, 2)
+            && variance.relative_error() <= self.max_relative_error
+    }
+    /// Split `samples` of a sampler with `samples_per_pixel` samples
+    /// into the ranges rendered one after another.
+    pub fn get_passes(&self, samples: &SampleRange, samples_per_pixel: i64) -> Vec<SampleRange> {
+        let last_sample: i64 = match samples.last_sample {
+            Some(last_sample) => std::cmp::min(last_sample, samples_per_pixel),
+            None => samples_per_pixel,
+        };
+        if self.pass_samples <= 0 || samples.first_sample + self.pass_samples >= last_sample {
+            return vec![*samples];
+        }
+        let mut passes: Vec<SampleRange> = Vec::new();
+        let mut first_sample: i64 = samples.first_sample;
+        while first_sample < last_sample {
+            let pass_last: i64 = std::cmp::min(first_sample + self.pass_samples, last_sample);
+            passes.push(SampleRange {
+                first_sample,
+                last_sample: Some(pass_last),
+                seed_offset: samples.seed_offset,
+            });
+            first_sample = pass_last;
+        }
+        passes
+    }
+}
+
 pub enum Sampler {
     Halton(HaltonSampler),
     MaxMinDist(MaxMinDistSampler),
@@ -147,6 +231,22 @@ impl Sampler {
             Sampler::ZeroTwoSequence(sampler) => sampler.start_next_sample(),
         }
     }
+    /// Skip the samples of the current pixel (see
+    /// [start_pixel()](#method.start_pixel)) before `range`, returns
+    /// false if there are no samples left to take.
+    pub fn start_sample_range(&mut self, range: &SampleRange) -> bool {
+        while self.get_current_sample_number() < range.first_sample {
+            if !self.start_next_sample() {
+                return false;
+            }
+        }
+        range.contains(self.get_current_sample_number())
+    }
+    /// Same as [start_next_sample()](#method.start_next_sample), but
+    /// stops at the end of `range`.
+    pub fn start_next_sample_in_range(&mut self, range: &SampleRange) -> bool {
+        self.start_next_sample() && range.contains(self.get_current_sample_number())
+    }
     pub fn reseed(&mut self, seed: u64) {
         match self {
             Sampler::Halton(sampler) => sampler.reseed(seed),
@@ -15,9 +15,11 @@ use crate::core::sampler::Sampler;
 
 // see scene.h
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Scene {
+    #[serde(with = "crate::core::snapshot::shared::vec")]
     pub lights: Vec<Arc<Light>>,
+    #[serde(with = "crate::core::snapshot::shared::vec")]
     pub infinite_lights: Vec<Arc<Light>>,
     pub aggregate: Arc<Primitive>,
     pub world_bound: Bounds3f,
@@ -0,0 +1,322 @@
+//! Parsing a scene file, creating all shapes and building the
+//! acceleration structure dominates the time a worker needs for a
+//! single tile. A **SceneSnapshot** stores the built **Scene**
+//! (aggregate, lights, materials and textures) together with the
+//! **RenderOptions** needed to create camera, film, sampler and
+//! integrator in a compact binary form. The collector writes it once
+//! and workers load it instead of parsing the scene again.
+//!
+//! Objects which are shared via **Arc** (triangle meshes, materials,
+//! area lights, media, instances) are written only once and restored
+//! as shared objects, see [shared](shared/index.html).
+
+// std
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+// others
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+// pbrt
+use crate::core::api::RenderOptions;
+use crate::core::scene::Scene;
+use crate::tilejob::scene_hash;
+
+/// Identifies a scene snapshot.
+pub const SCENE_SNAPSHOT_MAGIC: [u8; 4] = *b"PBRS";
+/// Increase whenever the layout of any serialized type changes.
+pub const SCENE_SNAPSHOT_VERSION: u32 = 4;
+
+/// How many decoded snapshots a process keeps for later tile jobs.
+pub const SCENE_SNAPSHOT_CACHE_SIZE: usize = 4;
+
+lazy_static::lazy_static! {
+    // decoded snapshots by scene hash, the most recently used last
+    static ref SNAPSHOT_CACHE: Mutex<Vec<(String, Arc<SceneSnapshot>)>> = Mutex::new(Vec::new());
+}
+
+thread_local! {
+    // objects written so far (address -> index)
+    static SHARED_WRITTEN: RefCell<Option<HashMap<usize, u32>>> = RefCell::new(None);
+    // objects read so far (in the same order)
+    static SHARED_READ: RefCell<Option<Vec<Arc<dyn Any + Send + Sync>>>> = RefCell::new(None);
+}
+
+/// Why a scene snapshot couldn't be written or loaded.
+#[derive(Debug)]
+pub struct SnapshotError {
+    pub error: String,
+}
+
+impl SnapshotError {
+    pub fn new(error: &str) -> Self {
+        SnapshotError {
+            error: error.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl From<bincode::Error> for SnapshotError {
+    fn from(error: bincode::Error) -> Self {
+        SnapshotError::new(&format!("{}", error))
+    }
+}
+
+pub struct SceneSnapshot {
+    pub pixelsamples: u32,
+    pub render_options: RenderOptions,
+    pub scene: Scene,
+}
+
+#[derive(Serialize)]
+struct SnapshotRef<'a> {
+    magic: [u8; 4],
+    version: u32,
+    pixelsamples: u32,
+    render_options: &'a RenderOptions,
+    scene: &'a Scene,
+}
+
+#[derive(Deserialize)]
+struct SnapshotHeader {
+    magic: [u8; 4],
+    version: u32,
+}
+
+#[derive(Deserialize)]
+struct SnapshotOwned {
+    _magic: [u8; 4],
+    _version: u32,
+    pixelsamples: u32,
+    render_options: RenderOptions,
+    scene: Scene,
+}
+
+impl SceneSnapshot {
+    /// Serialize the built scene and the options to create the
+    /// integrator for it.
+    pub fn to_bytes(
+        pixelsamples: u32,
+        render_options: &RenderOptions,
+        scene: &Scene,
+    ) -> Result<Vec<u8>, SnapshotError> {
+        let snapshot: SnapshotRef = SnapshotRef {
+            magic: SCENE_SNAPSHOT_MAGIC,
+            version: SCENE_SNAPSHOT_VERSION,
+            pixelsamples,
+            render_options,
+            scene,
+        };
+        // bincode::serialize() would walk the scene twice (to get the
+        // size first), the shared objects have to be seen only once
+        let mut data: Vec<u8> = Vec::new();
+        SHARED_WRITTEN.with(|written| *written.borrow_mut() = Some(HashMap::new()));
+        let result = bincode::serialize_into(&mut data, &snapshot);
+        SHARED_WRITTEN.with(|written| *written.borrow_mut() = None);
+        result?;
+        Ok(data)
+    }
+    pub fn from_bytes(data: &[u8]) -> Result<SceneSnapshot, SnapshotError> {
+        let header: SnapshotHeader = bincode::deserialize(data)?;
+        if header.magic != SCENE_SNAPSHOT_MAGIC {
+            return Err(SnapshotError::new("Not a scene snapshot"));
+        }
+        if header.version != SCENE_SNAPSHOT_VERSION {
+            return Err(SnapshotError::new(&format!(
+                "Scene snapshot version {} (expected {})",
+                header.version, SCENE_SNAPSHOT_VERSION
+            )));
+        }
+        SHARED_READ.with(|read| *read.borrow_mut() = Some(Vec::new()));
+        let result: bincode::Result<SnapshotOwned> = bincode::deserialize(data);
+        SHARED_READ.with(|read| *read.borrow_mut() = None);
+        let snapshot: SnapshotOwned = result?;
+        Ok(SceneSnapshot {
+            pixelsamples: snapshot.pixelsamples,
+            render_options: snapshot.render_options,
+            scene: snapshot.scene,
+        })
+    }
+    /// The snapshot with the given
+    /// [scene_hash()](../../tilejob/fn.scene_hash.html), if this
+    /// process decoded it before.
+    pub fn cached(scene_hash: &str) -> Option<Arc<SceneSnapshot>> {
+        let mut cache = SNAPSHOT_CACHE.lock().unwrap();
+        let index: usize = cache.iter().position(|(hash, _)| hash == scene_hash)?;
+        let entry = cache.remove(index);
+        let snapshot: Arc<SceneSnapshot> = entry.1.clone();
+        cache.push(entry);
+        Some(snapshot)
+    }
+    /// Like [from_bytes()](#method.from_bytes), but the snapshot is
+    /// decoded only once per process and shared by all tile jobs for
+    /// the same scene (see [cached()](#method.cached)).
+    pub fn from_bytes_cached(data: &[u8]) -> Result<Arc<SceneSnapshot>, SnapshotError> {
+        let hash: String = scene_hash(data);
+        if let Some(snapshot) = SceneSnapshot::cached(&hash) {
+            return Ok(snapshot);
+        }
+        // decoding takes a while, other scenes can be looked up meanwhile
+        let snapshot: Arc<SceneSnapshot> = Arc::new(SceneSnapshot::from_bytes(data)?);
+        let mut cache = SNAPSHOT_CACHE.lock().unwrap();
+        if let Some((_, cached)) = cache.iter().find(|(cached, _)| *cached == hash) {
+            return Ok(cached.clone());
+        }
+        if cache.len() >= SCENE_SNAPSHOT_CACHE_SIZE {
+            cache.remove(0);
+        }
+        cache.push((hash, snapshot.clone()));
+        Ok(snapshot)
+    }
+}
+
+/// Serde helpers for fields holding an **Arc** which might be shared
+/// with other parts of the scene, use with `#[serde(with = ...)]`.
+///
+/// The first time an object is written it gets the next index (after
+/// its own content, which might contain shared objects as well),
+/// later occurrences only write that index. Outside of a
+/// [SceneSnapshot](../struct.SceneSnapshot.html) the content is
+/// always written.
+pub mod shared {
+    use super::*;
+
+    #[derive(Serialize)]
+    enum SharedRef<'a, T> {
+        Value(&'a T),
+        Index(u32),
+    }
+
+    #[derive(Deserialize)]
+    enum SharedOwned<T> {
+        Value(T),
+        Index(u32),
+    }
+
+    pub fn serialize<S, T>(value: &Arc<T>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: Serialize,
+    {
+        let address: usize = &**value as *const T as usize;
+        let index: Option<u32> = SHARED_WRITTEN.with(|written| {
+            written
+                .borrow()
+                .as_ref()
+                .and_then(|map| map.get(&address).copied())
+        });
+        if let Some(index) = index {
+            return SharedRef::<T>::Index(index).serialize(serializer);
+        }
+        let result = SharedRef::Value(&**value).serialize(serializer);
+        SHARED_WRITTEN.with(|written| {
+            if let Some(map) = written.borrow_mut().as_mut() {
+                let index: u32 = map.len() as u32;
+                map.insert(address, index);
+            }
+        });
+        result
+    }
+
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<Arc<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: Deserialize<'de> + Send + Sync + 'static,
+    {
+        match SharedOwned::<T>::deserialize(deserializer)? {
+            SharedOwned::Value(value) => {
+                let value: Arc<T> = Arc::new(value);
+                SHARED_READ.with(|read| {
+                    if let Some(objects) = read.borrow_mut().as_mut() {
+                        objects.push(value.clone());
+                    }
+                });
+                Ok(value)
+            }
+            SharedOwned::Index(index) => {
+                let object: Option<Arc<dyn Any + Send + Sync>> = SHARED_READ.with(|read| {
+                    read.borrow()
+                        .as_ref()
+                        .and_then(|objects| objects.get(index as usize).cloned())
+                });
+                match object.map(|object| object.downcast::<T>()) {
+                    Some(Ok(value)) => Ok(value),
+                    _ => Err(serde::de::Error::custom(format!(
+                        "invalid shared object index {}",
+                        index
+                    ))),
+                }
+            }
+        }
+    }
+
+    struct Wrapper<'a, T>(&'a Arc<T>);
+
+    impl<'a, T: Serialize> Serialize for Wrapper<'a, T> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serialize(self.0, serializer)
+        }
+    }
+
+    struct WrapperOwned<T>(Arc<T>);
+
+    impl<'de, T> Deserialize<'de> for WrapperOwned<T>
+    where
+        T: Deserialize<'de> + Send + Sync + 'static,
+    {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserialize(deserializer).map(WrapperOwned)
+        }
+    }
+
+    /// Same as [shared](index.html) for `Option<Arc<T>>`.
+    pub mod option {
+        use super::*;
+
+        pub fn serialize<S, T>(value: &Option<Arc<T>>, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+            T: Serialize,
+        {
+            value.as_ref().map(Wrapper).serialize(serializer)
+        }
+
+        pub fn deserialize<'de, D, T>(deserializer: D) -> Result<Option<Arc<T>>, D::Error>
+        where
+            D: Deserializer<'de>,
+            T: Deserialize<'de> + Send + Sync + 'static,
+        {
+            let value: Option<WrapperOwned<T>> = Option::deserialize(deserializer)?;
+            Ok(value.map(|w| w.0))
+        }
+    }
+
+    /// Same as [shared](index.html) for `Vec<Arc<T>>`.
+    pub mod vec {
+        use super::*;
+
+        pub fn serialize<S, T>(value: &[Arc<T>], serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+            T: Serialize,
+        {
+            serializer.collect_seq(value.iter().map(Wrapper))
+        }
+
+        pub fn deserialize<'de, D, T>(deserializer: D) -> Result<Vec<Arc<T>>, D::Error>
+        where
+            D: Deserializer<'de>,
+            T: Deserialize<'de> + Send + Sync + 'static,
+        {
+            let value: Vec<WrapperOwned<T>> = Vec::deserialize(deserializer)?;
+            Ok(value.into_iter().map(|w| w.0).collect())
+        }
+    }
+}
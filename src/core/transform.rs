@@ -57,6 +57,7 @@ use serde::{Deserialize, Serialize};
 use std::cell::Cell;
 use std::f32::consts::PI;
 use std::ops::{Add, Mul};
+use std::sync::Arc;
 
 // pbrt
 use crate::core::geometry::{
@@ -901,10 +902,12 @@ impl DerivativeTerm {
     }
 }
 
-#[derive(Debug, Default, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct AnimatedTransform {
-    start_transform: Transform,
-    end_transform: Transform,
+    #[serde(with = "crate::core::snapshot::shared")]
+    start_transform: Arc<Transform>,
+    #[serde(with = "crate::core::snapshot::shared")]
+    end_transform: Arc<Transform>,
     start_time: Float,
     end_time: Float,
     actually_animated: bool,
@@ -921,17 +924,17 @@ pub struct AnimatedTransform {
 
 impl AnimatedTransform {
     pub fn new(
-        start_transform: &Transform,
+        start_transform: &Arc<Transform>,
         start_time: Float,
-        end_transform: &Transform,
+        end_transform: &Arc<Transform>,
         end_time: Float,
     ) -> Self {
         let mut at: AnimatedTransform = AnimatedTransform::default();
-        at.start_transform = *start_transform;
-        at.end_transform = *end_transform;
+        at.start_transform = start_transform.clone();
+        at.end_transform = end_transform.clone();
         at.start_time = start_time;
         at.end_time = end_time;
-        at.actually_animated = *start_transform != *end_transform;
+        at.actually_animated = **start_transform != **end_transform;
         AnimatedTransform::decompose(&start_transform.m, &mut at.t[0], &mut at.r[0], &mut at.s[0]);
         AnimatedTransform::decompose(&end_transform.m, &mut at.t[1], &mut at.r[1], &mut at.s[1]);
         // flip _r[1]_ if needed to select shortest path
@@ -2090,11 +2093,11 @@ impl AnimatedTransform {
     pub fn interpolate(&self, time: Float, t: &mut Transform) {
         // handle boundary conditions for matrix interpolation
         if !self.actually_animated || time <= self.start_time {
-            *t = self.start_transform;
+            *t = *self.start_transform;
             return;
         }
         if time >= self.end_time {
-            *t = self.end_transform;
+            *t = *self.end_transform;
             return;
         }
         let dt: Float = (time - self.start_time) / (self.end_time - self.start_time);
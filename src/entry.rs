@@ -7,30 +7,33 @@ struct PbrtParser;
 #[cfg(not(feature = "ecp"))]
 use wasm_bindgen::prelude::*;
 
-use crate::backend::get_content_binary;
+use crate::backend::{
+    get_content_binary, get_content_string, resolve_filename_in, set_content_backend,
+    ContentBackend, LocalBackend,
+};
 
 // parser
 use pest::Parser;
 
-// command line options
-use structopt::StructOpt;
 // pbrt
 use crate::core::api::{
     pbrt_accelerator, pbrt_active_transform_all, pbrt_active_transform_end_time,
     pbrt_active_transform_start_time, pbrt_area_light_source, pbrt_attribute_begin,
     pbrt_attribute_end, pbrt_camera, pbrt_cleanup, pbrt_concat_transform, pbrt_coord_sys_transform,
-    pbrt_film, pbrt_init, pbrt_integrator, pbrt_light_source, pbrt_look_at,
+    pbrt_film, pbrt_init, pbrt_integrator, pbrt_light_group, pbrt_light_source, pbrt_look_at,
     pbrt_make_named_material, pbrt_make_named_medium, pbrt_material, pbrt_medium_interface,
     pbrt_named_material, pbrt_object_begin, pbrt_object_end, pbrt_object_instance,
-    pbrt_pixel_filter, pbrt_reverse_orientation, pbrt_rotate, pbrt_sampler, pbrt_scale, pbrt_shape,
-    pbrt_texture, pbrt_transform, pbrt_transform_begin, pbrt_transform_end, pbrt_translate,
-    pbrt_world_begin,
+    pbrt_pixel_filter, pbrt_render_snapshot, pbrt_reverse_orientation, pbrt_rotate, pbrt_sampler, pbrt_scale, pbrt_shape,
+    pbrt_texture, pbrt_transform, pbrt_transform_begin, pbrt_transform_end, pbrt_transform_times,
+    pbrt_translate, pbrt_world_begin,
 };
 use crate::core::api::{ApiState, BsdfState, EcpState};
 use crate::core::geometry::{Normal3f, Point2f, Point3f, Vector3f};
 use crate::core::paramset::ParamSet;
 use crate::core::pbrt::{Float, Spectrum};
+use crate::core::snapshot::SceneSnapshot;
 use crate::core::transform::Transform;
+use crate::tilejob::{TileJob, TileJobError};
 // std
 use std::env;
 use std::fs::File;
@@ -38,6 +41,7 @@ use std::io::BufReader;
 use std::io::Read;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::Arc;
 
 #[cfg(not(feature = "ecp"))]
 #[wasm_bindgen]
@@ -177,6 +181,20 @@ fn pbrt_string_parameter(pairs: &mut pest::iterators::Pairs<Rule>) -> (String, S
     (string1, string2)
 }
 
+fn pbrt_string_parameters(pairs: &mut pest::iterators::Pairs<Rule>) -> (String, Vec<String>) {
+    // one or more strings with brackets, a single string without
+    let ident = pairs.next();
+    let string1: String = String::from_str(ident.unwrap().clone().as_span().as_str()).unwrap();
+    let mut strings: Vec<String> = Vec::new();
+    for pair in pairs {
+        if pair.as_rule() == Rule::string {
+            let ident = pair.into_inner().next();
+            strings.push(String::from_str(ident.unwrap().clone().as_span().as_str()).unwrap());
+        }
+    }
+    (string1, strings)
+}
+
 #[allow(dead_code)]
 fn pbrt_texture_parameter(pairs: &mut pest::iterators::Pairs<Rule>) -> (String, String) {
     // single string with or without brackets
@@ -382,11 +400,11 @@ fn extract_params(key_word: String, pairs: pest::iterators::Pair<Rule>) -> Param
                             params.add_sampled_spectrum_files(string1, strings);
                         }
                         Rule::string_param => {
-                            let tuple: (String, String) =
-                                pbrt_string_parameter(&mut parameter_pair.into_inner());
+                            let tuple: (String, Vec<String>) =
+                                pbrt_string_parameters(&mut parameter_pair.into_inner());
                             let string1: String = tuple.0;
-                            let string2: String = tuple.1;
-                            params.add_string(string1, string2);
+                            let strings: Vec<String> = tuple.1;
+                            params.add_strings(string1, strings);
                         }
                         Rule::texture_param => {
                             let tuple: (String, String) =
@@ -424,6 +442,12 @@ fn extract_params(key_word: String, pairs: pest::iterators::Pair<Rule>) -> Param
     params
 }
 
+/// A scene which doesn't follow the grammar is the client's fault,
+/// workers answer with a 4xx instead of panicking.
+fn parse_error(e: pest::error::Error<Rule>) -> TileJobError {
+    TileJobError::new(422, &format!("Unable to parse scene: {}", e))
+}
+
 #[allow(dead_code)]
 fn parse_line(
     api_state: &mut ApiState,
@@ -432,7 +456,7 @@ fn parse_line(
     identifier: &str,
     str_buf: String,
     integrator_arg: &Option<String>,
-) {
+) -> Result<(), TileJobError> {
     // #[cfg(not(feature = "ecp"))]
     // log(&msg);
     if str_buf == "" {
@@ -474,7 +498,7 @@ fn parse_line(
             "WorldEnd" => {
                 // WorldEnd
 
-                let output = pbrt_cleanup(api_state, ecp_state, integrator_arg);
+                let output = pbrt_cleanup(api_state, ecp_state, integrator_arg)?;
                 if let Some(o) = output {
                     ecp_state.set_output(&o);
                 }
@@ -484,7 +508,7 @@ fn parse_line(
     } else {
         let statement = String::from(identifier) + " " + &str_buf;
         let pairs = PbrtParser::parse(Rule::name_and_or_params, &statement)
-            .expect("unsuccessful parse")
+            .map_err(parse_error)?
             .next()
             .unwrap();
         for inner_pair in pairs.into_inner() {
@@ -517,14 +541,10 @@ fn parse_line(
                         }
                         "Include" => {
                             // Include
-                            let mut include_file: String = params.name.clone();
-                            if let Some(ref search_directory) = api_state.search_directory {
-                                let mut path_buf: PathBuf = PathBuf::from("/");
-                                path_buf.push(search_directory.as_ref());
-                                path_buf.push(params.name);
-                                include_file = String::from(path_buf.to_str().unwrap());
-                                // println!("DEBUG: {:?}", include_file);
-                            }
+                            let include_file: String = resolve_filename_in(
+                                api_state.search_directory.as_deref(),
+                                &params.name,
+                            );
                             let todo: Vec<&str> = for_printing.splitn(3, '"').collect();
                             println!("Include {:?}", include_file);
                             parse_file(
@@ -534,12 +554,16 @@ fn parse_line(
                                 ecp_state,
                                 todo[2],
                                 integrator_arg,
-                            );
+                            )?;
                         }
                         "Integrator" => {
                             // Integrator
                             pbrt_integrator(api_state, params);
                         }
+                        "LightGroup" => {
+                            // LightGroup
+                            pbrt_light_group(api_state, params);
+                        }
                         "LightSource" => {
                             // LightSource
                             pbrt_light_source(api_state, params);
@@ -554,7 +578,7 @@ fn parse_line(
                         }
                         "Material" => {
                             // Material
-                            pbrt_material(api_state, params);
+                            pbrt_material(api_state, bsdf_state, params);
                         }
                         "NamedMaterial" => {
                             // NamedMaterial
@@ -566,7 +590,7 @@ fn parse_line(
                         }
                         "ObjectInstance" => {
                             // ObjectInstance
-                            pbrt_object_instance(api_state, params);
+                            pbrt_object_instance(api_state, bsdf_state, params);
                         }
                         "PixelFilter" => {
                             // PixelFilter
@@ -737,6 +761,17 @@ fn parse_line(
                     );
                     pbrt_transform(api_state, &tr);
                 }
+                Rule::transform_times => {
+                    // TransformTimes start end
+                    let mut v: Vec<Float> = Vec::new();
+                    for rule_pair in inner_pair.into_inner() {
+                        let number: Float =
+                            f32::from_str(rule_pair.clone().as_span().as_str()).unwrap();
+                        v.push(number);
+                    }
+                    // println!("TransformTimes {} {}", v[0], v[1]);
+                    pbrt_transform_times(api_state, v[0], v[1]);
+                }
                 Rule::translate => {
                     // Translate x y z
                     let mut v: Vec<Float> = Vec::new();
@@ -757,6 +792,7 @@ fn parse_line(
             }
         }
     }
+    Ok(())
 }
 
 #[allow(dead_code)]
@@ -767,14 +803,18 @@ fn parse_file(
     ecp_state: &mut EcpState,
     append: &str,
     integrator_arg: &Option<String>,
-) {
+) -> Result<(), TileJobError> {
     // println!("FILE = {}", x);
-	let data = get_content_binary(&filename);
-	if data.is_err() {
-		return;
-	}
-	let data = data.unwrap();
-	let mut reader = BufReader::new(data.as_slice());
+    let data: Vec<u8> = match get_content_binary(&filename) {
+        Ok(data) => data,
+        Err(e) => {
+            return Err(TileJobError::new(
+                404,
+                &format!("Unable to read {:?}: {}", filename, e),
+            ));
+        }
+    };
+    let mut reader = BufReader::new(data.as_slice());
     // let f = File::open(filename.clone()).unwrap();
     // let ip: &Path = Path::new(filename.as_str());
     // if ip.is_relative() {
@@ -796,7 +836,7 @@ fn parse_file(
         str_buf += "\n";
     }
     let pairs = PbrtParser::parse(Rule::pbrt, &str_buf)
-        .expect("unsuccessful parse")
+        .map_err(parse_error)?
         .next()
         .unwrap();
     let mut identifier: &str = "";
@@ -823,7 +863,7 @@ fn parse_file(
                                     identifier,
                                     parse_again.clone(),
                                     integrator_arg,
-                                );
+                                )?;
                             }
                             identifier = statement_pair.as_str();
                             parse_again = String::default();
@@ -877,13 +917,14 @@ fn parse_file(
                 identifier,
                 parse_again.clone(),
                 integrator_arg,
-            ),
+            )?,
             _ => unreachable!(),
         }
     }
     // println!("Number of comment line(s):   {}", comment_count);
     // println!("Number of parameter line(s): {}", todo_count);
     // println!("Number of empty line(s):     {}", empty_count);
+    Ok(())
 }
 
 #[allow(dead_code)]
@@ -894,11 +935,11 @@ fn parse_data(
     ecp_state: &mut EcpState,
     append: &str,
     integrator_arg: &Option<String>,
-) {
-	api_state.search_directory = Some(Box::new(PathBuf::from(&ecp_state.filename)));
+) -> Result<(), TileJobError> {
+    api_state.set_search_directory(PathBuf::from(&ecp_state.filename));
 
 	let pairs = PbrtParser::parse(Rule::pbrt, &data)
-        .expect("unsuccessful parse")
+        .map_err(parse_error)?
         .next()
         .unwrap();
     let mut identifier: &str = "";
@@ -925,7 +966,7 @@ fn parse_data(
                                     identifier,
                                     parse_again.clone(),
                                     integrator_arg,
-                                );
+                                )?;
                             }
                             identifier = statement_pair.as_str();
                             parse_again = String::default();
@@ -979,23 +1020,28 @@ fn parse_data(
                 identifier,
                 parse_again.clone(),
                 integrator_arg,
-            ),
+            )?,
             _ => unreachable!(),
         }
     }
     // println!("Number of comment line(s):   {}", comment_count);
     // println!("Number of parameter line(s): {}", todo_count);
     // println!("Number of empty line(s):     {}", empty_count);
+    Ok(())
 }
 
+#[allow(dead_code)]
 pub fn entry(
     collector: bool,
     tile_size: i32,
     x: Option<u32>,
     y: Option<u32>,
     filename: &str,
-	data: &str,
+    data: &str,
+    content_backend: Arc<dyn ContentBackend>,
 ) -> Vec<u8> {
+    // all content (includes, textures, meshes) is fetched from here
+    let _content_backend = set_content_backend(content_backend);
     // handle command line options
 
     let git_describe = option_env!("GIT_DESCRIBE").unwrap_or("unknown");
@@ -1017,13 +1063,127 @@ pub fn entry(
     // #[cfg(not(feature = "ecp"))]
     // log(&msg);
 
-    parse_data(
+    if let Err(e) = parse_data(
         data,
         &mut api_state,
         &mut bsdf_state,
         &mut ecp_state,
         "",
         &None,
-    );
+    ) {
+        println!("ERROR: {}", e.error);
+    }
     ecp_state.get_output_for_js()
 }
+
+/// Render the tile described by `tile_job` on a worker. A scene
+/// snapshot written by the collector (`<filename>/main.snapshot`, see
+/// [SceneSnapshot](../core/snapshot/struct.SceneSnapshot.html)) is
+/// preferred over parsing `<filename>/main.pbrt` and building the
+/// scene again.
+#[allow(dead_code)]
+pub fn entry_tile_job(
+    tile_job: &TileJob,
+    content_backend: Arc<dyn ContentBackend>,
+) -> Result<Vec<u8>, TileJobError> {
+    let mut ecp_state = EcpState::default();
+    ecp_state.set_is_collector(false);
+    ecp_state.set_tile_size(tile_job.tile_size);
+    ecp_state.set_filename(&tile_job.filename);
+    ecp_state.x = Some(tile_job.x);
+    ecp_state.y = Some(tile_job.y);
+    ecp_state.samples = tile_job.get_sample_range();
+    let snapshot_file: String = format!("{}/main.snapshot", tile_job.filename);
+    // a snapshot with the expected scene hash was decoded before
+    let mut snapshot: Option<Arc<SceneSnapshot>> = match tile_job.scene_hash {
+        Some(ref hash) => SceneSnapshot::cached(hash),
+        None => None,
+    };
+    if snapshot.is_none() {
+        if let Ok(data) = content_backend.get_binary(&snapshot_file) {
+            tile_job.check_scene_hash(&data)?;
+            match SceneSnapshot::from_bytes_cached(&data) {
+                Ok(loaded) => snapshot = Some(loaded),
+                Err(e) => {
+                    return Err(TileJobError::new(
+                        500,
+                        &format!("Unable to load scene snapshot {}: {}", snapshot_file, e),
+                    ));
+                }
+            }
+        }
+    }
+    if let Some(snapshot) = snapshot {
+        // e.g. lens files of a realistic camera are still fetched
+        let _content_backend = set_content_backend(content_backend);
+        if let Some(output) = pbrt_render_snapshot(&snapshot, tile_job, 1, &ecp_state)? {
+            ecp_state.set_output(&output);
+        }
+        return Ok(ecp_state.get_output_for_js());
+    }
+    let main_file: String = format!("{}/main.pbrt", tile_job.filename);
+    let data: String = match content_backend.get_string(&main_file) {
+        Ok(data) => data,
+        Err(e) => return Err(TileJobError::new(404, &format!("{}", e))),
+    };
+    tile_job.check_scene_hash(data.as_bytes())?;
+    let _content_backend = set_content_backend(content_backend);
+    let (mut api_state, mut bsdf_state) = pbrt_init(0, 1, 0.0, 1.0, 0.0, 1.0);
+    api_state.set_tile_job(tile_job);
+    parse_data(
+        &data,
+        &mut api_state,
+        &mut bsdf_state,
+        &mut ecp_state,
+        "",
+        &tile_job.integrator,
+    )?;
+    let output: Vec<u8> = ecp_state.get_output_for_js();
+    if output.is_empty() {
+        return Err(TileJobError::new(
+            422,
+            &format!("{} doesn't render a tile (missing WorldEnd?)", main_file),
+        ));
+    }
+    Ok(output)
+}
+
+/// Render the scene file at `path` on this machine (see the
+/// **rs_pbrt** binary). The file's directory becomes the root of a
+/// [LocalBackend](../backend/struct.LocalBackend.html), the full
+/// image is rendered and written to disk.
+#[allow(dead_code)]
+pub fn entry_file(
+    path: &Path,
+    mut api_state: ApiState,
+    mut bsdf_state: BsdfState,
+    integrator_arg: &Option<String>,
+) {
+    let ip: PathBuf = if path.is_relative() {
+        env::current_dir().unwrap().join(path)
+    } else {
+        PathBuf::from(path)
+    };
+    let search_directory: &Path = ip.parent().unwrap();
+    let _content_backend = set_content_backend(Arc::new(LocalBackend::new(search_directory)));
+    let file_name: &str = ip.file_name().unwrap().to_str().unwrap();
+    let data: String = match get_content_string(file_name) {
+        Ok(data) => data,
+        Err(e) => {
+            println!("ERROR: {}", e);
+            return;
+        }
+    };
+    let mut ecp_state = EcpState::default();
+    ecp_state.set_is_collector(true);
+    if let Err(e) = parse_data(
+        &data,
+        &mut api_state,
+        &mut bsdf_state,
+        &mut ecp_state,
+        "",
+        integrator_arg,
+    ) {
+        println!("ERROR: {}", e.error);
+    }
+}
@@ -7,15 +7,20 @@ use std::time::Instant;
 
 // pbrt
 use crate::blockqueue::BlockQueue;
+use crate::collector::Dispatcher;
 use crate::core::camera::{Camera, CameraSample};
-use crate::core::film::{Film, FilmTile};
+#[cfg(target_arch = "wasm32")]
+use crate::core::film::set_collector_film;
+use crate::core::film::{AovSample, Film, FilmTile};
 use crate::core::geometry::{
-    nrm_abs_dot_vec3f, pnt2_inside_exclusivei, pnt3_offset_ray_origin, vec3_abs_dot_nrmf,
+    bnd2_intersect_bnd2i, nrm_abs_dot_vec3f, pnt2_inside_exclusivei, pnt3_offset_ray_origin, vec3_abs_dot_nrmf,
     vec3_dot_nrmf,
 };
 use crate::core::geometry::{
     Bounds2i, Bounds3f, Normal3f, Point2f, Point2i, Point3f, Ray, Vector2i, Vector3f,
 };
+#[cfg(not(target_arch = "wasm32"))]
+use crate::core::integrator::render_block_queue;
 use crate::core::interaction::{
     Interaction, InteractionCommon, MediumInteraction, SurfaceInteraction,
 };
@@ -27,7 +32,7 @@ use crate::core::medium::{HenyeyGreenstein, Medium, MediumInterface};
 use crate::core::pbrt::{Float, Spectrum};
 use crate::core::reflection::Bsdf;
 use crate::core::reflection::BxdfType;
-use crate::core::sampler::Sampler;
+use crate::core::sampler::{SampleRange, Sampler};
 use crate::core::sampling::Distribution1D;
 use crate::core::scene::Scene;
 
@@ -521,6 +526,17 @@ impl<'a> Vertex<'a> {
         }
         false
     }
+    /// The light group of the light (or area light) at this vertex.
+    pub fn get_light_group(&self) -> usize {
+        if let Some(ref ei) = self.ei {
+            if let Some(ref light) = ei.light {
+                return light.get_light_group();
+            }
+        } else if let Some(ref si) = self.si {
+            return si.get_light_group();
+        }
+        0_usize
+    }
     pub fn is_delta_light(&self) -> bool {
         if self.vertex_type != VertexType::Light {
             return false;
@@ -869,6 +885,7 @@ impl BDPTIntegrator {
         tile_size: i32,
         scene: &Scene,
         film: &'a Arc<Film>,
+        samples: &SampleRange,
     ) -> FilmTile<'a> {
         let sampler = &self.get_sampler();
         let camera = &self.get_camera();
@@ -879,7 +896,8 @@ impl BDPTIntegrator {
             y: y as i32,
         };
         let seed: i32 = tile.y * n_x_tiles + tile.x;
-        let mut tile_sampler: Box<Sampler> = sampler.clone_with_seed(seed as u64);
+        let mut tile_sampler: Box<Sampler> =
+            sampler.clone_with_seed(seed as u64 + samples.seed_offset);
         let x0: i32 = sample_bounds.p_min.x + tile.x * tile_size;
         let x1: i32 = std::cmp::min(x0 + tile_size, sample_bounds.p_max.x);
         let y0: i32 = sample_bounds.p_min.y + tile.y * tile_size;
@@ -888,12 +906,16 @@ impl BDPTIntegrator {
             Bounds2i::new(Point2i { x: x0, y: y0 }, Point2i { x: x1, y: y1 });
         // println!("Starting image tile {:?}", tile_bounds);
         let mut film_tile = film.get_film_tile(&tile_bounds);
+        let mut aov_sample: Option<AovSample> = film
+            .aov_layout
+            .as_ref()
+            .map(|layout| AovSample::new(layout.light_groups.len()));
         for p_pixel in &tile_bounds {
             tile_sampler.start_pixel(p_pixel);
             if !pnt2_inside_exclusivei(p_pixel, &integrator.pixel_bounds) {
                 continue;
             }
-            let mut done: bool = false;
+            let mut done: bool = !tile_sampler.start_sample_range(samples);
             while !done {
                 // Get a distribution for sampling
                 // the light at the start of the
@@ -950,6 +972,14 @@ impl BDPTIntegrator {
                             &mut light_vertices,
                         );
                     }
+                    if let Some(aov) = aov_sample.as_mut() {
+                        aov.clear();
+                        if n_camera > 1 {
+                            if let Some(ref si) = camera_vertices[1].si {
+                                aov.set_first_hit(&camera_vertices[0].p(), si);
+                            }
+                        }
+                    }
                     // Execute all BDPT connection strategies
                     let mut l: Spectrum = Spectrum::new(0.0 as Float);
                     // println!("n_camera = {:?}", n_camera);
@@ -970,6 +1000,7 @@ impl BDPTIntegrator {
                                 y: p_film.y,
                             };
                             let mut mis_weight: Option<Float> = Some(0.0 as Float);
+                            let mut light_group: usize = 0_usize;
                             let lpath: Spectrum = connect_bdpt(
                                 scene,
                                 &light_vertices,
@@ -981,6 +1012,7 @@ impl BDPTIntegrator {
                                 &mut tile_sampler,
                                 &mut p_film_new,
                                 mis_weight.as_mut(),
+                                Some(&mut light_group),
                             );
                             // if let Some(mis_weight_flt) = mis_weight {
                             //     println!("Connect bdpt s: {:?}, t: {:?}, lpath: {:?}, mis_weight: {:?}",
@@ -997,6 +1029,9 @@ impl BDPTIntegrator {
                             // }
                             if t != 1 {
                                 l += lpath;
+                                if let Some(aov) = aov_sample.as_mut() {
+                                    aov.add_radiance(&lpath, depth as u32, light_group);
+                                }
                             } else if !lpath.is_black() {
                                 film.add_splat(p_film_new, &lpath);
                             }
@@ -1009,7 +1044,11 @@ impl BDPTIntegrator {
                     //     l.y()
                     // );
                     film_tile.add_sample(p_film, &mut l, 1.0 as Float);
-                    done = !tile_sampler.start_next_sample();
+                    if let Some(aov) = aov_sample.as_ref() {
+                        // light tracing (t = 1) is only splatted into the image
+                        film_tile.add_aov_sample(p_film, aov, 1.0 as Float);
+                    }
+                    done = !tile_sampler.start_next_sample_in_range(samples);
                 }
             }
         }
@@ -1025,10 +1064,13 @@ impl BDPTIntegrator {
         x_start: Option<u32>,
         y_start: Option<u32>,
         data: &str,
+        samples: &SampleRange,
+        dispatcher: Option<&Dispatcher>,
     ) -> Option<Vec<u8>> {
-        // partition the image into tiles
+        // partition the image (or only the "pixelbounds") into tiles
         let film = self.get_camera().get_film();
-        let sample_bounds: Bounds2i = film.get_sample_bounds();
+        let sample_bounds: Bounds2i =
+            bnd2_intersect_bnd2i(&film.get_sample_bounds(), &self.pixel_bounds);
         let sample_extent: Vector2i = sample_bounds.diagonal();
         let n_x_tiles: i32 = (sample_extent.x + tile_size - 1) / tile_size;
         let n_y_tiles: i32 = (sample_extent.y + tile_size - 1) / tile_size;
@@ -1046,15 +1088,31 @@ impl BDPTIntegrator {
                         (0, 0),
                     );
                     let bq = &block_queue;
-                    let film = &film;
-                    while let Some((x, y)) = bq.next() {
-                        #[cfg(not(feature = "ecp"))]
-                        #[cfg(not(test))]
-						http_request(x, y, tile_size, data.to_string());
-
-                        #[cfg(test)]
-                        {
-                            let film_tile = self.render_tile(
+                    // in the browser the tiles are rendered by workers
+                    #[cfg(target_arch = "wasm32")]
+                    {
+                        // merge the padded tiles as they come back
+                        set_collector_film(Some(film.clone()));
+                        while let Some((x, y)) = bq.next() {
+                            #[cfg(not(feature = "ecp"))]
+                            #[cfg(not(test))]
+                            http_request(x, y, tile_size, data.to_string());
+                        }
+                    }
+                    // natively the collector renders the full image itself
+                    // (unless there are workers to send the tiles to)
+                    #[cfg(not(target_arch = "wasm32"))]
+                    {
+                        let film = &film;
+                        let mut bq = bq;
+                        let left: BlockQueue;
+                        if let Some(dispatcher) = dispatcher {
+                            // only the tiles no worker could render are left
+                            left = dispatcher.dispatch_block_queue(bq, &sample_bounds, film);
+                            bq = &left;
+                        }
+                        render_block_queue(bq, num_threads, film, |x, y| {
+                            self.render_tile(
                                 x,
                                 y,
                                 n_x_tiles,
@@ -1062,11 +1120,9 @@ impl BDPTIntegrator {
                                 tile_size,
                                 scene,
                                 film,
-                            );
-
-                            // send the tile through the channel to main thread
-                            film.merge_film_tile(&film_tile);
-                        }
+                                samples,
+                            )
+                        });
                     }
                 }
             } else {
@@ -1075,26 +1131,30 @@ impl BDPTIntegrator {
                 let y = y_start.unwrap();
                 #[cfg(ecp)]
                 let mut now = Instant::now();
-                let film_tile =
-                    self.render_tile(x, y, n_x_tiles, sample_bounds, tile_size, scene, film);
+                let film_tile = self.render_tile(
+                    x,
+                    y,
+                    n_x_tiles,
+                    sample_bounds,
+                    tile_size,
+                    scene,
+                    film,
+                    samples,
+                );
                 #[cfg(ecp)]
                 {
                     println!("render_tile: {}", now.elapsed().as_millis());
                     now = Instant::now();
                 }
-                let tile_image = film.get_tile_image(
-                    &film_tile,
-                    tile_size,
-                    x as i32,
-                    y as i32,
-                    sample_bounds,
-                    1.0 as Float,
-                );
+                // the collector merges the padded tile with its neighbours
+                let samples_per_pixel: u32 =
+                    samples.count(self.sampler.get_samples_per_pixel()) as u32;
+                let payload = film.get_tile_payload(&film_tile, samples_per_pixel, "bdpt");
                 #[cfg(ecp)]
-                println!("get_tile_image: {}", now.elapsed().as_millis());
-                return Some(tile_image);
+                println!("get_tile_payload: {}", now.elapsed().as_millis());
+                return Some(payload.to_bytes());
             }
-            #[cfg(test)]
+            #[cfg(not(target_arch = "wasm32"))]
             film.write_image(1.0 as Float);
         }
         None
@@ -2232,6 +2292,7 @@ pub fn connect_bdpt<'a>(
     sampler: &mut Sampler,
     p_raster: &mut Point2f,
     mis_weight_opt: Option<&mut Float>,
+    light_group_opt: Option<&mut usize>,
 ) -> Spectrum {
     // TODO: ProfilePhase _(Prof::BDPTConnectSubpaths);
     let mut l: Spectrum = Spectrum::default();
@@ -2478,6 +2539,15 @@ pub fn connect_bdpt<'a>(
     if let Some(mis_weight_ptr) = mis_weight_opt {
         *mis_weight_ptr = mis_weight_flt;
     }
+    if let Some(light_group_ptr) = light_group_opt {
+        *light_group_ptr = if s == 0 {
+            camera_vertices[t - 1].get_light_group()
+        } else if s == 1 {
+            sampled.get_light_group()
+        } else {
+            light_vertices[0].get_light_group()
+        };
+    }
     l
 }
 
@@ -400,6 +400,7 @@ impl MLTIntegrator {
             sampler,
             p_raster,
             None,
+            None,
         ) * (n_strategies as Float)
     }
     pub fn render(&self, scene: &Scene, num_threads: u8) {
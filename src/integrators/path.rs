@@ -2,19 +2,23 @@
 use std::borrow::Borrow;
 use std::cell::Cell;
 use std::sync::Arc;
+// others
+use smallvec::SmallVec;
 // pbrt
 // use crate::core::bssrdf::Bssrdf;
 use crate::core::camera::Camera;
+use crate::core::film::AovSample;
 use crate::core::geometry::{vec3_abs_dot_nrmf, vec3_dot_nrmf};
-use crate::core::geometry::{Bounds2i, Point2f, Ray, Vector3f};
-use crate::core::integrator::uniform_sample_one_light;
+use crate::core::geometry::{Bounds2i, Point2f, Point3f, Ray, Vector3f};
+use crate::core::guiding::{GuidingVertex, PathGuiding};
+use crate::core::integrator::sample_one_light;
 use crate::core::interaction::{Interaction, SurfaceInteraction};
 use crate::core::lightdistrib::create_light_sample_distribution;
 use crate::core::lightdistrib::LightDistribution;
 use crate::core::material::TransportMode;
 use crate::core::pbrt::{Float, Spectrum};
 use crate::core::reflection::BxdfType;
-use crate::core::sampler::Sampler;
+use crate::core::sampler::{AdaptiveSampling, SampleRange, Sampler};
 use crate::core::sampling::Distribution1D;
 use crate::core::scene::Scene;
 
@@ -32,6 +36,7 @@ pub struct PathIntegrator {
     rr_threshold: Float,           // 1.0
     light_sample_strategy: String, // "spatial"
     light_distribution: Option<Arc<LightDistribution>>,
+    guiding: Option<PathGuiding>,
 }
 
 impl PathIntegrator {
@@ -42,6 +47,7 @@ impl PathIntegrator {
         pixel_bounds: Bounds2i,
         rr_threshold: Float,
         light_sample_strategy: String,
+        guiding: Option<PathGuiding>,
     ) -> Self {
         PathIntegrator {
             camera,
@@ -51,21 +57,57 @@ impl PathIntegrator {
             rr_threshold,
             light_sample_strategy,
             light_distribution: None,
+            guiding,
         }
     }
     pub fn preprocess(&mut self, scene: &Scene) {
         self.light_distribution =
             create_light_sample_distribution(self.light_sample_strategy.clone(), scene);
+        if let Some(ref mut guiding) = self.guiding {
+            guiding.preprocess(scene);
+        }
+    }
+    /// With path guiding the first passes train the guiding
+    /// distribution (see [PathGuiding](../../core/guiding/struct.PathGuiding.html)).
+    pub fn get_passes(
+        &self,
+        samples: &SampleRange,
+        sampling: &AdaptiveSampling,
+    ) -> Vec<SampleRange> {
+        let samples_per_pixel: i64 = self.sampler.get_samples_per_pixel();
+        match self.guiding {
+            Some(ref guiding) => guiding.get_passes(samples, samples_per_pixel, sampling),
+            None => sampling.get_passes(samples, samples_per_pixel),
+        }
+    }
+    pub fn prepare_pass(&mut self, pass: &SampleRange) {
+        if let Some(ref mut guiding) = self.guiding {
+            guiding.prepare_pass(pass);
+        }
     }
     pub fn li(
+        &self,
+        r: &Ray,
+        scene: &Scene,
+        sampler: &mut Sampler,
+        // arena: &mut Arena,
+        depth: i32,
+    ) -> Spectrum {
+        self.li_aov(r, scene, sampler, depth, None)
+    }
+    /// Same as [li()](#method.li), but also fills in the AOVs (if
+    /// any) of the camera ray.
+    pub fn li_aov(
         &self,
         r: &Ray,
         scene: &Scene,
         sampler: &mut Sampler,
         // arena: &mut Arena,
         _depth: i32,
+        mut aovs: Option<&mut AovSample>,
     ) -> Spectrum {
         // TODO: ProfilePhase p(Prof::SamplerIntegratorLi);
+        let camera_origin: Point3f = r.o;
         let mut l: Spectrum = Spectrum::default();
         let mut beta: Spectrum = Spectrum::new(1.0 as Float);
         let mut ray: Ray = Ray {
@@ -87,6 +129,8 @@ impl PathIntegrator {
         // refracted rays that are about to be refracted back out of a
         // medium and thus have their beta value increased.
         let mut eta_scale: Float = 1.0;
+        // vertices of a path training the guiding distribution
+        let mut guiding_vertices: SmallVec<[GuidingVertex; 8]> = SmallVec::new();
         loop {
             // find next path vertex and accumulate contribution
             // println!("Path tracer bounce {:?}, current L = {:?}, beta = {:?}",
@@ -97,7 +141,11 @@ impl PathIntegrator {
                 // possibly add emitted light at intersection
                 if bounces == 0 || specular_bounce {
                     // add emitted light at path vertex
-                    l += beta * isect.le(&-ray.d);
+                    let le: Spectrum = beta * isect.le(&-ray.d);
+                    l += le;
+                    if let Some(aovs) = aovs.as_mut() {
+                        aovs.add_radiance(&le, bounces, isect.get_light_group());
+                    }
                     // println!("Added Le -> L = {:?}", l);
                 }
                 // terminate path if _maxDepth_ was reached
@@ -115,6 +163,11 @@ impl PathIntegrator {
                     // bounces--;
                     continue;
                 }
+                if bounces == 0 {
+                    if let Some(aovs) = aovs.as_mut() {
+                        aovs.set_first_hit(&camera_origin, &isect);
+                    }
+                }
                 if let Some(ref light_distribution) = self.light_distribution {
                     let distrib: Arc<Distribution1D> = light_distribution.lookup(&isect.common.p);
                     // Sample illumination from lights to find path contribution.
@@ -124,14 +177,12 @@ impl PathIntegrator {
                         if bsdf.num_components(bsdf_flags) > 0 {
                             // TODO: ++total_paths;
                             let it: &SurfaceInteraction = isect.borrow();
-                            let ld: Spectrum = beta
-                                * uniform_sample_one_light(
-                                    it,
-                                    scene,
-                                    sampler,
-                                    false,
-                                    Some(&distrib),
-                                );
+                            let (ld, light_group) =
+                                sample_one_light(it, scene, sampler, false, Some(&distrib));
+                            let ld: Spectrum = beta * ld;
+                            if let Some(aovs) = aovs.as_mut() {
+                                aovs.add_radiance(&ld, bounces + 1, light_group);
+                            }
                             // TODO: println!("Sampled direct lighting Ld = {:?}", ld);
                             // TODO: if ld.is_black() {
                             //     ++zero_radiance_paths;
@@ -145,14 +196,39 @@ impl PathIntegrator {
                         let mut pdf: Float = 0.0 as Float;
                         let bsdf_flags: u8 = BxdfType::BsdfAll as u8;
                         let mut sampled_type: u8 = u8::max_value(); // != 0
-                        let f: Spectrum = bsdf.sample_f(
-                            &wo,
-                            &mut wi,
-                            &sampler.get_2d(),
-                            &mut pdf,
-                            bsdf_flags,
-                            &mut sampled_type,
-                        );
+                        let guiding: Option<&PathGuiding> = match self.guiding {
+                            Some(ref guiding)
+                                if isect.bssrdf.is_none() && PathGuiding::is_guidable(bsdf) =>
+                            {
+                                Some(guiding)
+                            }
+                            _ => None,
+                        };
+                        let guide = guiding.and_then(|guiding| {
+                            guiding.lookup(&isect.common.p).map(|tree| (guiding, tree))
+                        });
+                        let f: Spectrum = if let Some((guiding, tree)) = guide {
+                            let u_strategy: Float = sampler.get_1d();
+                            guiding.sample_f(
+                                tree,
+                                bsdf,
+                                &wo,
+                                &mut wi,
+                                u_strategy,
+                                &sampler.get_2d(),
+                                &mut pdf,
+                                &mut sampled_type,
+                            )
+                        } else {
+                            bsdf.sample_f(
+                                &wo,
+                                &mut wi,
+                                &sampler.get_2d(),
+                                &mut pdf,
+                                bsdf_flags,
+                                &mut sampled_type,
+                            )
+                        };
 
                         // println!("Sampled BSDF, f = {:?}, pdf = {:?}", f, pdf);
                         if f.is_black() || pdf == 0.0 as Float {
@@ -171,6 +247,17 @@ impl PathIntegrator {
                             isect.shading.n,
                             pdf
                         );
+                        if let Some(guiding) = guiding {
+                            if guiding.is_training() {
+                                guiding_vertices.push(GuidingVertex {
+                                    p: isect.common.p,
+                                    wi,
+                                    beta,
+                                    l,
+                                    pdf,
+                                });
+                            }
+                        }
                         specular_bounce = (sampled_type & BxdfType::BsdfSpecular as u8) != 0_u8;
                         if ((sampled_type & BxdfType::BsdfSpecular as u8) != 0_u8)
                             && ((sampled_type & BxdfType::BsdfTransmission as u8) != 0_u8)
@@ -214,14 +301,17 @@ impl PathIntegrator {
                                     // account for the direct subsurface scattering component
                                     let distrib: Arc<Distribution1D> =
                                         light_distribution.lookup(&pi.common.p);
-                                    l += beta
-                                        * uniform_sample_one_light(
-                                            &pi,
-                                            scene,
-                                            sampler,
-                                            false,
-                                            Some(&distrib),
-                                        );
+                                    let (ld, light_group) = sample_one_light(
+                                        &pi,
+                                        scene,
+                                        sampler,
+                                        false,
+                                        Some(&distrib),
+                                    );
+                                    l += beta * ld;
+                                    if let Some(aovs) = aovs.as_mut() {
+                                        aovs.add_radiance(&(beta * ld), bounces + 2, light_group);
+                                    }
                                     // account for the indirect subsurface scattering component
                                     let mut wi: Vector3f = Vector3f::default();
                                     let mut pdf: Float = 0.0 as Float;
@@ -270,7 +360,11 @@ impl PathIntegrator {
                 if bounces == 0 || specular_bounce {
                     // for (const auto &light : scene.infiniteLights)
                     for light in &scene.infinite_lights {
-                        l += beta * light.le(&ray);
+                        let le: Spectrum = beta * light.le(&ray);
+                        l += le;
+                        if let Some(aovs) = aovs.as_mut() {
+                            aovs.add_radiance(&le, bounces, light.get_light_group());
+                        }
                     }
                     // println!("Added infinite area lights -> L = {:?}", l);
                 }
@@ -279,6 +373,11 @@ impl PathIntegrator {
             }
             bounces += 1_u32;
         }
+        if let Some(ref guiding) = self.guiding {
+            if !guiding_vertices.is_empty() {
+                guiding.record_path(&guiding_vertices, &l);
+            }
+        }
         l
     }
     pub fn get_camera(&self) -> Arc<Camera> {
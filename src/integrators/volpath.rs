@@ -5,9 +5,10 @@ use std::sync::Arc;
 // pbrt
 // use crate::core::bssrdf::Bssrdf;
 use crate::core::camera::Camera;
+use crate::core::film::AovSample;
 use crate::core::geometry::{vec3_abs_dot_nrmf, vec3_dot_nrmf};
-use crate::core::geometry::{Bounds2i, Point2f, Ray, Vector3f};
-use crate::core::integrator::uniform_sample_one_light;
+use crate::core::geometry::{Bounds2i, Point2f, Point3f, Ray, Vector3f};
+use crate::core::integrator::sample_one_light;
 use crate::core::interaction::{Interaction, MediumInteraction, SurfaceInteraction};
 use crate::core::lightdistrib::create_light_sample_distribution;
 use crate::core::lightdistrib::LightDistribution;
@@ -59,14 +60,28 @@ impl VolPathIntegrator {
             create_light_sample_distribution(self.light_sample_strategy.clone(), scene);
     }
     pub fn li(
+        &self,
+        r: &mut Ray,
+        scene: &Scene,
+        sampler: &mut Sampler,
+        // arena: &mut Arena,
+        depth: i32,
+    ) -> Spectrum {
+        self.li_aov(r, scene, sampler, depth, None)
+    }
+    /// Same as [li()](#method.li), but also fills in the AOVs (if
+    /// any) of the camera ray.
+    pub fn li_aov(
         &self,
         r: &mut Ray,
         scene: &Scene,
         sampler: &mut Sampler,
         // arena: &mut Arena,
         _depth: i32,
+        mut aovs: Option<&mut AovSample>,
     ) -> Spectrum {
         // TODO: ProfilePhase p(Prof::SamplerIntegratorLi);
+        let camera_origin: Point3f = r.o;
         let mut l: Spectrum = Spectrum::default();
         let mut beta: Spectrum = Spectrum::new(1.0 as Float);
         let mut ray: Ray = Ray {
@@ -117,14 +132,17 @@ impl VolPathIntegrator {
                         // handle scattering at point in medium for volumetric path tracer
                         if let Some(ref light_distribution) = self.light_distribution {
                             let distrib: Arc<Distribution1D> = light_distribution.lookup(&mi_p);
-                            l += beta
-                                * uniform_sample_one_light(
-                                    &mi as &dyn Interaction,
-                                    scene,
-                                    sampler,
-                                    true,
-                                    Some(&distrib),
-                                );
+                            let (ld, light_group) = sample_one_light(
+                                &mi as &dyn Interaction,
+                                scene,
+                                sampler,
+                                true,
+                                Some(&distrib),
+                            );
+                            l += beta * ld;
+                            if let Some(aovs) = aovs.as_mut() {
+                                aovs.add_radiance(&(beta * ld), bounces + 1, light_group);
+                            }
                             let mut wi: Vector3f = Vector3f::default();
                             phase.sample_p(&(-ray.d), &mut wi, sampler.get_2d());
                             ray = mi.spawn_ray(&wi);
@@ -136,7 +154,11 @@ impl VolPathIntegrator {
                     // possibly add emitted light at intersection
                     if bounces == 0 || specular_bounce {
                         // add emitted light at path vertex
-                        l += beta * isect.le(&-ray.d);
+                        let le: Spectrum = beta * isect.le(&-ray.d);
+                        l += le;
+                        if let Some(aovs) = aovs.as_mut() {
+                            aovs.add_radiance(&le, bounces, isect.get_light_group());
+                        }
                     }
                     // terminate path if _maxDepth_ was reached
                     if bounces >= self.max_depth {
@@ -152,20 +174,23 @@ impl VolPathIntegrator {
                         // bounces--;
                         continue;
                     }
+                    if bounces == 0 {
+                        if let Some(aovs) = aovs.as_mut() {
+                            aovs.set_first_hit(&camera_origin, &isect);
+                        }
+                    }
                     if let Some(ref light_distribution) = self.light_distribution {
                         let light_distrib: Arc<Distribution1D> =
                             light_distribution.lookup(&isect.common.p);
                         // Sample illumination from lights to find
                         // attenuated path contribution.
                         let it: &SurfaceInteraction = isect.borrow();
-                        l += beta
-                            * uniform_sample_one_light(
-                                it,
-                                scene,
-                                sampler,
-                                true,
-                                Some(&light_distrib),
-                            );
+                        let (ld, light_group) =
+                            sample_one_light(it, scene, sampler, true, Some(&light_distrib));
+                        l += beta * ld;
+                        if let Some(aovs) = aovs.as_mut() {
+                            aovs.add_radiance(&(beta * ld), bounces + 1, light_group);
+                        }
                         if let Some(ref bsdf) = isect.bsdf {
                             // Sample BSDF to get new path direction
                             let wo: Vector3f = -ray.d;
@@ -237,14 +262,21 @@ impl VolPathIntegrator {
                                         // account for the direct subsurface scattering component
                                         let distrib: Arc<Distribution1D> =
                                             light_distribution.lookup(&pi.common.p);
-                                        l += beta
-                                            * uniform_sample_one_light(
-                                                &pi,
-                                                scene,
-                                                sampler,
-                                                true,
-                                                Some(&distrib),
+                                        let (ld, light_group) = sample_one_light(
+                                            &pi,
+                                            scene,
+                                            sampler,
+                                            true,
+                                            Some(&distrib),
+                                        );
+                                        l += beta * ld;
+                                        if let Some(aovs) = aovs.as_mut() {
+                                            aovs.add_radiance(
+                                                &(beta * ld),
+                                                bounces + 2,
+                                                light_group,
                                             );
+                                        }
                                         // account for the indirect subsurface scattering component
                                         let mut wi: Vector3f = Vector3f::default();
                                         let mut pdf: Float = 0.0 as Float;
@@ -318,14 +350,17 @@ impl VolPathIntegrator {
                         // handle scattering at point in medium for volumetric path tracer
                         if let Some(ref light_distribution) = self.light_distribution {
                             let distrib: Arc<Distribution1D> = light_distribution.lookup(&mi_p);
-                            l += beta
-                                * uniform_sample_one_light(
-                                    &mi as &dyn Interaction,
-                                    scene,
-                                    sampler,
-                                    true,
-                                    Some(&distrib),
-                                );
+                            let (ld, light_group) = sample_one_light(
+                                &mi as &dyn Interaction,
+                                scene,
+                                sampler,
+                                true,
+                                Some(&distrib),
+                            );
+                            l += beta * ld;
+                            if let Some(aovs) = aovs.as_mut() {
+                                aovs.add_radiance(&(beta * ld), bounces + 1, light_group);
+                            }
                             let mut wi: Vector3f = Vector3f::default();
                             phase.sample_p(&(-ray.d), &mut wi, sampler.get_2d());
                             ray = mi.spawn_ray(&wi);
@@ -336,7 +371,11 @@ impl VolPathIntegrator {
                 // add emitted light from the environment
                 if bounces == 0 || specular_bounce {
                     for light in &scene.infinite_lights {
-                        l += beta * light.le(&mut ray);
+                        let le: Spectrum = beta * light.le(&mut ray);
+                        l += le;
+                        if let Some(aovs) = aovs.as_mut() {
+                            aovs.add_radiance(&le, bounces, light.get_light_group());
+                        }
                     }
                 }
                 // terminate path if ray escaped
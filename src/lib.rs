@@ -10,6 +10,7 @@ extern crate serde;
 pub mod accelerators;
 pub mod blockqueue;
 pub mod cameras;
+pub mod collector;
 pub mod core;
 mod entry;
 pub mod filters;
@@ -20,16 +21,18 @@ pub mod media;
 pub mod samplers;
 pub mod shapes;
 pub mod textures;
+pub mod tilejob;
 pub mod backend;
 
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::backend::ContentBackend;
+    use crate::tilejob::TileJob;
+    use std::sync::Arc;
 
-    #[test]
-    fn lib_entry_test() {
-        let data = r##"# Camera
+    const CORNELL_DATA: &str = r##"# Camera
     	Scale -1 1 1 # swap x-axis direction
     	LookAt -0.2779999521691323 -0.800000037997961 0.2730000129668042 # position
     		   -0.2779999521691323 -0.7990000379504636 0.2730000129668042 # target
@@ -247,12 +250,1748 @@ mod tests {
     	  AttributeEnd
     	WorldEnd
     "##;
-        lib_entry(16, data.to_string());
+
+    /// A temporary directory, unique per test and run (so tests
+    /// running in parallel don't share files), removed when dropped.
+    struct TestDir {
+        path: std::path::PathBuf,
+    }
+
+    impl TestDir {
+        fn new(name: &str) -> Self {
+            use std::sync::atomic::{AtomicUsize, Ordering};
+            static COUNTER: AtomicUsize = AtomicUsize::new(0);
+            let path: std::path::PathBuf = std::env::temp_dir().join(format!(
+                "rs_pbrt_{}_{}_{}",
+                name,
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            std::fs::create_dir_all(&path).unwrap();
+            TestDir { path }
+        }
+        fn join(&self, path: &str) -> std::path::PathBuf {
+            self.path.join(path)
+        }
+        /// Write `scene_text` to `main.pbrt` in the subdirectory
+        /// `scene` (if not empty).
+        fn write_scene(&self, scene: &str, scene_text: &str) -> std::path::PathBuf {
+            let scene_dir: std::path::PathBuf = self.path.join(scene);
+            std::fs::create_dir_all(&scene_dir).unwrap();
+            let scene_file: std::path::PathBuf = scene_dir.join("main.pbrt");
+            std::fs::write(&scene_file, scene_text).unwrap();
+            scene_file
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.path);
+        }
+    }
+
+    /// Parse `scene_file` like **rs_pbrt** does, but write the scene
+    /// snapshot a collector sends to its workers (to `dir`) instead of
+    /// rendering, and load it again.
+    fn snapshot_scene_file(
+        dir: &TestDir,
+        scene_file: &std::path::Path,
+    ) -> core::snapshot::SceneSnapshot {
+        let snapshot_file: std::path::PathBuf = dir.join("main.snapshot");
+        let (mut api_state, bsdf_state) = core::api::pbrt_init(0, 1, 0.0, 1.0, 0.0, 1.0);
+        api_state.set_snapshot_file(snapshot_file.to_str().unwrap());
+        entry::entry_file(scene_file, api_state, bsdf_state, &None);
+        let data: Vec<u8> = std::fs::read(&snapshot_file).unwrap();
+        core::snapshot::SceneSnapshot::from_bytes(&data).unwrap()
+    }
+
+    /// Like `snapshot_scene_file()`, for a scene given as text.
+    fn render_scene_snapshot(name: &str, scene_text: &str) -> core::snapshot::SceneSnapshot {
+        let dir: TestDir = TestDir::new(name);
+        let scene_file: std::path::PathBuf = dir.write_scene("", scene_text);
+        snapshot_scene_file(&dir, &scene_file)
+    }
+
+    /// Render `scene_text` like **rs_pbrt** does and load the image.
+    fn render_scene_image(name: &str, scene_text: &str) -> image::RgbImage {
+        let dir: TestDir = TestDir::new(name);
+        let scene_file: std::path::PathBuf = dir.write_scene("", scene_text);
+        let image_file: std::path::PathBuf = dir.join("image.png");
+        let (mut api_state, bsdf_state) = core::api::pbrt_init(0, 1, 0.0, 1.0, 0.0, 1.0);
+        api_state.set_image_file(image_file.to_str().unwrap());
+        entry::entry_file(&scene_file, api_state, bsdf_state, &None);
+        image::open(&image_file).unwrap().to_rgb8()
+    }
+
+    #[test]
+    fn lib_entry_test() {
+        let data = CORNELL_DATA;
+        let content_backend = Arc::new(backend::MemoryBackend::new());
+        content_backend.insert_string("cornell/main.pbrt", data);
+        entry::entry(
+            true,
+            16,
+            None,
+            None,
+            "cornell",
+            data,
+            content_backend,
+        );
+    }
+
+    #[test]
+    fn scene_snapshot_test() {
+        use crate::core::film::TilePayload;
+        use crate::core::snapshot::SceneSnapshot;
+
+        let snapshot: SceneSnapshot = render_scene_snapshot("scene_snapshot", CORNELL_DATA);
+        assert_eq!(snapshot.scene.lights.len(), 2);
+        let data: Vec<u8> = SceneSnapshot::to_bytes(
+            snapshot.pixelsamples,
+            &snapshot.render_options,
+            &snapshot.scene,
+        )
+        .unwrap();
+        assert!(SceneSnapshot::from_bytes(&data[1..]).is_err());
+        // a worker renders the same tile from the snapshot
+        let content_backend = Arc::new(backend::MemoryBackend::new());
+        content_backend.insert_string("cornell/main.pbrt", CORNELL_DATA);
+        let mut tile_job: TileJob = TileJob::new("cornell", 10, 12, 16);
+        let parsed = entry::entry_tile_job(&tile_job, content_backend.clone()).unwrap();
+        content_backend.insert("cornell/main.snapshot", data.clone());
+        let loaded = entry::entry_tile_job(&tile_job, content_backend.clone()).unwrap();
+        assert!(!parsed.is_empty());
+        assert_eq!(parsed, loaded);
+        // the worker decoded the snapshot once, later jobs share it
+        let cached = SceneSnapshot::cached(&tilejob::scene_hash(&data)).unwrap();
+        let decoded = SceneSnapshot::from_bytes_cached(&data).unwrap();
+        assert!(Arc::ptr_eq(&cached, &decoded));
+        // a job for other scene content is refused
+        tile_job.scene_hash = Some(tilejob::scene_hash(CORNELL_DATA.as_bytes()));
+        let error = entry::entry_tile_job(&tile_job, content_backend.clone()).unwrap_err();
+        assert_eq!(error.status, 409);
+        tile_job.scene_hash = Some(tilejob::scene_hash(&data));
+        // split the pixel samples between two workers
+        tile_job.last_sample = Some(4);
+        let first = entry::entry_tile_job(&tile_job, content_backend.clone()).unwrap();
+        tile_job.first_sample = 4;
+        tile_job.last_sample = None;
+        let second = entry::entry_tile_job(&tile_job, content_backend).unwrap();
+        let first = TilePayload::from_bytes(&first).unwrap();
+        let second = TilePayload::from_bytes(&second).unwrap();
+        assert_eq!(first.samples_per_pixel + second.samples_per_pixel, 8);
+        assert_eq!(first.integrator, "path");
+    }
+
+    #[test]
+    fn object_instance_test() {
+        use crate::core::geometry::{Point3f, Ray, Vector3f};
+        use crate::core::interaction::SurfaceInteraction;
+        use crate::core::snapshot::SceneSnapshot;
+        use std::cell::Cell;
+
+        let data: &str = r##"LookAt 0 0 10  0 0 0  0 1 0
+Camera "perspective" "float fov" [ 45 ]
+Film "image" "integer xresolution" [ 32 ] "integer yresolution" [ 32 ]
+WorldBegin
+ObjectBegin "lamp"
+  AttributeBegin
+    Translate 0 1 0
+    AreaLightSource "diffuse" "rgb L" [ 4 4 4 ]
+    Shape "sphere" "float radius" [ 0.25 ]
+  AttributeEnd
+  Shape "sphere" "float radius" [ 0.5 ]
+ObjectEnd
+AttributeBegin
+  Translate -3 0 0
+  ObjectInstance "lamp"
+AttributeEnd
+AttributeBegin
+  Translate 3 0 0
+  Scale 2 2 2
+  ObjectInstance "lamp"
+AttributeEnd
+WorldEnd
+"##;
+        let snapshot: SceneSnapshot = render_scene_snapshot("object_instance", data);
+        // one area light per instance
+        assert_eq!(snapshot.scene.lights.len(), 2);
+        // (x, y) of a ray down the z axis -> (hit, emitted)
+        let probes: [(f32, f32, bool, bool); 6] = [
+            (-3.0, 0.0, true, false),
+            (-3.0, 1.0, true, true),
+            (3.0, 0.0, true, false),
+            (3.0, 2.0, true, true),
+            (3.0, 0.5, true, false),
+            (0.0, 0.0, false, false),
+        ];
+        for (x, y, hit, emitted) in &probes {
+            let ray: Ray = Ray {
+                o: Point3f {
+                    x: *x,
+                    y: *y,
+                    z: 10.0,
+                },
+                d: Vector3f {
+                    x: 0.0,
+                    y: 0.0,
+                    z: -1.0,
+                },
+                t_max: Cell::new(std::f32::INFINITY),
+                ..Default::default()
+            };
+            let mut isect: SurfaceInteraction = SurfaceInteraction::default();
+            assert_eq!(snapshot.scene.intersect(&ray, &mut isect), *hit);
+            if *hit {
+                let le = isect.le(&-ray.d);
+                assert_eq!(!le.is_black(), *emitted, "{} {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn motion_blur_test() {
+        use crate::core::geometry::{Point3f, Ray, Vector3f};
+        use crate::core::interaction::SurfaceInteraction;
+        use crate::core::snapshot::SceneSnapshot;
+        use std::cell::Cell;
+
+        let data: &str = r##"LookAt 0 0 10  0 0 0  0 1 0
+Camera "perspective" "float fov" [ 45 ] "float shutteropen" [ 0 ] "float shutterclose" [ 1 ]
+Film "image" "integer xresolution" [ 32 ] "integer yresolution" [ 32 ]
+TransformTimes 0 1
+WorldBegin
+ObjectBegin "ball"
+  Shape "sphere" "float radius" [ 0.5 ]
+ObjectEnd
+AttributeBegin
+  Translate -2 2 0
+  ActiveTransform EndTime
+  Translate 4 0 0
+  ActiveTransform All
+  Shape "sphere" "float radius" [ 0.5 ]
+AttributeEnd
+AttributeBegin
+  Translate 0 -2 0
+  ActiveTransform EndTime
+  Rotate 90 0 0 1
+  ActiveTransform All
+  Translate 2 0 0
+  ObjectInstance "ball"
+AttributeEnd
+WorldEnd
+"##;
+        let snapshot: SceneSnapshot = render_scene_snapshot("motion_blur", data);
+        // the scene bounds have to cover the whole motion
+        let bounds = snapshot.scene.world_bound();
+        assert!(bounds.p_min.x <= -2.5 && bounds.p_max.x >= 2.5);
+        assert!(bounds.p_min.y <= -2.5 && bounds.p_max.y >= 2.5);
+        // (x, y, time) of a ray down the z axis -> hit
+        let probes: [(f32, f32, f32, bool); 10] = [
+            (-2.0, 2.0, 0.0, true),
+            (-2.0, 2.0, 1.0, false),
+            (2.0, 2.0, 0.0, false),
+            (2.0, 2.0, 1.0, true),
+            (0.0, 2.0, 0.5, true),
+            (0.0, 2.0, 0.0, false),
+            // the instance rotates around (0, -2, 0) by 90 degrees
+            (2.0, -2.0, 0.0, true),
+            (2.0, -2.0, 1.0, false),
+            (0.0, 0.0, 1.0, true),
+            (0.0, 0.0, 0.0, false),
+        ];
+        for (x, y, time, hit) in &probes {
+            let mut ray: Ray = Ray {
+                o: Point3f {
+                    x: *x,
+                    y: *y,
+                    z: 10.0,
+                },
+                d: Vector3f {
+                    x: 0.0,
+                    y: 0.0,
+                    z: -1.0,
+                },
+                t_max: Cell::new(std::f32::INFINITY),
+                time: *time,
+                ..Default::default()
+            };
+            assert_eq!(snapshot.scene.intersect_p(&mut ray), *hit);
+            let mut isect: SurfaceInteraction = SurfaceInteraction::default();
+            assert_eq!(
+                snapshot.scene.intersect(&ray, &mut isect),
+                *hit,
+                "{} {} {}",
+                x,
+                y,
+                time
+            );
+            if *hit {
+                assert!((isect.common.p.z - 0.5).abs() < 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn transform_cache_test() {
+        use crate::core::api::TransformCache;
+        use crate::core::geometry::Vector3f;
+        use crate::core::primitive::Primitive;
+        use crate::core::shape::Shape;
+        use crate::core::snapshot::SceneSnapshot;
+        use crate::core::transform::Transform;
+
+        let mut cache: TransformCache = TransformCache::default();
+        let t: Transform = Transform::translate(&Vector3f {
+            x: 1.0,
+            y: 2.0,
+            z: 3.0,
+        });
+        let (t0, t0_inv) = cache.lookup(&t);
+        let (t1, t1_inv) = cache.lookup(&t);
+        assert!(Arc::ptr_eq(&t0, &t1));
+        assert!(Arc::ptr_eq(&t0_inv, &t1_inv));
+        // the inverse of the inverse is the cached transform again
+        let (t2, t2_inv) = cache.lookup(&Transform::inverse(&t));
+        assert!(Arc::ptr_eq(&t2, &t0_inv));
+        assert!(Arc::ptr_eq(&t2_inv, &t0));
+        assert_eq!(cache.len(), 2);
+        assert!(!Arc::ptr_eq(&t0, &cache.get(&Transform::default())));
+        // shapes below the same transform share it (also in a snapshot)
+        let data: &str = r##"LookAt 0 0 10  0 0 0  0 1 0
+Camera "perspective" "float fov" [ 45 ]
+Film "image" "integer xresolution" [ 32 ] "integer yresolution" [ 32 ]
+WorldBegin
+AttributeBegin
+  Translate 1 0 0
+  Shape "sphere" "float radius" [ 0.5 ]
+  Shape "sphere" "float radius" [ 0.25 ]
+  Shape "sphere" "float radius" [ 0.125 ]
+AttributeEnd
+AttributeBegin
+  Translate 1 0 0
+  Shape "sphere" "float radius" [ 1 ]
+AttributeEnd
+WorldEnd
+"##;
+        let snapshot: SceneSnapshot = render_scene_snapshot("transform_cache", data);
+        let mut transforms: Vec<(Arc<Transform>, Arc<Transform>)> = Vec::new();
+        if let Primitive::BVH(bvh) = &*snapshot.scene.aggregate {
+            for primitive in &bvh.primitives {
+                if let Primitive::Geometric(primitive) = &**primitive {
+                    if let Shape::Sphr(sphere) = &*primitive.shape {
+                        transforms.push((
+                            sphere.object_to_world.clone(),
+                            sphere.world_to_object.clone(),
+                        ));
+                    }
+                }
+            }
+        }
+        assert_eq!(transforms.len(), 4);
+        for (object_to_world, world_to_object) in &transforms[1..] {
+            assert!(Arc::ptr_eq(object_to_world, &transforms[0].0));
+            assert!(Arc::ptr_eq(world_to_object, &transforms[0].1));
+        }
+    }
+
+    #[test]
+    fn material_id_test() {
+        use crate::core::pbrt::Float;
+        use crate::core::primitive::Primitive;
+        use crate::core::shape::Shape;
+        use crate::core::snapshot::SceneSnapshot;
+
+        // shapes below the same (anonymous) Material directive share
+        // its material ID
+        let data: &str = r##"LookAt 0 0 10  0 0 0  0 1 0
+Camera "perspective" "float fov" [ 45 ]
+Film "image" "integer xresolution" [ 32 ] "integer yresolution" [ 32 ]
+WorldBegin
+Material "plastic" "rgb Kd" [ 0.5 0.1 0.1 ]
+Shape "sphere" "float radius" [ 0.5 ]
+Shape "sphere" "float radius" [ 0.25 ]
+AttributeBegin
+  Translate 1 0 0
+  Shape "sphere" "float radius" [ 0.125 ]
+AttributeEnd
+Material "plastic" "rgb Kd" [ 0.5 0.1 0.1 ]
+Shape "sphere" "float radius" [ 1 ]
+WorldEnd
+"##;
+        let snapshot: SceneSnapshot = render_scene_snapshot("material_id", data);
+        let mut material_ids: Vec<(Float, u32)> = Vec::new();
+        if let Primitive::BVH(bvh) = &*snapshot.scene.aggregate {
+            for primitive in &bvh.primitives {
+                if let Primitive::Geometric(primitive) = &**primitive {
+                    if let Shape::Sphr(sphere) = &*primitive.shape {
+                        material_ids.push((sphere.radius, primitive.material_id));
+                    }
+                }
+            }
+        }
+        material_ids.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+        assert_eq!(material_ids.len(), 4);
+        assert!(material_ids[1].1 > 0);
+        assert_eq!(material_ids[2].1, material_ids[1].1);
+        assert_eq!(material_ids[3].1, material_ids[1].1);
+        // a second directive is a different material
+        assert_ne!(material_ids[0].1, material_ids[1].1);
+    }
+
+    #[test]
+    fn tile_job_test() {
+        let json: &str = r#"{"version": 1, "filename": "cornell", "x": 1, "y": 2, "tile_size": 16}"#;
+        let tile_job: TileJob = TileJob::from_json(json).unwrap();
+        assert_eq!(tile_job.get_sample_range().count(8), 8);
+        assert_eq!(TileJob::from_json(&tile_job.to_json()).unwrap().y, 2);
+        assert_eq!(TileJob::from_json("{").unwrap_err().status, 400);
+        let mut tile_job: TileJob = TileJob::new("../cornell", 1, 2, 16);
+        assert_eq!(tile_job.validate().unwrap_err().status, 400);
+        tile_job.filename = String::from("cornell");
+        tile_job.first_sample = 4;
+        tile_job.last_sample = Some(4);
+        assert_eq!(tile_job.validate().unwrap_err().status, 422);
+        // unknown names and integrators without tiles are rejected
+        for (integrator, sampler) in &[("foo", "halton"), ("sppm", "halton"), ("path", "foo")] {
+            let mut tile_job: TileJob = TileJob::new("cornell", 0, 0, 16);
+            tile_job.integrator = Some(integrator.to_string());
+            tile_job.sampler = Some(sampler.to_string());
+            assert_eq!(tile_job.validate().unwrap_err().status, 422);
+        }
+    }
+
+    #[test]
+    fn pixel_bounds_test() {
+        use crate::core::film::TilePayload;
+
+        // only the tiles of a 40x32 region are rendered
+        let data: String = CORNELL_DATA.replace(
+            "Integrator \"path\"",
+            "Integrator \"path\" \"integer pixelbounds\" [ 100 140 200 232 ]",
+        );
+        let content_backend = Arc::new(backend::MemoryBackend::new());
+        content_backend.insert_string("cornell/main.pbrt", &data);
+        let tile_job: TileJob = TileJob::new("cornell", 1, 1, 16);
+        let payload = entry::entry_tile_job(&tile_job, content_backend.clone()).unwrap();
+        let payload: TilePayload = TilePayload::from_bytes(&payload).unwrap();
+        // the padded tile around the pixels [116, 132) x [216, 232)
+        assert!(payload.pixel_bounds.p_min.x > 100 && payload.pixel_bounds.p_min.x <= 116);
+        assert!(payload.pixel_bounds.p_min.y > 200 && payload.pixel_bounds.p_min.y <= 216);
+        assert!(payload.pixel_bounds.p_max.x >= 132 && payload.pixel_bounds.p_max.y >= 232);
+        assert!(payload.pixels.iter().any(|p| p[3] > 0.0));
+        // the last column of tiles ends at the region
+        let tile_job: TileJob = TileJob::new("cornell", 2, 0, 16);
+        let payload = entry::entry_tile_job(&tile_job, content_backend.clone()).unwrap();
+        let payload: TilePayload = TilePayload::from_bytes(&payload).unwrap();
+        assert!(payload.pixel_bounds.p_min.x > 116 && payload.pixel_bounds.p_min.x <= 132);
+        assert!(payload.pixel_bounds.p_max.x >= 140 && payload.pixel_bounds.p_max.x < 148);
+        // tiles outside of the 3x2 grid are rejected
+        for (x, y) in &[(3, 0), (0, 2), (u32::MAX, 0)] {
+            let tile_job: TileJob = TileJob::new("cornell", *x, *y, 16);
+            let error = entry::entry_tile_job(&tile_job, content_backend.clone()).unwrap_err();
+            assert_eq!(error.status, 422);
+        }
+        // so are scenes which can't be parsed (or don't render anything)
+        let unknown: String = CORNELL_DATA.replace("Integrator \"path\"", "Integrator \"foo\"");
+        for data in &["LookAt 0 0 1", "WorldBegin\n", unknown.as_str()] {
+            content_backend.insert_string("broken/main.pbrt", data);
+            let tile_job: TileJob = TileJob::new("broken", 0, 0, 16);
+            let error = entry::entry_tile_job(&tile_job, content_backend.clone()).unwrap_err();
+            assert_eq!(error.status, 422);
+        }
+        // an included file which doesn't exist isn't skipped
+        content_backend.insert_string("broken/main.pbrt", "Include \"missing.pbrt\"\n");
+        let tile_job: TileJob = TileJob::new("broken", 0, 0, 16);
+        let error = entry::entry_tile_job(&tile_job, content_backend).unwrap_err();
+        assert_eq!(error.status, 404);
+    }
+
+    #[test]
+    fn adaptive_sampling_test() {
+        use crate::core::film::{TilePayload, VarianceEstimator};
+        use crate::core::pbrt::Float;
+        use crate::core::sampler::{AdaptiveSampling, SampleRange};
+
+        // merging estimators is the same as adding all values to one
+        let values: [Float; 6] = [0.5, 1.5, 0.25, 2.0, 1.0, 0.75];
+        let mut all: VarianceEstimator = VarianceEstimator::default();
+        let mut first: VarianceEstimator = VarianceEstimator::default();
+        let mut second: VarianceEstimator = VarianceEstimator::default();
+        for (i, v) in values.iter().enumerate() {
+            all.add(*v);
+            if i < 2 {
+                first.add(*v);
+            } else {
+                second.add(*v);
+            }
+        }
+        let merged: VarianceEstimator = first.merge(&second);
+        assert_eq!(merged.count(), 6);
+        assert!((merged.mean() - all.mean()).abs() < 1e-6);
+        assert!((merged.variance() - all.variance()).abs() < 1e-5);
+        assert_eq!(VarianceEstimator::default().merge(&all).count(), 6);
+        // a black pixel converges, a noisy one doesn't
+        let sampling: AdaptiveSampling = AdaptiveSampling {
+            max_relative_error: 0.05,
+            min_samples: 4,
+            pass_samples: 3,
+        };
+        let mut black: VarianceEstimator = VarianceEstimator::default();
+        for _ in 0..4 {
+            black.add(0.0);
+        }
+        assert!(sampling.has_converged(&black));
+        assert!(!sampling.has_converged(&all));
+        // passes of 3 samples within samples 2..10
+        let samples: SampleRange = SampleRange {
+            first_sample: 2,
+            last_sample: Some(10),
+            seed_offset: 0,
+        };
+        let passes: Vec<SampleRange> = sampling.get_passes(&samples, 16);
+        assert_eq!(passes.len(), 3);
+        assert_eq!(passes[0].first_sample, 2);
+        assert_eq!(passes[2].first_sample, 8);
+        assert_eq!(passes[2].last_sample, Some(10));
+        assert_eq!(AdaptiveSampling::default().get_passes(&samples, 16).len(), 1);
+        // an adaptive tile stops early in smooth pixels
+        let filter_weights = |data: &str| -> Float {
+            let content_backend = Arc::new(backend::MemoryBackend::new());
+            content_backend.insert_string("cornell/main.pbrt", data);
+            let tile_job: TileJob = TileJob::new("cornell", 10, 12, 16);
+            let payload = entry::entry_tile_job(&tile_job, content_backend).unwrap();
+            let payload: TilePayload = TilePayload::from_bytes(&payload).unwrap();
+            payload.pixels.iter().map(|p| p[3]).sum()
+        };
+        let fixed: Float = filter_weights(CORNELL_DATA);
+        let adaptive: Float = filter_weights(&CORNELL_DATA.replace(
+            "Integrator \"path\"",
+            "Integrator \"path\" \"float maxrelativeerror\" [ 0.5 ] \"integer minsamples\" [ 2 ]",
+        ));
+        assert!(adaptive > 0.0 && adaptive < fixed);
+    }
+
+    #[test]
+    fn aov_test() {
+        use crate::core::film::TilePayload;
+        use crate::core::pbrt::Float;
+        use crate::core::spectrum::xyz_to_rgb;
+
+        // the area light is the only light (in its own group)
+        let data: String = CORNELL_DATA.replace("##LightGroup", "LightGroup").replace(
+            "Film \"image\"",
+            "Film \"image\" \"string aovs\" \
+             [ \"albedo\" \"depth\" \"objectid\" \"direct\" \"indirect\" \"lightgroups\" ]",
+        );
+        for integrator in &["path", "bdpt"] {
+            let data: String = data.replace(
+                "Integrator \"path\"",
+                &format!("Integrator \"{}\"", integrator),
+            );
+            let content_backend = Arc::new(backend::MemoryBackend::new());
+            content_backend.insert_string("cornell/main.pbrt", &data);
+            let tile_job: TileJob = TileJob::new("cornell", 10, 12, 16);
+            let payload = entry::entry_tile_job(&tile_job, content_backend).unwrap();
+            let payload: TilePayload = TilePayload::from_bytes(&payload).unwrap();
+            // albedo 3, depth 1, object ID 2, direct 3, indirect 3 and
+            // two light groups ("default" and "Emitter")
+            assert_eq!(payload.aov_stride, 18);
+            assert_eq!(payload.aovs.len(), payload.pixels.len() * 18);
+            let mut indirect: Float = 0.0;
+            for (pixel, aovs) in payload.pixels.iter().zip(payload.aovs.chunks(18)) {
+                let weight: Float = pixel[3];
+                if weight <= 0.0 {
+                    continue;
+                }
+                let mut rgb: [Float; 3] = [0.0; 3];
+                xyz_to_rgb(&[pixel[0], pixel[1], pixel[2]], &mut rgb);
+                for c in 0..3 {
+                    assert!((0.0..=1.0).contains(&(aovs[c] / weight)));
+                    // direct and indirect add up to the image ...
+                    let l: Float = aovs[6 + c] + aovs[9 + c];
+                    assert!((l - rgb[c]).abs() <= 1e-3 * (1.0 + rgb[c].abs()));
+                    // ... and so do the light groups
+                    assert_eq!(aovs[12 + c], 0.0);
+                    assert!((aovs[15 + c] - l).abs() <= 1e-3 * (1.0 + l.abs()));
+                    indirect += aovs[9 + c];
+                }
+                assert!(aovs[3] / weight > 0.0);
+                assert!(aovs[5] > 0.0);
+            }
+            assert!(indirect > 0.0, "{} has no indirect light", integrator);
+        }
+    }
+
+    #[test]
+    fn denoiser_test() {
+        use crate::core::denoiser::{BilateralDenoiser, DenoiserGuides};
+        use crate::core::film::{Aov, Film};
+        use crate::core::geometry::{Bounds2f, Point2f};
+        use crate::core::paramset::ParamSet;
+        use crate::core::pbrt::Float;
+        use crate::filters::boxfilter::BoxFilter;
+
+        // the denoiser asks the film for the AOVs guiding it
+        let mut params: ParamSet = ParamSet::default();
+        params.add_string(String::from("denoiser"), String::from("bilateral"));
+        params.add_strings(String::from("aovs"), vec![String::from("depth")]);
+        let crop_window: Bounds2f = Bounds2f {
+            p_min: Point2f { x: 0.0, y: 0.0 },
+            p_max: Point2f { x: 1.0, y: 1.0 },
+        };
+        let film: Arc<Film> = Film::create(
+            &params,
+            BoxFilter::create(&ParamSet::default()),
+            &crop_window,
+            "",
+            false,
+            &[],
+        );
+        assert!(film.denoiser.is_some());
+        let layout = film.aov_layout.as_ref().unwrap();
+        assert_eq!(layout.aovs, vec![Aov::Depth, Aov::Albedo, Aov::Normal]);
+        // a noisy image of two walls meeting at x = 8
+        let (width, height): (usize, usize) = (16, 8);
+        let mut rgb: Vec<Float> = Vec::new();
+        let mut albedo: Vec<Float> = Vec::new();
+        let mut normal: Vec<Float> = Vec::new();
+        let mut depth: Vec<Float> = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                let left: bool = x < width / 2;
+                let noise: Float = if (x * 7 + y * 3) % 4 < 2 { 0.1 } else { -0.1 };
+                let v: Float = if left { 0.2 } else { 0.8 } + noise;
+                rgb.extend(&[v, v, v]);
+                albedo.extend(&[0.5, 0.5, 0.5]);
+                normal.extend(if left {
+                    &[0.0, 0.0, 1.0]
+                } else {
+                    &[1.0, 0.0, 0.0]
+                });
+                depth.push(1.0);
+            }
+        }
+        let guides: DenoiserGuides = DenoiserGuides {
+            albedo: &albedo,
+            normal: &normal,
+            depth: &depth,
+        };
+        let denoised: Vec<Float> = BilateralDenoiser::new(3).denoise(&rgb, &guides, width, height);
+        let error = |image: &[Float]| -> Float {
+            let mut sum: Float = 0.0;
+            for (i, v) in image.iter().enumerate() {
+                let expected: Float = if (i / 3) % width < width / 2 {
+                    0.2
+                } else {
+                    0.8
+                };
+                sum += (v - expected).abs();
+            }
+            sum / image.len() as Float
+        };
+        assert!(error(&denoised) < 0.5 * error(&rgb));
+        // nothing bleeds across the edge
+        for y in 0..height {
+            let i: usize = 3 * (y * width + width / 2);
+            assert!((denoised[i - 3] - 0.2).abs() < 0.1);
+            assert!((denoised[i] - 0.8).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn path_guiding_test() {
+        use crate::core::geometry::{Point2f, Vector3f};
+        use crate::core::guiding::{dir_to_square, square_to_dir, DTree, PathGuiding};
+        use crate::core::paramset::ParamSet;
+        use crate::core::pbrt::Float;
+        use crate::core::sampler::{AdaptiveSampling, SampleRange};
+
+        // directions survive the trip to the square and back
+        let d: Vector3f = Vector3f {
+            x: 0.48,
+            y: -0.6,
+            z: 0.64,
+        };
+        let e: Vector3f = square_to_dir(&dir_to_square(&d));
+        assert!((d.x - e.x).abs() + (d.y - e.y).abs() + (d.z - e.z).abs() < 1e-4);
+        // a quadtree trained over a few passes on a bright corner
+        let bright = |u: &Point2f| u.x < 0.25 && u.y < 0.125;
+        let mut tree: DTree = DTree::default();
+        for _ in 0..4 {
+            let mut training: DTree = tree.cleared();
+            for i in 0..64 {
+                for j in 0..64 {
+                    let u: Point2f = Point2f {
+                        x: (i as Float + 0.5) / 64.0,
+                        y: (j as Float + 0.5) / 64.0,
+                    };
+                    training.record(&u, if bright(&u) { 100.0 } else { 1.0 });
+                }
+            }
+            tree = training.refine();
+        }
+        assert!(tree.get_node_count() > 4);
+        // the pdf integrates to one ...
+        let n: usize = 256;
+        let mut integral: f64 = 0.0;
+        let mut bright_integral: f64 = 0.0;
+        for i in 0..n {
+            for j in 0..n {
+                let u: Point2f = Point2f {
+                    x: (i as Float + 0.5) / n as Float,
+                    y: (j as Float + 0.5) / n as Float,
+                };
+                let pdf: f64 = tree.pdf(&u) as f64 / (n * n) as f64;
+                integral += pdf;
+                if bright(&u) {
+                    bright_integral += pdf;
+                }
+            }
+        }
+        assert!((integral - 1.0).abs() < 1e-3, "integral = {}", integral);
+        assert!(bright_integral > 0.7);
+        // ... and the samples follow it
+        let mut bright_samples: usize = 0;
+        for i in 0..n {
+            for j in 0..n {
+                let p: Point2f = tree.sample(&Point2f {
+                    x: (i as Float + 0.5) / n as Float,
+                    y: (j as Float + 0.5) / n as Float,
+                });
+                assert!(tree.pdf(&p) > 0.0);
+                if bright(&p) {
+                    bright_samples += 1;
+                }
+            }
+        }
+        let fraction: f64 = bright_samples as f64 / (n * n) as f64;
+        assert!((fraction - bright_integral).abs() < 0.01);
+        // training passes of 1, 2, 4 and 8 samples, then the rest
+        let mut params: ParamSet = ParamSet::default();
+        params.add_bool(String::from("guiding"), true);
+        params.add_int(String::from("guidingsamples"), 16);
+        let guiding: PathGuiding = PathGuiding::create(&params, 64).unwrap();
+        let samples: SampleRange = SampleRange {
+            first_sample: 0,
+            last_sample: None,
+            seed_offset: 0,
+        };
+        let passes: Vec<SampleRange> =
+            guiding.get_passes(&samples, 64, &AdaptiveSampling::default());
+        let first_samples: Vec<i64> = passes.iter().map(|pass| pass.first_sample).collect();
+        assert_eq!(first_samples, vec![0, 1, 3, 7, 15]);
+        assert_eq!(passes[4].last_sample, Some(64));
+        // guiding changes the noise, not the brightness of the image
+        let render = |name: &str, integrator: &str| -> Float {
+            let data: String = CORNELL_DATA
+                .replace("[ 500 ]", "[ 32 ]")
+                .replace("[8]", "[64]")
+                .replace("Integrator \"path\"", integrator);
+            let image = render_scene_image(name, &data);
+            let sum: u64 = image.as_raw().iter().map(|v| *v as u64).sum();
+            sum as Float / image.as_raw().len() as Float
+        };
+        let plain: Float = render("guiding_plain", "Integrator \"path\"");
+        let guided: Float = render(
+            "guiding_guided",
+            "Integrator \"path\" \"bool guiding\" [ \"true\" ]",
+        );
+        assert!(plain > 0.0);
+        assert!(
+            (guided - plain).abs() < 0.03 * plain,
+            "{} vs {}",
+            guided,
+            plain
+        );
+    }
+
+    #[test]
+    fn quadrics_and_curves_test() {
+        use crate::core::film::TilePayload;
+        use crate::core::geometry::{Point2f, Point3f, Ray, Vector3f};
+        use crate::core::interaction::{InteractionCommon, SurfaceInteraction};
+        use crate::core::pbrt::Float;
+        use crate::core::shape::Shape;
+        use crate::core::transform::Transform;
+        use crate::shapes::curve::{Curve, CurveType};
+        use crate::shapes::cylinder::Cylinder;
+        use crate::shapes::disk::Disk;
+        use std::cell::Cell;
+
+        let identity: Arc<Transform> = Arc::new(Transform::default());
+        let down = |x: Float, y: Float| -> Ray {
+            Ray {
+                o: Point3f { x, y, z: 5.0 },
+                d: Vector3f {
+                    x: 0.0,
+                    y: 0.0,
+                    z: -1.0,
+                },
+                t_max: Cell::new(std::f32::INFINITY),
+                ..Default::default()
+            }
+        };
+        // a half annulus
+        let disk = Shape::Dsk(Disk::new(identity.clone(), identity.clone(), false, 1.0, 2.0, 1.0, 180.0));
+        let mut t_hit: Float = 0.0;
+        let mut isect: SurfaceInteraction = SurfaceInteraction::default();
+        assert!(disk.intersect(&down(0.0, 1.5), &mut t_hit, &mut isect));
+        assert!((t_hit - 4.0).abs() < 1e-4);
+        assert!(!disk.intersect_p(&down(0.0, 0.5)));
+        assert!(!disk.intersect_p(&down(0.0, -1.5)));
+        let cylinder = Shape::Clndr(Cylinder::new(identity.clone(), identity.clone(), false, 1.0, -1.0, 1.0, 360.0));
+        let mut isect: SurfaceInteraction = SurfaceInteraction::default();
+        let mut side: Ray = down(-5.0, 0.0);
+        side.o.z = 0.5;
+        side.d = Vector3f {
+            x: 1.0,
+            y: 0.0,
+            z: 0.0,
+        };
+        assert!(cylinder.intersect(&side, &mut t_hit, &mut isect));
+        assert!((t_hit - 4.0).abs() < 1e-4);
+        assert!((isect.common.p.x + 1.0).abs() < 1e-4);
+        // a straight curve along the x axis
+        let cp: [Point3f; 4] = [
+            Point3f {
+                x: -1.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            Point3f {
+                x: -0.3,
+                y: 0.0,
+                z: 0.0,
+            },
+            Point3f {
+                x: 0.3,
+                y: 0.0,
+                z: 0.0,
+            },
+            Point3f {
+                x: 1.0,
+                y: 0.0,
+                z: 0.0,
+            },
+        ];
+        let segments = Curve::create(identity.clone(), identity.clone(), false, &cp, 0.2, 0.2, CurveType::Flat, None, 2);
+        assert_eq!(segments.len(), 4);
+        let hits: Vec<Float> = segments
+            .iter()
+            .filter_map(|segment| {
+                let mut t_hit: Float = 0.0;
+                let mut isect: SurfaceInteraction = SurfaceInteraction::default();
+                if segment.intersect(&down(0.4, 0.05), &mut t_hit, &mut isect) {
+                    assert!((isect.common.p.x - 0.4).abs() < 1e-3);
+                    Some(t_hit)
+                } else {
+                    None
+                }
+            })
+            .collect();
+        assert_eq!(hits.len(), 1);
+        assert!((hits[0] - 5.0).abs() < 1e-3);
+        assert!(!segments[2].intersect_p(&down(0.4, 0.15)));
+        // area light sampling stays on the (partial) surfaces
+        let iref: InteractionCommon = InteractionCommon::default();
+        for i in 0..64 {
+            let u: Point2f = Point2f {
+                x: (i % 8) as Float / 8.0 + 0.01,
+                y: (i / 8) as Float / 8.0 + 0.01,
+            };
+            let mut pdf: Float = 0.0;
+            let it: InteractionCommon = disk.sample(u, &mut pdf);
+            let r: Float = (it.p.x * it.p.x + it.p.y * it.p.y).sqrt();
+            assert!(r >= 1.0 - 1e-4 && r <= 2.0 + 1e-4 && it.p.y >= -1e-4);
+            assert!((pdf * disk.area() - 1.0).abs() < 1e-4);
+            let it: InteractionCommon = cylinder.sample(u, &mut pdf);
+            assert!((it.p.x * it.p.x + it.p.y * it.p.y - 1.0).abs() < 1e-4);
+            let it: InteractionCommon = segments[0].sample(u, &mut pdf);
+            assert!(it.p.x >= -1.0 - 1e-4 && it.p.x <= -0.5 + 1e-4);
+            assert!(it.p.y.abs() <= 0.1 + 1e-4 && it.p.z.abs() <= 0.1 + 1e-4);
+            assert!(pdf > 0.0);
+            let _ = segments[0].sample_with_ref_point(&iref, u, &mut pdf);
+        }
+        // a scene using all three (with a disk as area light)
+        const SHAPES_DATA: &str = r##"
+        LookAt 0 -6 1  0 0 0  0 0 1
+        Camera "perspective" "float fov" [ 40 ]
+        Film "image" "integer xresolution" [ 32 ] "integer yresolution" [ 32 ]
+        Sampler "sobol" "integer pixelsamples" [ 4 ]
+        Integrator "path"
+        WorldBegin
+          AttributeBegin
+            AreaLightSource "diffuse" "rgb L" [ 4 4 4 ]
+            Translate 0 0 3
+            ReverseOrientation
+            Shape "disk" "float radius" [ 1.5 ] "float innerradius" [ 0.2 ]
+          AttributeEnd
+          Material "matte" "rgb Kd" [ 0.5 0.5 0.5 ]
+          Shape "cylinder" "float radius" [ 0.5 ] "float zmin" [ -1 ] "float zmax" [ 0.5 ]
+          Shape "curve" "point P" [ -1.5 -1 -0.5  -0.5 -1 0.5  0.5 -1 0.5  1.5 -1 -0.5 ]
+            "float width" [ 0.1 ]
+          Translate 0 0 -1
+          Shape "disk" "float radius" [ 4 ]
+        WorldEnd
+        "##;
+        let content_backend = Arc::new(backend::MemoryBackend::new());
+        content_backend.insert_string("shapes/main.pbrt", SHAPES_DATA);
+        let tile_job: TileJob = TileJob::new("shapes", 1, 1, 16);
+        let data = entry::entry_tile_job(&tile_job, content_backend).unwrap();
+        let payload: TilePayload = TilePayload::from_bytes(&data).unwrap();
+        let lit: usize = payload.pixels.iter().filter(|p| p[1] > 0.0).count();
+        assert!(lit > payload.pixels.len() / 2);
+    }
+
+    #[test]
+    fn cone_paraboloid_hyperboloid_test() {
+        use crate::core::geometry::{Point2f, Point3f, Ray, Vector3f};
+        use crate::core::interaction::{InteractionCommon, SurfaceInteraction};
+        use crate::core::pbrt::Float;
+        use crate::core::shape::Shape;
+        use crate::core::transform::Transform;
+        use crate::shapes::cone::Cone;
+        use crate::shapes::hyperboloid::Hyperboloid;
+        use crate::shapes::paraboloid::Paraboloid;
+        use std::cell::Cell;
+
+        let identity: Arc<Transform> = Arc::new(Transform::default());
+        let ray = |o: Point3f, d: Vector3f| -> Ray {
+            Ray {
+                o,
+                d,
+                t_max: Cell::new(std::f32::INFINITY),
+                ..Default::default()
+            }
+        };
+        let down = |x: Float, y: Float| {
+            ray(
+                Point3f { x, y, z: 5.0 },
+                Vector3f {
+                    x: 0.0,
+                    y: 0.0,
+                    z: -1.0,
+                },
+            )
+        };
+        let hit = |shape: &Shape, r: &Ray| -> Option<Float> {
+            let mut t_hit: Float = 0.0;
+            let mut isect: SurfaceInteraction = SurfaceInteraction::default();
+            if shape.intersect(r, &mut t_hit, &mut isect) {
+                assert!(shape.intersect_p(r));
+                Some(t_hit)
+            } else {
+                assert!(!shape.intersect_p(r));
+                None
+            }
+        };
+        // a quarter of a cone
+        let cone = Shape::Cn(Cone::new(identity.clone(), identity.clone(), false, 1.0, 1.0, 90.0));
+        assert!((hit(&cone, &down(0.3, 0.2)).unwrap() - (4.0 + 0.13_f32.sqrt())).abs() < 1e-4);
+        assert!(hit(&cone, &down(-0.3, 0.2)).is_none());
+        let paraboloid = Shape::Prbld(Paraboloid::new(identity.clone(), identity.clone(), false, 1.0, 0.0, 1.0, 360.0));
+        assert!((hit(&paraboloid, &down(0.5, 0.0)).unwrap() - 4.75).abs() < 1e-4);
+        assert!(hit(&paraboloid, &down(1.5, 0.0)).is_none());
+        // hyperboloids can be cylinders ...
+        let p1: Point3f = Point3f {
+            x: 1.0,
+            y: 0.0,
+            z: -1.0,
+        };
+        let p2: Point3f = Point3f {
+            x: 1.0,
+            y: 0.0,
+            z: 1.0,
+        };
+        let cylinder = Shape::Hprbld(Hyperboloid::new(identity.clone(), identity.clone(), false, p1, p2, 360.0));
+        let side: Ray = ray(
+            Point3f {
+                x: -5.0,
+                y: 0.0,
+                z: 0.5,
+            },
+            Vector3f {
+                x: 1.0,
+                y: 0.0,
+                z: 0.0,
+            },
+        );
+        assert!((hit(&cylinder, &side).unwrap() - 4.0).abs() < 1e-3);
+        assert!((cylinder.area() - 4.0 * std::f32::consts::PI).abs() < 1e-3);
+        // ... or cones
+        let p1: Point3f = Point3f {
+            x: 1.0,
+            y: 0.0,
+            z: 0.0,
+        };
+        let p2: Point3f = Point3f {
+            x: 0.0,
+            y: 0.0,
+            z: 1.0,
+        };
+        let tip = Shape::Hprbld(Hyperboloid::new(identity.clone(), identity.clone(), false, p1, p2, 360.0));
+        let full_cone = Shape::Cn(Cone::new(identity.clone(), identity.clone(), false, 1.0, 1.0, 360.0));
+        assert!((tip.area() - full_cone.area()).abs() < 1e-4);
+        assert!((tip.area() - std::f32::consts::PI * 2.0_f32.sqrt()).abs() < 1e-4);
+        // samples are uniform by area, a cone's mean height is 1/3
+        let iref: InteractionCommon = InteractionCommon::default();
+        let n: usize = 32;
+        let mut mean_z: [Float; 2] = [0.0; 2];
+        for i in 0..n * n {
+            let u: Point2f = Point2f {
+                x: ((i % n) as Float + 0.5) / n as Float,
+                y: ((i / n) as Float + 0.5) / n as Float,
+            };
+            let mut pdf: Float = 0.0;
+            for (j, shape) in [&tip, &full_cone].iter().enumerate() {
+                let it: InteractionCommon = shape.sample(u, &mut pdf);
+                let r: Float = (it.p.x * it.p.x + it.p.y * it.p.y).sqrt();
+                assert!((r - (1.0 - it.p.z)).abs() < 1e-4);
+                // normals point away from the axis and up
+                assert!(it.n.x * it.p.x + it.n.y * it.p.y >= 0.0 && it.n.z > 0.0);
+                assert!((pdf * shape.area() - 1.0).abs() < 1e-4);
+                mean_z[j] += it.p.z / (n * n) as Float;
+            }
+            let it: InteractionCommon = paraboloid.sample(u, &mut pdf);
+            assert!((it.p.x * it.p.x + it.p.y * it.p.y - it.p.z).abs() < 1e-4);
+            assert!(it.n.z < 0.0);
+            let _ = cylinder.sample_with_ref_point(&iref, u, &mut pdf);
+        }
+        assert!((mean_z[0] - 1.0 / 3.0).abs() < 1e-3);
+        assert!((mean_z[1] - 1.0 / 3.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn heightfield_test() {
+        use crate::core::film::TilePayload;
+        use crate::core::geometry::{Point2f, Point3f, Ray, Vector3f};
+        use crate::core::interaction::{InteractionCommon, SurfaceInteraction};
+        use crate::core::pbrt::Float;
+        use crate::core::shape::Shape;
+        use crate::core::transform::Transform;
+        use crate::shapes::heightfield::{create_heightfield_mesh, Heightfield};
+        use crate::shapes::triangle::Triangle;
+        use std::cell::Cell;
+
+        let (nx, ny): (i32, i32) = (9, 7);
+        let z: Vec<Float> = (0..nx * ny)
+            .map(|i| ((i % nx) as Float * 0.7).sin() * ((i / nx) as Float * 0.5).cos() * 0.3)
+            .collect();
+        let object_to_world: Arc<Transform> = Arc::new(
+            Transform::translate(&Vector3f {
+                x: -2.0,
+                y: -1.5,
+                z: 0.0,
+            }) * Transform::scale(4.0, 3.0, 1.0),
+        );
+        let world_to_object: Arc<Transform> = Arc::new(Transform::inverse(&object_to_world));
+        let grid = Shape::Hghtfld(Heightfield::new(
+            object_to_world.clone(),
+            world_to_object.clone(),
+            false,
+            nx,
+            ny,
+            z.clone(),
+        ));
+        let mesh = create_heightfield_mesh(&object_to_world, &world_to_object, false, nx, ny, &z);
+        let triangles: Vec<Shape> = (0..mesh.n_triangles)
+            .map(|id| Shape::Trngl(Triangle::new(mesh.clone(), id)))
+            .collect();
+        let area: Float = triangles.iter().map(|t| t.area()).sum();
+        assert!((grid.area() - area).abs() < 1e-3 * area);
+        // the grid walk finds the same (nearest) hits as the triangles
+        let mut hits: usize = 0;
+        for i in 0..400 {
+            let o: Point3f = Point3f {
+                x: (i % 20) as Float * 0.2537 - 2.61,
+                y: (i / 20) as Float * 0.2113 - 2.03,
+                z: 2.0,
+            };
+            let d: Vector3f = Vector3f {
+                x: 0.9 - (i % 7) as Float * 0.3,
+                y: (i % 5) as Float * 0.2 - 0.4,
+                z: -1.0,
+            };
+            let ray: Ray = Ray {
+                o,
+                d,
+                t_max: Cell::new(std::f32::INFINITY),
+                ..Default::default()
+            };
+            let mut t_nearest: Option<Float> = None;
+            let mut n_nearest: Vector3f = Vector3f::default();
+            for triangle in &triangles {
+                let mut t_hit: Float = 0.0;
+                let mut isect: SurfaceInteraction = SurfaceInteraction::default();
+                if triangle.intersect(&ray, &mut t_hit, &mut isect)
+                    && t_nearest.map_or(true, |t| t_hit < t)
+                {
+                    t_nearest = Some(t_hit);
+                    n_nearest = Vector3f::from(isect.common.n);
+                }
+            }
+            let mut t_hit: Float = 0.0;
+            let mut isect: SurfaceInteraction = SurfaceInteraction::default();
+            let hit: bool = grid.intersect(&ray, &mut t_hit, &mut isect);
+            assert_eq!(hit, grid.intersect_p(&ray));
+            assert_eq!(hit, t_nearest.is_some(), "ray {}", i);
+            if let Some(t) = t_nearest {
+                hits += 1;
+                assert!((t_hit - t).abs() < 1e-3, "ray {}: {} vs {}", i, t_hit, t);
+                let n: Vector3f = Vector3f::from(isect.common.n);
+                assert!((n - n_nearest).length() < 1e-3);
+                assert!(n.z > 0.0);
+            }
+        }
+        assert!(hits > 100 && hits < 400);
+        // samples lie on the surface
+        let mut pdf: Float = 0.0;
+        for i in 0..64 {
+            let u: Point2f = Point2f {
+                x: (i % 8) as Float / 8.0 + 0.03,
+                y: (i / 8) as Float / 8.0 + 0.05,
+            };
+            let it: InteractionCommon = grid.sample(u, &mut pdf);
+            assert!((pdf * grid.area() - 1.0).abs() < 1e-4);
+            let ray: Ray = Ray {
+                o: Point3f {
+                    x: it.p.x,
+                    y: it.p.y,
+                    z: 2.0,
+                },
+                d: Vector3f {
+                    x: 0.0,
+                    y: 0.0,
+                    z: -1.0,
+                },
+                t_max: Cell::new(std::f32::INFINITY),
+                ..Default::default()
+            };
+            let mut t_hit: Float = 0.0;
+            let mut isect: SurfaceInteraction = SurfaceInteraction::default();
+            assert!(grid.intersect(&ray, &mut t_hit, &mut isect));
+            assert!((isect.common.p.z - it.p.z).abs() < 1e-3);
+        }
+        // both modes render (nearly) the same image
+        const HEIGHTFIELD_DATA: &str = r##"
+        LookAt 0 -4 3  0 0 0  0 0 1
+        Camera "perspective" "float fov" [ 45 ]
+        Film "image" "integer xresolution" [ 16 ] "integer yresolution" [ 16 ]
+        Sampler "halton" "integer pixelsamples" [ 4 ]
+        Integrator "directlighting"
+        WorldBegin
+          LightSource "distant" "point from" [ 1 -1 3 ] "blackbody L" [ 5500 3 ]
+          Material "matte"
+          Scale 4 4 1
+          Translate -0.5 -0.5 0
+          Shape "heightfield" "integer nu" [ 4 ] "integer nv" [ 3 ] "string mode" [ "MODE" ]
+            "float Pz" [ 0 0.2 0.1 0  0.3 0.6 0.5 0.2  0 0.1 0.4 0 ]
+        WorldEnd
+        "##;
+        let mut images: Vec<TilePayload> = Vec::new();
+        for mode in ["grid", "triangles"].iter() {
+            let content_backend = Arc::new(backend::MemoryBackend::new());
+            content_backend.insert_string(
+                "heightfield/main.pbrt",
+                &HEIGHTFIELD_DATA.replace("MODE", mode),
+            );
+            let tile_job: TileJob = TileJob::new("heightfield", 0, 0, 16);
+            let data = entry::entry_tile_job(&tile_job, content_backend).unwrap();
+            images.push(TilePayload::from_bytes(&data).unwrap());
+        }
+        let sum = |payload: &TilePayload| -> Float { payload.pixels.iter().map(|p| p[1]).sum() };
+        assert!(sum(&images[0]) > 0.0);
+        assert!((sum(&images[0]) - sum(&images[1])).abs() < 1e-3 * sum(&images[0]));
+    }
+
+    #[test]
+    fn triangle_alpha_test() {
+        use crate::core::geometry::{Point3f, Ray, Vector3f};
+        use crate::core::interaction::SurfaceInteraction;
+        use crate::core::paramset::ParamSet;
+        use crate::core::pbrt::Float;
+        use crate::core::shape::Shape;
+        use crate::core::texture::Texture;
+        use crate::core::transform::Transform;
+        use crate::shapes::triangle::{create_alpha_textures, Triangle, TriangleMesh};
+        use crate::textures::constant::ConstantTexture;
+        use std::cell::Cell;
+        use std::collections::HashMap;
+
+        let mut float_textures: HashMap<String, Arc<Texture<Float>>> = HashMap::new();
+        float_textures.insert(
+            String::from("cut"),
+            Arc::new(Texture::Constant(ConstantTexture::new(0.0))),
+        );
+        // an opaque constant needs no mask
+        let mut params: ParamSet = ParamSet::default();
+        params.add_float(String::from("alpha"), 1.0);
+        let (alpha, shadow_alpha) = create_alpha_textures(&params, &float_textures);
+        assert!(alpha.is_none() && shadow_alpha.is_none());
+        // fractional constant for camera rays, texture for shadow rays
+        let mut params: ParamSet = ParamSet::default();
+        params.add_float(String::from("alpha"), 0.5);
+        params.add_texture(String::from("shadowalpha"), String::from("cut"));
+        let (alpha, shadow_alpha) = create_alpha_textures(&params, &float_textures);
+        assert!(alpha.is_some() && shadow_alpha.is_some());
+        let quad = |alpha, shadow_alpha| -> Vec<Shape> {
+            let p: Vec<Point3f> = vec![
+                Point3f {
+                    x: -1.0,
+                    y: -1.0,
+                    z: 0.0,
+                },
+                Point3f {
+                    x: 1.0,
+                    y: -1.0,
+                    z: 0.0,
+                },
+                Point3f {
+                    x: 1.0,
+                    y: 1.0,
+                    z: 0.0,
+                },
+                Point3f {
+                    x: -1.0,
+                    y: 1.0,
+                    z: 0.0,
+                },
+            ];
+            let mesh = Arc::new(TriangleMesh::new(
+                Arc::new(Transform::default()),
+                Arc::new(Transform::default()),
+                false,
+                2,
+                vec![0, 1, 2, 0, 2, 3],
+                4,
+                p,
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+                alpha,
+                shadow_alpha,
+            ));
+            (0..2)
+                .map(|id| Shape::Trngl(Triangle::new(mesh.clone(), id)))
+                .collect()
+        };
+        let ray = |i: usize| -> Ray {
+            Ray {
+                o: Point3f {
+                    x: (i % 31) as Float * 0.061 - 0.9,
+                    y: (i / 31) as Float * 0.053 - 0.9,
+                    z: 1.0,
+                },
+                d: Vector3f {
+                    x: 0.0,
+                    y: 0.0,
+                    z: -1.0,
+                },
+                t_max: Cell::new(std::f32::INFINITY),
+                ..Default::default()
+            }
+        };
+        let hits = |shapes: &Vec<Shape>, r: &Ray| -> (bool, bool) {
+            let mut hit: bool = false;
+            let mut hit_p: bool = false;
+            for shape in shapes {
+                let mut t_hit: Float = 0.0;
+                let mut isect: SurfaceInteraction = SurfaceInteraction::default();
+                hit |= shape.intersect(r, &mut t_hit, &mut isect);
+                hit_p |= shape.intersect_p(r);
+            }
+            (hit, hit_p)
+        };
+        let n_rays: usize = 31 * 31;
+        // zero alpha cuts everything away
+        let cut = quad(
+            Some(Arc::new(Texture::Constant(ConstantTexture::new(0.0)))),
+            None,
+        );
+        assert!((0..n_rays).all(|i| hits(&cut, &ray(i)) == (false, false)));
+        // fractional alpha keeps roughly that fraction of the hits, and
+        // the same ray always gets the same answer
+        let half = quad(alpha.clone(), None);
+        let mut n_hits: usize = 0;
+        for i in 0..n_rays {
+            let (hit, hit_p) = hits(&half, &ray(i));
+            assert_eq!(hit, hit_p);
+            assert_eq!(hits(&half, &ray(i)), (hit, hit_p));
+            if hit {
+                n_hits += 1;
+            }
+        }
+        let fraction: Float = n_hits as Float / n_rays as Float;
+        assert!(fraction > 0.4 && fraction < 0.6, "{}", fraction);
+        // a shadow alpha only affects shadow rays
+        let shadow = quad(None, shadow_alpha);
+        assert!((0..n_rays).all(|i| hits(&shadow, &ray(i)) == (true, false)));
+    }
+
+    #[test]
+    fn hlbvh_test() {
+        use crate::accelerators::bvh::{BVHAccel, SplitMethod};
+        use crate::core::geometry::{Point3f, Ray, Vector3f};
+        use crate::core::interaction::SurfaceInteraction;
+        use crate::core::pbrt::Float;
+        use crate::core::primitive::{GeometricPrimitive, Primitive};
+        use crate::core::shape::Shape;
+        use crate::core::transform::Transform;
+        use crate::shapes::sphere::Sphere;
+        use std::cell::Cell;
+
+        // a jittered grid of small spheres
+        let mut prims: Vec<Arc<Primitive>> = Vec::new();
+        for i in 0..1000 {
+            let p: Vector3f = Vector3f {
+                x: (i % 10) as Float + ((i * 7) % 5) as Float * 0.1,
+                y: ((i / 10) % 10) as Float + ((i * 3) % 7) as Float * 0.05,
+                z: (i / 100) as Float,
+            };
+            let object_to_world: Arc<Transform> = Arc::new(Transform::translate(&p));
+            let world_to_object: Arc<Transform> = Arc::new(Transform::inverse(&object_to_world));
+            let sphere = Arc::new(Shape::Sphr(Sphere::new(
+                object_to_world,
+                world_to_object,
+                false,
+                0.3,
+                -0.3,
+                0.3,
+                360.0,
+            )));
+            prims.push(Arc::new(Primitive::Geometric(Box::new(
+                GeometricPrimitive::new(sphere, None, None, None),
+            ))));
+        }
+        let sah = BVHAccel::new(prims.clone(), 4, SplitMethod::SAH, 2);
+        let hlbvh = BVHAccel::new(prims, 4, SplitMethod::HLBVH, 2);
+        assert_eq!(hlbvh.primitives.len(), sah.primitives.len());
+        assert!(hlbvh.sah_cost() < 2.0 * sah.sah_cost());
+        // both trees find the same nearest hits
+        let mut hits: usize = 0;
+        for i in 0..500 {
+            let o: Point3f = Point3f {
+                x: (i % 25) as Float * 0.41 - 0.5,
+                y: (i / 25) as Float * 0.53 - 0.5,
+                z: -5.0,
+            };
+            let d: Vector3f = Vector3f {
+                x: 0.1,
+                y: 0.05,
+                z: 1.0,
+            };
+            let r0: Ray = Ray {
+                o,
+                d,
+                t_max: Cell::new(std::f32::INFINITY),
+                ..Default::default()
+            };
+            let r1: Ray = r0.clone();
+            let mut isect0: SurfaceInteraction = SurfaceInteraction::default();
+            let mut isect1: SurfaceInteraction = SurfaceInteraction::default();
+            let hit: bool = sah.intersect(&r0, &mut isect0);
+            assert_eq!(hlbvh.intersect(&r1, &mut isect1), hit);
+            assert_eq!(hlbvh.intersect_p(&r0), sah.intersect_p(&r0));
+            if hit {
+                hits += 1;
+                assert!((r0.t_max.get() - r1.t_max.get()).abs() < 1e-4);
+            }
+        }
+        assert!(hits > 100);
+    }
+
+    #[test]
+    fn accelerator_parity_test() {
+        use crate::accelerators::bvh::{BVHAccel, SplitMethod};
+        use crate::accelerators::kdtreeaccel::KdTreeAccel;
+        use crate::core::api::{accelerator_test_rays, choose_accelerator};
+        use crate::core::geometry::Ray;
+        use crate::core::interaction::SurfaceInteraction;
+        use crate::core::primitive::Primitive;
+        use crate::core::snapshot::SceneSnapshot;
+        use std::path::PathBuf;
+
+        let dir: TestDir = TestDir::new("accelerator_parity");
+        for scene in &["cornell", "killeroo", "veach-mis", "caustic_glass"] {
+            let path: PathBuf = PathBuf::from(format!("content/{}/main.pbrt", scene));
+            let snapshot: SceneSnapshot = snapshot_scene_file(&dir, &path);
+            let primitives: Vec<Arc<Primitive>> = match &*snapshot.scene.aggregate {
+                Primitive::BVH(bvh) => bvh.primitives.clone(),
+                _ => panic!("{}: expected a BVH", scene),
+            };
+            assert!(!choose_accelerator(&primitives).is_empty());
+            let accelerators: Vec<Primitive> = vec![
+                Primitive::BVH(Box::new(BVHAccel::new(
+                    primitives.clone(),
+                    4,
+                    SplitMethod::SAH,
+                    2,
+                ))),
+                Primitive::BVH(Box::new(BVHAccel::new(
+                    primitives.clone(),
+                    4,
+                    SplitMethod::HLBVH,
+                    4,
+                ))),
+                Primitive::KdTree(Box::new(KdTreeAccel::new(
+                    primitives.clone(),
+                    80,
+                    1,
+                    0.5,
+                    1,
+                    -1,
+                ))),
+            ];
+            if let Primitive::KdTree(kd_tree) = &accelerators[2] {
+                let stats = kd_tree.statistics();
+                assert!(stats.primitive_references >= primitives.len());
+                assert!(kd_tree.sah_cost() > 0.0);
+            }
+            // all accelerators find the same nearest hits
+            let mut hits: usize = 0;
+            for ray in accelerator_test_rays(&snapshot.scene.world_bound, 2000) {
+                let mut t_hit: Option<f32> = None;
+                for (i, accelerator) in accelerators.iter().enumerate() {
+                    let r: Ray = ray.clone();
+                    let mut isect: SurfaceInteraction = SurfaceInteraction::default();
+                    let hit: bool = accelerator.intersect(&r, &mut isect);
+                    assert_eq!(accelerator.intersect_p(&ray), hit, "{} {}", scene, i);
+                    if i == 0 {
+                        t_hit = if hit { Some(r.t_max.get()) } else { None };
+                        if hit {
+                            hits += 1;
+                        }
+                    } else if let Some(t) = t_hit {
+                        assert!(hit, "{} {}", scene, i);
+                        assert!((r.t_max.get() - t).abs() <= 1e-4 * t.max(1.0));
+                    } else {
+                        assert!(!hit, "{} {}", scene, i);
+                    }
+                }
+            }
+            assert!(hits > 0, "{}", scene);
+        }
+    }
+
+    #[test]
+    fn wide_bvh_test() {
+        use crate::accelerators::bvh::{BVHAccel, SplitMethod};
+        use crate::core::geometry::{Point3f, Ray, Vector3f};
+        use crate::core::interaction::SurfaceInteraction;
+        use crate::core::pbrt::Float;
+        use crate::core::primitive::{GeometricPrimitive, Primitive};
+        use crate::core::shape::Shape;
+        use crate::core::transform::Transform;
+        use crate::shapes::sphere::Sphere;
+        use std::cell::Cell;
+
+        let sphere = |p: Vector3f, radius: Float| -> Arc<Primitive> {
+            let object_to_world: Arc<Transform> = Arc::new(Transform::translate(&p));
+            let world_to_object: Arc<Transform> = Arc::new(Transform::inverse(&object_to_world));
+            Arc::new(Primitive::Geometric(Box::new(GeometricPrimitive::new(
+                Arc::new(Shape::Sphr(Sphere::new(
+                    object_to_world,
+                    world_to_object,
+                    false,
+                    radius,
+                    -radius,
+                    radius,
+                    360.0,
+                ))),
+                None,
+                None,
+                None,
+            ))))
+        };
+        let prims: Vec<Arc<Primitive>> = (0..600)
+            .map(|i| {
+                sphere(
+                    Vector3f {
+                        x: ((i * 37) % 17) as Float * 0.6,
+                        y: ((i * 11) % 13) as Float * 0.7,
+                        z: ((i * 5) % 19) as Float * 0.5,
+                    },
+                    0.1 + (i % 4) as Float * 0.1,
+                )
+            })
+            .collect();
+        let binary = BVHAccel::new(prims.clone(), 4, SplitMethod::SAH, 2);
+        let wide: Vec<BVHAccel> = vec![
+            BVHAccel::new(prims.clone(), 4, SplitMethod::SAH, 4),
+            BVHAccel::new(prims.clone(), 4, SplitMethod::HLBVH, 8),
+            // a single primitive ends up in a leaf below the root
+            BVHAccel::new(prims[..1].to_vec(), 4, SplitMethod::SAH, 4),
+        ];
+        assert!(wide[0].n_nodes() < binary.n_nodes());
+        assert!(wide[1].sah_cost() < binary.sah_cost());
+        let binary_prim: Primitive = Primitive::BVH(Box::new(binary));
+        for i in 0..600 {
+            let o: Point3f = Point3f {
+                x: (i % 30) as Float * 0.37 - 0.5,
+                y: (i / 30) as Float * 0.47 - 0.5,
+                z: -4.0,
+            };
+            let d: Vector3f = Vector3f {
+                x: (i % 7) as Float * 0.03 - 0.1,
+                y: 0.05,
+                z: 1.0,
+            };
+            // the single sphere is compared against itself
+            let expected: [&Primitive; 3] = [&binary_prim, &binary_prim, &prims[0]];
+            for (bvh, expected) in wide.iter().zip(expected.iter()) {
+                let r0: Ray = Ray {
+                    o,
+                    d,
+                    t_max: Cell::new(std::f32::INFINITY),
+                    ..Default::default()
+                };
+                let r1: Ray = r0.clone();
+                let mut isect0: SurfaceInteraction = SurfaceInteraction::default();
+                let mut isect1: SurfaceInteraction = SurfaceInteraction::default();
+                let hit: bool = expected.intersect(&r0, &mut isect0);
+                assert_eq!(bvh.intersect(&r1, &mut isect1), hit);
+                assert_eq!(bvh.intersect_p(&r0), expected.intersect_p(&r0));
+                if hit {
+                    assert!((r0.t_max.get() - r1.t_max.get()).abs() < 1e-4);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn collector_test() {
+        use crate::collector::Dispatcher;
+        use crate::core::api::pbrt_init;
+        use std::path::PathBuf;
+
+        let dir: TestDir = TestDir::new("collector");
+        let scene_file: PathBuf = dir.write_scene("cornell", CORNELL_DATA);
+        // a worker rendering tiles from the same directory
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url: String = format!("http://{}", server.server_addr().to_ip().unwrap());
+        let content_backend = Arc::new(backend::LocalBackend::new(dir.path.clone()));
+        std::thread::spawn(move || {
+            for mut request in server.incoming_requests() {
+                let mut body: String = String::new();
+                request.as_reader().read_to_string(&mut body).unwrap();
+                let output = TileJob::from_json(&body)
+                    .and_then(|tile_job| entry::entry_tile_job(&tile_job, content_backend.clone()))
+                    .unwrap();
+                request.respond(tiny_http::Response::from_data(output)).unwrap();
+            }
+        });
+        let render = |image_file: &str, dispatcher: Option<Dispatcher>| -> Vec<u8> {
+            let image_file: PathBuf = dir.join(image_file);
+            let (mut api_state, bsdf_state) = pbrt_init(1, 2, 0.4, 0.6, 0.4, 0.6);
+            api_state.set_image_file(image_file.to_str().unwrap());
+            if let Some(dispatcher) = dispatcher {
+                api_state.set_dispatcher(dispatcher);
+            }
+            entry::entry_file(&scene_file, api_state, bsdf_state, &None);
+            std::fs::read(&image_file).unwrap()
+        };
+        // every other tile is sent to a worker which isn't there first
+        let workers: Vec<String> = vec![url, String::from("http://127.0.0.1:9")];
+        let dispatcher: Dispatcher = Dispatcher::new(workers, &scene_file);
+        assert_eq!(dispatcher.tile_job.filename, "cornell");
+        let local: Vec<u8> = render("local.png", None);
+        let remote: Vec<u8> = render("remote.png", Some(dispatcher));
+        assert!(local == remote);
+        // tiles which fail on all workers are rendered by the collector
+        let workers: Vec<String> = vec![String::from("http://127.0.0.1:9")];
+        let mut dispatcher: Dispatcher = Dispatcher::new(workers, &scene_file);
+        dispatcher.retries = 0;
+        let fallback: Vec<u8> = render("fallback.png", Some(dispatcher));
+        assert!(local == fallback);
+        // a worker which refuses each job (4xx) is asked once per tile,
+        // one which always sends back the first tile isn't merged
+        let refusing = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let wrong = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let workers: Vec<String> = vec![
+            format!("http://{}", refusing.server_addr().to_ip().unwrap()),
+            format!("http://{}", wrong.server_addr().to_ip().unwrap()),
+        ];
+        let requests = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let refused = requests.clone();
+        std::thread::spawn(move || {
+            for request in refusing.incoming_requests() {
+                refused.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                let error = tilejob::TileJobError::new(422, "Refused");
+                let response = tiny_http::Response::from_string(error.to_json());
+                request.respond(response.with_status_code(422)).unwrap();
+            }
+        });
+        let content_backend = Arc::new(backend::LocalBackend::new(dir.path.clone()));
+        std::thread::spawn(move || {
+            for mut request in wrong.incoming_requests() {
+                let mut body: String = String::new();
+                request.as_reader().read_to_string(&mut body).unwrap();
+                let mut tile_job: TileJob = TileJob::from_json(&body).unwrap();
+                tile_job.x = 0;
+                tile_job.y = 0;
+                let output = entry::entry_tile_job(&tile_job, content_backend.clone()).unwrap();
+                let response = tiny_http::Response::from_data(output);
+                request.respond(response).unwrap();
+            }
+        });
+        for (worker, retries) in workers.into_iter().zip(&[3, 0]) {
+            let mut dispatcher: Dispatcher = Dispatcher::new(vec![worker], &scene_file);
+            dispatcher.retries = *retries;
+            let fallback: Vec<u8> = render("fallback.png", Some(dispatcher));
+            assert!(local == fallback);
+        }
+        // 7x7 tiles
+        assert_eq!(requests.load(std::sync::atomic::Ordering::SeqCst), 49);
+    }
+
+    #[test]
+    fn content_backend_test() {
+        let local = backend::LocalBackend::new(concat!(env!("CARGO_MANIFEST_DIR"), "/content"));
+        assert!(local.exists("/cornell/main.pbrt"));
+        assert!(!local.exists("/cornell/missing.pbrt"));
+        let data: String = local.get_string("/cornell/main.pbrt").unwrap();
+        assert_eq!(local.size("cornell/main.pbrt"), Some(data.len() as u64));
+        let memory = backend::MemoryBackend::new();
+        memory.insert_string("cornell/main.pbrt", &data);
+        assert_eq!(memory.get_string("/cornell/main.pbrt").unwrap(), data);
+        assert!(memory.get_binary("/cornell/missing.pbrt").is_err());
+        // the selected backend is per thread and restored by the guard
+        let threads: Vec<std::thread::JoinHandle<()>> = (0..4)
+            .map(|i| {
+                std::thread::spawn(move || {
+                    let memory = Arc::new(backend::MemoryBackend::new());
+                    memory.insert_string("scene/main.pbrt", &format!("{}", i));
+                    let _guard = backend::set_content_backend(memory);
+                    for _ in 0..100 {
+                        let data: String = backend::get_content_string("/scene/main.pbrt").unwrap();
+                        assert_eq!(data, format!("{}", i));
+                        std::thread::yield_now();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        {
+            let _guard = backend::set_content_backend(Arc::new(backend::MemoryBackend::new()));
+            assert!(!backend::content_exists("/cornell/main.pbrt"));
+        }
+        assert!(backend::content_exists("/cornell/main.pbrt"));
+    }
+
+    #[test]
+    fn tile_payload_test() {
+        use crate::core::film::{Film, TilePayload};
+        use crate::core::geometry::pnt2_inside_exclusivei;
+        use crate::core::geometry::{Bounds2f, Bounds2i, Point2f, Point2i};
+        use crate::core::paramset::ParamSet;
+        use crate::core::pbrt::{Float, Spectrum};
+        use crate::filters::gaussian::GaussianFilter;
+
+        let mut params: ParamSet = ParamSet::default();
+        params.add_int(String::from("xresolution"), 32);
+        params.add_int(String::from("yresolution"), 24);
+        let crop_window: Bounds2f = Bounds2f {
+            p_min: Point2f { x: 0.0, y: 0.0 },
+            p_max: Point2f { x: 1.0, y: 1.0 },
+        };
+        let filter_params: ParamSet = ParamSet::default();
+        // single process: merge the film tiles directly
+        let film: Arc<Film> = Film::create(
+            &params,
+            GaussianFilter::create(&filter_params),
+            &crop_window,
+            "",
+            false,
+            &[],
+        );
+        // distributed: merge the padded tiles sent by workers
+        let collector_film: Arc<Film> = Film::create(
+            &params,
+            GaussianFilter::create(&filter_params),
+            &crop_window,
+            "",
+            false,
+            &[],
+        );
+        let sample_bounds: Bounds2i = film.get_sample_bounds();
+        let tile_size: i32 = 8;
+        let mut y0: i32 = sample_bounds.p_min.y;
+        while y0 < sample_bounds.p_max.y {
+            let mut x0: i32 = sample_bounds.p_min.x;
+            while x0 < sample_bounds.p_max.x {
+                let tile_bounds: Bounds2i = Bounds2i::new(
+                    Point2i { x: x0, y: y0 },
+                    Point2i {
+                        x: std::cmp::min(x0 + tile_size, sample_bounds.p_max.x),
+                        y: std::cmp::min(y0 + tile_size, sample_bounds.p_max.y),
+                    },
+                );
+                let mut film_tile = film.get_film_tile(&tile_bounds);
+                for pixel in &tile_bounds {
+                    let mut l: Spectrum =
+                        Spectrum::new(((pixel.x * 7 + pixel.y * 13) % 17) as Float);
+                    let p_film: Point2f = Point2f {
+                        x: pixel.x as Float + 0.25,
+                        y: pixel.y as Float + 0.75,
+                    };
+                    film_tile.add_sample(p_film, &mut l, 1.0 as Float);
+                }
+                // the padded tile reaches into its neighbours
+                if x0 > 0 {
+                    assert!(film_tile.pixel_bounds.p_min.x < x0);
+                }
+                let data: Vec<u8> = film.get_tile_payload(&film_tile, 1, "path").to_bytes();
+                let payload: TilePayload = TilePayload::from_bytes(&data).unwrap();
+                assert_eq!(payload.integrator, "path");
+                assert_eq!(payload.splat_xyz.len(), 0);
+                collector_film.merge_tile_payload(&payload);
+                film.merge_film_tile(&film_tile);
+                x0 += tile_size;
+            }
+            y0 += tile_size;
+        }
+        let bounds: Bounds2i = film.get_cropped_pixel_bounds();
+        assert_eq!(
+            film.get_image_rgb8(&bounds, 1.0 as Float),
+            collector_film.get_image_rgb8(&bounds, 1.0 as Float)
+        );
+        // splats are sent along and merged
+        film.add_splat(Point2f { x: 3.5, y: 5.5 }, &Spectrum::new(0.25));
+        let empty_tile = film.get_film_tile(&Bounds2i::default());
+        let payload: TilePayload = film.get_tile_payload(&empty_tile, 1, "bdpt");
+        assert_eq!(payload.splat_xyz.len(), 1);
+        let changed: Bounds2i = collector_film.merge_tile_payload(&payload);
+        assert!(pnt2_inside_exclusivei(Point2i { x: 3, y: 5 }, &changed));
+        assert_eq!(
+            film.get_image_rgb8(&bounds, 1.0 as Float),
+            collector_film.get_image_rgb8(&bounds, 1.0 as Float)
+        );
+        // truncated data is rejected
+        assert!(TilePayload::from_bytes(&payload.to_bytes()[..20]).is_err());
     }
 }
 
 #[cfg(not(feature = "ecp"))]
 #[wasm_bindgen]
 pub fn lib_entry(tile_size: i32, filename: String, data: String) -> Vec<u8> {
-    entry::entry(true, tile_size, None, None, &filename, &data)
+    entry::entry(
+        true,
+        tile_size,
+        None,
+        None,
+        &filename,
+        &data,
+        backend::default_content_backend(),
+    )
+}
+
+/// Called by the browser for each tile a worker sends back, see
+/// [merge_collector_tile()](core/film/fn.merge_collector_tile.html).
+#[cfg(not(feature = "ecp"))]
+#[wasm_bindgen]
+pub fn merge_tile(data: Vec<u8>) -> Vec<u8> {
+    core::film::merge_collector_tile(&data)
 }
@@ -21,12 +21,15 @@ use crate::core::transform::Transform;
 #[derive(Serialize, Deserialize)]
 pub struct DiffuseAreaLight {
     pub l_emit: Spectrum,
+    #[serde(with = "crate::core::snapshot::shared")]
     pub shape: Arc<Shape>,
     pub two_sided: bool,
     pub area: Float,
     // inherited from class Light (see light.h)
     pub flags: u8,
     pub n_samples: i32,
+    /// index of the light group (see `LightGroup`), 0 if none
+    pub light_group: usize,
     pub medium_interface: MediumInterface,
     // light_to_world: Transform,
     // world_to_light: Transform,
@@ -58,6 +61,7 @@ impl DiffuseAreaLight {
             // inherited from class Light (see light.h)
             flags: LightFlags::Area as u8,
             n_samples: std::cmp::max(1_i32, n_samples),
+            light_group: 0,
             medium_interface: MediumInterface { inside, outside },
             // light_to_world: *light_to_world,
             // world_to_light: Transform::inverse(*light_to_world),
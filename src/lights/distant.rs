@@ -2,7 +2,7 @@
 use serde::{Deserialize, Serialize};
 use std::cell::Cell;
 use std::f32::consts::PI;
-use std::sync::RwLock;
+use std::sync::{Arc, RwLock};
 
 // pbrt
 use crate::core::geometry::vec3_coordinate_system;
@@ -26,13 +26,22 @@ pub struct DistantLight {
     // inherited from class Light (see light.h)
     pub flags: u8,
     pub n_samples: i32,
+    /// index of the light group (see `LightGroup`), 0 if none
+    pub light_group: usize,
     pub medium_interface: MediumInterface,
-    pub light_to_world: Transform,
-    pub world_to_light: Transform,
+    #[serde(with = "crate::core::snapshot::shared")]
+    pub light_to_world: Arc<Transform>,
+    #[serde(with = "crate::core::snapshot::shared")]
+    pub world_to_light: Arc<Transform>,
 }
 
 impl DistantLight {
-    pub fn new(light_to_world: &Transform, l: &Spectrum, w_light: &Vector3f) -> Self {
+    pub fn new(
+        light_to_world: &Arc<Transform>,
+        world_to_light: &Arc<Transform>,
+        l: &Spectrum,
+        w_light: &Vector3f,
+    ) -> Self {
         DistantLight {
             l: *l,
             w_light: light_to_world.transform_vector(&*w_light).normalize(),
@@ -40,9 +49,10 @@ impl DistantLight {
             world_radius: RwLock::new(0.0),
             flags: LightFlags::DeltaDirection as u8,
             n_samples: 1_i32,
+            light_group: 0,
             medium_interface: MediumInterface::default(),
-            light_to_world: Transform::default(),
-            world_to_light: Transform::default(),
+            light_to_world: light_to_world.clone(),
+            world_to_light: world_to_light.clone(),
         }
     }
     // Light
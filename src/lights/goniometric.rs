@@ -2,6 +2,8 @@
 use std::cell::Cell;
 use std::f32::consts::PI;
 use std::io::BufReader;
+#[cfg(feature = "openexr")]
+use std::io::Cursor;
 use std::sync::Arc;
 // others
 #[cfg(feature = "openexr")]
@@ -9,6 +11,7 @@ use half::f16;
 #[cfg(feature = "openexr")]
 use openexr::{FrameBufferMut, InputFile, PixelType};
 // pbrt
+use crate::backend::get_content_binary;
 use crate::core::geometry::{pnt3_distance_squaredf, spherical_phi, spherical_theta};
 use crate::core::geometry::{Normal3f, Point2f, Point2i, Point3f, Ray, Vector3f};
 use crate::core::interaction::{Interaction, InteractionCommon};
@@ -52,24 +55,30 @@ pub struct GonioPhotometricLight {
     // inherited from class Light (see light.h)
     pub flags: u8,
     pub n_samples: i32,
+    /// index of the light group (see `LightGroup`), 0 if none
+    pub light_group: usize,
     pub medium_interface: MediumInterface,
-    pub light_to_world: Transform,
-    pub world_to_light: Transform,
+    #[serde(with = "crate::core::snapshot::shared")]
+    pub light_to_world: Arc<Transform>,
+    #[serde(with = "crate::core::snapshot::shared")]
+    pub world_to_light: Arc<Transform>,
 }
 
 impl GonioPhotometricLight {
     #[cfg(not(feature = "openexr"))]
     pub fn new(
-        light_to_world: &Transform,
+        light_to_world: &Arc<Transform>,
+        world_to_light: &Arc<Transform>,
         medium_interface: &MediumInterface,
         i: &Spectrum,
         texname: String,
     ) -> Self {
-        GonioPhotometricLight::new_hdr(light_to_world, medium_interface, i, texname)
+        GonioPhotometricLight::new_hdr(light_to_world, world_to_light, medium_interface, i, texname)
     }
     #[cfg(feature = "openexr")]
     pub fn new(
-        light_to_world: &Transform,
+        light_to_world: &Arc<Transform>,
+        world_to_light: &Arc<Transform>,
         medium_interface: &MediumInterface,
         i: &Spectrum,
         texname: String,
@@ -80,9 +89,9 @@ impl GonioPhotometricLight {
             let mut resolution: Point2i = Point2i::default();
             let mut names_and_fills: Vec<(&str, f64)> = Vec::new();
             // header
-            let file_result = std::fs::File::open(texname.clone());
+            let file_result = get_content_binary(&texname);
             if file_result.is_ok() {
-                let mut file = file_result.unwrap();
+                let mut file = Cursor::new(file_result.unwrap());
                 let input_file_result = InputFile::new(&mut file);
                 if input_file_result.is_ok() {
                     let input_file = input_file_result.unwrap();
@@ -106,7 +115,7 @@ impl GonioPhotometricLight {
                         ];
                     {
                         // read pixels
-                        let mut file = std::fs::File::open(texname.clone()).unwrap();
+                        let mut file = Cursor::new(get_content_binary(&texname).unwrap());
                         let mut input_file = InputFile::new(&mut file).unwrap();
                         let mut fb = FrameBufferMut::new(resolution.x as u32, resolution.y as u32);
                         fb.insert_channels(&names_and_fills[..], &mut pixel_data);
@@ -139,17 +148,30 @@ impl GonioPhotometricLight {
                         mipmap: Some(projection_map),
                         flags: LightFlags::DeltaPosition as u8,
                         n_samples: 1_i32,
+                        light_group: 0,
                         medium_interface: MediumInterface::default(),
-                        light_to_world: Transform::default(),
-                        world_to_light: Transform::default(),
+                        light_to_world: Arc::new(Transform::default()),
+                        world_to_light: Arc::new(Transform::default()),
                     }
                 } else {
                     // try to open an HDR image instead (TODO: check extension upfront)
-                    GonioPhotometricLight::new_hdr(light_to_world, medium_interface, i, texname)
+                    GonioPhotometricLight::new_hdr(
+                        light_to_world,
+                        world_to_light,
+                        medium_interface,
+                        i,
+                        texname,
+                    )
                 }
             } else {
                 // try to open an HDR image instead (TODO: check extension upfront)
-                GonioPhotometricLight::new_hdr(light_to_world, medium_interface, i, texname)
+                GonioPhotometricLight::new_hdr(
+                    light_to_world,
+                    world_to_light,
+                    medium_interface,
+                    i,
+                    texname,
+                )
             }
         } else {
             GonioPhotometricLight {
@@ -158,21 +180,23 @@ impl GonioPhotometricLight {
                 mipmap: None,
                 flags: LightFlags::DeltaPosition as u8,
                 n_samples: 1_i32,
+                light_group: 0,
                 medium_interface: MediumInterface::default(),
-                light_to_world: Transform::default(),
-                world_to_light: Transform::default(),
+                light_to_world: Arc::new(Transform::default()),
+                world_to_light: Arc::new(Transform::default()),
             }
         }
     }
     pub fn new_hdr(
-        light_to_world: &Transform,
+        light_to_world: &Arc<Transform>,
+        world_to_light: &Arc<Transform>,
         _medium_interface: &MediumInterface,
         i: &Spectrum,
         texname: String,
     ) -> Self {
         if texname != "" {
-            let file = std::fs::File::open(texname).unwrap();
-            let reader = BufReader::new(file);
+            let data = get_content_binary(&texname).unwrap_or_default();
+            let reader = BufReader::new(data.as_slice());
             let img_result = image::hdr::HdrDecoder::with_strictness(reader, false);
             if img_result.is_ok() {
                 if let Ok(hdr) = img_result {
@@ -209,9 +233,10 @@ impl GonioPhotometricLight {
                             mipmap: Some(projection_map),
                             flags: LightFlags::DeltaPosition as u8,
                             n_samples: 1_i32,
+                            light_group: 0,
                             medium_interface: MediumInterface::default(),
-                            light_to_world: *light_to_world,
-                            world_to_light: Transform::inverse(&*light_to_world),
+                            light_to_world: light_to_world.clone(),
+                            world_to_light: world_to_light.clone(),
                         };
                     }
                 }
@@ -225,9 +250,10 @@ impl GonioPhotometricLight {
             mipmap: None,
             flags: LightFlags::DeltaPosition as u8,
             n_samples: 1_i32,
+            light_group: 0,
             medium_interface: MediumInterface::default(),
-            light_to_world: Transform::default(),
-            world_to_light: Transform::default(),
+            light_to_world: Arc::new(Transform::default()),
+            world_to_light: Arc::new(Transform::default()),
         }
     }
     pub fn scale(&self, w: &Vector3f) -> Spectrum {
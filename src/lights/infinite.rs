@@ -2,6 +2,8 @@
 use std::cell::Cell;
 use std::f32::consts::PI;
 use std::io::BufReader;
+#[cfg(feature = "openexr")]
+use std::io::Cursor;
 use std::sync::{Arc, RwLock};
 
 #[cfg(not(feature = "ecp"))]
@@ -68,27 +70,43 @@ pub struct InfiniteAreaLight {
     // inherited from class Light (see light.h)
     pub flags: u8,
     pub n_samples: i32,
+    /// index of the light group (see `LightGroup`), 0 if none
+    pub light_group: usize,
     pub medium_interface: MediumInterface,
-    pub light_to_world: Transform,
-    pub world_to_light: Transform,
+    #[serde(with = "crate::core::snapshot::shared")]
+    pub light_to_world: Arc<Transform>,
+    #[serde(with = "crate::core::snapshot::shared")]
+    pub world_to_light: Arc<Transform>,
 }
 
 impl InfiniteAreaLight {
     #[cfg(not(feature = "openexr"))]
-    pub fn new(light_to_world: &Transform, l: &Spectrum, n_samples: i32, texmap: String) -> Self {
-        InfiniteAreaLight::new_hdr(light_to_world, l, n_samples, texmap)
+    pub fn new(
+        light_to_world: &Arc<Transform>,
+        world_to_light: &Arc<Transform>,
+        l: &Spectrum,
+        n_samples: i32,
+        texmap: String,
+    ) -> Self {
+        InfiniteAreaLight::new_hdr(light_to_world, world_to_light, l, n_samples, texmap)
     }
     #[cfg(feature = "openexr")]
-    pub fn new(light_to_world: &Transform, l: &Spectrum, n_samples: i32, texmap: String) -> Self {
+    pub fn new(
+        light_to_world: &Arc<Transform>,
+        world_to_light: &Arc<Transform>,
+        l: &Spectrum,
+        n_samples: i32,
+        texmap: String,
+    ) -> Self {
         // read texel data from _texmap_ and initialize _Lmap_
         if texmap != String::from("") {
             // https://cessen.github.io/openexr-rs/openexr/index.html
             let mut resolution: Point2i = Point2i::default();
             let mut names_and_fills: Vec<(&str, f64)> = Vec::new();
             // header
-            let file_result = std::fs::File::open(texmap.clone());
+            let file_result = get_content_binary(&texmap);
             if file_result.is_ok() {
-                let mut file = file_result.unwrap();
+                let mut file = Cursor::new(file_result.unwrap());
                 let input_file_result = InputFile::new(&mut file);
                 if input_file_result.is_ok() {
                     let input_file = input_file_result.unwrap();
@@ -112,7 +130,7 @@ impl InfiniteAreaLight {
                         ];
                     {
                         // read pixels
-                        let mut file = std::fs::File::open(texmap.clone()).unwrap();
+                        let mut file = Cursor::new(get_content_binary(&texmap).unwrap());
                         let mut input_file = InputFile::new(&mut file).unwrap();
                         let mut fb = FrameBufferMut::new(resolution.x as u32, resolution.y as u32);
                         fb.insert_channels(&names_and_fills[..], &mut pixel_data);
@@ -169,32 +187,33 @@ impl InfiniteAreaLight {
                         distribution,
                         flags: LightFlags::Infinite as u8,
                         n_samples: std::cmp::max(1_i32, n_samples),
+                        light_group: 0,
                         medium_interface: MediumInterface::default(),
-                        light_to_world: *light_to_world,
-                        world_to_light: Transform::inverse(&*light_to_world),
+                        light_to_world: light_to_world.clone(),
+                        world_to_light: world_to_light.clone(),
                     }
                 } else {
                     // try to open an HDR image instead (TODO: check extension upfront)
-                    InfiniteAreaLight::new_hdr(light_to_world, l, n_samples, texmap)
+                    InfiniteAreaLight::new_hdr(light_to_world, world_to_light, l, n_samples, texmap)
                 }
             } else {
                 // try to open an HDR image instead (TODO: check extension upfront)
-                InfiniteAreaLight::new_hdr(light_to_world, l, n_samples, texmap)
+                InfiniteAreaLight::new_hdr(light_to_world, world_to_light, l, n_samples, texmap)
             }
         } else {
             InfiniteAreaLight::default(n_samples, l)
         }
     }
     pub fn new_hdr(
-        light_to_world: &Transform,
+        light_to_world: &Arc<Transform>,
+        world_to_light: &Arc<Transform>,
         l: &Spectrum,
         n_samples: i32,
         texmap: String,
     ) -> Self {
         // read texel data from _texmap_ and initialize _Lmap_
         if texmap != "" {
-//            let file = std::fs::File::open(texmap).unwrap();
-			let data = get_content_binary(&texmap).unwrap();
+            let data = get_content_binary(&texmap).unwrap_or_default();
             let reader = BufReader::new(data.as_slice());
             let img_result = image::hdr::HdrDecoder::with_strictness(reader, false);
             if img_result.is_ok() {
@@ -253,16 +272,17 @@ impl InfiniteAreaLight {
                             distribution,
                             flags: LightFlags::Infinite as u8,
                             n_samples: std::cmp::max(1_i32, n_samples),
+                            light_group: 0,
                             medium_interface: MediumInterface::default(),
-                            light_to_world: *light_to_world,
-                            world_to_light: Transform::inverse(&*light_to_world),
+                            light_to_world: light_to_world.clone(),
+                            world_to_light: world_to_light.clone(),
                         };
                     }
                 }
             } else {
-				let msg = format!("Error Infinite Light: {:?}", img_result);
+				#[cfg(target_arch = "wasm32")]
 				#[cfg(not(feature = "ecp"))]
-				log(&msg);
+				log(&format!("Error Infinite Light: {:?}", img_result));
                 println!("WARNING: InfiniteAreaLight::new() ... no OpenEXR support !!!");
             }
         }
@@ -307,9 +327,10 @@ impl InfiniteAreaLight {
             distribution,
             flags: LightFlags::Infinite as u8,
             n_samples: std::cmp::max(1_i32, n_samples),
+            light_group: 0,
             medium_interface: MediumInterface::default(),
-            light_to_world: Transform::default(),
-            world_to_light: Transform::default(),
+            light_to_world: Arc::new(Transform::default()),
+            world_to_light: Arc::new(Transform::default()),
         }
     }
     // Light
@@ -23,6 +23,8 @@ pub struct PointLight {
     // inherited from class Light (see light.h)
     pub flags: u8,
     pub n_samples: i32,
+    /// index of the light group (see `LightGroup`), 0 if none
+    pub light_group: usize,
     pub medium_interface: MediumInterface,
 }
 
@@ -45,6 +47,7 @@ impl PointLight {
             i: *i,
             flags: LightFlags::DeltaPosition as u8,
             n_samples: 1_i32,
+            light_group: 0,
             medium_interface: MediumInterface { inside, outside },
         }
     }
@@ -2,6 +2,8 @@
 use std::cell::Cell;
 use std::f32::consts::PI;
 use std::io::BufReader;
+#[cfg(feature = "openexr")]
+use std::io::Cursor;
 use std::sync::Arc;
 // others
 #[cfg(feature = "openexr")]
@@ -9,6 +11,7 @@ use half::f16;
 #[cfg(feature = "openexr")]
 use openexr::{FrameBufferMut, InputFile, PixelType};
 // pbrt
+use crate::backend::get_content_binary;
 use crate::core::geometry::{pnt2_inside_bnd2f, pnt3_distance_squaredf};
 use crate::core::geometry::{Bounds2f, Normal3f, Point2f, Point2i, Point3f, Ray, Vector3f};
 use crate::core::interaction::{Interaction, InteractionCommon};
@@ -58,25 +61,38 @@ pub struct ProjectionLight {
     // inherited from class Light (see light.h)
     pub flags: u8,
     pub n_samples: i32,
+    /// index of the light group (see `LightGroup`), 0 if none
+    pub light_group: usize,
     pub medium_interface: MediumInterface,
-    pub light_to_world: Transform,
-    pub world_to_light: Transform,
+    #[serde(with = "crate::core::snapshot::shared")]
+    pub light_to_world: Arc<Transform>,
+    #[serde(with = "crate::core::snapshot::shared")]
+    pub world_to_light: Arc<Transform>,
 }
 
 impl ProjectionLight {
     #[cfg(not(feature = "openexr"))]
     pub fn new(
-        light_to_world: &Transform,
+        light_to_world: &Arc<Transform>,
+        world_to_light: &Arc<Transform>,
         medium_interface: &MediumInterface,
         i: &Spectrum,
         texname: String,
         fov: Float,
     ) -> Self {
-        ProjectionLight::new_hdr(light_to_world, medium_interface, i, texname, fov)
+        ProjectionLight::new_hdr(
+            light_to_world,
+            world_to_light,
+            medium_interface,
+            i,
+            texname,
+            fov,
+        )
     }
     #[cfg(feature = "openexr")]
     pub fn new(
-        light_to_world: &Transform,
+        light_to_world: &Arc<Transform>,
+        world_to_light: &Arc<Transform>,
         medium_interface: &MediumInterface,
         i: &Spectrum,
         texname: String,
@@ -87,9 +103,9 @@ impl ProjectionLight {
             let mut resolution: Point2i = Point2i::default();
             let mut names_and_fills: Vec<(&str, f64)> = Vec::new();
             // header
-            let file_result = std::fs::File::open(texname.clone());
+            let file_result = get_content_binary(&texname);
             if file_result.is_ok() {
-                let mut file = file_result.unwrap();
+                let mut file = Cursor::new(file_result.unwrap());
                 let input_file_result = InputFile::new(&mut file);
                 if input_file_result.is_ok() {
                     let input_file = input_file_result.unwrap();
@@ -113,7 +129,7 @@ impl ProjectionLight {
                         ];
                     {
                         // read pixels
-                        let mut file = std::fs::File::open(texname.clone()).unwrap();
+                        let mut file = Cursor::new(get_content_binary(&texname).unwrap());
                         let mut input_file = InputFile::new(&mut file).unwrap();
                         let mut fb = FrameBufferMut::new(resolution.x as u32, resolution.y as u32);
                         fb.insert_channels(&names_and_fills[..], &mut pixel_data);
@@ -189,9 +205,10 @@ impl ProjectionLight {
                         cos_total_width,
                         flags: LightFlags::DeltaPosition as u8,
                         n_samples: 1_i32,
+                        light_group: 0,
                         medium_interface: MediumInterface::default(),
-                        light_to_world: *light_to_world,
-                        world_to_light: Transform::inverse(&*light_to_world),
+                        light_to_world: light_to_world.clone(),
+                        world_to_light: world_to_light.clone(),
                     };
                 } else {
                     // try to open an HDR image instead (TODO: check extension upfront)
@@ -205,7 +222,14 @@ impl ProjectionLight {
                 }
             } else {
                 // try to open an HDR image instead (TODO: check extension upfront)
-                return ProjectionLight::new_hdr(light_to_world, medium_interface, i, texname, fov);
+                return ProjectionLight::new_hdr(
+                    light_to_world,
+                    world_to_light,
+                    medium_interface,
+                    i,
+                    texname,
+                    fov,
+                );
             }
         }
         ProjectionLight {
@@ -219,21 +243,23 @@ impl ProjectionLight {
             cos_total_width: 0.0 as Float,
             flags: LightFlags::DeltaPosition as u8,
             n_samples: 1_i32,
+            light_group: 0,
             medium_interface: MediumInterface::default(),
-            light_to_world: Transform::default(),
-            world_to_light: Transform::default(),
+            light_to_world: Arc::new(Transform::default()),
+            world_to_light: Arc::new(Transform::default()),
         }
     }
     pub fn new_hdr(
-        light_to_world: &Transform,
+        light_to_world: &Arc<Transform>,
+        world_to_light: &Arc<Transform>,
         _medium_interface: &MediumInterface,
         i: &Spectrum,
         texname: String,
         fov: Float,
     ) -> Self {
         if texname != "" {
-            let file = std::fs::File::open(texname).unwrap();
-            let reader = BufReader::new(file);
+            let data = get_content_binary(&texname).unwrap_or_default();
+            let reader = BufReader::new(data.as_slice());
             let img_result = image::hdr::HdrDecoder::with_strictness(reader, false);
             if img_result.is_ok() {
                 if let Ok(hdr) = img_result {
@@ -311,9 +337,10 @@ impl ProjectionLight {
                             cos_total_width,
                             flags: LightFlags::DeltaPosition as u8,
                             n_samples: 1_i32,
+                            light_group: 0,
                             medium_interface: MediumInterface::default(),
-                            light_to_world: *light_to_world,
-                            world_to_light: Transform::inverse(&*light_to_world),
+                            light_to_world: light_to_world.clone(),
+                            world_to_light: world_to_light.clone(),
                         };
                     }
                 }
@@ -332,9 +359,10 @@ impl ProjectionLight {
             cos_total_width: 0.0 as Float,
             flags: LightFlags::DeltaPosition as u8,
             n_samples: 1_i32,
+            light_group: 0,
             medium_interface: MediumInterface::default(),
-            light_to_world: Transform::default(),
-            world_to_light: Transform::default(),
+            light_to_world: Arc::new(Transform::default()),
+            world_to_light: Arc::new(Transform::default()),
         }
     }
     pub fn projection(&self, w: &Vector3f) -> Spectrum {
@@ -27,14 +27,19 @@ pub struct SpotLight {
     // inherited from class Light (see light.h)
     pub flags: u8,
     pub n_samples: i32,
+    /// index of the light group (see `LightGroup`), 0 if none
+    pub light_group: usize,
     pub medium_interface: MediumInterface,
-    pub light_to_world: Transform,
-    pub world_to_light: Transform,
+    #[serde(with = "crate::core::snapshot::shared")]
+    pub light_to_world: Arc<Transform>,
+    #[serde(with = "crate::core::snapshot::shared")]
+    pub world_to_light: Arc<Transform>,
 }
 
 impl SpotLight {
     pub fn new(
-        light_to_world: &Transform,
+        light_to_world: &Arc<Transform>,
+        world_to_light: &Arc<Transform>,
         medium_interface: &MediumInterface,
         i: &Spectrum,
         total_width: Float,
@@ -54,9 +59,10 @@ impl SpotLight {
             cos_falloff_start: radians(falloff_start).cos(),
             flags: LightFlags::DeltaPosition as u8,
             n_samples: 1_i32,
+            light_group: 0,
             medium_interface: MediumInterface { inside, outside },
-            light_to_world: *light_to_world,
-            world_to_light: Transform::inverse(light_to_world),
+            light_to_world: light_to_world.clone(),
+            world_to_light: world_to_light.clone(),
         }
     }
     pub fn falloff(&self, w: &Vector3f) -> Float {
@@ -7,14 +7,13 @@ use std::time::Instant;
 #[macro_use]
 extern crate serde;
 
-use serde::{Deserialize, Serialize};
-
 #[macro_use]
 extern crate impl_ops;
 
 pub mod accelerators;
 pub mod blockqueue;
 pub mod cameras;
+pub mod collector;
 pub mod core;
 mod entry;
 pub mod filters;
@@ -25,22 +24,11 @@ pub mod media;
 pub mod samplers;
 pub mod shapes;
 pub mod textures;
+pub mod tilejob;
 pub mod backend;
 
 use crate::accelerators::bvh::BVHPrimitiveInfo;
 
-#[derive(Clone, Serialize, Deserialize)]
-pub struct RenderTileInfo {
-    pub x: u32,
-    pub y: u32,
-    pub tile_size: i32,
-    pub filename: String,
-    // pub dimi: usize,
-    // pub dimj: usize,
-    // pub height: usize,
-    // pub width: usize,
-}
-
 //#[cfg(feature = "ecp")]
 #[fastly::main]
 fn main(mut req: Request) -> Result<Response, Error> {
@@ -66,19 +54,27 @@ fn main(mut req: Request) -> Result<Response, Error> {
 			let now = Instant::now();
 			let b = req.into_body();
 			let s = b.into_string();
-			let input : RenderTileInfo = serde_json::from_str(&s).unwrap();
-			let main_file = format!("{}/main.pbrt", input.filename);
-			let msg = format!("Couldn't get content from {}", main_file);
-			let data = backend::get_content_string(&main_file).expect(&msg);
-			let output = entry::entry(false, input.tile_size, Some(input.x), Some(input.y), &input.filename, &data);
+			let content_backend = backend::default_content_backend();
+			let result = tilejob::TileJob::from_json(&s)
+				.and_then(|tile_job| entry::entry_tile_job(&tile_job, content_backend));
 			println!("Elapsed: {}", now.elapsed().as_millis());
-			Ok(Response::from_status(StatusCode::OK)
-				.with_header("Access-Control-Allow-Origin", HeaderValue::from_static("*"))
-				.with_header("Vary", HeaderValue::from_static("Origin"))
-				.with_body(output)
-				.with_content_type(mime::APPLICATION_OCTET_STREAM))
-				// .with_content_type(mime::IMAGE_JPEG)
-				// .with_body(d))
+			match result {
+				Ok(output) => Ok(Response::from_status(StatusCode::OK)
+					.with_header("Access-Control-Allow-Origin", HeaderValue::from_static("*"))
+					.with_header("Vary", HeaderValue::from_static("Origin"))
+					.with_body(output)
+					.with_content_type(mime::APPLICATION_OCTET_STREAM)),
+				Err(e) => {
+					println!("ERROR: {}", e.error);
+					Ok(Response::from_status(
+						StatusCode::from_u16(e.status).unwrap_or(StatusCode::BAD_REQUEST),
+					)
+					.with_header("Access-Control-Allow-Origin", HeaderValue::from_static("*"))
+					.with_header("Vary", HeaderValue::from_static("Origin"))
+					.with_body(e.to_json())
+					.with_content_type(mime::APPLICATION_JSON))
+				}
+			}
 		}
         // If request is to the `/` path, send a default response.
         "/" => Ok(Response::from_status(StatusCode::OK)
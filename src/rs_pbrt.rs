@@ -0,0 +1,142 @@
+//! Native command line renderer. Parses a **.pbrt** scene file from
+//! the local file system and renders the full image (no collector or
+//! workers involved).
+
+#[macro_use]
+extern crate impl_ops;
+
+#[macro_use]
+extern crate serde;
+
+pub mod accelerators;
+pub mod backend;
+pub mod blockqueue;
+pub mod cameras;
+pub mod collector;
+pub mod core;
+mod entry;
+pub mod filters;
+pub mod integrators;
+pub mod lights;
+pub mod materials;
+pub mod media;
+pub mod samplers;
+pub mod shapes;
+pub mod textures;
+pub mod tilejob;
+
+// std
+use std::path::PathBuf;
+use std::time::Duration;
+// others
+use structopt::StructOpt;
+// pbrt
+use crate::collector::Dispatcher;
+use crate::core::api::pbrt_init;
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// The integrators which can render a full image
+const INTEGRATORS: &[&str] = &[
+    "ao",
+    "ambientocclusion",
+    "directlighting",
+    "whitted",
+    "path",
+    "bdpt",
+    "volpath",
+];
+
+/// Parse a PBRT scene file (v3) and render it.
+#[derive(StructOpt)]
+#[structopt(name = "rs_pbrt")]
+struct Args {
+    /// Use specified number of threads for rendering
+    #[structopt(short = "t", long = "nthreads", default_value = "0")]
+    nthreads: u8,
+    /// Override the number of pixel samples of the scene
+    #[structopt(short = "s", long = "pixelsamples", default_value = "0")]
+    pixelsamples: u32,
+    /// Specify an image crop window <x0 x1 y0 y1>
+    #[structopt(long = "cropwindow", number_of_values = 4, allow_hyphen_values = true)]
+    cropwindow: Vec<f32>,
+    /// Override the integrator of the scene
+    #[structopt(short = "i", long = "integrator", possible_values = INTEGRATORS)]
+    integrator: Option<String>,
+    /// Write the final image to the given filename
+    #[structopt(short = "o", long = "outfile")]
+    outfile: Option<String>,
+    /// Automatically reduce a number of quality settings to render more quickly
+    #[structopt(long = "quick")]
+    quick: bool,
+    /// Write a snapshot of the built scene for render workers instead of rendering
+    #[structopt(long = "snapshot")]
+    snapshot: Option<String>,
+    /// Benchmark the BVH variants and the kd-tree with this many rays instead of rendering
+    #[structopt(long = "bvhbench")]
+    bvhbench: Option<usize>,
+    /// Send the tiles to render workers (base URLs, comma separated)
+    #[structopt(short = "w", long = "workers", use_delimiter = true)]
+    workers: Vec<String>,
+    /// Name of the scene directory on the workers [default: parent directory of <path>]
+    #[structopt(long = "scene")]
+    scene: Option<String>,
+    /// Tiles each worker renders at the same time
+    #[structopt(long = "jobs", default_value = "2")]
+    jobs: usize,
+    /// How often a failed tile is sent again
+    #[structopt(long = "retries", default_value = "3")]
+    retries: u32,
+    /// Seconds a worker gets for a single tile
+    #[structopt(long = "timeout", default_value = "300")]
+    timeout: u64,
+    /// The path to the file to read
+    #[structopt(parse(from_os_str))]
+    path: PathBuf,
+}
+
+fn main() {
+    let args = Args::from_args();
+    let git_describe = option_env!("GIT_DESCRIBE").unwrap_or("unknown");
+    println!("pbrt version {} ({})", VERSION, git_describe);
+    println!("Copyright (c) 2016-2021 Jan Douglas Bert Walter.");
+    println!("Rust code based on C++ code by Matt Pharr, Greg Humphreys, and Wenzel Jakob.");
+    let (cropx0, cropx1, cropy0, cropy1) = if args.cropwindow.len() == 4 {
+        (
+            args.cropwindow[0],
+            args.cropwindow[1],
+            args.cropwindow[2],
+            args.cropwindow[3],
+        )
+    } else {
+        (0.0, 1.0, 0.0, 1.0)
+    };
+    let (mut api_state, bsdf_state) = pbrt_init(
+        args.pixelsamples,
+        args.nthreads,
+        cropx0,
+        cropx1,
+        cropy0,
+        cropy1,
+    );
+    if let Some(ref outfile) = args.outfile {
+        api_state.set_image_file(outfile);
+    }
+    api_state.set_quick_render(args.quick);
+    if let Some(ref snapshot) = args.snapshot {
+        api_state.set_snapshot_file(snapshot);
+    }
+    if let Some(rays) = args.bvhbench {
+        api_state.set_bvh_benchmark(rays);
+    }
+    if !args.workers.is_empty() {
+        let mut dispatcher: Dispatcher = Dispatcher::new(args.workers.clone(), &args.path);
+        if let Some(ref scene) = args.scene {
+            dispatcher.set_scene(scene);
+        }
+        dispatcher.jobs_per_worker = args.jobs;
+        dispatcher.retries = args.retries;
+        dispatcher.timeout = Duration::from_secs(args.timeout);
+        api_state.set_dispatcher(dispatcher);
+    }
+    entry::entry_file(&args.path, api_state, bsdf_state, &args.integrator);
+}
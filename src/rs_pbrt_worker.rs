@@ -0,0 +1,201 @@
+//! Native render worker. Serves the same `/rendertile` contract as
+//! the Fastly worker (see [tilejob](tilejob/index.html)) on a local
+//! port, scene content is read from a directory. The directory is
+//! also served below `/content/`, which is where the browser
+//! collector fetches scene files from. Decoded scene snapshots are
+//! kept for the following tile jobs of the same scene.
+//!
+//! ```shell
+//! rs_pbrt_worker --port 8080 content
+//! ```
+
+#[macro_use]
+extern crate impl_ops;
+
+#[macro_use]
+extern crate serde;
+
+pub mod accelerators;
+pub mod backend;
+pub mod blockqueue;
+pub mod cameras;
+pub mod collector;
+pub mod core;
+mod entry;
+pub mod filters;
+pub mod integrators;
+pub mod lights;
+pub mod materials;
+pub mod media;
+pub mod samplers;
+pub mod shapes;
+pub mod textures;
+pub mod tilejob;
+
+// std
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::Arc;
+// others
+use structopt::StructOpt;
+use tiny_http::{Header, Method, Request, Response, Server};
+// pbrt
+use crate::backend::{ContentBackend, LocalBackend};
+use crate::tilejob::{TileJob, TileJobError};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Render tiles for a collector over HTTP.
+#[derive(StructOpt)]
+#[structopt(name = "rs_pbrt_worker")]
+struct Args {
+    /// Address to listen on
+    #[structopt(short = "b", long = "bind", default_value = "0.0.0.0")]
+    bind: String,
+    /// Port to listen on
+    #[structopt(short = "p", long = "port", default_value = "8080")]
+    port: u16,
+    /// Number of requests handled at the same time (0 = number of CPUs)
+    #[structopt(short = "t", long = "nthreads", default_value = "0")]
+    nthreads: u8,
+    /// The directory with one sub-directory per scene
+    #[structopt(parse(from_os_str), default_value = "content")]
+    content: PathBuf,
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).unwrap()
+}
+
+fn respond(request: Request, status: u16, content_type: &str, body: Vec<u8>) {
+    let response = Response::from_data(body)
+        .with_status_code(status)
+        .with_header(header("Content-Type", content_type))
+        .with_header(header("Access-Control-Allow-Origin", "*"))
+        .with_header(header("Vary", "Origin"));
+    if let Err(e) = request.respond(response) {
+        println!("WARNING: Unable to send response: {}", e);
+    }
+}
+
+fn respond_error(request: Request, error: &TileJobError) {
+    println!("ERROR: {}", error.error);
+    respond(
+        request,
+        error.status,
+        "application/json",
+        error.to_json().into_bytes(),
+    );
+}
+
+fn render_tile(mut request: Request, content_backend: &Arc<LocalBackend>) {
+    let mut body: String = String::new();
+    if let Err(e) = request.as_reader().read_to_string(&mut body) {
+        let error = TileJobError::new(400, &format!("Unable to read tile job: {}", e));
+        return respond_error(request, &error);
+    }
+    // a panic while rendering must not take the handler thread down
+    let result = TileJob::from_json(&body).and_then(|tile_job| {
+        panic::catch_unwind(AssertUnwindSafe(|| {
+            entry::entry_tile_job(&tile_job, content_backend.clone())
+        }))
+        .unwrap_or_else(|_| Err(TileJobError::new(500, "Rendering the tile panicked")))
+    });
+    match result {
+        Ok(output) => respond(request, 200, "application/octet-stream", output),
+        Err(error) => respond_error(request, &error),
+    }
+}
+
+fn get_content(request: Request, content_backend: &Arc<LocalBackend>) {
+    let url: String = request.url().to_string();
+    let path: &str = url
+        .trim_start_matches("/content")
+        .split('?')
+        .next()
+        .unwrap_or("");
+    if path.split('/').any(|part| part == "..") || !content_backend.exists(path) {
+        return respond(
+            request,
+            404,
+            "text/plain",
+            b"The page you requested could not be found\n".to_vec(),
+        );
+    }
+    match content_backend.get_binary(path) {
+        Ok(data) => respond(request, 200, "application/octet-stream", data),
+        Err(e) => respond(request, 500, "text/plain", format!("{}\n", e).into_bytes()),
+    }
+}
+
+fn handle(request: Request, content_backend: &Arc<LocalBackend>) {
+    let method: Method = request.method().clone();
+    let url: String = request.url().to_string();
+    match (method, url.as_str()) {
+        (Method::Post, "/rendertile") => render_tile(request, content_backend),
+        (Method::Get, _) | (Method::Head, _) if url.starts_with("/content/") => {
+            get_content(request, content_backend)
+        }
+        (Method::Post, _) | (Method::Get, _) | (Method::Head, _) => respond(
+            request,
+            404,
+            "text/plain",
+            b"The page you requested could not be found\n".to_vec(),
+        ),
+        _ => {
+            let response = Response::from_string("This method is not allowed\n")
+                .with_status_code(405)
+                .with_header(header("Allow", "GET, HEAD, POST"))
+                .with_header(header("Access-Control-Allow-Origin", "*"))
+                .with_header(header("Vary", "Origin"));
+            if let Err(e) = request.respond(response) {
+                println!("WARNING: Unable to send response: {}", e);
+            }
+        }
+    }
+}
+
+fn main() {
+    let args = Args::from_args();
+    let git_describe = option_env!("GIT_DESCRIBE").unwrap_or("unknown");
+    println!("pbrt version {} ({})", VERSION, git_describe);
+    println!("Copyright (c) 2016-2021 Jan Douglas Bert Walter.");
+    println!("Rust code based on C++ code by Matt Pharr, Greg Humphreys, and Wenzel Jakob.");
+    if !args.content.is_dir() {
+        println!("ERROR: {:?} is not a directory", args.content);
+        std::process::exit(1);
+    }
+    let address: String = format!("{}:{}", args.bind, args.port);
+    let server: Arc<Server> = match Server::http(&address) {
+        Ok(server) => Arc::new(server),
+        Err(e) => {
+            println!("ERROR: Unable to listen on {}: {}", address, e);
+            std::process::exit(1);
+        }
+    };
+    // all tiles are rendered from the same directory
+    let content_backend: Arc<LocalBackend> = Arc::new(LocalBackend::new(args.content.clone()));
+    let num_threads: usize = if args.nthreads == 0_u8 {
+        num_cpus::get()
+    } else {
+        args.nthreads as usize
+    };
+    println!(
+        "Serving {:?} on http://{} ({} threads)",
+        args.content, address, num_threads
+    );
+    let handlers: Vec<std::thread::JoinHandle<()>> = (0..num_threads)
+        .map(|_| {
+            let server = server.clone();
+            let content_backend = content_backend.clone();
+            std::thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    handle(request, &content_backend);
+                }
+            })
+        })
+        .collect();
+    for handler in handlers {
+        let _ = handler.join();
+    }
+}
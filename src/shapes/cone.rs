@@ -0,0 +1,326 @@
+// std
+use std::f32::consts::PI;
+use std::sync::Arc;
+// pbrt
+use crate::core::efloat::quadratic_efloat;
+use crate::core::efloat::EFloat;
+use crate::core::geometry::{
+    nrm_abs_dot_vec3f, pnt3_distance_squaredf, vec3_cross_vec3, vec3_dot_vec3f,
+};
+use crate::core::geometry::{Bounds3f, Normal3f, Point2f, Point3f, Ray, Vector3f, XYEnum};
+use crate::core::interaction::{Interaction, InteractionCommon, SurfaceInteraction};
+use crate::core::material::Material;
+use crate::core::pbrt::Float;
+use crate::core::pbrt::{clamp_t, gamma, radians};
+use crate::core::transform::Transform;
+
+// see cone.h
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Cone {
+    pub radius: Float,
+    pub height: Float,
+    pub phi_max: Float,
+    // inherited from class Shape (see shape.h)
+    #[serde(with = "crate::core::snapshot::shared")]
+    pub object_to_world: Arc<Transform>,
+    #[serde(with = "crate::core::snapshot::shared")]
+    pub world_to_object: Arc<Transform>,
+    pub reverse_orientation: bool,
+    pub transform_swaps_handedness: bool,
+    pub material: Option<Arc<Material>>,
+}
+
+impl Default for Cone {
+    fn default() -> Self {
+        let object_to_world: Arc<Transform> = Arc::new(Transform::default());
+        Cone {
+            // Shape
+            transform_swaps_handedness: object_to_world.swaps_handedness(),
+            object_to_world,
+            world_to_object: Arc::new(Transform::default()),
+            reverse_orientation: false,
+            // Cone
+            radius: 1.0,
+            height: 1.0,
+            phi_max: radians(360.0),
+            material: None,
+        }
+    }
+}
+
+impl Cone {
+    pub fn new(
+        object_to_world: Arc<Transform>,
+        world_to_object: Arc<Transform>,
+        reverse_orientation: bool,
+        height: Float,
+        radius: Float,
+        phi_max: Float,
+    ) -> Self {
+        Cone {
+            // Shape
+            transform_swaps_handedness: object_to_world.swaps_handedness(),
+            object_to_world,
+            world_to_object,
+            reverse_orientation,
+            // Cone
+            radius,
+            height,
+            phi_max: radians(clamp_t(phi_max, 0.0, 360.0)),
+            material: None,
+        }
+    }
+    // Shape
+    pub fn object_bound(&self) -> Bounds3f {
+        Bounds3f {
+            p_min: Point3f {
+                x: -self.radius,
+                y: -self.radius,
+                z: 0.0,
+            },
+            p_max: Point3f {
+                x: self.radius,
+                y: self.radius,
+                z: self.height,
+            },
+        }
+    }
+    pub fn world_bound(&self) -> Bounds3f {
+        // in C++: Bounds3f Shape::WorldBound() const { return (*ObjectToWorld)(ObjectBound()); }
+        self.object_to_world.transform_bounds(&self.object_bound())
+    }
+    /// Shared by **intersect()** and **intersect_p()**. Returns the
+    /// object space ray, the parametric distance, the hit point, its
+    /// $\phi$ and the error bounds of the hit point.
+    fn intersect_object(&self, r: &Ray) -> Option<(Ray, Float, Point3f, Float, Vector3f)> {
+        // transform _Ray_ to object space
+        let mut o_err: Vector3f = Vector3f::default();
+        let mut d_err: Vector3f = Vector3f::default();
+        let ray: Ray = self
+            .world_to_object
+            .transform_ray_with_error(r, &mut o_err, &mut d_err);
+
+        // compute quadratic cone coefficients
+
+        // initialize _EFloat_ ray coordinate values
+        let ox = EFloat::new(ray.o.x as f32, o_err.x as f32);
+        let oy = EFloat::new(ray.o.y as f32, o_err.y as f32);
+        let oz = EFloat::new(ray.o.z as f32, o_err.z as f32);
+        let dx = EFloat::new(ray.d.x as f32, d_err.x as f32);
+        let dy = EFloat::new(ray.d.y as f32, d_err.y as f32);
+        let dz = EFloat::new(ray.d.z as f32, d_err.z as f32);
+        let mut k: EFloat =
+            EFloat::new(self.radius as f32, 0.0) / EFloat::new(self.height as f32, 0.0);
+        k = k * k;
+        let height: EFloat = EFloat::new(self.height as f32, 0.0);
+        let a: EFloat = dx * dx + dy * dy - k * dz * dz;
+        let b: EFloat = (dx * ox + dy * oy - k * dz * (oz - height)) * 2.0f32;
+        let c: EFloat = ox * ox + oy * oy - k * (oz - height) * (oz - height);
+
+        // solve quadratic equation for _t_ values
+        let mut t0: EFloat = EFloat::default();
+        let mut t1: EFloat = EFloat::default();
+        if !quadratic_efloat(a, b, c, &mut t0, &mut t1) {
+            return None;
+        }
+        // check quadric shape _t0_ and _t1_ for nearest intersection
+        if t0.upper_bound() > ray.t_max.get() as f32 || t1.lower_bound() <= 0.0f32 {
+            return None;
+        }
+        for t_shape_hit in [t0, t1].iter() {
+            if t_shape_hit.lower_bound() <= 0.0f32 {
+                continue;
+            }
+            if t_shape_hit.upper_bound() > ray.t_max.get() as f32 {
+                return None;
+            }
+            // compute cone inverse mapping
+            let p_hit: Point3f = ray.position(t_shape_hit.v);
+            let mut phi: Float = p_hit.y.atan2(p_hit.x);
+            if phi < 0.0 as Float {
+                phi += 2.0 as Float * PI;
+            }
+            // test cone intersection against clipping parameters
+            if p_hit.z < 0.0 as Float || p_hit.z > self.height || phi > self.phi_max {
+                continue;
+            }
+            // compute error bounds for cone intersection
+            let px: EFloat = ox + *t_shape_hit * dx;
+            let py: EFloat = oy + *t_shape_hit * dy;
+            let pz: EFloat = oz + *t_shape_hit * dz;
+            let p_error: Vector3f = Vector3f {
+                x: px.get_absolute_error(),
+                y: py.get_absolute_error(),
+                z: pz.get_absolute_error(),
+            };
+            return Some((ray, t_shape_hit.v, p_hit, phi, p_error));
+        }
+        None
+    }
+    pub fn intersect(&self, r: &Ray, t_hit: &mut Float, isect: &mut SurfaceInteraction) -> bool {
+        // TODO: ProfilePhase p(Prof::ShapeIntersect);
+        let (ray, t_shape_hit, p_hit, phi, p_error) = match self.intersect_object(r) {
+            Some(hit) => hit,
+            None => return false,
+        };
+        // find parametric representation of cone hit
+        let u: Float = phi / self.phi_max;
+        let v: Float = p_hit.z / self.height;
+        // compute cone $\dpdu$ and $\dpdv$
+        let dpdu: Vector3f = Vector3f {
+            x: -self.phi_max * p_hit.y,
+            y: self.phi_max * p_hit.x,
+            z: 0.0,
+        };
+        let dpdv: Vector3f = Vector3f {
+            x: -p_hit.x / (1.0 as Float - v),
+            y: -p_hit.y / (1.0 as Float - v),
+            z: self.height,
+        };
+        // compute cone $\dndu$ and $\dndv$
+        let d2_p_duu: Vector3f = Vector3f {
+            x: p_hit.x,
+            y: p_hit.y,
+            z: 0.0,
+        } * -self.phi_max
+            * self.phi_max;
+        let d2_p_duv: Vector3f = Vector3f {
+            x: p_hit.y,
+            y: -p_hit.x,
+            z: 0.0,
+        } * (self.phi_max / (1.0 as Float - v));
+        let d2_p_dvv: Vector3f = Vector3f {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        };
+        // compute coefficients for fundamental forms
+        let ec: Float = vec3_dot_vec3f(&dpdu, &dpdu);
+        let fc: Float = vec3_dot_vec3f(&dpdu, &dpdv);
+        let gc: Float = vec3_dot_vec3f(&dpdv, &dpdv);
+        let nc: Vector3f = vec3_cross_vec3(&dpdu, &dpdv).normalize();
+        let el: Float = vec3_dot_vec3f(&nc, &d2_p_duu);
+        let fl: Float = vec3_dot_vec3f(&nc, &d2_p_duv);
+        let gl: Float = vec3_dot_vec3f(&nc, &d2_p_dvv);
+        // compute $\dndu$ and $\dndv$ from fundamental form coefficients
+        let inv_egf2: Float = 1.0 / (ec * gc - fc * fc);
+        let dndu = dpdu * (fl * fc - el * gc) * inv_egf2 + dpdv * (el * fc - fl * ec) * inv_egf2;
+        let dndu = Normal3f {
+            x: dndu.x,
+            y: dndu.y,
+            z: dndu.z,
+        };
+        let dndv = dpdu * (gl * fc - fl * gc) * inv_egf2 + dpdv * (fl * fc - gl * ec) * inv_egf2;
+        let dndv = Normal3f {
+            x: dndv.x,
+            y: dndv.y,
+            z: dndv.z,
+        };
+        // initialize _SurfaceInteraction_ from parametric information
+        let uv_hit: Point2f = Point2f { x: u, y: v };
+        let wo: Vector3f = -ray.d;
+        *isect = SurfaceInteraction::new(
+            &p_hit, &p_error, uv_hit, &wo, &dpdu, &dpdv, &dndu, &dndv, ray.time, None,
+        );
+        // adjust normal based on orientation and handedness
+        if self.reverse_orientation ^ self.transform_swaps_handedness {
+            isect.common.n *= -1.0 as Float;
+            isect.shading.n *= -1.0 as Float;
+        }
+        self.object_to_world.transform_surface_interaction(isect);
+        *t_hit = t_shape_hit;
+        true
+    }
+    pub fn intersect_p(&self, r: &Ray) -> bool {
+        // TODO: ProfilePhase p(Prof::ShapeIntersect);
+        self.intersect_object(r).is_some()
+    }
+    pub fn get_reverse_orientation(&self) -> bool {
+        self.reverse_orientation
+    }
+    pub fn get_transform_swaps_handedness(&self) -> bool {
+        self.transform_swaps_handedness
+    }
+    pub fn get_object_to_world(&self) -> Transform {
+        *self.object_to_world
+    }
+    pub fn area(&self) -> Float {
+        self.radius
+            * ((self.height * self.height) + (self.radius * self.radius)).sqrt()
+            * self.phi_max
+            / 2.0 as Float
+    }
+    pub fn sample(&self, u: Point2f, pdf: &mut Float) -> InteractionCommon {
+        // pbrt-v3 doesn't sample cones (LOG(FATAL)), the area grows
+        // linearly towards the base, so $1 - v$ is distributed like
+        // the square root of a uniform sample
+        let v: Float = 1.0 as Float - u[XYEnum::X].sqrt();
+        let phi: Float = u[XYEnum::Y] * self.phi_max;
+        let (sin_phi, cos_phi) = phi.sin_cos();
+        let r: Float = self.radius * (1.0 as Float - v);
+        let p_obj: Point3f = Point3f {
+            x: r * cos_phi,
+            y: r * sin_phi,
+            z: v * self.height,
+        };
+        let mut it: InteractionCommon = InteractionCommon::default();
+        it.n = self
+            .object_to_world
+            .transform_normal(&Normal3f {
+                x: self.height * cos_phi,
+                y: self.height * sin_phi,
+                z: self.radius,
+            })
+            .normalize();
+        if self.reverse_orientation {
+            it.n *= -1.0 as Float;
+        }
+        let p_obj_error: Vector3f = Vector3f::from(p_obj).abs() * gamma(5_i32);
+        it.p = self.object_to_world.transform_point_with_abs_error(
+            &p_obj,
+            &p_obj_error,
+            &mut it.p_error,
+        );
+        *pdf = 1.0 as Float / self.area();
+        it
+    }
+    pub fn sample_with_ref_point(
+        &self,
+        iref: &InteractionCommon,
+        u: Point2f,
+        pdf: &mut Float,
+    ) -> InteractionCommon {
+        let intr: InteractionCommon = self.sample(u, pdf);
+        let mut wi: Vector3f = intr.p - iref.p;
+        if wi.length_squared() == 0.0 as Float {
+            *pdf = 0.0 as Float;
+        } else {
+            wi = wi.normalize();
+            // convert from area measure, as returned by the Sample()
+            // call above, to solid angle measure.
+            *pdf *= pnt3_distance_squaredf(&iref.p, &intr.p) / nrm_abs_dot_vec3f(&intr.n, &-wi);
+            if (*pdf).is_infinite() {
+                *pdf = 0.0 as Float;
+            }
+        }
+        intr
+    }
+    pub fn pdf_with_ref_point(&self, iref: &dyn Interaction, wi: &Vector3f) -> Float {
+        // intersect sample ray with area light geometry
+        let ray: Ray = iref.spawn_ray(wi);
+        let mut t_hit: Float = 0.0;
+        let mut isect_light: SurfaceInteraction = SurfaceInteraction::default();
+        if self.intersect(&ray, &mut t_hit, &mut isect_light) {
+            // convert light sample weight to solid angle measure
+            let mut pdf: Float = pnt3_distance_squaredf(&iref.get_p(), &isect_light.common.p)
+                / (nrm_abs_dot_vec3f(&isect_light.common.n, &-(*wi)) * self.area());
+            if pdf.is_infinite() {
+                pdf = 0.0 as Float;
+            }
+            pdf
+        } else {
+            0.0 as Float
+        }
+    }
+}
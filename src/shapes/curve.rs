@@ -71,12 +71,15 @@ impl CurveCommon {
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Curve {
+    #[serde(with = "crate::core::snapshot::shared")]
     pub common: Arc<CurveCommon>,
     pub u_min: Float,
     pub u_max: Float,
     // inherited from class Shape (see shape.h)
-    pub object_to_world: Transform,
-    pub world_to_object: Transform,
+    #[serde(with = "crate::core::snapshot::shared")]
+    pub object_to_world: Arc<Transform>,
+    #[serde(with = "crate::core::snapshot::shared")]
+    pub world_to_object: Arc<Transform>,
     pub reverse_orientation: bool,
     pub transform_swaps_handedness: bool,
     pub material: Option<Arc<Material>>,
@@ -84,8 +87,8 @@ pub struct Curve {
 
 impl Curve {
     pub fn new(
-        object_to_world: Transform,
-        world_to_object: Transform,
+        object_to_world: Arc<Transform>,
+        world_to_object: Arc<Transform>,
         reverse_orientation: bool,
         common: Arc<CurveCommon>,
         u_min: Float,
@@ -97,16 +100,16 @@ impl Curve {
             u_min,
             u_max,
             // Shape
+            transform_swaps_handedness: object_to_world.swaps_handedness(),
             object_to_world,
             world_to_object,
             reverse_orientation,
-            transform_swaps_handedness: object_to_world.swaps_handedness(),
             material: None,
         }
     }
     pub fn create(
-        o2w: Transform,
-        w2o: Transform,
+        o2w: Arc<Transform>,
+        w2o: Arc<Transform>,
         reverse_orientation: bool,
         c: &[Point3f; 4],
         w0: Float,
@@ -124,8 +127,8 @@ impl Curve {
             // segments.push_back(std::make_shared<Curve>(o2w, w2o, reverseOrientation,
             //                                            common, u_min, u_max));
             let curve: Arc<Shape> = Arc::new(Shape::Crv(Curve::new(
-                o2w,
-                w2o,
+                o2w.clone(),
+                w2o.clone(),
                 reverse_orientation,
                 common.clone(),
                 u_min,
@@ -160,6 +163,7 @@ impl Curve {
             // for intersection with it.
 
             let u: [Float; 3] = [u0, (u0 + u1) / 2.0 as Float, u1];
+            let mut hit: bool = false;
             // pointer to the 4 control points for the current segment.
             for seg in 0..2 {
                 let cps: &[Point3f] = &cp_split[seg * 3..seg * 3 + 4];
@@ -204,14 +208,10 @@ impl Curve {
                     t_hit,
                     isect,
                 ) {
-                    // If we found an intersection and this is a shadow ray,
-                    // we can exit out immediately.
-                    if *t_hit == 0.0 as Float {
-                        true;
-                    }
+                    hit = true;
                 }
             }
-            false
+            hit
         } else {
             // intersect ray with curve segment
 
@@ -289,6 +289,8 @@ impl Curve {
             // if (t_hit != nullptr) {
             // FIXME: this t_hit isn't quite right for ribbons...
             *t_hit = pc.z / ray_length;
+            // the other sub-segment should only report closer hits
+            ray.t_max.set(*t_hit);
             // compute error bounds for curve intersection
             let p_error: Vector3f = Vector3f {
                 x: 2.0 as Float * hit_width,
@@ -321,8 +323,8 @@ impl Curve {
                 }
                 dpdv = ray_to_object.transform_vector(&dpdv_plane);
             }
-            let mut si: SurfaceInteraction = SurfaceInteraction::new(
-                &ray.position(pc.z),
+            *isect = SurfaceInteraction::new(
+                &ray.position(*t_hit),
                 &p_error,
                 Point2f { x: u, y: v },
                 &-ray.d,
@@ -333,7 +335,12 @@ impl Curve {
                 ray.time,
                 None,
             );
-            self.object_to_world.transform_surface_interaction(&mut si);
+            // adjust normal based on orientation and handedness
+            if self.reverse_orientation ^ self.transform_swaps_handedness {
+                isect.common.n *= -1.0 as Float;
+                isect.shading.n *= -1.0 as Float;
+            }
+            self.object_to_world.transform_surface_interaction(isect);
             // if let Some(ref shape) = si.shape {
             //     isect.shape = Some(shape.clone());
             // }
@@ -487,7 +494,7 @@ impl Curve {
         self.transform_swaps_handedness
     }
     pub fn get_object_to_world(&self) -> Transform {
-        self.object_to_world
+        *self.object_to_world
     }
     pub fn area(&self) -> Float {
         // compute object-space control points for curve segment, _cp_obj_
@@ -505,9 +512,54 @@ impl Curve {
         }
         approx_length * avg_width
     }
-    pub fn sample(&self, _u: Point2f, _pdf: &mut Float) -> InteractionCommon {
-        println!("FATAL: Curve::sample not implemented.");
-        InteractionCommon::default()
+    pub fn sample(&self, u: Point2f, pdf: &mut Float) -> InteractionCommon {
+        // pbrt-v3 doesn't sample curves, pick a point along the segment
+        // and across its width (uniformly with respect to the
+        // approximate area() below)
+        let cu: Float = lerp(u.x, self.u_min, self.u_max);
+        let mut dpdu: Vector3f = Vector3f::default();
+        let p_curve: Point3f = eval_bezier(&self.common.cp_obj, cu, Some(&mut dpdu));
+        let width: Float = lerp(cu, self.common.width[0], self.common.width[1]);
+        let mut dpdv: Vector3f = Vector3f::default();
+        if self.common.curve_type == CurveType::Ribbon {
+            // interpolate the ribbon's normal (see recursive_intersect())
+            let sin0: Float = ((1.0 as Float - cu) * self.common.normal_angle).sin()
+                * self.common.inv_sin_normal_angle;
+            let sin1: Float = (cu * self.common.normal_angle).sin() * self.common.inv_sin_normal_angle;
+            let n: Normal3f = self.common.n[0] * sin0 + self.common.n[1] * sin1;
+            dpdv = nrm_cross_vec3(&n, &dpdu).normalize();
+        } else {
+            // flat and cylinder curves face the ray, any direction
+            // perpendicular to the curve will do
+            let mut tmp: Vector3f = Vector3f::default();
+            vec3_coordinate_system(&dpdu.normalize(), &mut dpdv, &mut tmp);
+        }
+        let p_obj: Point3f = p_curve + dpdv * (width * (u.y - 0.5 as Float));
+        let mut it: InteractionCommon = InteractionCommon::default();
+        let n_obj: Vector3f = vec3_cross_vec3(&dpdu, &dpdv);
+        it.n = self
+            .object_to_world
+            .transform_normal(&Normal3f {
+                x: n_obj.x,
+                y: n_obj.y,
+                z: n_obj.z,
+            })
+            .normalize();
+        if self.reverse_orientation {
+            it.n *= -1.0 as Float;
+        }
+        let p_obj_error: Vector3f = Vector3f {
+            x: width,
+            y: width,
+            z: width,
+        } * 2.0 as Float;
+        it.p = self.object_to_world.transform_point_with_abs_error(
+            &p_obj,
+            &p_obj_error,
+            &mut it.p_error,
+        );
+        *pdf = 1.0 as Float / self.area();
+        it
     }
     pub fn sample_with_ref_point(
         &self,
@@ -553,8 +605,8 @@ impl Curve {
 }
 
 pub fn create_curve_shape(
-    o2w: &Transform,
-    w2o: &Transform,
+    o2w: &Arc<Transform>,
+    w2o: &Arc<Transform>,
     reverse_orientation: bool,
     params: &ParamSet,
 ) -> Vec<Arc<Shape>> {
@@ -600,8 +652,8 @@ pub fn create_curve_shape(
     }
     if n.is_empty() {
         Curve::create(
-            *o2w,
-            *w2o,
+            o2w.clone(),
+            w2o.clone(),
             reverse_orientation,
             &[cp[0], cp[1], cp[2], cp[3]],
             width0,
@@ -612,8 +664,8 @@ pub fn create_curve_shape(
         )
     } else {
         Curve::create(
-            *o2w,
-            *w2o,
+            o2w.clone(),
+            w2o.clone(),
             reverse_orientation,
             &[cp[0], cp[1], cp[2], cp[3]],
             width0,
@@ -23,8 +23,10 @@ pub struct Cylinder {
     pub z_max: Float,
     pub phi_max: Float,
     // inherited from class Shape (see shape.h)
-    pub object_to_world: Transform,
-    pub world_to_object: Transform,
+    #[serde(with = "crate::core::snapshot::shared")]
+    pub object_to_world: Arc<Transform>,
+    #[serde(with = "crate::core::snapshot::shared")]
+    pub world_to_object: Arc<Transform>,
     pub reverse_orientation: bool,
     pub transform_swaps_handedness: bool,
     pub material: Option<Arc<Material>>,
@@ -32,13 +34,13 @@ pub struct Cylinder {
 
 impl Default for Cylinder {
     fn default() -> Self {
-        let object_to_world: Transform = Transform::default();
+        let object_to_world: Arc<Transform> = Arc::new(Transform::default());
         Cylinder {
             // Shape
+            transform_swaps_handedness: object_to_world.swaps_handedness(),
             object_to_world,
-            world_to_object: Transform::default(),
+            world_to_object: Arc::new(Transform::default()),
             reverse_orientation: false,
-            transform_swaps_handedness: object_to_world.swaps_handedness(),
             // Cylinder
             radius: 1.0,
             z_min: -1.0,
@@ -51,8 +53,8 @@ impl Default for Cylinder {
 
 impl Cylinder {
     pub fn new(
-        object_to_world: Transform,
-        world_to_object: Transform,
+        object_to_world: Arc<Transform>,
+        world_to_object: Arc<Transform>,
         reverse_orientation: bool,
         radius: Float,
         z_min: Float,
@@ -61,10 +63,10 @@ impl Cylinder {
     ) -> Self {
         Cylinder {
             // Shape
+            transform_swaps_handedness: object_to_world.swaps_handedness(),
             object_to_world,
             world_to_object,
             reverse_orientation,
-            transform_swaps_handedness: object_to_world.swaps_handedness(),
             // Cylinder
             radius,
             z_min: z_min.min(z_max),
@@ -233,6 +235,11 @@ impl Cylinder {
         *isect = SurfaceInteraction::new(
             &p_hit, &p_error, uv_hit, &wo, &dpdu, &dpdv, &dndu, &dndv, ray.time, None,
         );
+        // adjust normal based on orientation and handedness
+        if self.reverse_orientation ^ self.transform_swaps_handedness {
+            isect.common.n *= -1.0 as Float;
+            isect.shading.n *= -1.0 as Float;
+        }
         self.object_to_world.transform_surface_interaction(isect);
         // if let Some(ref shape) = si.shape {
         //     isect.shape = Some(shape.clone());
@@ -326,7 +333,7 @@ impl Cylinder {
         self.transform_swaps_handedness
     }
     pub fn get_object_to_world(&self) -> Transform {
-        self.object_to_world
+        *self.object_to_world
     }
     pub fn area(&self) -> Float {
         (self.z_max - self.z_min) * self.radius * self.phi_max
@@ -7,7 +7,7 @@ use crate::core::geometry::{Bounds3f, Normal3f, Point2f, Point3f, Ray, Vector3f}
 use crate::core::interaction::{Interaction, InteractionCommon, SurfaceInteraction};
 use crate::core::material::Material;
 use crate::core::pbrt::Float;
-use crate::core::pbrt::{clamp_t, radians};
+use crate::core::pbrt::{clamp_t, lerp, radians};
 use crate::core::sampling::concentric_sample_disk;
 use crate::core::transform::Transform;
 
@@ -20,8 +20,10 @@ pub struct Disk {
     pub inner_radius: Float,
     pub phi_max: Float,
     // inherited from class Shape (see shape.h)
-    pub object_to_world: Transform,
-    pub world_to_object: Transform,
+    #[serde(with = "crate::core::snapshot::shared")]
+    pub object_to_world: Arc<Transform>,
+    #[serde(with = "crate::core::snapshot::shared")]
+    pub world_to_object: Arc<Transform>,
     pub reverse_orientation: bool,
     pub transform_swaps_handedness: bool,
     pub material: Option<Arc<Material>>,
@@ -29,13 +31,13 @@ pub struct Disk {
 
 impl Default for Disk {
     fn default() -> Self {
-        let object_to_world: Transform = Transform::default();
+        let object_to_world: Arc<Transform> = Arc::new(Transform::default());
         Disk {
             // Shape
+            transform_swaps_handedness: object_to_world.swaps_handedness(),
             object_to_world,
-            world_to_object: Transform::default(),
+            world_to_object: Arc::new(Transform::default()),
             reverse_orientation: false,
-            transform_swaps_handedness: object_to_world.swaps_handedness(),
             // Disk
             height: 0.0,
             radius: 1.0,
@@ -48,8 +50,8 @@ impl Default for Disk {
 
 impl Disk {
     pub fn new(
-        object_to_world: Transform,
-        world_to_object: Transform,
+        object_to_world: Arc<Transform>,
+        world_to_object: Arc<Transform>,
         reverse_orientation: bool,
         height: Float,
         radius: Float,
@@ -58,10 +60,10 @@ impl Disk {
     ) -> Self {
         Disk {
             // Shape
+            transform_swaps_handedness: object_to_world.swaps_handedness(),
             object_to_world,
             world_to_object,
             reverse_orientation,
-            transform_swaps_handedness: object_to_world.swaps_handedness(),
             // Disk
             height,
             radius,
@@ -150,6 +152,11 @@ impl Disk {
         *isect = SurfaceInteraction::new(
             &p_hit, &p_error, uv_hit, &wo, &dpdu, &dpdv, &dndu, &dndv, ray.time, None,
         );
+        // adjust normal based on orientation and handedness
+        if self.reverse_orientation ^ self.transform_swaps_handedness {
+            isect.common.n *= -1.0 as Float;
+            isect.shading.n *= -1.0 as Float;
+        }
         self.object_to_world.transform_surface_interaction(isect);
         // if let Some(ref shape) = si.shape {
         //     isect.shape = Some(shape.clone());
@@ -202,7 +209,7 @@ impl Disk {
         self.transform_swaps_handedness
     }
     pub fn get_object_to_world(&self) -> Transform {
-        self.object_to_world
+        *self.object_to_world
     }
     pub fn area(&self) -> Float {
         self.phi_max
@@ -210,11 +217,28 @@ impl Disk {
             * (self.radius * self.radius - self.inner_radius * self.inner_radius)
     }
     pub fn sample(&self, u: Point2f, pdf: &mut Float) -> InteractionCommon {
-        let pd: Point2f = concentric_sample_disk(&u);
-        let p_obj: Point3f = Point3f {
-            x: pd.x * self.radius,
-            y: pd.y * self.radius,
-            z: self.height,
+        let p_obj: Point3f = if self.inner_radius == 0.0 as Float && self.phi_max >= 2.0 * PI {
+            let pd: Point2f = concentric_sample_disk(&u);
+            Point3f {
+                x: pd.x * self.radius,
+                y: pd.y * self.radius,
+                z: self.height,
+            }
+        } else {
+            // pbrt-v3 ignores _innerRadius_ and _phiMax_ here, sample
+            // the partial disk (annulus) uniformly instead
+            let r: Float = lerp(
+                u.x,
+                self.inner_radius * self.inner_radius,
+                self.radius * self.radius,
+            )
+            .sqrt();
+            let phi: Float = u.y * self.phi_max;
+            Point3f {
+                x: r * phi.cos(),
+                y: r * phi.sin(),
+                z: self.height,
+            }
         };
         let mut it: InteractionCommon = InteractionCommon::default();
         it.n = self
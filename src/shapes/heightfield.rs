@@ -0,0 +1,426 @@
+// std
+use std::sync::Arc;
+// pbrt
+use crate::core::geometry::{nrm_abs_dot_vec3f, pnt3_abs, pnt3_distance_squaredf, vec3_cross_vec3};
+use crate::core::geometry::{Bounds3f, Normal3f, Point2f, Point3f, Ray, Vector3f, XYEnum};
+use crate::core::interaction::{Interaction, InteractionCommon, SurfaceInteraction};
+use crate::core::material::Material;
+use crate::core::pbrt::gamma;
+use crate::core::pbrt::Float;
+use crate::core::sampling::Distribution1D;
+use crate::core::transform::Transform;
+use crate::shapes::triangle::{ray_triangle_intersect, TriangleMesh};
+
+// see heightfield.cpp
+
+/// Expand a heightfield into a triangle mesh (two triangles per grid
+/// cell), like **CreateHeightfield()** in C++ does.
+pub fn create_heightfield_mesh(
+    object_to_world: &Arc<Transform>,
+    world_to_object: &Arc<Transform>,
+    reverse_orientation: bool,
+    nx: i32,
+    ny: i32,
+    z: &[Float],
+) -> Arc<TriangleMesh> {
+    let n_vertices: usize = (nx * ny) as usize;
+    let mut p: Vec<Point3f> = Vec::with_capacity(n_vertices);
+    let mut uvs: Vec<Point2f> = Vec::with_capacity(n_vertices);
+    for y in 0..ny {
+        for x in 0..nx {
+            let uv: Point2f = Point2f {
+                x: x as Float / (nx - 1) as Float,
+                y: y as Float / (ny - 1) as Float,
+            };
+            // in world space
+            p.push(object_to_world.transform_point(&Point3f {
+                x: uv.x,
+                y: uv.y,
+                z: z[(y * nx + x) as usize],
+            }));
+            uvs.push(uv);
+        }
+    }
+    let n_triangles: usize = 2 * ((nx - 1) * (ny - 1)) as usize;
+    let mut vertex_indices: Vec<u32> = Vec::with_capacity(3 * n_triangles);
+    for y in 0..(ny - 1) {
+        for x in 0..(nx - 1) {
+            for vertex in heightfield_cell_triangles(x, y).iter() {
+                vertex_indices.push((vertex.1 * nx + vertex.0) as u32);
+            }
+        }
+    }
+    Arc::new(TriangleMesh::new(
+        object_to_world.clone(),
+        world_to_object.clone(),
+        reverse_orientation,
+        n_triangles as u32,
+        vertex_indices,
+        n_vertices as u32,
+        p,
+        Vec::new(),
+        Vec::new(),
+        uvs,
+        None,
+        None,
+    ))
+}
+
+/// The grid vertices (x, y) of the two triangles covering cell (x, y).
+fn heightfield_cell_triangles(x: i32, y: i32) -> [(i32, i32); 6] {
+    [
+        (x, y),
+        (x + 1, y),
+        (x + 1, y + 1),
+        (x, y),
+        (x + 1, y + 1),
+        (x, y + 1),
+    ]
+}
+
+/// A heightfield which is intersected by walking the grid cells the
+/// ray passes (instead of putting two triangles per cell into the
+/// BVH). Hits are the same as for the expanded triangle mesh.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Heightfield {
+    pub nx: i32,
+    pub ny: i32,
+    /// *nx* times *ny* heights, row by row
+    pub z: Vec<Float>,
+    pub z_min: Float,
+    pub z_max: Float,
+    /// world space area of each triangle (for sampling)
+    pub triangle_areas: Distribution1D,
+    pub area: Float,
+    // inherited from class Shape (see shape.h)
+    #[serde(with = "crate::core::snapshot::shared")]
+    pub object_to_world: Arc<Transform>,
+    #[serde(with = "crate::core::snapshot::shared")]
+    pub world_to_object: Arc<Transform>,
+    pub reverse_orientation: bool,
+    pub transform_swaps_handedness: bool,
+    pub material: Option<Arc<Material>>,
+}
+
+impl Heightfield {
+    pub fn new(
+        object_to_world: Arc<Transform>,
+        world_to_object: Arc<Transform>,
+        reverse_orientation: bool,
+        nx: i32,
+        ny: i32,
+        z: Vec<Float>,
+    ) -> Self {
+        let z_min: Float = z.iter().cloned().fold(std::f32::INFINITY, Float::min);
+        let z_max: Float = z.iter().cloned().fold(-std::f32::INFINITY, Float::max);
+        let mut heightfield: Heightfield = Heightfield {
+            // Shape
+            transform_swaps_handedness: object_to_world.swaps_handedness(),
+            object_to_world,
+            world_to_object,
+            reverse_orientation,
+            // Heightfield
+            nx,
+            ny,
+            z,
+            z_min,
+            z_max,
+            triangle_areas: Distribution1D::default(),
+            area: 0.0 as Float,
+            material: None,
+        };
+        let mut areas: Vec<Float> = Vec::with_capacity(2 * ((nx - 1) * (ny - 1)) as usize);
+        for y in 0..(ny - 1) {
+            for x in 0..(nx - 1) {
+                for i in 0..2 {
+                    let [p0, p1, p2] = heightfield.triangle(x, y, i);
+                    let p0: Point3f = heightfield.object_to_world.transform_point(&p0);
+                    let p1: Point3f = heightfield.object_to_world.transform_point(&p1);
+                    let p2: Point3f = heightfield.object_to_world.transform_point(&p2);
+                    areas.push(0.5 as Float * vec3_cross_vec3(&(p1 - p0), &(p2 - p0)).length());
+                }
+            }
+        }
+        heightfield.area = areas.iter().sum();
+        heightfield.triangle_areas = Distribution1D::new(areas);
+        heightfield
+    }
+    /// Object space vertices of triangle `i` (0 or 1) of cell (x, y).
+    fn triangle(&self, x: i32, y: i32, i: usize) -> [Point3f; 3] {
+        let vertices = heightfield_cell_triangles(x, y);
+        let vertex = |(vx, vy): (i32, i32)| -> Point3f {
+            Point3f {
+                x: vx as Float / (self.nx - 1) as Float,
+                y: vy as Float / (self.ny - 1) as Float,
+                z: self.z[(vy * self.nx + vx) as usize],
+            }
+        };
+        [
+            vertex(vertices[3 * i]),
+            vertex(vertices[3 * i + 1]),
+            vertex(vertices[3 * i + 2]),
+        ]
+    }
+    /// Walk the grid cells along the object space `ray` and return
+    /// the first hit: distance, cell, triangle, and barycentrics.
+    fn intersect_grid(&self, ray: &Ray) -> Option<(Float, i32, i32, usize, [Float; 3])> {
+        let mut t0: Float = 0.0;
+        let mut t1: Float = 0.0;
+        if !self.object_bound().intersect_b(ray, &mut t0, &mut t1) {
+            return None;
+        }
+        let cells_x: Float = (self.nx - 1) as Float;
+        let cells_y: Float = (self.ny - 1) as Float;
+        // ray in grid coordinates (one unit per cell)
+        let p_enter: Point3f = ray.position(t0);
+        let gx: Float = p_enter.x * cells_x;
+        let gy: Float = p_enter.y * cells_y;
+        let dgx: Float = ray.d.x * cells_x;
+        let dgy: Float = ray.d.y * cells_y;
+        let mut x: i32 = (gx.floor() as i32).max(0).min(self.nx - 2);
+        let mut y: i32 = (gy.floor() as i32).max(0).min(self.ny - 2);
+        let step_x: i32 = if dgx < 0.0 as Float { -1 } else { 1 };
+        let step_y: i32 = if dgy < 0.0 as Float { -1 } else { 1 };
+        let mut next_t_x: Float = if dgx > 0.0 as Float {
+            t0 + ((x + 1) as Float - gx) / dgx
+        } else if dgx < 0.0 as Float {
+            t0 + (x as Float - gx) / dgx
+        } else {
+            std::f32::INFINITY
+        };
+        let mut next_t_y: Float = if dgy > 0.0 as Float {
+            t0 + ((y + 1) as Float - gy) / dgy
+        } else if dgy < 0.0 as Float {
+            t0 + (y as Float - gy) / dgy
+        } else {
+            std::f32::INFINITY
+        };
+        let delta_t_x: Float = 1.0 as Float / dgx.abs();
+        let delta_t_y: Float = 1.0 as Float / dgy.abs();
+        // be conservative when skipping cells the ray passes above or
+        // below
+        let margin: Float = 1e-3 as Float * (self.z_max - self.z_min + 1.0 as Float);
+        let mut t_cell: Float = t0;
+        loop {
+            let t_exit: Float = next_t_x.min(next_t_y).min(t1);
+            let z_enter: Float = ray.o.z + ray.d.z * t_cell;
+            let z_exit: Float = ray.o.z + ray.d.z * t_exit;
+            let corners: [Float; 4] = [
+                self.z[(y * self.nx + x) as usize],
+                self.z[(y * self.nx + x + 1) as usize],
+                self.z[((y + 1) * self.nx + x) as usize],
+                self.z[((y + 1) * self.nx + x + 1) as usize],
+            ];
+            let cell_min: Float = corners.iter().cloned().fold(std::f32::INFINITY, Float::min);
+            let cell_max: Float = corners
+                .iter()
+                .cloned()
+                .fold(-std::f32::INFINITY, Float::max);
+            if z_enter.max(z_exit) >= cell_min - margin && z_enter.min(z_exit) <= cell_max + margin
+            {
+                let mut hit: Option<(Float, i32, i32, usize, [Float; 3])> = None;
+                for i in 0..2 {
+                    let [p0, p1, p2] = self.triangle(x, y, i);
+                    if let Some((t, b0, b1, b2)) = ray_triangle_intersect(ray, &p0, &p1, &p2) {
+                        if hit.map_or(true, |h| t < h.0) {
+                            hit = Some((t, x, y, i, [b0, b1, b2]));
+                        }
+                    }
+                }
+                // the triangles of a cell don't reach into other cells
+                if hit.is_some() {
+                    return hit;
+                }
+            }
+            if t_exit >= t1 {
+                return None;
+            }
+            // step to the next cell
+            if next_t_x < next_t_y {
+                x += step_x;
+                if x < 0 || x > self.nx - 2 {
+                    return None;
+                }
+                t_cell = next_t_x;
+                next_t_x += delta_t_x;
+            } else {
+                y += step_y;
+                if y < 0 || y > self.ny - 2 {
+                    return None;
+                }
+                t_cell = next_t_y;
+                next_t_y += delta_t_y;
+            }
+        }
+    }
+    // Shape
+    pub fn object_bound(&self) -> Bounds3f {
+        Bounds3f {
+            p_min: Point3f {
+                x: 0.0,
+                y: 0.0,
+                z: self.z_min,
+            },
+            p_max: Point3f {
+                x: 1.0,
+                y: 1.0,
+                z: self.z_max,
+            },
+        }
+    }
+    pub fn world_bound(&self) -> Bounds3f {
+        // in C++: Bounds3f Shape::WorldBound() const { return (*ObjectToWorld)(ObjectBound()); }
+        self.object_to_world.transform_bounds(&self.object_bound())
+    }
+    pub fn intersect(&self, r: &Ray, t_hit: &mut Float, isect: &mut SurfaceInteraction) -> bool {
+        // TODO: ProfilePhase p(Prof::ShapeIntersect);
+        // transform _Ray_ to object space
+        let ray: Ray = self.world_to_object.transform_ray(r);
+        let (t, x, y, i, b) = match self.intersect_grid(&ray) {
+            Some(hit) => hit,
+            None => return false,
+        };
+        let [p0, p1, p2] = self.triangle(x, y, i);
+        // $(u,v)$ are the $(x,y)$ coordinates of the grid
+        let uv: [Point2f; 3] = [
+            Point2f { x: p0.x, y: p0.y },
+            Point2f { x: p1.x, y: p1.y },
+            Point2f { x: p2.x, y: p2.y },
+        ];
+        // compute triangle partial derivatives
+        let duv02 = uv[0] - uv[2];
+        let duv12 = uv[1] - uv[2];
+        let dp02: Vector3f = p0 - p2;
+        let dp12: Vector3f = p1 - p2;
+        let inv_det: Float = 1.0 as Float / (duv02.x * duv12.y - duv02.y * duv12.x);
+        let dpdu: Vector3f = (dp02 * duv12.y - dp12 * duv02.y) * inv_det;
+        let dpdv: Vector3f = (dp02 * -duv12.x + dp12 * duv02.x) * inv_det;
+        // compute error bounds for triangle intersection
+        let p_abs_sum: Point3f =
+            pnt3_abs(&(p0 * b[0])) + pnt3_abs(&(p1 * b[1])) + pnt3_abs(&(p2 * b[2]));
+        let p_error: Vector3f = Vector3f {
+            x: p_abs_sum.x,
+            y: p_abs_sum.y,
+            z: p_abs_sum.z,
+        } * gamma(7);
+        // interpolate $(u,v)$ parametric coordinates and hit point
+        let p_hit: Point3f = p0 * b[0] + p1 * b[1] + p2 * b[2];
+        let uv_hit: Point2f = uv[0] * b[0] + uv[1] * b[1] + uv[2] * b[2];
+        let wo: Vector3f = -ray.d;
+        *isect = SurfaceInteraction::new(
+            &p_hit,
+            &p_error,
+            uv_hit,
+            &wo,
+            &dpdu,
+            &dpdv,
+            &Normal3f::default(),
+            &Normal3f::default(),
+            ray.time,
+            None,
+        );
+        // same orientation as the triangles of
+        // **create_heightfield_mesh()**, which are in world space
+        if self.reverse_orientation {
+            isect.common.n *= -1.0 as Float;
+            isect.shading.n *= -1.0 as Float;
+        }
+        self.object_to_world.transform_surface_interaction(isect);
+        *t_hit = t;
+        true
+    }
+    pub fn intersect_p(&self, r: &Ray) -> bool {
+        // TODO: ProfilePhase p(Prof::ShapeIntersect);
+        let ray: Ray = self.world_to_object.transform_ray(r);
+        self.intersect_grid(&ray).is_some()
+    }
+    pub fn get_reverse_orientation(&self) -> bool {
+        self.reverse_orientation
+    }
+    pub fn get_transform_swaps_handedness(&self) -> bool {
+        self.transform_swaps_handedness
+    }
+    pub fn get_object_to_world(&self) -> Transform {
+        *self.object_to_world
+    }
+    pub fn area(&self) -> Float {
+        self.area
+    }
+    pub fn sample(&self, u: Point2f, pdf: &mut Float) -> InteractionCommon {
+        // pick a triangle by area and reuse the remainder of u.x
+        let mut offset: usize = 0;
+        let x: Float = self
+            .triangle_areas
+            .sample_continuous(u[XYEnum::X], None, Some(&mut offset));
+        let ux: Float = (x * self.triangle_areas.count() as Float - offset as Float)
+            .max(0.0 as Float)
+            .min(1.0 as Float);
+        let cell: i32 = (offset / 2) as i32;
+        let [p0, p1, p2] = self.triangle(cell % (self.nx - 1), cell / (self.nx - 1), offset % 2);
+        // uniform sample on triangle
+        let su0: Float = ux.sqrt();
+        let bx: Float = 1.0 as Float - su0;
+        let by: Float = u[XYEnum::Y] * su0;
+        let bz: Float = 1.0 as Float - bx - by;
+        let p_obj: Point3f = p0 * bx + p1 * by + p2 * bz;
+        let p_abs_sum: Point3f = pnt3_abs(&(p0 * bx)) + pnt3_abs(&(p1 * by)) + pnt3_abs(&(p2 * bz));
+        let p_obj_error: Vector3f = Vector3f {
+            x: p_abs_sum.x,
+            y: p_abs_sum.y,
+            z: p_abs_sum.z,
+        } * gamma(6);
+        let mut it: InteractionCommon = InteractionCommon::default();
+        it.n = self
+            .object_to_world
+            .transform_normal(&Normal3f::from(vec3_cross_vec3(&(p1 - p0), &(p2 - p0))))
+            .normalize();
+        if self.reverse_orientation {
+            it.n *= -1.0 as Float;
+        }
+        it.p = self.object_to_world.transform_point_with_abs_error(
+            &p_obj,
+            &p_obj_error,
+            &mut it.p_error,
+        );
+        *pdf = 1.0 as Float / self.area;
+        it
+    }
+    pub fn sample_with_ref_point(
+        &self,
+        iref: &InteractionCommon,
+        u: Point2f,
+        pdf: &mut Float,
+    ) -> InteractionCommon {
+        let intr: InteractionCommon = self.sample(u, pdf);
+        let mut wi: Vector3f = intr.p - iref.p;
+        if wi.length_squared() == 0.0 as Float {
+            *pdf = 0.0 as Float;
+        } else {
+            wi = wi.normalize();
+            // convert from area measure, as returned by the Sample()
+            // call above, to solid angle measure.
+            *pdf *= pnt3_distance_squaredf(&iref.p, &intr.p) / nrm_abs_dot_vec3f(&intr.n, &-wi);
+            if (*pdf).is_infinite() {
+                *pdf = 0.0 as Float;
+            }
+        }
+        intr
+    }
+    pub fn pdf_with_ref_point(&self, iref: &dyn Interaction, wi: &Vector3f) -> Float {
+        // intersect sample ray with area light geometry
+        let ray: Ray = iref.spawn_ray(wi);
+        let mut t_hit: Float = 0.0;
+        let mut isect_light: SurfaceInteraction = SurfaceInteraction::default();
+        if self.intersect(&ray, &mut t_hit, &mut isect_light) {
+            // convert light sample weight to solid angle measure
+            let mut pdf: Float = pnt3_distance_squaredf(&iref.get_p(), &isect_light.common.p)
+                / (nrm_abs_dot_vec3f(&isect_light.common.n, &-(*wi)) * self.area());
+            if pdf.is_infinite() {
+                pdf = 0.0 as Float;
+            }
+            pdf
+        } else {
+            0.0 as Float
+        }
+    }
+}
@@ -0,0 +1,421 @@
+// std
+use std::f32::consts::PI;
+use std::sync::Arc;
+// pbrt
+use crate::core::efloat::quadratic_efloat;
+use crate::core::efloat::EFloat;
+use crate::core::geometry::{
+    nrm_abs_dot_vec3f, pnt3_distance_squaredf, pnt3_lerp, vec3_cross_vec3, vec3_dot_vec3f,
+};
+use crate::core::geometry::{Bounds3f, Normal3f, Point2f, Point3f, Ray, Vector3f, XYEnum};
+use crate::core::interaction::{Interaction, InteractionCommon, SurfaceInteraction};
+use crate::core::material::Material;
+use crate::core::pbrt::Float;
+use crate::core::pbrt::{clamp_t, gamma, radians};
+use crate::core::transform::Transform;
+
+// see hyperboloid.h
+
+/// Number of steps used to tabulate the area along the hyperboloid.
+const AREA_STEPS: usize = 32;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Hyperboloid {
+    pub p1: Point3f,
+    pub p2: Point3f,
+    pub z_min: Float,
+    pub z_max: Float,
+    pub phi_max: Float,
+    pub r_max: Float,
+    pub ah: Float,
+    pub ch: Float,
+    /// Area (divided by _phi_max_) between _p1_ and the point at
+    /// $v = i / AREA\_STEPS$ for sampling
+    pub area_cdf: Vec<Float>,
+    // inherited from class Shape (see shape.h)
+    #[serde(with = "crate::core::snapshot::shared")]
+    pub object_to_world: Arc<Transform>,
+    #[serde(with = "crate::core::snapshot::shared")]
+    pub world_to_object: Arc<Transform>,
+    pub reverse_orientation: bool,
+    pub transform_swaps_handedness: bool,
+    pub material: Option<Arc<Material>>,
+}
+
+impl Default for Hyperboloid {
+    fn default() -> Self {
+        Hyperboloid::new(
+            Arc::new(Transform::default()),
+            Arc::new(Transform::default()),
+            false,
+            Point3f::default(),
+            Point3f {
+                x: 1.0,
+                y: 1.0,
+                z: 1.0,
+            },
+            360.0,
+        )
+    }
+}
+
+impl Hyperboloid {
+    pub fn new(
+        object_to_world: Arc<Transform>,
+        world_to_object: Arc<Transform>,
+        reverse_orientation: bool,
+        point1: Point3f,
+        point2: Point3f,
+        tm: Float,
+    ) -> Self {
+        let mut p1: Point3f = point1;
+        let mut p2: Point3f = point2;
+        let radius1: Float = (p1.x * p1.x + p1.y * p1.y).sqrt();
+        let radius2: Float = (p2.x * p2.x + p2.y * p2.y).sqrt();
+        // compute implicit function coefficients for hyperboloid
+        if p2.z == 0.0 as Float {
+            std::mem::swap(&mut p1, &mut p2);
+        }
+        let mut pp: Point3f = p1;
+        let mut ah: Float;
+        let mut ch: Float;
+        let mut iterations: u32 = 0;
+        loop {
+            pp += (p2 - p1) * 2.0 as Float;
+            let xy1: Float = pp.x * pp.x + pp.y * pp.y;
+            let xy2: Float = p2.x * p2.x + p2.y * p2.y;
+            ah = (1.0 as Float / xy1 - (pp.z * pp.z) / (xy1 * p2.z * p2.z))
+                / (1.0 as Float - (xy2 * pp.z * pp.z) / (xy1 * p2.z * p2.z));
+            ch = (ah * xy2 - 1.0 as Float) / (p2.z * p2.z);
+            if ah.is_finite() {
+                break;
+            }
+            // C++ loops forever for degenerate points
+            iterations += 1;
+            if iterations == 32 {
+                println!(
+                    "WARNING: Degenerate hyperboloid through {:?} and {:?}",
+                    point1, point2
+                );
+                break;
+            }
+        }
+        let mut area_cdf: Vec<Float> = Vec::with_capacity(AREA_STEPS + 1);
+        area_cdf.push(0.0 as Float);
+        let h: Float = 1.0 as Float / AREA_STEPS as Float;
+        for i in 0..AREA_STEPS {
+            // Simpson's rule for each step
+            let v0: Float = i as Float * h;
+            let step: Float = h / 6.0 as Float
+                * (area_density(&p1, &p2, v0)
+                    + 4.0 as Float * area_density(&p1, &p2, v0 + 0.5 as Float * h)
+                    + area_density(&p1, &p2, v0 + h));
+            area_cdf.push(area_cdf[i] + step);
+        }
+        Hyperboloid {
+            // Shape
+            transform_swaps_handedness: object_to_world.swaps_handedness(),
+            object_to_world,
+            world_to_object,
+            reverse_orientation,
+            // Hyperboloid
+            p1,
+            p2,
+            z_min: p1.z.min(p2.z),
+            z_max: p1.z.max(p2.z),
+            phi_max: radians(clamp_t(tm, 0.0, 360.0)),
+            r_max: radius1.max(radius2),
+            ah,
+            ch,
+            area_cdf,
+            material: None,
+        }
+    }
+    // Shape
+    pub fn object_bound(&self) -> Bounds3f {
+        Bounds3f {
+            p_min: Point3f {
+                x: -self.r_max,
+                y: -self.r_max,
+                z: self.z_min,
+            },
+            p_max: Point3f {
+                x: self.r_max,
+                y: self.r_max,
+                z: self.z_max,
+            },
+        }
+    }
+    pub fn world_bound(&self) -> Bounds3f {
+        // in C++: Bounds3f Shape::WorldBound() const { return (*ObjectToWorld)(ObjectBound()); }
+        self.object_to_world.transform_bounds(&self.object_bound())
+    }
+    /// Shared by **intersect()** and **intersect_p()**. Returns the
+    /// object space ray, the parametric distance, the hit point, its
+    /// $\phi$ and the error bounds of the hit point.
+    fn intersect_object(&self, r: &Ray) -> Option<(Ray, Float, Point3f, Float, Vector3f)> {
+        // transform _Ray_ to object space
+        let mut o_err: Vector3f = Vector3f::default();
+        let mut d_err: Vector3f = Vector3f::default();
+        let ray: Ray = self
+            .world_to_object
+            .transform_ray_with_error(r, &mut o_err, &mut d_err);
+
+        // compute quadratic hyperboloid coefficients
+
+        // initialize _EFloat_ ray coordinate values
+        let ox = EFloat::new(ray.o.x as f32, o_err.x as f32);
+        let oy = EFloat::new(ray.o.y as f32, o_err.y as f32);
+        let oz = EFloat::new(ray.o.z as f32, o_err.z as f32);
+        let dx = EFloat::new(ray.d.x as f32, d_err.x as f32);
+        let dy = EFloat::new(ray.d.y as f32, d_err.y as f32);
+        let dz = EFloat::new(ray.d.z as f32, d_err.z as f32);
+        let ah: EFloat = EFloat::new(self.ah as f32, 0.0);
+        let ch: EFloat = EFloat::new(self.ch as f32, 0.0);
+        let a: EFloat = ah * dx * dx + ah * dy * dy - ch * dz * dz;
+        let b: EFloat = (ah * dx * ox + ah * dy * oy - ch * dz * oz) * 2.0f32;
+        let c: EFloat = ah * ox * ox + ah * oy * oy - ch * oz * oz - EFloat::new(1.0, 0.0);
+
+        // solve quadratic equation for _t_ values
+        let mut t0: EFloat = EFloat::default();
+        let mut t1: EFloat = EFloat::default();
+        if !quadratic_efloat(a, b, c, &mut t0, &mut t1) {
+            return None;
+        }
+        // check quadric shape _t0_ and _t1_ for nearest intersection
+        if t0.upper_bound() > ray.t_max.get() as f32 || t1.lower_bound() <= 0.0f32 {
+            return None;
+        }
+        for t_shape_hit in [t0, t1].iter() {
+            if t_shape_hit.lower_bound() <= 0.0f32 {
+                continue;
+            }
+            if t_shape_hit.upper_bound() > ray.t_max.get() as f32 {
+                return None;
+            }
+            // compute hyperboloid inverse mapping
+            let p_hit: Point3f = ray.position(t_shape_hit.v);
+            let v: Float = (p_hit.z - self.p1.z) / (self.p2.z - self.p1.z);
+            let pr: Point3f = pnt3_lerp(v, &self.p1, &self.p2);
+            let mut phi: Float =
+                (pr.x * p_hit.y - p_hit.x * pr.y).atan2(p_hit.x * pr.x + p_hit.y * pr.y);
+            if phi < 0.0 as Float {
+                phi += 2.0 as Float * PI;
+            }
+            // test hyperboloid intersection against clipping parameters
+            if p_hit.z < self.z_min || p_hit.z > self.z_max || phi > self.phi_max {
+                continue;
+            }
+            // compute error bounds for hyperboloid intersection
+            let px: EFloat = ox + *t_shape_hit * dx;
+            let py: EFloat = oy + *t_shape_hit * dy;
+            let pz: EFloat = oz + *t_shape_hit * dz;
+            let p_error: Vector3f = Vector3f {
+                x: px.get_absolute_error(),
+                y: py.get_absolute_error(),
+                z: pz.get_absolute_error(),
+            };
+            return Some((ray, t_shape_hit.v, p_hit, phi, p_error));
+        }
+        None
+    }
+    pub fn intersect(&self, r: &Ray, t_hit: &mut Float, isect: &mut SurfaceInteraction) -> bool {
+        // TODO: ProfilePhase p(Prof::ShapeIntersect);
+        let (ray, t_shape_hit, p_hit, phi, p_error) = match self.intersect_object(r) {
+            Some(hit) => hit,
+            None => return false,
+        };
+        // find parametric representation of hyperboloid hit
+        let u: Float = phi / self.phi_max;
+        let v: Float = (p_hit.z - self.p1.z) / (self.p2.z - self.p1.z);
+        // compute hyperboloid $\dpdu$ and $\dpdv$
+        let (sin_phi, cos_phi) = phi.sin_cos();
+        let dpdu: Vector3f = Vector3f {
+            x: -self.phi_max * p_hit.y,
+            y: self.phi_max * p_hit.x,
+            z: 0.0,
+        };
+        let dpdv: Vector3f = Vector3f {
+            x: (self.p2.x - self.p1.x) * cos_phi - (self.p2.y - self.p1.y) * sin_phi,
+            y: (self.p2.x - self.p1.x) * sin_phi + (self.p2.y - self.p1.y) * cos_phi,
+            z: self.p2.z - self.p1.z,
+        };
+        // compute hyperboloid $\dndu$ and $\dndv$
+        let d2_p_duu: Vector3f = Vector3f {
+            x: p_hit.x,
+            y: p_hit.y,
+            z: 0.0,
+        } * -self.phi_max
+            * self.phi_max;
+        let d2_p_duv: Vector3f = Vector3f {
+            x: -dpdv.y,
+            y: dpdv.x,
+            z: 0.0,
+        } * self.phi_max;
+        let d2_p_dvv: Vector3f = Vector3f {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        };
+        // compute coefficients for fundamental forms
+        let ec: Float = vec3_dot_vec3f(&dpdu, &dpdu);
+        let fc: Float = vec3_dot_vec3f(&dpdu, &dpdv);
+        let gc: Float = vec3_dot_vec3f(&dpdv, &dpdv);
+        let nc: Vector3f = vec3_cross_vec3(&dpdu, &dpdv).normalize();
+        let el: Float = vec3_dot_vec3f(&nc, &d2_p_duu);
+        let fl: Float = vec3_dot_vec3f(&nc, &d2_p_duv);
+        let gl: Float = vec3_dot_vec3f(&nc, &d2_p_dvv);
+        // compute $\dndu$ and $\dndv$ from fundamental form coefficients
+        let inv_egf2: Float = 1.0 / (ec * gc - fc * fc);
+        let dndu = dpdu * (fl * fc - el * gc) * inv_egf2 + dpdv * (el * fc - fl * ec) * inv_egf2;
+        let dndu = Normal3f {
+            x: dndu.x,
+            y: dndu.y,
+            z: dndu.z,
+        };
+        let dndv = dpdu * (gl * fc - fl * gc) * inv_egf2 + dpdv * (fl * fc - gl * ec) * inv_egf2;
+        let dndv = Normal3f {
+            x: dndv.x,
+            y: dndv.y,
+            z: dndv.z,
+        };
+        // initialize _SurfaceInteraction_ from parametric information
+        let uv_hit: Point2f = Point2f { x: u, y: v };
+        let wo: Vector3f = -ray.d;
+        *isect = SurfaceInteraction::new(
+            &p_hit, &p_error, uv_hit, &wo, &dpdu, &dpdv, &dndu, &dndv, ray.time, None,
+        );
+        // adjust normal based on orientation and handedness
+        if self.reverse_orientation ^ self.transform_swaps_handedness {
+            isect.common.n *= -1.0 as Float;
+            isect.shading.n *= -1.0 as Float;
+        }
+        self.object_to_world.transform_surface_interaction(isect);
+        *t_hit = t_shape_hit;
+        true
+    }
+    pub fn intersect_p(&self, r: &Ray) -> bool {
+        // TODO: ProfilePhase p(Prof::ShapeIntersect);
+        self.intersect_object(r).is_some()
+    }
+    pub fn get_reverse_orientation(&self) -> bool {
+        self.reverse_orientation
+    }
+    pub fn get_transform_swaps_handedness(&self) -> bool {
+        self.transform_swaps_handedness
+    }
+    pub fn get_object_to_world(&self) -> Transform {
+        *self.object_to_world
+    }
+    pub fn area(&self) -> Float {
+        // the closed form in hyperboloid.cpp is wrong, use the table
+        self.phi_max * self.area_cdf[AREA_STEPS]
+    }
+    pub fn sample(&self, u: Point2f, pdf: &mut Float) -> InteractionCommon {
+        // pbrt-v3 doesn't sample hyperboloids (LOG(FATAL)), find the
+        // step of the area table first, the area density is assumed
+        // to be linear within a step
+        let target: Float = u[XYEnum::X] * self.area_cdf[AREA_STEPS];
+        let i: usize = match self.area_cdf.iter().rposition(|area| *area <= target) {
+            Some(i) => i.min(AREA_STEPS - 1),
+            None => 0,
+        };
+        let h: Float = 1.0 as Float / AREA_STEPS as Float;
+        let g0: Float = area_density(&self.p1, &self.p2, i as Float * h);
+        let g1: Float = area_density(&self.p1, &self.p2, (i + 1) as Float * h);
+        let step: Float = self.area_cdf[i + 1] - self.area_cdf[i];
+        let frac: Float = if step > 0.0 as Float {
+            clamp_t((target - self.area_cdf[i]) / step, 0.0, 1.0)
+        } else {
+            0.0 as Float
+        };
+        let t: Float = if (g1 - g0).abs() < 1e-5 as Float * (g0 + g1) {
+            frac
+        } else {
+            (-g0 + ((1.0 as Float - frac) * g0 * g0 + frac * g1 * g1).sqrt()) / (g1 - g0)
+        };
+        let v: Float = (i as Float + t) * h;
+        let phi: Float = u[XYEnum::Y] * self.phi_max;
+        let (sin_phi, cos_phi) = phi.sin_cos();
+        let pr: Point3f = pnt3_lerp(v, &self.p1, &self.p2);
+        let p_obj: Point3f = Point3f {
+            x: pr.x * cos_phi - pr.y * sin_phi,
+            y: pr.x * sin_phi + pr.y * cos_phi,
+            z: pr.z,
+        };
+        // $\dpdu \times \dpdv$ before rotating by $\phi$
+        let d: Vector3f = self.p2 - self.p1;
+        let n: Normal3f = Normal3f {
+            x: pr.x * d.z,
+            y: pr.y * d.z,
+            z: -(pr.x * d.x + pr.y * d.y),
+        };
+        let mut it: InteractionCommon = InteractionCommon::default();
+        it.n = self
+            .object_to_world
+            .transform_normal(&Normal3f {
+                x: n.x * cos_phi - n.y * sin_phi,
+                y: n.x * sin_phi + n.y * cos_phi,
+                z: n.z,
+            })
+            .normalize();
+        if self.reverse_orientation {
+            it.n *= -1.0 as Float;
+        }
+        let p_obj_error: Vector3f = Vector3f::from(p_obj).abs() * gamma(5_i32);
+        it.p = self.object_to_world.transform_point_with_abs_error(
+            &p_obj,
+            &p_obj_error,
+            &mut it.p_error,
+        );
+        *pdf = 1.0 as Float / self.area();
+        it
+    }
+    pub fn sample_with_ref_point(
+        &self,
+        iref: &InteractionCommon,
+        u: Point2f,
+        pdf: &mut Float,
+    ) -> InteractionCommon {
+        let intr: InteractionCommon = self.sample(u, pdf);
+        let mut wi: Vector3f = intr.p - iref.p;
+        if wi.length_squared() == 0.0 as Float {
+            *pdf = 0.0 as Float;
+        } else {
+            wi = wi.normalize();
+            // convert from area measure, as returned by the Sample()
+            // call above, to solid angle measure.
+            *pdf *= pnt3_distance_squaredf(&iref.p, &intr.p) / nrm_abs_dot_vec3f(&intr.n, &-wi);
+            if (*pdf).is_infinite() {
+                *pdf = 0.0 as Float;
+            }
+        }
+        intr
+    }
+    pub fn pdf_with_ref_point(&self, iref: &dyn Interaction, wi: &Vector3f) -> Float {
+        // intersect sample ray with area light geometry
+        let ray: Ray = iref.spawn_ray(wi);
+        let mut t_hit: Float = 0.0;
+        let mut isect_light: SurfaceInteraction = SurfaceInteraction::default();
+        if self.intersect(&ray, &mut t_hit, &mut isect_light) {
+            // convert light sample weight to solid angle measure
+            let mut pdf: Float = pnt3_distance_squaredf(&iref.get_p(), &isect_light.common.p)
+                / (nrm_abs_dot_vec3f(&isect_light.common.n, &-(*wi)) * self.area());
+            if pdf.is_infinite() {
+                pdf = 0.0 as Float;
+            }
+            pdf
+        } else {
+            0.0 as Float
+        }
+    }
+}
+
+/// Length of $\dpdu \times \dpdv$ (divided by _phi_max_) at $v$ for
+/// the surface swept by the line from _p1_ to _p2_.
+fn area_density(p1: &Point3f, p2: &Point3f, v: Float) -> Float {
+    let p: Point3f = pnt3_lerp(v, p1, p2);
+    let d: Vector3f = *p2 - *p1;
+    let radial: Float = p.x * d.x + p.y * d.y;
+    (d.z * d.z * (p.x * p.x + p.y * p.y) + radial * radial).sqrt()
+}
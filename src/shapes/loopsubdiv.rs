@@ -268,8 +268,8 @@ fn loop_gamma(valence: i32) -> Float {
 }
 
 pub fn loop_subdivide(
-    object_to_world: &Transform,
-    world_to_object: &Transform,
+    object_to_world: &Arc<Transform>,
+    world_to_object: &Arc<Transform>,
     reverse_orientation: bool,
     n_levels: i32,
     vertex_indices: &[i32],
@@ -277,7 +277,7 @@ pub fn loop_subdivide(
 ) -> Arc<TriangleMesh> {
 
 	#[cfg(not(feature="ecp"))]
-	return Arc::new(TriangleMesh::new(*object_to_world, *world_to_object, reverse_orientation, 0, vec![], 0, vec![], vec![], vec![], vec![], None, None));
+	return Arc::new(TriangleMesh::new(object_to_world.clone(), world_to_object.clone(), reverse_orientation, 0, vec![], 0, vec![], vec![], vec![], vec![], None, None));
     // allocate _LoopSubdiv_ vertices and faces
     let mut verts: Vec<Arc<SDVertex>> = Vec::with_capacity(p.len());
     for item in p {
@@ -657,8 +657,8 @@ pub fn loop_subdivide(
         n_ws.push(object_to_world.transform_normal(item));
     }
     Arc::new(TriangleMesh::new(
-        *object_to_world,
-        *world_to_object,
+        object_to_world.clone(),
+        world_to_object.clone(),
         reverse_orientation,
         ntris.try_into().unwrap(),
         vertex_indices,
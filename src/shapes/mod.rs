@@ -10,6 +10,7 @@
 //! - Curve
 //! - Cylinder
 //! - Disk
+//! - Heightfield
 //! - Hyperboloid
 //! - Paraboloid
 //! - Sphere
@@ -17,11 +18,16 @@
 //!
 //! ## Cones
 //!
-//! TODO
+//! A cone is centered around the z axis, its base with the given
+//! radius lies in the $z = 0$ plane and its tip at the given height.
 //!
 //! ## Curves
 //!
-//! TODO
+//! Thin shapes like hair, fur or grass are represented by cubic
+//! Bézier splines. A curve is split into segments which are
+//! intersected by recursively subdividing them, a segment is either
+//! a flat ribbon facing the ray, a ribbon with interpolated normals,
+//! or shaded as if it were a cylinder.
 //!
 //! ## Spheres
 //!
@@ -39,6 +45,11 @@
 //! positions where each individual triangle just stores three offsets
 //! into this array for its three vertices.
 //!
+//! Triangle meshes (including PLY meshes) accept `"alpha"` and
+//! `"shadowalpha"` as a float texture or a constant to cut away
+//! parts of the surface, e.g. for leaves or fences. Fractional values
+//! are treated as stochastic transparency.
+//!
 //! ## Disks
 //!
 //! The disk is an interesting quadric since it has a particularly
@@ -50,20 +61,34 @@
 //! Another useful quadric is the cylinder. Cylinder shapes are
 //! centered around the z axis.
 //!
+//! ## Heightfields
+//!
+//! A heightfield is a grid of *nu* by *nv* heights over the unit
+//! square. By default it is intersected by walking the grid cells
+//! the ray passes, `"string mode" [ "triangles" ]` expands it into
+//! a triangle mesh instead.
+//!
 //! ## Hyperboloids
 //!
-//! TODO
+//! The hyperboloid is described by sweeping the line between two
+//! points around the z axis, which covers (partial) hyperboloids of
+//! one sheet, cylinders and cones.
 //!
 //! ## Paraboloids
 //!
-//! TODO
+//! The paraboloid is centered around the z axis, clipped by $z_{min}$
+//! and $z_{max}$, and reaches the given radius at $z_{max}$.
 //!
 
-// pub mod curve;
-// pub mod cylinder;
-// pub mod disk;
+pub mod cone;
+pub mod curve;
+pub mod cylinder;
+pub mod disk;
+pub mod heightfield;
+pub mod hyperboloid;
 pub mod loopsubdiv;
 pub mod nurbs;
+pub mod paraboloid;
 pub mod plymesh;
 pub mod sphere;
 pub mod triangle;
@@ -24,25 +24,21 @@ use crate::core::pbrt::Float;
 use crate::core::shape::Shape;
 use crate::core::texture::Texture;
 use crate::core::transform::Transform;
-use crate::shapes::triangle::{Triangle, TriangleMesh};
-use crate::textures::constant::ConstantTexture;
-use crate::backend::get_content_binary;
+use crate::shapes::triangle::{create_alpha_textures, Triangle, TriangleMesh};
+use crate::backend::{get_content_binary, resolve_filename_in};
 
 pub fn create_ply_mesh<S: BuildHasher>(
-    o2w: &Transform,
-    w2o: &Transform,
+    o2w: &Arc<Transform>,
+    w2o: &Arc<Transform>,
     reverse_orientation: bool,
     params: &ParamSet,
     float_textures: Arc<HashMap<String, Arc<Texture<Float>>, S>>,
     search_directory: Option<&PathBuf>,
 ) -> Vec<Arc<Shape>> {
-    let mut filename: String = params.find_one_string("filename", String::new());
-    if let Some(ref search_directory) = search_directory {
-        let mut path_buf: PathBuf = PathBuf::from("/");
-        path_buf.push(search_directory);
-        path_buf.push(filename);
-        filename = String::from(path_buf.to_str().unwrap());
-   }
+    let filename: String = resolve_filename_in(
+        search_directory,
+        &params.find_one_string("filename", String::new()),
+    );
    let res = get_content_binary(&filename);
    if res.is_err() {
 	   println!("Error getting content {}: {:?}", filename, res);
@@ -250,45 +246,10 @@ pub fn create_ply_mesh<S: BuildHasher>(
     }
     let s_ws: Vec<Vector3f> = Vec::new();
     // look up an alpha texture, if applicable
-    let mut alpha_tex: Option<Arc<Texture<Float>>> = None;
-    let alpha_tex_name: String = params.find_texture("alpha");
-    if alpha_tex_name != "" {
-        alpha_tex = match float_textures.get(alpha_tex_name.as_str()) {
-            Some(float_texture) => Some(float_texture.clone()),
-            None => {
-                println!(
-                    "Couldn't find float texture {:?} for \"alpha\" parameter",
-                    alpha_tex_name.as_str()
-                );
-                None
-            }
-        }
-    } else if params.find_one_float("alpha", 1.0 as Float) == 0.0 as Float {
-        alpha_tex = Some(Arc::new(Texture::Constant(ConstantTexture::new(
-            0.0 as Float,
-        ))));
-    }
-    let mut shadow_alpha_tex: Option<Arc<Texture<Float>>> = None;
-    let shadow_alpha_tex_name: String = params.find_texture("shadowalpha");
-    if shadow_alpha_tex_name != "" {
-        shadow_alpha_tex = match float_textures.get(shadow_alpha_tex_name.as_str()) {
-            Some(float_texture) => Some(float_texture.clone()),
-            None => {
-                println!(
-                    "Couldn't find float texture {:?} for \"shadowalpha\" parameter",
-                    shadow_alpha_tex_name.as_str()
-                );
-                None
-            }
-        }
-    } else if params.find_one_float("shadowalpha", 1.0 as Float) == 0.0 as Float {
-        shadow_alpha_tex = Some(Arc::new(Texture::Constant(ConstantTexture::new(
-            0.0 as Float,
-        ))));
-    }
+    let (alpha_tex, shadow_alpha_tex) = create_alpha_textures(params, &float_textures);
     let mesh = Arc::new(TriangleMesh::new(
-        *o2w,
-        *w2o,
+        o2w.clone(),
+        w2o.clone(),
         reverse_orientation,
         (tm_vertex_indices.len() / 3).try_into().unwrap(), // n_triangles
         tm_vertex_indices,
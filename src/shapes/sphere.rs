@@ -27,8 +27,10 @@ pub struct Sphere {
     pub theta_max: Float,
     pub phi_max: Float,
     // inherited from class Shape (see shape.h)
-    pub object_to_world: Transform,
-    pub world_to_object: Transform,
+    #[serde(with = "crate::core::snapshot::shared")]
+    pub object_to_world: Arc<Transform>,
+    #[serde(with = "crate::core::snapshot::shared")]
+    pub world_to_object: Arc<Transform>,
     pub reverse_orientation: bool,
     pub transform_swaps_handedness: bool,
     pub material: Option<Arc<Material>>,
@@ -36,13 +38,13 @@ pub struct Sphere {
 
 impl Default for Sphere {
     fn default() -> Self {
-        let object_to_world: Transform = Transform::default();
+        let object_to_world: Arc<Transform> = Arc::new(Transform::default());
         Sphere {
             // Shape
+            transform_swaps_handedness: object_to_world.swaps_handedness(),
             object_to_world,
-            world_to_object: Transform::default(),
+            world_to_object: Arc::new(Transform::default()),
             reverse_orientation: false,
-            transform_swaps_handedness: object_to_world.swaps_handedness(),
             // Sphere
             radius: 1.0,
             z_min: -1.0,
@@ -57,8 +59,8 @@ impl Default for Sphere {
 
 impl Sphere {
     pub fn new(
-        object_to_world: Transform,
-        world_to_object: Transform,
+        object_to_world: Arc<Transform>,
+        world_to_object: Arc<Transform>,
         reverse_orientation: bool,
         radius: Float,
         z_min: Float,
@@ -67,10 +69,10 @@ impl Sphere {
     ) -> Self {
         Sphere {
             // Shape
+            transform_swaps_handedness: object_to_world.swaps_handedness(),
             object_to_world,
             world_to_object,
             reverse_orientation,
-            transform_swaps_handedness: object_to_world.swaps_handedness(),
             // Sphere
             radius,
             z_min: clamp_t(z_min.min(z_max), -radius, radius),
@@ -256,6 +258,11 @@ impl Sphere {
         *isect = SurfaceInteraction::new(
             &p_hit, &p_error, uv_hit, &wo, &dpdu, &dpdv, &dndu, &dndv, ray.time, None,
         );
+        // adjust normal based on orientation and handedness
+        if self.reverse_orientation ^ self.transform_swaps_handedness {
+            isect.common.n *= -1.0 as Float;
+            isect.shading.n *= -1.0 as Float;
+        }
         self.object_to_world.transform_surface_interaction(isect);
         // if let Some(ref shape) = si.shape {
         //     isect.shape = Some(shape.clone());
@@ -356,7 +363,7 @@ impl Sphere {
         self.transform_swaps_handedness
     }
     pub fn get_object_to_world(&self) -> Transform {
-        self.object_to_world
+        *self.object_to_world
     }
     pub fn area(&self) -> Float {
         self.phi_max * self.radius * (self.z_max - self.z_min)
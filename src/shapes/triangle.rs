@@ -1,5 +1,7 @@
 // std
 use std::cell::Cell;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
 use std::mem;
 use std::sync::Arc;
 // pbrt
@@ -12,11 +14,13 @@ use crate::core::geometry::{
     Bounds3f, Normal3f, Point2f, Point3f, Ray, Vector2f, Vector3f, XYEnum,
 };
 use crate::core::interaction::{Interaction, InteractionCommon, Shading, SurfaceInteraction};
+use crate::core::paramset::ParamSet;
 use crate::core::pbrt::gamma;
 use crate::core::pbrt::Float;
 // use crate::core::sampling::uniform_sample_triangle;
 use crate::core::texture::Texture;
 use crate::core::transform::Transform;
+use crate::textures::constant::ConstantTexture;
 
 // see triangle.h
 
@@ -39,16 +43,18 @@ pub struct TriangleMesh {
     pub alpha_mask: Option<Arc<Texture<Float>>>,
     pub shadow_alpha_mask: Option<Arc<Texture<Float>>>,
     // inherited from class Shape (see shape.h)
-    pub object_to_world: Transform, // TODO: not pub?
-    pub world_to_object: Transform, // TODO: not pub?
+    #[serde(with = "crate::core::snapshot::shared")]
+    pub object_to_world: Arc<Transform>, // TODO: not pub?
+    #[serde(with = "crate::core::snapshot::shared")]
+    pub world_to_object: Arc<Transform>, // TODO: not pub?
     pub reverse_orientation: bool,
     pub transform_swaps_handedness: bool, // TODO: not pub?
 }
 
 impl TriangleMesh {
     pub fn new(
-        object_to_world: Transform,
-        world_to_object: Transform,
+        object_to_world: Arc<Transform>,
+        world_to_object: Arc<Transform>,
         reverse_orientation: bool,
         n_triangles: u32,
         vertex_indices: Vec<u32>,
@@ -62,10 +68,10 @@ impl TriangleMesh {
     ) -> Self {
         TriangleMesh {
             // Shape
+            transform_swaps_handedness: object_to_world.swaps_handedness(),
             object_to_world,
             world_to_object,
             reverse_orientation,
-            transform_swaps_handedness: object_to_world.swaps_handedness(),
             // TriangleMesh
             n_triangles,
             vertex_indices,
@@ -82,6 +88,7 @@ impl TriangleMesh {
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Triangle {
+    #[serde(with = "crate::core::snapshot::shared")]
     mesh: Arc<TriangleMesh>,
     pub id: u32,
 }
@@ -138,139 +145,10 @@ impl Triangle {
         let p0: &Point3f = &self.mesh.p[idx[0] as usize];
         let p1: &Point3f = &self.mesh.p[idx[1] as usize];
         let p2: &Point3f = &self.mesh.p[idx[2] as usize];
-        // translate vertices based on ray origin
-        let mut p0t: Point3f = *p0
-            - Vector3f {
-                x: ray.o.x,
-                y: ray.o.y,
-                z: ray.o.z,
-            };
-        let mut p1t: Point3f = *p1
-            - Vector3f {
-                x: ray.o.x,
-                y: ray.o.y,
-                z: ray.o.z,
-            };
-        let mut p2t: Point3f = *p2
-            - Vector3f {
-                x: ray.o.x,
-                y: ray.o.y,
-                z: ray.o.z,
-            };
-        // permute components of triangle vertices and ray direction
-        let kz: usize = vec3_max_dimensionf(&ray.d.abs());
-        let mut kx: usize = kz + 1;
-        if kx == 3 {
-            kx = 0;
-        }
-        let mut ky: usize = kx + 1;
-        if ky == 3 {
-            ky = 0;
-        }
-        let d: Vector3f = vec3_permutef(&ray.d, kx, ky, kz);
-        p0t = pnt3_permutef(&p0t, kx, ky, kz);
-        p1t = pnt3_permutef(&p1t, kx, ky, kz);
-        p2t = pnt3_permutef(&p2t, kx, ky, kz);
-        // apply shear transformation to translated vertex positions
-        let sx: Float = -d.x / d.z;
-        let sy: Float = -d.y / d.z;
-        let sz: Float = 1.0 / d.z;
-        p0t.x += sx * p0t.z;
-        p0t.y += sy * p0t.z;
-        p1t.x += sx * p1t.z;
-        p1t.y += sy * p1t.z;
-        p2t.x += sx * p2t.z;
-        p2t.y += sy * p2t.z;
-        // compute edge function coefficients _e0_, _e1_, and _e2_
-        let mut e0: Float = p1t.x * p2t.y - p1t.y * p2t.x;
-        let mut e1: Float = p2t.x * p0t.y - p2t.y * p0t.x;
-        let mut e2: Float = p0t.x * p1t.y - p0t.y * p1t.x;
-        // fall back to double precision test at triangle edges
-        if mem::size_of::<Float>() == mem::size_of::<f32>() && (e0 == 0.0 || e1 == 0.0 || e2 == 0.0)
-        {
-            let p2txp1ty: f64 = p2t.x as f64 * p1t.y as f64;
-            let p2typ1tx: f64 = p2t.y as f64 * p1t.x as f64;
-            e0 = (p2typ1tx - p2txp1ty) as Float;
-            let p0txp2ty = p0t.x as f64 * p2t.y as f64;
-            let p0typ2tx = p0t.y as f64 * p2t.x as f64;
-            e1 = (p0typ2tx - p0txp2ty) as Float;
-            let p1txp0ty = p1t.x as f64 * p0t.y as f64;
-            let p1typ0tx = p1t.y as f64 * p0t.x as f64;
-            e2 = (p1typ0tx - p1txp0ty) as Float;
-        }
-        // perform triangle edge and determinant tests
-        if (e0 < 0.0 || e1 < 0.0 || e2 < 0.0) && (e0 > 0.0 || e1 > 0.0 || e2 > 0.0) {
-            return false;
-        }
-        let det: Float = e0 + e1 + e2;
-        if det == 0.0 {
-            return false;
-        }
-        // compute scaled hit distance to triangle and test against ray $t$ range
-        p0t.z *= sz;
-        p1t.z *= sz;
-        p2t.z *= sz;
-        let t_scaled: Float = e0 * p0t.z + e1 * p1t.z + e2 * p2t.z;
-        if det < 0.0 && (t_scaled >= 0.0 || t_scaled < ray.t_max.get() * det) {
-            return false;
-        } else if det > 0.0 && (t_scaled <= 0.0 || t_scaled > ray.t_max.get() * det) {
-            return false;
-        }
-        // compute barycentric coordinates and $t$ value for triangle intersection
-        let inv_det: Float = 1.0 / det;
-        let b0: Float = e0 * inv_det;
-        let b1: Float = e1 * inv_det;
-        let b2: Float = e2 * inv_det;
-        let t: Float = t_scaled * inv_det;
-
-        // ensure that computed triangle $t$ is conservatively greater than zero
-
-        // compute $\delta_z$ term for triangle $t$ error bounds
-        let max_zt: Float = vec3_max_componentf(
-            &Vector3f {
-                x: p0t.z,
-                y: p1t.z,
-                z: p2t.z,
-            }
-            .abs(),
-        );
-        let delta_z: Float = gamma(3_i32) * max_zt;
-        // compute $\delta_x$ and $\delta_y$ terms for triangle $t$ error bounds
-        let max_xt: Float = vec3_max_componentf(
-            &Vector3f {
-                x: p0t.x,
-                y: p1t.x,
-                z: p2t.x,
-            }
-            .abs(),
-        );
-        let max_yt: Float = vec3_max_componentf(
-            &Vector3f {
-                x: p0t.y,
-                y: p1t.y,
-                z: p2t.y,
-            }
-            .abs(),
-        );
-        let delta_x: Float = gamma(5) * (max_xt + max_zt);
-        let delta_y: Float = gamma(5) * (max_yt + max_zt);
-        // compute $\delta_e$ term for triangle $t$ error bounds
-        let delta_e: Float =
-            2.0 * (gamma(2) * max_xt * max_yt + delta_y * max_xt + delta_x * max_yt);
-        // compute $\delta_t$ term for triangle $t$ error bounds and check _t_
-        let max_e: Float = vec3_max_componentf(
-            &Vector3f {
-                x: e0,
-                y: e1,
-                z: e2,
-            }
-            .abs(),
-        );
-        let delta_t: Float =
-            3.0 * (gamma(3) * max_e * max_zt + delta_e * max_zt + delta_z * max_e) * inv_det.abs();
-        if t <= delta_t {
-            return false;
-        }
+        let (t, b0, b1, b2) = match ray_triangle_intersect(ray, p0, p1, p2) {
+            Some(hit) => hit,
+            None => return false,
+        };
         // compute triangle partial derivatives
         let uv: [Point2f; 3] = self.get_uvs();
         // compute deltas for triangle partial derivatives
@@ -309,7 +187,6 @@ impl Triangle {
         let p_hit: Point3f = *p0 * b0 + *p1 * b1 + *p2 * b2;
         let uv_hit: Point2f = uv[0] * b0 + uv[1] * b1 + uv[2] * b2;
         // test intersection against alpha texture, if present
-        // TODO: testAlphaTexture
         if let Some(alpha_mask) = &self.mesh.alpha_mask {
             let wo: Vector3f = -ray.d;
             let isect_local: SurfaceInteraction = SurfaceInteraction::new(
@@ -324,7 +201,7 @@ impl Triangle {
                 ray.time,
                 None,
             );
-            if alpha_mask.evaluate(&isect_local) == 0.0 as Float {
+            if alpha_cutout(alpha_mask.evaluate(&isect_local), ray) {
                 return false;
             }
         }
@@ -456,139 +333,10 @@ impl Triangle {
         let p0: &Point3f = &self.mesh.p[idx[0] as usize];
         let p1: &Point3f = &self.mesh.p[idx[1] as usize];
         let p2: &Point3f = &self.mesh.p[idx[2] as usize];
-        // translate vertices based on ray origin
-        let mut p0t: Point3f = *p0
-            - Vector3f {
-                x: ray.o.x,
-                y: ray.o.y,
-                z: ray.o.z,
-            };
-        let mut p1t: Point3f = *p1
-            - Vector3f {
-                x: ray.o.x,
-                y: ray.o.y,
-                z: ray.o.z,
-            };
-        let mut p2t: Point3f = *p2
-            - Vector3f {
-                x: ray.o.x,
-                y: ray.o.y,
-                z: ray.o.z,
-            };
-        // permute components of triangle vertices and ray direction
-        let kz: usize = vec3_max_dimensionf(&ray.d.abs());
-        let mut kx: usize = kz + 1;
-        if kx == 3 {
-            kx = 0;
-        }
-        let mut ky: usize = kx + 1;
-        if ky == 3 {
-            ky = 0;
-        }
-        let d: Vector3f = vec3_permutef(&ray.d, kx, ky, kz);
-        p0t = pnt3_permutef(&p0t, kx, ky, kz);
-        p1t = pnt3_permutef(&p1t, kx, ky, kz);
-        p2t = pnt3_permutef(&p2t, kx, ky, kz);
-        // apply shear transformation to translated vertex positions
-        let sx: Float = -d.x / d.z;
-        let sy: Float = -d.y / d.z;
-        let sz: Float = 1.0 / d.z;
-        p0t.x += sx * p0t.z;
-        p0t.y += sy * p0t.z;
-        p1t.x += sx * p1t.z;
-        p1t.y += sy * p1t.z;
-        p2t.x += sx * p2t.z;
-        p2t.y += sy * p2t.z;
-        // compute edge function coefficients _e0_, _e1_, and _e2_
-        let mut e0: Float = p1t.x * p2t.y - p1t.y * p2t.x;
-        let mut e1: Float = p2t.x * p0t.y - p2t.y * p0t.x;
-        let mut e2: Float = p0t.x * p1t.y - p0t.y * p1t.x;
-        // fall back to double precision test at triangle edges
-        if mem::size_of::<Float>() == mem::size_of::<f32>() && (e0 == 0.0 || e1 == 0.0 || e2 == 0.0)
-        {
-            let p2txp1ty: f64 = p2t.x as f64 * p1t.y as f64;
-            let p2typ1tx: f64 = p2t.y as f64 * p1t.x as f64;
-            e0 = (p2typ1tx - p2txp1ty) as Float;
-            let p0txp2ty = p0t.x as f64 * p2t.y as f64;
-            let p0typ2tx = p0t.y as f64 * p2t.x as f64;
-            e1 = (p0typ2tx - p0txp2ty) as Float;
-            let p1txp0ty = p1t.x as f64 * p0t.y as f64;
-            let p1typ0tx = p1t.y as f64 * p0t.x as f64;
-            e2 = (p1typ0tx - p1txp0ty) as Float;
-        }
-        // perform triangle edge and determinant tests
-        if (e0 < 0.0 || e1 < 0.0 || e2 < 0.0) && (e0 > 0.0 || e1 > 0.0 || e2 > 0.0) {
-            return false;
-        }
-        let det: Float = e0 + e1 + e2;
-        if det == 0.0 {
-            return false;
-        }
-        // compute scaled hit distance to triangle and test against ray $t$ range
-        p0t.z *= sz;
-        p1t.z *= sz;
-        p2t.z *= sz;
-        let t_scaled: Float = e0 * p0t.z + e1 * p1t.z + e2 * p2t.z;
-        if det < 0.0 && (t_scaled >= 0.0 || t_scaled < ray.t_max.get() * det) {
-            return false;
-        } else if det > 0.0 && (t_scaled <= 0.0 || t_scaled > ray.t_max.get() * det) {
-            return false;
-        }
-        // compute barycentric coordinates and $t$ value for triangle intersection
-        let inv_det: Float = 1.0 / det;
-        let b0: Float = e0 * inv_det;
-        let b1: Float = e1 * inv_det;
-        let b2: Float = e2 * inv_det;
-        let t: Float = t_scaled * inv_det;
-
-        // ensure that computed triangle $t$ is conservatively greater than zero
-
-        // compute $\delta_z$ term for triangle $t$ error bounds
-        let max_zt: Float = vec3_max_componentf(
-            &Vector3f {
-                x: p0t.z,
-                y: p1t.z,
-                z: p2t.z,
-            }
-            .abs(),
-        );
-        let delta_z: Float = gamma(3_i32) * max_zt;
-        // compute $\delta_x$ and $\delta_y$ terms for triangle $t$ error bounds
-        let max_xt: Float = vec3_max_componentf(
-            &Vector3f {
-                x: p0t.x,
-                y: p1t.x,
-                z: p2t.x,
-            }
-            .abs(),
-        );
-        let max_yt: Float = vec3_max_componentf(
-            &Vector3f {
-                x: p0t.y,
-                y: p1t.y,
-                z: p2t.y,
-            }
-            .abs(),
-        );
-        let delta_x: Float = gamma(5) * (max_xt + max_zt);
-        let delta_y: Float = gamma(5) * (max_yt + max_zt);
-        // compute $\delta_e$ term for triangle $t$ error bounds
-        let delta_e: Float =
-            2.0 * (gamma(2) * max_xt * max_yt + delta_y * max_xt + delta_x * max_yt);
-        // compute $\delta_t$ term for triangle $t$ error bounds and check _t_
-        let max_e: Float = vec3_max_componentf(
-            &Vector3f {
-                x: e0,
-                y: e1,
-                z: e2,
-            }
-            .abs(),
-        );
-        let delta_t: Float =
-            3.0 * (gamma(3) * max_e * max_zt + delta_e * max_zt + delta_z * max_e) * inv_det.abs();
-        if t <= delta_t {
-            return false;
-        }
+        let (_t, b0, b1, b2) = match ray_triangle_intersect(ray, p0, p1, p2) {
+            Some(hit) => hit,
+            None => return false,
+        };
         // TODO: if (testAlphaTexture && (mesh->alphaMask || mesh->shadowAlphaMask)) { ... }
         if self.mesh.alpha_mask.is_some() || self.mesh.shadow_alpha_mask.is_some() {
             // compute triangle partial derivatives
@@ -642,12 +390,12 @@ impl Triangle {
                 None,
             );
             if let Some(alpha_mask) = &self.mesh.alpha_mask {
-                if alpha_mask.evaluate(&isect_local) == 0.0 as Float {
+                if alpha_cutout(alpha_mask.evaluate(&isect_local), ray) {
                     return false;
                 }
             }
             if let Some(shadow_alpha_mask) = &self.mesh.shadow_alpha_mask {
-                if shadow_alpha_mask.evaluate(&isect_local) == 0.0 as Float {
+                if alpha_cutout(shadow_alpha_mask.evaluate(&isect_local), ray) {
                     return false;
                 }
             }
@@ -662,7 +410,7 @@ impl Triangle {
         self.mesh.transform_swaps_handedness
     }
     pub fn get_object_to_world(&self) -> Transform {
-        self.mesh.object_to_world
+        *self.mesh.object_to_world
     }
     pub fn area(&self) -> Float {
         let idx1: usize = (self.id * 3) as usize;
@@ -763,3 +511,224 @@ impl Triangle {
         }
     }
 }
+
+/// Looks up the `"alpha"` and `"shadowalpha"` parameters of a
+/// triangle mesh. Each one is either the name of a float texture or a
+/// constant; a constant of one (fully opaque) yields no mask at all.
+pub fn create_alpha_textures<S: BuildHasher>(
+    params: &ParamSet,
+    float_textures: &HashMap<String, Arc<Texture<Float>>, S>,
+) -> (Option<Arc<Texture<Float>>>, Option<Arc<Texture<Float>>>) {
+    (
+        find_alpha_texture(params, float_textures, "alpha"),
+        find_alpha_texture(params, float_textures, "shadowalpha"),
+    )
+}
+
+fn find_alpha_texture<S: BuildHasher>(
+    params: &ParamSet,
+    float_textures: &HashMap<String, Arc<Texture<Float>>, S>,
+    name: &str,
+) -> Option<Arc<Texture<Float>>> {
+    let tex_name: String = params.find_texture(name);
+    if tex_name != "" {
+        match float_textures.get(tex_name.as_str()) {
+            Some(float_texture) => Some(float_texture.clone()),
+            None => {
+                println!(
+                    "ERROR: Couldn't find float texture {:?} for {:?} parameter",
+                    tex_name.as_str(),
+                    name
+                );
+                None
+            }
+        }
+    } else {
+        let alpha: Float = params.find_one_float(name, 1.0 as Float);
+        if alpha < 1.0 as Float {
+            Some(Arc::new(Texture::Constant(ConstantTexture::new(
+                alpha.max(0.0 as Float),
+            ))))
+        } else {
+            None
+        }
+    }
+}
+
+/// Decides whether a hit with the given alpha value is cut away. Zero
+/// alpha always cuts, one never does, and anything in between is
+/// stochastic transparency: the hit survives with probability
+/// `alpha`. The random number is a hash of the ray, so `intersect()`
+/// and `intersect_p()` agree for the same ray and renders stay
+/// deterministic.
+fn alpha_cutout(alpha: Float, ray: &Ray) -> bool {
+    if alpha >= 1.0 as Float {
+        false
+    } else if alpha <= 0.0 as Float {
+        true
+    } else {
+        hash_ray(ray) > alpha
+    }
+}
+
+/// Maps a ray origin and direction to a float in [0, 1).
+fn hash_ray(ray: &Ray) -> Float {
+    let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+    for v in &[ray.o.x, ray.o.y, ray.o.z, ray.d.x, ray.d.y, ray.d.z] {
+        h = mix_bits(h ^ u64::from((*v as f32).to_bits()));
+    }
+    (h >> 40) as Float / (1u64 << 24) as Float
+}
+
+// see MixBits in pbrt.h (pbrt-v4)
+fn mix_bits(mut v: u64) -> u64 {
+    v ^= v >> 31;
+    v = v.wrapping_mul(0x7fb5_d329_728e_a185);
+    v ^= v >> 27;
+    v = v.wrapping_mul(0x81da_def4_bc2d_d44d);
+    v ^= v >> 33;
+    v
+}
+
+/// The watertight ray-triangle test shared by triangles and
+/// heightfields. Returns the parametric distance along the ray and
+/// the barycentric coordinates of the hit.
+pub fn ray_triangle_intersect(
+    ray: &Ray,
+    p0: &Point3f,
+    p1: &Point3f,
+    p2: &Point3f,
+) -> Option<(Float, Float, Float, Float)> {
+    // translate vertices based on ray origin
+    let mut p0t: Point3f = *p0
+        - Vector3f {
+            x: ray.o.x,
+            y: ray.o.y,
+            z: ray.o.z,
+        };
+    let mut p1t: Point3f = *p1
+        - Vector3f {
+            x: ray.o.x,
+            y: ray.o.y,
+            z: ray.o.z,
+        };
+    let mut p2t: Point3f = *p2
+        - Vector3f {
+            x: ray.o.x,
+            y: ray.o.y,
+            z: ray.o.z,
+        };
+    // permute components of triangle vertices and ray direction
+    let kz: usize = vec3_max_dimensionf(&ray.d.abs());
+    let mut kx: usize = kz + 1;
+    if kx == 3 {
+        kx = 0;
+    }
+    let mut ky: usize = kx + 1;
+    if ky == 3 {
+        ky = 0;
+    }
+    let d: Vector3f = vec3_permutef(&ray.d, kx, ky, kz);
+    p0t = pnt3_permutef(&p0t, kx, ky, kz);
+    p1t = pnt3_permutef(&p1t, kx, ky, kz);
+    p2t = pnt3_permutef(&p2t, kx, ky, kz);
+    // apply shear transformation to translated vertex positions
+    let sx: Float = -d.x / d.z;
+    let sy: Float = -d.y / d.z;
+    let sz: Float = 1.0 / d.z;
+    p0t.x += sx * p0t.z;
+    p0t.y += sy * p0t.z;
+    p1t.x += sx * p1t.z;
+    p1t.y += sy * p1t.z;
+    p2t.x += sx * p2t.z;
+    p2t.y += sy * p2t.z;
+    // compute edge function coefficients _e0_, _e1_, and _e2_
+    let mut e0: Float = p1t.x * p2t.y - p1t.y * p2t.x;
+    let mut e1: Float = p2t.x * p0t.y - p2t.y * p0t.x;
+    let mut e2: Float = p0t.x * p1t.y - p0t.y * p1t.x;
+    // fall back to double precision test at triangle edges
+    if mem::size_of::<Float>() == mem::size_of::<f32>() && (e0 == 0.0 || e1 == 0.0 || e2 == 0.0) {
+        let p2txp1ty: f64 = p2t.x as f64 * p1t.y as f64;
+        let p2typ1tx: f64 = p2t.y as f64 * p1t.x as f64;
+        e0 = (p2typ1tx - p2txp1ty) as Float;
+        let p0txp2ty = p0t.x as f64 * p2t.y as f64;
+        let p0typ2tx = p0t.y as f64 * p2t.x as f64;
+        e1 = (p0typ2tx - p0txp2ty) as Float;
+        let p1txp0ty = p1t.x as f64 * p0t.y as f64;
+        let p1typ0tx = p1t.y as f64 * p0t.x as f64;
+        e2 = (p1typ0tx - p1txp0ty) as Float;
+    }
+    // perform triangle edge and determinant tests
+    if (e0 < 0.0 || e1 < 0.0 || e2 < 0.0) && (e0 > 0.0 || e1 > 0.0 || e2 > 0.0) {
+        return None;
+    }
+    let det: Float = e0 + e1 + e2;
+    if det == 0.0 {
+        return None;
+    }
+    // compute scaled hit distance to triangle and test against ray $t$ range
+    p0t.z *= sz;
+    p1t.z *= sz;
+    p2t.z *= sz;
+    let t_scaled: Float = e0 * p0t.z + e1 * p1t.z + e2 * p2t.z;
+    if det < 0.0 && (t_scaled >= 0.0 || t_scaled < ray.t_max.get() * det) {
+        return None;
+    } else if det > 0.0 && (t_scaled <= 0.0 || t_scaled > ray.t_max.get() * det) {
+        return None;
+    }
+    // compute barycentric coordinates and $t$ value for triangle intersection
+    let inv_det: Float = 1.0 / det;
+    let b0: Float = e0 * inv_det;
+    let b1: Float = e1 * inv_det;
+    let b2: Float = e2 * inv_det;
+    let t: Float = t_scaled * inv_det;
+
+    // ensure that computed triangle $t$ is conservatively greater than zero
+
+    // compute $\delta_z$ term for triangle $t$ error bounds
+    let max_zt: Float = vec3_max_componentf(
+        &Vector3f {
+            x: p0t.z,
+            y: p1t.z,
+            z: p2t.z,
+        }
+        .abs(),
+    );
+    let delta_z: Float = gamma(3_i32) * max_zt;
+    // compute $\delta_x$ and $\delta_y$ terms for triangle $t$ error bounds
+    let max_xt: Float = vec3_max_componentf(
+        &Vector3f {
+            x: p0t.x,
+            y: p1t.x,
+            z: p2t.x,
+        }
+        .abs(),
+    );
+    let max_yt: Float = vec3_max_componentf(
+        &Vector3f {
+            x: p0t.y,
+            y: p1t.y,
+            z: p2t.y,
+        }
+        .abs(),
+    );
+    let delta_x: Float = gamma(5) * (max_xt + max_zt);
+    let delta_y: Float = gamma(5) * (max_yt + max_zt);
+    // compute $\delta_e$ term for triangle $t$ error bounds
+    let delta_e: Float = 2.0 * (gamma(2) * max_xt * max_yt + delta_y * max_xt + delta_x * max_yt);
+    // compute $\delta_t$ term for triangle $t$ error bounds and check _t_
+    let max_e: Float = vec3_max_componentf(
+        &Vector3f {
+            x: e0,
+            y: e1,
+            z: e2,
+        }
+        .abs(),
+    );
+    let delta_t: Float =
+        3.0 * (gamma(3) * max_e * max_zt + delta_e * max_zt + delta_z * max_e) * inv_det.abs();
+    if t <= delta_t {
+        return None;
+    }
+    Some((t, b0, b1, b2))
+}
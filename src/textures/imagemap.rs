@@ -56,7 +56,7 @@ where
         convert: F,
     ) -> ImageTexture<T> {
 		let data = get_content_binary(&filename).unwrap();
-        let img_result: ImageResult<DynamicImage> = image::load_from_memory_with_format(&data, image::ImageFormat::Png);
+        let img_result: ImageResult<DynamicImage> = image::load_from_memory(&data);
         if img_result.is_err() {
 			let mipmap = Arc::new(MipMap::new(Point2i::default(), &vec![], do_trilinear, max_aniso, wrap_mode));
 			return ImageTexture {mapping, mipmap};
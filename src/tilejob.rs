@@ -0,0 +1,248 @@
+//! The JSON protocol between a collector and its render workers. The
+//! collector POSTs a [TileJob](struct.TileJob.html) to `/rendertile`,
+//! the worker answers with a binary
+//! [TilePayload](../core/film/struct.TilePayload.html) or, for a
+//! request it can't handle, with a 4xx status and a
+//! [TileJobError](struct.TileJobError.html) as JSON body.
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "filename": "cornell",
+//!   "scene_hash": "8d3c7e1f0a6b2c44",
+//!   "x": 3, "y": 5, "tile_size": 16,
+//!   "resolution": [500, 500],
+//!   "crop_window": [0.0, 1.0, 0.0, 1.0],
+//!   "first_sample": 0, "last_sample": 8, "seed_offset": 0,
+//!   "integrator": "path",
+//!   "sampler": "sobol", "pixelsamples": 16
+//! }
+//! ```
+//!
+//! Everything after `tile_size` is optional. Several workers can split
+//! the samples of the same tile (`first_sample`..`last_sample`), the
+//! collector adds their payloads.
+
+// pbrt
+use crate::core::pbrt::Float;
+use crate::core::sampler::SampleRange;
+
+/// Increase whenever the meaning of a field changes.
+pub const TILE_JOB_VERSION: u32 = 1;
+/// The integrators a worker can render tiles with
+pub const TILE_INTEGRATORS: &[&str] = &[
+    "ao",
+    "ambientocclusion",
+    "directlighting",
+    "whitted",
+    "path",
+    "bdpt",
+    "volpath",
+];
+/// The samplers a tile job can select
+pub const TILE_SAMPLERS: &[&str] = &[
+    "lowdiscrepancy",
+    "02sequence",
+    "maxmindist",
+    "halton",
+    "sobol",
+    "random",
+    "stratified",
+];
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct TileJob {
+    /// The protocol version the collector speaks
+    pub version: u32,
+    /// The scene directory within the content backend
+    pub filename: String,
+    /// Hash of the scene the worker renders from (see
+    /// [scene_hash()](fn.scene_hash.html)), checked if given
+    #[serde(default)]
+    pub scene_hash: Option<String>,
+    /// Tile column
+    pub x: u32,
+    /// Tile row
+    pub y: u32,
+    /// Size of a tile in pixels
+    pub tile_size: i32,
+    /// Overrides the film's resolution (x, y)
+    #[serde(default)]
+    pub resolution: Option<[i32; 2]>,
+    /// Overrides the film's crop window (x0, x1, y0, y1)
+    #[serde(default)]
+    pub crop_window: Option<[Float; 4]>,
+    /// Index of the first pixel sample to take
+    #[serde(default)]
+    pub first_sample: u32,
+    /// One past the last pixel sample to take (all if not given)
+    #[serde(default)]
+    pub last_sample: Option<u32>,
+    /// Added to the seed of each tile sampler
+    #[serde(default)]
+    pub seed_offset: u64,
+    /// Overrides the scene's integrator
+    #[serde(default)]
+    pub integrator: Option<String>,
+    /// Overrides the scene's sampler
+    #[serde(default)]
+    pub sampler: Option<String>,
+    /// Overrides the number of pixel samples
+    #[serde(default)]
+    pub pixelsamples: Option<u32>,
+}
+
+impl TileJob {
+    pub fn new(filename: &str, x: u32, y: u32, tile_size: i32) -> Self {
+        TileJob {
+            version: TILE_JOB_VERSION,
+            filename: String::from(filename),
+            x,
+            y,
+            tile_size,
+            ..Default::default()
+        }
+    }
+    /// Parse and validate a job sent by a collector.
+    pub fn from_json(json: &str) -> Result<TileJob, TileJobError> {
+        let job: TileJob = match serde_json::from_str(json) {
+            Ok(job) => job,
+            Err(e) => {
+                return Err(TileJobError::new(400, &format!("Malformed tile job: {}", e)));
+            }
+        };
+        job.validate()?;
+        Ok(job)
+    }
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+    pub fn validate(&self) -> Result<(), TileJobError> {
+        if self.version != TILE_JOB_VERSION {
+            return Err(TileJobError::new(
+                400,
+                &format!(
+                    "Unsupported tile job version {} (expected {})",
+                    self.version, TILE_JOB_VERSION
+                ),
+            ));
+        }
+        if self.filename.is_empty() || self.filename.split('/').any(|part| part == "..") {
+            return Err(TileJobError::new(
+                400,
+                &format!("Invalid scene {:?}", self.filename),
+            ));
+        }
+        if self.tile_size <= 0 {
+            return Err(TileJobError::new(
+                422,
+                &format!("Invalid tile size {}", self.tile_size),
+            ));
+        }
+        if let Some(ref integrator) = self.integrator {
+            if !TILE_INTEGRATORS.contains(&integrator.as_str()) {
+                return Err(TileJobError::new(
+                    422,
+                    &format!("Integrator {:?} can't render tiles", integrator),
+                ));
+            }
+        }
+        if let Some(ref sampler) = self.sampler {
+            if !TILE_SAMPLERS.contains(&sampler.as_str()) {
+                return Err(TileJobError::new(
+                    422,
+                    &format!("Sampler {:?} unknown", sampler),
+                ));
+            }
+        }
+        if let Some(resolution) = self.resolution {
+            if resolution[0] <= 0 || resolution[1] <= 0 {
+                return Err(TileJobError::new(
+                    422,
+                    &format!("Invalid resolution {:?}", resolution),
+                ));
+            }
+        }
+        if let Some(cr) = self.crop_window {
+            if cr.iter().any(|v| !(0.0..=1.0).contains(v)) || cr[0] >= cr[1] || cr[2] >= cr[3] {
+                return Err(TileJobError::new(
+                    422,
+                    &format!("Invalid crop window {:?}", cr),
+                ));
+            }
+        }
+        if let Some(last_sample) = self.last_sample {
+            if last_sample <= self.first_sample {
+                return Err(TileJobError::new(
+                    422,
+                    &format!(
+                        "Empty sample range {}..{}",
+                        self.first_sample, last_sample
+                    ),
+                ));
+            }
+        }
+        if self.pixelsamples == Some(0) {
+            return Err(TileJobError::new(422, "Invalid number of pixel samples 0"));
+        }
+        Ok(())
+    }
+    /// Check the hash of the scene content the worker loaded (if the
+    /// collector sent one).
+    pub fn check_scene_hash(&self, data: &[u8]) -> Result<(), TileJobError> {
+        if let Some(ref expected) = self.scene_hash {
+            let hash: String = scene_hash(data);
+            if *expected != hash {
+                return Err(TileJobError::new(
+                    409,
+                    &format!(
+                        "Scene {:?} has hash {} (expected {})",
+                        self.filename, hash, expected
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+    pub fn get_sample_range(&self) -> SampleRange {
+        SampleRange {
+            first_sample: self.first_sample as i64,
+            last_sample: self.last_sample.map(|last_sample| last_sample as i64),
+            seed_offset: self.seed_offset,
+        }
+    }
+}
+
+/// The JSON body of a 4xx response.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TileJobError {
+    pub version: u32,
+    /// HTTP status code
+    pub status: u16,
+    pub error: String,
+}
+
+impl TileJobError {
+    pub fn new(status: u16, error: &str) -> Self {
+        TileJobError {
+            version: TILE_JOB_VERSION,
+            status,
+            error: String::from(error),
+        }
+    }
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+/// Hash (64-bit FNV-1a, hex) of the scene content a worker renders
+/// from: the scene snapshot if there is one, the main **.pbrt** file
+/// otherwise.
+pub fn scene_hash(data: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in data {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    format!("{:016x}", hash)
+}